@@ -57,6 +57,105 @@ macro_rules! COMMAND_MODIFY {
     };
 }
 
+macro_rules! COMMAND_MOVE {
+    () => {
+        "move"
+    };
+}
+
+macro_rules! COMMAND_BOM {
+    () => {
+        "bom"
+    };
+}
+
+macro_rules! COMMAND_SEARCH {
+    () => {
+        "search"
+    };
+}
+
+macro_rules! COMMAND_EXPORT {
+    () => {
+        "export"
+    };
+}
+
+macro_rules! COMMAND_IMPORT {
+    () => {
+        "import"
+    };
+}
+
+macro_rules! COMMAND_BACKUP {
+    () => {
+        "backup"
+    };
+}
+
+macro_rules! COMMAND_BACKUPS {
+    () => {
+        "backups"
+    };
+}
+
+macro_rules! COMMAND_RESTORE {
+    () => {
+        "restore"
+    };
+}
+
+macro_rules! COMMAND_LABEL {
+    () => {
+        "label"
+    };
+}
+
+macro_rules! COMMAND_SCAN {
+    () => {
+        "scan"
+    };
+}
+
+macro_rules! COMMAND_ALTERNATE {
+    () => {
+        "alternate"
+    };
+}
+
+macro_rules! COMMAND_ALTERNATES {
+    () => {
+        "alternates"
+    };
+}
+
+macro_rules! COMMAND_PO {
+    () => {
+        "po"
+    };
+}
+
+macro_rules! COMMAND_AUDIT {
+    () => {
+        "audit"
+    };
+}
+
+macro_rules! COMMAND_ATTACH {
+    () => {
+        "attach"
+    };
+}
+
+macro_rules! COMMAND_NOTE {
+    () => {
+        "note"
+    };
+}
+
+// 搜索结果最多显示的条数
+const SEARCH_LIMIT: usize = 20;
+
 /**
  * @description: 获取命令行输入, 并返回参数列表
  * @param {*} progam_name
@@ -65,9 +164,9 @@ macro_rules! COMMAND_MODIFY {
 fn get_cmd(progam_name: &str, cmd_data: String) -> Result<Vec<String>, io::Error> {
     let input = cmd_data.trim(); // 去除输入两端的空白字符
 
-    if input.len() == 0 {
+    if input.is_empty() {
         // 如果输入为空, 返回错误
-        return Err(io::Error::new(io::ErrorKind::Other, "Empty input"));
+        return Err(io::Error::other("Empty input"));
     }
 
     // 分割输入字符串为参数列表
@@ -118,19 +217,45 @@ fn add_electronic_component(
         .expect("Failed to read line");
     let describe = input.trim(); // 去除输入两端的空白字符
                                  // 输入类型
-    println!("Enter the type of the electronic component: ");
+    println!("Enter the type or category of the electronic component (e.g. 电阻, 半导体/MOSFET): ");
     let mut input = String::new();
     io::stdin()
         .read_line(&mut input)
         .expect("Failed to read line");
     let element_type = input.trim(); // 去除输入两端的空白字符
 
+    // 内置类型直接使用, 其他输入作为已有的分类路径, 分类不存在时确认后再创建, 避免输错类型时多出一个分类
+    let (element_type, category) = match ElementType::from_string(element_type) {
+        Ok(element_type) => (element_type, None),
+        Err(_) => match bom_manage_ctrl.find_category(element_type) {
+            Ok(category) => (ElementType::Unknown, Some(category.id)),
+            Err(BomError::NotFound(_)) => {
+                println!("Category {element_type} does not exist, create it? (y/N) default N. ");
+                let mut input = String::new();
+                io::stdin()
+                    .read_line(&mut input)
+                    .expect("Failed to read line");
+                if input.trim() != "y" {
+                    return Err(format!("No such type or category: {element_type}").into());
+                }
+                (
+                    ElementType::Unknown,
+                    Some(bom_manage_ctrl.add_category(element_type)?.id),
+                )
+            }
+            Err(err) => return Err(err.into()),
+        },
+    };
+
     let res = Element {
         describe: describe.to_string(),
         model: name.clone(),
-        number: number,
-        element_type: ElementType::from_string(element_type)?,
+        manufacturer: String::new(),
+        number,
+        element_type,
         state: ElementStatus::from_number(number)?,
+        category,
+        ..Default::default()
     };
     bom_manage_ctrl.add_element(res)?;
     //
@@ -157,23 +282,70 @@ fn view_electronic_component(
         return Err("No name provided".into());
     }
     if name == SUBCOMMAND_ALL!() {
-        for (key, value) in bom_manage_ctrl.element_map.iter() {
-            println!("Key: {key}, Value: {:#?}", value);
+        for value in bom_manage_ctrl.query(&ElementQuery::new())? {
+            println!("Key: {}, Value: {:#?}", value.key(), value);
+            print_locations(&value.key(), bom_manage_ctrl)?;
         }
     } else {
-        let element = bom_manage_ctrl.element_map.get(name);
-        match element {
-            Some(element) => {
+        match bom_manage_ctrl.element(name) {
+            Ok(element) => {
                 println!("{:#?}", element);
+                if let Some(path) = bom_manage_ctrl.category_path(&element)? {
+                    println!("Category: {path}");
+                }
+                print_locations(name, bom_manage_ctrl)?;
             }
-            None => {
+            Err(BomError::NotFound(_)) => {
                 println!("No such electronic component: {}", name);
             }
+            Err(err) => return Err(err.into()),
         }
     }
     Ok(())
 }
 
+/**
+ * @description: 打印元件的存放位置和数量
+ * @param {&str} key 元件键
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn print_locations(key: &str, bom_manage_ctrl: &BomManageCtrl) -> Result<(), Box<dyn Error>> {
+    for location in bom_manage_ctrl.locations(key)? {
+        println!("Location: {} x {}", location.path, location.quantity);
+    }
+    let unplaced = bom_manage_ctrl.unplaced(key)?;
+    if unplaced > 0 {
+        println!("Location: (unplaced) x {unplaced}");
+    }
+    Ok(())
+}
+
+/**
+ * @description: 在存放位置之间移动元件
+ * @param {*} matches 命令行参数, 元件/数量/新位置/原位置
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn move_electronic_component(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let name = matches
+        .get_one::<String>("name")
+        .ok_or("No name provided")?;
+    let quantity = *matches
+        .get_one::<u32>("quantity")
+        .ok_or("No quantity provided")?;
+    let to = matches
+        .get_one::<String>("to")
+        .ok_or("No location provided")?;
+    let from = matches.get_one::<String>("from").map(|s| s.as_str());
+    bom_manage_ctrl.move_stock(name, from, Some(to), quantity)?;
+    println!("Moved {quantity} of {name} to {to}.");
+    Ok(())
+}
+
 fn remove_electronic_component(
     matches: &ArgMatches,
     bom_manage_ctrl: &mut BomManageCtrl,
@@ -186,15 +358,15 @@ fn remove_electronic_component(
         println!("Please provide a name for the electronic component.");
         return Err("No name provided".into());
     }
-    
+
     // name 等于 all, 删除库所有数据
     if name == SUBCOMMAND_ALL!() {
         let mut input = String::new();
 
         println!("Confirm remove all electronic components? (y/N) default N. ");
         io::stdin()
-        .read_line(&mut input)
-        .expect("Failed to read line");
+            .read_line(&mut input)
+            .expect("Failed to read line");
         let input = input.trim(); // 去除输入两端的空白字符
 
         if input == "y" {
@@ -227,169 +399,1536 @@ fn remove_electronic_component(
             }
         };
 
-        bom_manage_ctrl.reduce_element(name.to_string(), number)?;
+        match bom_manage_ctrl.reduce_element(name.to_string(), number) {
+            Ok(_) => {}
+            Err(BomError::InsufficientStock { available, .. }) => {
+                // 库存不足, 询问是否取出全部库存
+                println!("Only {available} in stock, take all of them? (y/N) default N. ");
+                let mut input = String::new();
+                io::stdin()
+                    .read_line(&mut input)
+                    .expect("Failed to read line");
+                if input.trim() == "y" {
+                    let report =
+                        bom_manage_ctrl.reduce_element_available(name.to_string(), number)?;
+                    println!("Took {}, short of {}.", report.taken, report.shortfall);
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
     }
 
     Ok(())
 }
 /**
- * @description: 处理命令流程
- * @param {Vec} args 命令行参数
+ * @description: 读取项目 BOM 并打印缺料报告
+ * @param {*} matches 命令行参数, BOM 文件路径和板数
+ * @param {*} bom_manage_ctrl
  * @return {*}
  */
-fn command_handle(args: Vec<String>, bom_manage_ctrl: &mut BomManageCtrl) {
-    // 创建一个 clap 的 Command 对象，定义命令行接口
-    let matches_result = Command::new("My CLI App")
-        .subcommand_required(true)
-        .subcommand(
-            Command::new("greet")
-                .about("Prints a greeting message")
-                .arg(
-                    Arg::new("name")
-                        .help("Name of the person to greet")
-                        .required(false)
-                        .value_parser(clap::value_parser!(String)),
-                ),
-        )
-        .subcommand(Command::new("status").about("Prints the current status"))
-        .subcommand(
-            Command::new(COMMAND_ADD!())
-                .about("Add a new electronic component")
-                .arg(
-                    Arg::new("name")
-                        .help(
-                            "Input the name of the electronic component, such as R10K, C20uF, etc.",
-                        )
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
-                ),
-        )
-        .subcommand(
-            Command::new(COMMAND_REMOVE!())
-                .about("Remove a new electronic component")
-                .arg(
-                    Arg::new("name")
-                        .help("Remove a new electronic component, such as R10K, C20uF, all, etc.")
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
-                ),
-        )
-        .subcommand(
-            Command::new(COMMAND_VIEW!())
-                .about("View some new electronic component")
-                .arg(
-                    Arg::new("name")
-                        .help("View some new electronic component, such as R10K, C20uF, all, etc.")
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
-                ),
-        )
-        .subcommand(Command::new(COMMAND_MODIFY!()).about("Modify a new electronic component"))
-        .subcommand(Command::new("exit").about("Exit the program"))
-        .try_get_matches_from(args);
-
-    // 处理命令行参数
-    match matches_result {
-        Ok(matches) => match matches.subcommand() {
-            Some(("greet", sub_matches)) => handle_greet(sub_matches),
-            Some(("status", _sub_matches)) => handle_status(),
-            Some((COMMAND_ADD!(), sub_matches)) => {
-                match add_electronic_component(sub_matches, bom_manage_ctrl) {
-                    Ok(_) => println!("Add electronic component successfully!"),
-                    Err(err) => println!("Error: {err}"),
-                }
-            }
-            Some((COMMAND_VIEW!(), sub_matches)) => {
-                match view_electronic_component(sub_matches, bom_manage_ctrl) {
-                    Ok(_) => {}
-                    Err(err) => println!("Error: {err}"),
-                }
-            }
-            // Some((COMMAND_MODIFY!(), sub_matches)) => match modify_electronic_component(sub_matches, bom_manage_ctrl) {
+fn check_project_bom(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let file = matches
+        .get_one::<String>("file")
+        .ok_or("No file provided")?;
+    let boards = *matches.get_one::<u32>("boards").unwrap_or(&1);
+    bom_manage_ctrl.substitute_policy = if matches.get_flag("exact") {
+        SubstitutePolicy::exact()
+    } else {
+        SubstitutePolicy::default()
+    };
+    let report = bom_manage_ctrl.import_bom(file, &BomFormat::from_path(file), boards)?;
 
-            // },
-            Some((COMMAND_REMOVE!(), sub_matches)) => {
-                match remove_electronic_component(sub_matches, bom_manage_ctrl) {
-                    Ok(_) => {},
-                    Err(err) => println!("Error: {err}"),
-                }
-            }
-            Some(("exit", _sub_matches)) => {
-                println!("Exiting...");
-                process::exit(0);
-            }
-            _ => println!("Invalid command"),
-        },
-        Err(err) => {
-            println!("{}", err);
+    println!(
+        "{:<16} {:<20} {:<20} {:>8} {:>8} {:>8}",
+        "References", "Value", "Element", "Required", "OnHand", "Short"
+    );
+    for line in report.lines.iter() {
+        println!(
+            "{:<16} {:<20} {:<20} {:>8} {:>8} {:>8}",
+            line.line.references.join(","),
+            line.line.value,
+            line.element.as_deref().unwrap_or("-"),
+            line.required,
+            line.on_hand,
+            line.shortfall
+        );
+        for substitution in line.substitutions() {
+            println!(
+                "{:<16} {:<20} {:<20} {:>8} ({:?})",
+                "",
+                "  substitute",
+                substitution.element,
+                substitution.quantity,
+                substitution.matched_by
+            );
         }
     }
+    let unmatched = report.unmatched().count();
+    if unmatched > 0 {
+        println!("{unmatched} line(s) not found in stock.");
+    }
+    if report.is_buildable() {
+        println!("Enough stock for {boards} board(s).");
+    } else {
+        println!("Not enough stock for {boards} board(s).");
+    }
+    Ok(())
 }
 
-fn main() {
-    let progam_name = env!("CARGO_PKG_NAME");
-    let mut bom_manage_ctrl = match BomManageCtrl::new(DATA_FILE!(), TABLE_NAME!()) {
-        Ok(bom_manage_ctrl) => bom_manage_ctrl,
-        Err(error) => panic!("Error: {error}"),
-    };
-    let config = Config::builder().build();
-    let mut rl = Editor::<(), DefaultHistory>::with_config(config).unwrap();
-    if rl.load_history("history.txt").is_err() {
-        println!("No previous history.");
+/**
+ * @description: 搜索元件, 支持部分型号/描述/分类/厂家料号, 拼写错误时模糊匹配
+ * @param {*} matches 命令行参数, 搜索内容可以有多个词
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn search_electronic_component(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let query = matches
+        .get_many::<String>("query")
+        .ok_or("No query provided")?
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let hits = bom_manage_ctrl.search(&query, SEARCH_LIMIT)?;
+    if hits.is_empty() {
+        println!("No electronic component matches: {query}");
+        return Ok(());
     }
-
-    loop {
-        let readline = match rl.readline(format!("{progam_name}>> ").as_str()) {
-            Ok(line) => {
-                match rl.add_history_entry(line.as_str()) {
-                    Ok(_) => {}
-                    Err(err) => {
-                        println!("Error: {err}");
-                    }
-                }
-                line
-            }
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
-            }
-            Err(ReadlineError::Eof) => {
-                println!("CTRL-D");
-                break;
-            }
-            Err(err) => {
-                println!("Error: {:?}", err);
-                break;
-            }
-        };
-
-        // 解析数据
-        match get_cmd(&progam_name, readline) {
-            Ok(args) => {
-                command_handle(args, &mut bom_manage_ctrl);
-                rl.save_history("history.txt").unwrap();
-            }
-            Err(err) => match err.kind() {
-                io::ErrorKind::Other => {
-                    // 不做处理
-                    continue;
-                }
-                _ => {
-                    println!("Error: {err}");
-                    continue;
-                }
-            },
-        }
+    if hits[0].fuzzy {
+        println!("No exact match, showing similar components:");
     }
-    rl.save_history("history.txt").unwrap();
+    println!(
+        "{:<24} {:<32} {:>8} {:<10}",
+        "Element", "Describe", "Number", "State"
+    );
+    for hit in hits.iter() {
+        println!(
+            "{:<24} {:<32} {:>8} {:<10}",
+            hit.element.key(),
+            hit.element.describe,
+            hit.element.number,
+            hit.element.state.to_string()
+        );
+    }
+    Ok(())
 }
 
-fn handle_greet(matches: &ArgMatches) {
-    let binding = "World".to_string();
-    let name = matches.get_one::<String>("name").unwrap_or(&binding);
+/**
+ * @description: 导出整个库存, .json/.yaml 为单个文件, 其他路径为 CSV 目录
+ * @param {*} matches 命令行参数, 文件路径
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn export_inventory(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let path = matches
+        .get_one::<String>("path")
+        .ok_or("No path provided")?;
+    bom_manage_ctrl.export(path, ExportFormat::from_path(path))?;
+    println!("Exported inventory to {path}.");
+    Ok(())
+}
+
+/**
+ * @description: 导入库存, 默认合并, --replace 替换整个库存, --dry-run 只显示差异
+ * @param {*} matches 命令行参数, 文件路径和选项
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn import_inventory(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let path = matches
+        .get_one::<String>("path")
+        .ok_or("No path provided")?;
+    let mode = if matches.get_flag("replace") {
+        ImportMode::Replace
+    } else {
+        ImportMode::Merge
+    };
+    let dry_run = matches.get_flag("dry-run");
+    let diff = bom_manage_ctrl.import(path, ExportFormat::from_path(path), mode, dry_run)?;
+
+    for key in diff.added.iter() {
+        println!("+ {key}");
+    }
+    for change in diff.changed.iter() {
+        println!("~ {} ({})", change.key, change.fields.join(", "));
+    }
+    for key in diff.removed.iter() {
+        println!("- {key}");
+    }
+    println!(
+        "{} added, {} changed, {} removed, {} unchanged, {} movement(s), {} supplier part(s), {} part group(s).",
+        diff.added.len(),
+        diff.changed.len(),
+        diff.removed.len(),
+        diff.unchanged,
+        diff.movements,
+        diff.suppliers,
+        diff.part_groups
+    );
+    println!(
+        "{} build(s), {} purchase order(s), {} audit(s), {} attachment(s), {} note(s).",
+        diff.builds, diff.purchase_orders, diff.audits, diff.attachments, diff.notes
+    );
+    if dry_run {
+        println!("Dry run, nothing was written.");
+    }
+    Ok(())
+}
+
+/**
+ * @description: 打印备份列表
+ * @param {&[Backup]} backups 备份
+ * @return {*}
+ */
+fn print_backups(backups: &[Backup]) {
+    if backups.is_empty() {
+        println!("No backups.");
+        return;
+    }
+    println!("{:>6} {:>12} {:<12} Path", "Id", "Timestamp", "Reason");
+    for backup in backups.iter() {
+        println!(
+            "{:>6} {:>12} {:<12} {}",
+            backup.id, backup.timestamp, backup.reason, backup.path
+        );
+    }
+}
+
+/**
+ * @description: 手动备份数据库
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn backup_database(bom_manage_ctrl: &mut BomManageCtrl) -> Result<(), Box<dyn Error>> {
+    let backup = bom_manage_ctrl.backup(backup::BACKUP_MANUAL)?;
+    println!("Backup {} saved to {}.", backup.id, backup.path);
+    Ok(())
+}
+
+/**
+ * @description: 恢复到某一份备份, 恢复前当前数据库也会备份
+ * @param {*} matches 命令行参数, 备份号
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn restore_database(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let id = *matches
+        .get_one::<u32>("id")
+        .ok_or("No backup id provided")?;
+    println!("Confirm restore backup {id}? The current data is backed up first. (y/N) default N. ");
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    if input.trim() == "y" {
+        bom_manage_ctrl.restore_backup(id)?;
+        println!("Restored backup {id}.");
+    }
+    Ok(())
+}
+
+/**
+ * @description: 生成标签文件, .svg/.png 每张标签一个文件, .pdf 按标签纸排版
+ * @param {*} matches 命令行参数, 元件或位置, 文件路径, 标签纸
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn print_labels(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let name = matches
+        .get_one::<String>("name")
+        .ok_or("No name provided")?;
+    let path = matches
+        .get_one::<String>("path")
+        .ok_or("No path provided")?;
+    let stock = match matches.get_one::<String>("stock") {
+        Some(stock) => *LabelStock::find(stock)?,
+        None => LabelStock::default(),
+    };
+    let labels = if matches.get_flag("location") {
+        vec![bom_manage_ctrl.location_label(name)?]
+    } else if name == SUBCOMMAND_ALL!() {
+        bom_manage_ctrl.element_labels()?
+    } else {
+        vec![bom_manage_ctrl.element_label(name)?]
+    };
+    for file in label::write_labels(&labels, path, &stock)? {
+        println!("Wrote {file}.");
+    }
+    Ok(())
+}
+
+/**
+ * @description: 处理扫码枪输入的标签, 元件显示库存后输入 +N 入库或 -N 取出, 位置显示其中的元件
+ * @param {&str} text 扫描到的标签内容或元件名
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn scan_label(text: &str, bom_manage_ctrl: &mut BomManageCtrl) -> Result<(), Box<dyn Error>> {
+    let element = match bom_manage_ctrl.scan(text)? {
+        Scanned::Element(element) => element,
+        Scanned::Location { path, .. } => {
+            println!("Location: {path}");
+            for element in bom_manage_ctrl.query(&ElementQuery::new().location(&path))? {
+                println!("{} x {}", element.key(), element.number);
+            }
+            return Ok(());
+        }
+    };
+    let key = element.key();
+    println!("{key} x {} ({})", element.number, element.describe);
+    print_locations(&key, bom_manage_ctrl)?;
+    println!("Enter +N to add, -N to take, empty to skip: ");
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(());
+    }
+    let invalid = "Please enter +N or -N, such as +10.";
+    if let Some(number) = input.strip_prefix('+') {
+        let number = number.trim().parse::<u32>().map_err(|_| invalid)?;
+        bom_manage_ctrl.add_element(Element { number, ..element })?;
+    } else if let Some(number) = input.strip_prefix('-') {
+        let number = number.trim().parse::<u32>().map_err(|_| invalid)?;
+        bom_manage_ctrl.reduce_element(key.clone(), number)?;
+    } else {
+        return Err(invalid.into());
+    }
+    println!("{key} x {}.", bom_manage_ctrl.element(&key)?.number);
+    Ok(())
+}
+
+/**
+ * @description: 把元件加入替代料组, 按参数顺序排优先级, 或从组中删除
+ * @param {*} matches 命令行参数, 组名和元件
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn edit_part_group(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let group = matches
+        .get_one::<String>("group")
+        .ok_or("No group provided")?;
+    let names = matches
+        .get_many::<String>("name")
+        .ok_or("No electronic component provided")?;
+    let remove = matches.get_flag("remove");
+    for name in names {
+        if remove {
+            bom_manage_ctrl.remove_alternate(group, name)?;
+            println!("Removed {name} from {group}.");
+        } else {
+            bom_manage_ctrl.add_alternate(group, name)?;
+            println!("Added {name} to {group}.");
+        }
+    }
+    Ok(())
+}
+
+/**
+ * @description: 打印所有替代料组
+ * @param {&[PartGroup]} groups 替代料组
+ * @return {*}
+ */
+fn print_part_groups(groups: &[PartGroup]) {
+    if groups.is_empty() {
+        println!("No part groups.");
+        return;
+    }
+    for group in groups.iter() {
+        println!("{}:", group.name);
+        for member in group.members.iter() {
+            let manufacturer = if member.manufacturer.is_empty() {
+                "any"
+            } else {
+                member.manufacturer.as_str()
+            };
+            println!(
+                "  {:>3} {:<24} {}",
+                member.priority, member.model, manufacturer
+            );
+        }
+    }
+}
+
+/**
+ * @description: 打印采购单
+ * @param {&[PurchaseOrder]} orders 采购单
+ * @return {*}
+ */
+fn print_purchase_orders(orders: &[PurchaseOrder]) {
+    if orders.is_empty() {
+        println!("Nothing to order.");
+        return;
+    }
+    for order in orders.iter() {
+        let supplier = match order.supplier.as_str() {
+            "" => "(no supplier)",
+            supplier => supplier,
+        };
+        match order.id {
+            0 => println!("Draft: {supplier}"),
+            id => println!("PO {id}: {supplier} ({})", order.status),
+        }
+        println!(
+            "  {:<16} {:<20} {:>8} {:>8} {:>10}",
+            "SKU", "Element", "Needed", "Quantity", "UnitPrice"
+        );
+        for line in order.lines.iter() {
+            println!(
+                "  {:<16} {:<20} {:>8} {:>8} {:>10}",
+                line.sku,
+                line.key(),
+                line.needed,
+                line.quantity,
+                line.unit_price
+                    .map_or("-".to_string(), |p| format!("{p:.4}"))
+            );
+        }
+        println!("  Total: {:.2}", order.total());
+    }
+}
+
+/**
+ * @description: 采购单: 生成草稿, 保存, 列出, 收货或取消
+ * @param {*} matches 命令行参数, 子命令
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn purchase_order(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("draft", sub_matches)) | Some(("create", sub_matches)) => {
+            let orders = if matches.subcommand_name() == Some("create") {
+                bom_manage_ctrl.create_purchase_orders()?
+            } else {
+                bom_manage_ctrl.draft_purchase_orders()?
+            };
+            print_purchase_orders(&orders);
+            if let Some(path) = sub_matches.get_one::<String>("path") {
+                purchase::write_orders(&orders, path)?;
+                println!("Wrote {path}.");
+            }
+        }
+        Some(("list", _sub_matches)) => print_purchase_orders(&bom_manage_ctrl.purchase_orders()?),
+        Some(("receive", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            let order = bom_manage_ctrl.receive_purchase_order(id)?;
+            println!(
+                "Received PO {id}, {} component(s) added to stock.",
+                order.lines.len()
+            );
+        }
+        Some(("cancel", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            bom_manage_ctrl.cancel_purchase_order(id)?;
+            println!("Cancelled PO {id}.");
+        }
+        _ => println!("Invalid command"),
+    }
+    Ok(())
+}
+
+/**
+ * @description: 盘点范围的说明
+ * @param {&Audit} audit 盘点单
+ * @return {*}
+ */
+fn audit_scope(audit: &Audit) -> String {
+    match audit.scope {
+        AuditScope::All => "all components".to_string(),
+        AuditScope::Location(_) => format!("location {}", audit.path),
+        AuditScope::Category(_) => format!("category {}", audit.path),
+    }
+}
+
+/**
+ * @description: 打印盘点单, 列出每个元件的账面数量, 实际数量和差异
+ * @param {&Audit} audit 盘点单
+ * @return {*}
+ */
+fn print_audit(audit: &Audit) {
+    println!(
+        "Audit {}: {} ({})",
+        audit.id,
+        audit_scope(audit),
+        audit.status
+    );
+    println!(
+        "  {:<24} {:>8} {:>8} {:>8}",
+        "Element", "Expected", "Counted", "Variance"
+    );
+    for line in audit.lines.iter() {
+        println!(
+            "  {:<24} {:>8} {:>8} {:>8}",
+            line.key(),
+            line.expected,
+            line.counted.map_or("-".to_string(), |c| c.to_string()),
+            line.variance()
+                .map_or("-".to_string(), |v| format!("{v:+}"))
+        );
+    }
+    println!(
+        "  {} with variance, {} not counted",
+        audit.variances().len(),
+        audit.uncounted().len()
+    );
+}
+
+/**
+ * @description: 盘点: 按位置或分类开始盘点, 录入实际数量, 查看差异, 调整库存或取消
+ * @param {*} matches 命令行参数, 子命令
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn audit(matches: &ArgMatches, bom_manage_ctrl: &mut BomManageCtrl) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("start", sub_matches)) => {
+            let audit = match (
+                sub_matches.get_one::<String>("location"),
+                sub_matches.get_one::<String>("category"),
+            ) {
+                (Some(path), _) => bom_manage_ctrl.start_location_audit(path)?,
+                (None, Some(path)) => bom_manage_ctrl.start_category_audit(path)?,
+                (None, None) => bom_manage_ctrl.start_audit()?,
+            };
+            print_audit(&audit);
+        }
+        Some(("count", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            let name = sub_matches
+                .get_one::<String>("name")
+                .ok_or("No name provided")?;
+            let counted = *sub_matches
+                .get_one::<u32>("quantity")
+                .ok_or("No quantity provided")?;
+            bom_manage_ctrl.record_count(id, name, counted)?;
+            println!("Counted {counted} of {name}.");
+        }
+        Some(("show", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            print_audit(&bom_manage_ctrl.audit(id)?);
+        }
+        Some(("list", _sub_matches)) => {
+            let audits = bom_manage_ctrl.audits()?;
+            if audits.is_empty() {
+                println!("No audits.");
+            }
+            for audit in audits.iter() {
+                println!(
+                    "Audit {}: {} {} ({}), {} with variance, {} not counted",
+                    audit.id,
+                    supplier::format_date(audit.created),
+                    audit_scope(audit),
+                    audit.status,
+                    audit.variances().len(),
+                    audit.uncounted().len()
+                );
+            }
+        }
+        Some(("apply", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            let audit = bom_manage_ctrl.apply_audit(id)?;
+            println!(
+                "Applied audit {id}, adjusted {} component(s).",
+                audit.variances().len()
+            );
+        }
+        Some(("cancel", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            bom_manage_ctrl.cancel_audit(id)?;
+            println!("Cancelled audit {id}.");
+        }
+        _ => println!("Invalid command"),
+    }
+    Ok(())
+}
+
+/**
+ * @description: 用系统默认程序打开文件
+ * @param {&Path} path 文件路径
+ * @return {*}
+ */
+fn open_with_system(path: &std::path::Path) -> io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = process::Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = process::Command::new("xdg-open");
+    command.arg(path).spawn()?;
+    Ok(())
+}
+
+/**
+ * @description: 附件: 添加, 列出, 打开, 删除, 清理没有被引用的文件
+ * @param {*} matches 命令行参数, 子命令
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn attachment(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("add", sub_matches)) => {
+            let name = sub_matches
+                .get_one::<String>("name")
+                .ok_or("No name provided")?;
+            let path = sub_matches
+                .get_one::<String>("path")
+                .ok_or("No path provided")?;
+            let kind = match sub_matches.get_one::<String>("kind") {
+                Some(kind) => Some(AttachmentKind::from_string(kind)?),
+                None => None,
+            };
+            let attachment = bom_manage_ctrl.add_attachment(name, path, kind)?;
+            println!(
+                "Attached {} to {name} as {} ({}).",
+                attachment.name, attachment.id, attachment.kind
+            );
+        }
+        Some(("list", sub_matches)) => {
+            let name = sub_matches
+                .get_one::<String>("name")
+                .ok_or("No name provided")?;
+            let attachments = bom_manage_ctrl.attachments(name)?;
+            if attachments.is_empty() {
+                println!("No attachments.");
+            }
+            for attachment in attachments.iter() {
+                println!(
+                    "{:>4} {:<10} {:>10} {} {}",
+                    attachment.id,
+                    attachment.kind.to_string(),
+                    attachment.size,
+                    supplier::format_date(attachment.added),
+                    attachment.name
+                );
+            }
+        }
+        Some(("open", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            // 附件按内容存放没有扩展名, 复制到临时目录后再打开
+            let dir = std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
+            let path = bom_manage_ctrl.save_attachment(id, &dir.to_string_lossy())?;
+            open_with_system(&path)?;
+            println!("Opened {}.", path.display());
+        }
+        Some(("remove", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            bom_manage_ctrl.remove_attachment(id)?;
+            println!("Removed attachment {id}.");
+        }
+        Some(("gc", _sub_matches)) => {
+            let gc = bom_manage_ctrl.gc_attachments()?;
+            println!(
+                "Removed {} orphaned file(s), {} bytes, and {} stale record(s).",
+                gc.files, gc.bytes, gc.rows
+            );
+        }
+        _ => println!("Invalid command"),
+    }
+    Ok(())
+}
+
+/**
+ * @description: 备注: 添加, 列出, 删除
+ * @param {*} matches 命令行参数, 子命令
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn note(matches: &ArgMatches, bom_manage_ctrl: &mut BomManageCtrl) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("add", sub_matches)) => {
+            let name = sub_matches
+                .get_one::<String>("name")
+                .ok_or("No name provided")?;
+            // 命令行按空格分割, 把剩下的词重新拼成一句
+            let text = sub_matches
+                .get_many::<String>("text")
+                .ok_or("No text provided")?
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let note = bom_manage_ctrl.add_note(name, &text)?;
+            println!("Added note {} to {name}.", note.id);
+        }
+        Some(("list", sub_matches)) => {
+            let name = sub_matches
+                .get_one::<String>("name")
+                .ok_or("No name provided")?;
+            let notes = bom_manage_ctrl.notes(name)?;
+            if notes.is_empty() {
+                println!("No notes.");
+            }
+            for note in notes.iter() {
+                let operator = match note.operator.as_str() {
+                    "" => String::new(),
+                    operator => format!(" {operator}"),
+                };
+                println!(
+                    "{:>4} {}{operator}: {}",
+                    note.id,
+                    supplier::format_date(note.timestamp),
+                    note.text
+                );
+            }
+        }
+        Some(("remove", sub_matches)) => {
+            let id = *sub_matches.get_one::<i64>("id").ok_or("No id provided")?;
+            bom_manage_ctrl.remove_note(id)?;
+            println!("Removed note {id}.");
+        }
+        _ => println!("Invalid command"),
+    }
+    Ok(())
+}
+
+/**
+ * @description: 数据库损坏时列出备份, 询问是否恢复最新的备份
+ * @return {恢复后可以重新打开时返回 true}
+ */
+fn restore_corrupt_database() -> Result<bool, Box<dyn Error>> {
+    let backups = backup::list_backups(DATA_FILE!())?;
+    let Some(latest) = backups.last() else {
+        println!("No backups of {} found.", DATA_FILE!());
+        return Ok(false);
+    };
+    print_backups(&backups);
+    println!(
+        "Restore the latest backup {}? The damaged file is kept as {}.corrupt. (y/N) default N. ",
+        latest.id,
+        DATA_FILE!()
+    );
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    if input.trim() != "y" {
+        return Ok(false);
+    }
+    backup::restore_backup_file(DATA_FILE!(), latest.id)?;
+    Ok(true)
+}
+
+/**
+ * @description: 处理命令流程
+ * @param {Vec} args 命令行参数
+ * @return {*}
+ */
+fn command_handle(args: Vec<String>, bom_manage_ctrl: &mut BomManageCtrl) {
+    // 创建一个 clap 的 Command 对象，定义命令行接口
+    let matches_result = Command::new("My CLI App")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("greet")
+                .about("Prints a greeting message")
+                .arg(
+                    Arg::new("name")
+                        .help("Name of the person to greet")
+                        .required(false)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Inventory statistics, value, consumption and stockout forecast")
+                .arg(
+                    Arg::new("days")
+                        .long("days")
+                        .help("Count consumption over the last N days, default 90")
+                        .value_parser(clap::value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .help("Count consumption from this date, e.g. 2024-08-01")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Count consumption up to this date, default now")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("top")
+                        .long("top")
+                        .help("Rows per list, 0 for all, default 10")
+                        .value_parser(clap::value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_ADD!())
+                .about("Add a new electronic component")
+                .arg(
+                    Arg::new("name")
+                        .help(
+                            "Input the name of the electronic component, such as R10K, C20uF, etc.",
+                        )
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_REMOVE!())
+                .about("Remove a new electronic component")
+                .arg(
+                    Arg::new("name")
+                        .help("Remove a new electronic component, such as R10K, C20uF, all, etc.")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_VIEW!())
+                .about("View some new electronic component")
+                .arg(
+                    Arg::new("name")
+                        .help("View some new electronic component, such as R10K, C20uF, all, etc.")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(Command::new(COMMAND_MODIFY!()).about("Modify a new electronic component"))
+        .subcommand(
+            Command::new(COMMAND_MOVE!())
+                .about("Move an electronic component to a storage location, such as 柜子A/抽屉3/格子12")
+                .arg(
+                    Arg::new("name")
+                        .help("Name of the electronic component")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("quantity")
+                        .help("Quantity to move")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("to")
+                        .help("New location, created if missing")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("from")
+                        .help("Old location, default takes unplaced stock")
+                        .required(false)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_BOM!())
+                .about("Check a project BOM (KiCad CSV/XML) against stock")
+                .arg(
+                    Arg::new("file")
+                        .help("Path of the BOM file")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("boards")
+                        .help("Number of boards to build, default 1")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("exact")
+                        .long("exact")
+                        .help("Only use the matched component, no alternates")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_SEARCH!())
+                .about("Search electronic components by model, describe, category or MPN")
+                .arg(
+                    Arg::new("query")
+                        .help("Words to search, such as r10k, 10k resistor, etc.")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_EXPORT!())
+                .about("Export the whole inventory to JSON, YAML or a CSV directory")
+                .arg(
+                    Arg::new("path")
+                        .help("File ending with .json/.yaml/.yml, anything else is a CSV directory")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_IMPORT!())
+                .about("Import an exported inventory, merging into the current one by default")
+                .arg(
+                    Arg::new("path")
+                        .help("File ending with .json/.yaml/.yml, anything else is a CSV directory")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("replace")
+                        .long("replace")
+                        .help("Replace the whole inventory instead of merging")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Only show what would change")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(Command::new(COMMAND_BACKUP!()).about("Back up the database"))
+        .subcommand(Command::new(COMMAND_BACKUPS!()).about("List the database backups"))
+        .subcommand(
+            Command::new(COMMAND_RESTORE!())
+                .about("Restore the database from a backup")
+                .arg(
+                    Arg::new("id")
+                        .help("Backup id, see the backups command")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u32)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_LABEL!())
+                .about("Write QR labels as SVG, PNG or PDF label sheets")
+                .arg(
+                    Arg::new("name")
+                        .help("Electronic component, all, or a location with --location")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("path")
+                        .help("File ending with .svg/.png/.pdf")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("location")
+                        .long("location")
+                        .help("Label a storage location instead of a component")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("stock")
+                        .long("stock")
+                        .help("Label stock: 62x29 (default), 50x25, L7160, L7651, 5160")
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_SCAN!())
+                .about("Look up a scanned label or component, then add or take stock")
+                .arg(
+                    Arg::new("text")
+                        .help("Label text or component name, scanned labels also work without this command")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_ALTERNATE!())
+                .about("Add components to a part group of alternates, in order of priority")
+                .arg(
+                    Arg::new("group")
+                        .help("Name of the part group")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("name")
+                        .help("Electronic components, model@manufacturer for one manufacturer")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("remove")
+                        .long("remove")
+                        .help("Remove the components from the group")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(Command::new(COMMAND_ALTERNATES!()).about("List the part groups of alternates"))
+        .subcommand(
+            Command::new(COMMAND_PO!())
+                .about("Purchase orders for components below their reorder point or short for builds")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("draft")
+                        .about("Show what to order without saving")
+                        .arg(
+                            Arg::new("path")
+                                .help("Also write the orders to a .csv or .md file")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("create")
+                        .about("Save the draft as open purchase orders")
+                        .arg(
+                            Arg::new("path")
+                                .help("Also write the orders to a .csv or .md file")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(Command::new("list").about("List all purchase orders"))
+                .subcommand(
+                    Command::new("receive")
+                        .about("Add all components of an open purchase order to stock")
+                        .arg(
+                            Arg::new("id")
+                                .help("Purchase order id, see po list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("cancel")
+                        .about("Cancel an open purchase order")
+                        .arg(
+                            Arg::new("id")
+                                .help("Purchase order id, see po list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_AUDIT!())
+                .about("Cycle count: compare physical stock with the system and correct it")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("start")
+                        .about("Start counting a location, a category or everything")
+                        .arg(
+                            Arg::new("location")
+                                .long("location")
+                                .help("Location path, such as 柜子A/抽屉3")
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("category")
+                                .long("category")
+                                .help("Category path, such as 电阻")
+                                .conflicts_with("location")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("count")
+                        .about("Record the counted quantity of a component")
+                        .arg(
+                            Arg::new("id")
+                                .help("Audit id, see audit list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        )
+                        .arg(
+                            Arg::new("name")
+                                .help("Name of the electronic component")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("quantity")
+                                .help("Counted quantity")
+                                .required(true)
+                                .value_parser(clap::value_parser!(u32)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show counted quantities and variances")
+                        .arg(
+                            Arg::new("id")
+                                .help("Audit id, see audit list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                )
+                .subcommand(Command::new("list").about("List all audits"))
+                .subcommand(
+                    Command::new("apply")
+                        .about("Correct stock by the variances, recorded as \"audit\" in the ledger")
+                        .arg(
+                            Arg::new("id")
+                                .help("Audit id, see audit list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("cancel")
+                        .about("Cancel an open audit without changing stock")
+                        .arg(
+                            Arg::new("id")
+                                .help("Audit id, see audit list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new(COMMAND_ATTACH!())
+                .about("Datasheets, photos and footprints attached to components")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Attach a local file to a component")
+                        .arg(
+                            Arg::new("name")
+                                .help("Name of the electronic component")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("path")
+                                .help("File to attach, it is copied next to the database")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("kind")
+                                .long("kind")
+                                .help("datasheet, image, footprint or other, guessed from the extension by default")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the attachments of a component")
+                        .arg(
+                            Arg::new("name")
+                                .help("Name of the electronic component")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("open")
+                        .about("Open an attachment with the system viewer")
+                        .arg(
+                            Arg::new("id")
+                                .help("Attachment id, see attach list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove an attachment, the file is deleted by attach gc")
+                        .arg(
+                            Arg::new("id")
+                                .help("Attachment id, see attach list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                )
+                .subcommand(Command::new("gc").about("Delete attachment files no component uses")),
+        )
+        .subcommand(
+            Command::new(COMMAND_NOTE!())
+                .about("Timestamped notes on components")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a note to a component")
+                        .arg(
+                            Arg::new("name")
+                                .help("Name of the electronic component")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("text")
+                                .help("Note text")
+                                .required(true)
+                                .num_args(1..)
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the notes of a component")
+                        .arg(
+                            Arg::new("name")
+                                .help("Name of the electronic component")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a note")
+                        .arg(
+                            Arg::new("id")
+                                .help("Note id, see note list")
+                                .required(true)
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                ),
+        )
+        .subcommand(Command::new("exit").about("Exit the program"))
+        .try_get_matches_from(args);
+
+    // 处理命令行参数
+    match matches_result {
+        Ok(matches) => match matches.subcommand() {
+            Some(("greet", sub_matches)) => handle_greet(sub_matches),
+            Some(("status", sub_matches)) => match handle_status(sub_matches, bom_manage_ctrl) {
+                Ok(_) => {}
+                Err(err) => println!("Error: {err}"),
+            },
+            Some((COMMAND_ADD!(), sub_matches)) => {
+                match add_electronic_component(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => println!("Add electronic component successfully!"),
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_VIEW!(), sub_matches)) => {
+                match view_electronic_component(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            // Some((COMMAND_MODIFY!(), sub_matches)) => match modify_electronic_component(sub_matches, bom_manage_ctrl) {
+
+            // },
+            Some((COMMAND_REMOVE!(), sub_matches)) => {
+                match remove_electronic_component(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_MOVE!(), sub_matches)) => {
+                match move_electronic_component(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_BOM!(), sub_matches)) => {
+                match check_project_bom(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_SEARCH!(), sub_matches)) => {
+                match search_electronic_component(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_EXPORT!(), sub_matches)) => {
+                match export_inventory(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_IMPORT!(), sub_matches)) => {
+                match import_inventory(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_BACKUP!(), _sub_matches)) => match backup_database(bom_manage_ctrl) {
+                Ok(_) => {}
+                Err(err) => println!("Error: {err}"),
+            },
+            Some((COMMAND_BACKUPS!(), _sub_matches)) => match bom_manage_ctrl.backups() {
+                Ok(backups) => print_backups(&backups),
+                Err(err) => println!("Error: {err}"),
+            },
+            Some((COMMAND_RESTORE!(), sub_matches)) => {
+                match restore_database(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_LABEL!(), sub_matches)) => {
+                match print_labels(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_SCAN!(), sub_matches)) => {
+                let text = sub_matches
+                    .get_one::<String>("text")
+                    .cloned()
+                    .unwrap_or_default();
+                match scan_label(&text, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_ALTERNATE!(), sub_matches)) => {
+                match edit_part_group(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_ALTERNATES!(), _sub_matches)) => match bom_manage_ctrl.part_groups() {
+                Ok(groups) => print_part_groups(&groups),
+                Err(err) => println!("Error: {err}"),
+            },
+            Some((COMMAND_PO!(), sub_matches)) => {
+                match purchase_order(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_AUDIT!(), sub_matches)) => match audit(sub_matches, bom_manage_ctrl) {
+                Ok(_) => {}
+                Err(err) => println!("Error: {err}"),
+            },
+            Some((COMMAND_ATTACH!(), sub_matches)) => {
+                match attachment(sub_matches, bom_manage_ctrl) {
+                    Ok(_) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            Some((COMMAND_NOTE!(), sub_matches)) => match note(sub_matches, bom_manage_ctrl) {
+                Ok(_) => {}
+                Err(err) => println!("Error: {err}"),
+            },
+            Some(("exit", _sub_matches)) => {
+                println!("Exiting...");
+                process::exit(0);
+            }
+            _ => println!("Invalid command"),
+        },
+        Err(err) => {
+            println!("{}", err);
+        }
+    }
+}
+
+fn main() {
+    let progam_name = env!("CARGO_PKG_NAME");
+    let mut bom_manage_ctrl = match BomManageCtrl::new(DATA_FILE!(), TABLE_NAME!()) {
+        Ok(bom_manage_ctrl) => bom_manage_ctrl,
+        // 数据库损坏时不能当作空库存继续使用, 从备份恢复后重新打开
+        Err(BomError::CorruptFile(error)) => {
+            println!("Error: corrupt data file: {error}");
+            match restore_corrupt_database() {
+                Ok(true) => match BomManageCtrl::new(DATA_FILE!(), TABLE_NAME!()) {
+                    Ok(bom_manage_ctrl) => bom_manage_ctrl,
+                    Err(error) => panic!("Error: {error}"),
+                },
+                Ok(false) => process::exit(1),
+                Err(error) => panic!("Error: {error}"),
+            }
+        }
+        Err(error) => panic!("Error: {error}"),
+    };
+    let config = Config::builder().build();
+    let mut rl = Editor::<(), DefaultHistory>::with_config(config).unwrap();
+    if rl.load_history("history.txt").is_err() {
+        println!("No previous history.");
+    }
+
+    loop {
+        let readline = match rl.readline(format!("{progam_name}>> ").as_str()) {
+            Ok(line) => {
+                match rl.add_history_entry(line.as_str()) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        println!("Error: {err}");
+                    }
+                }
+                line
+            }
+            Err(ReadlineError::Interrupted) => {
+                println!("CTRL-C");
+                break;
+            }
+            Err(ReadlineError::Eof) => {
+                println!("CTRL-D");
+                break;
+            }
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break;
+            }
+        };
+
+        // 扫码枪扫描标签时直接输入标签内容, 不需要输入命令
+        if readline.trim().starts_with(label::LABEL_PREFIX) {
+            if let Err(err) = scan_label(readline.trim(), &mut bom_manage_ctrl) {
+                println!("Error: {err}");
+            }
+            continue;
+        }
+
+        // 解析数据
+        match get_cmd(progam_name, readline) {
+            Ok(args) => {
+                command_handle(args, &mut bom_manage_ctrl);
+                rl.save_history("history.txt").unwrap();
+            }
+            Err(err) => match err.kind() {
+                io::ErrorKind::Other => {
+                    // 不做处理
+                    continue;
+                }
+                _ => {
+                    println!("Error: {err}");
+                    continue;
+                }
+            },
+        }
+    }
+    rl.save_history("history.txt").unwrap();
+}
+
+fn handle_greet(matches: &ArgMatches) {
+    let binding = "World".to_string();
+    let name = matches.get_one::<String>("name").unwrap_or(&binding);
     println!("Hello, {}!", name);
 }
 
-fn handle_status() {
-    println!("Everything is running smoothly.");
+/**
+ * @description: 打印库存统计报表
+ * @param {*} matches 命令行参数, 统计消耗的时间范围和每个列表的行数
+ * @param {*} bom_manage_ctrl
+ * @return {*}
+ */
+fn handle_status(
+    matches: &ArgMatches,
+    bom_manage_ctrl: &mut BomManageCtrl,
+) -> Result<(), Box<dyn Error>> {
+    let date = |name: &str| -> Result<Option<i64>, Box<dyn Error>> {
+        match matches.get_one::<String>(name) {
+            Some(text) => match supplier::parse_date(text) {
+                Some(timestamp) => Ok(Some(timestamp)),
+                None => Err(format!("Invalid date: {text}").into()),
+            },
+            None => Ok(None),
+        }
+    };
+    let mut options = match matches.get_one::<u32>("days") {
+        Some(days) => ReportOptions::last_days(*days),
+        None => ReportOptions::default(),
+    };
+    if let Some(to) = date("to")? {
+        options.from = to - (options.to - options.from);
+        options.to = to;
+    }
+    if let Some(from) = date("from")? {
+        options.from = from;
+    }
+    if let Some(top) = matches.get_one::<usize>("top") {
+        options.top = *top;
+    }
+    if options.from >= options.to {
+        return Err("The start date must be before the end date.".into());
+    }
+    let report = bom_manage_ctrl.inventory_report(&options)?;
+
+    let stock = &report.stock;
+    println!(
+        "Inventory: {} components, {} pcs",
+        stock.total.parts, stock.total.quantity
+    );
+    println!("  {:<12} {:>8} {:>10}", "Type", "Parts", "Quantity");
+    for (element_type, count) in stock.by_type.iter() {
+        println!(
+            "  {:<12} {:>8} {:>10}",
+            element_type.to_string(),
+            count.parts,
+            count.quantity
+        );
+    }
+    println!("  {:<12} {:>8} {:>10}", "Status", "Parts", "Quantity");
+    for (status, count) in stock.by_status.iter() {
+        println!(
+            "  {:<12} {:>8} {:>10}",
+            status.to_string(),
+            count.parts,
+            count.quantity
+        );
+    }
+    println!(
+        "Value: {:.2} {} ({} components without a price)",
+        report.value.total,
+        report.value.currency,
+        report.value.unpriced.len()
+    );
+
+    println!(
+        "Consumption {} to {}:",
+        supplier::format_date(report.from),
+        supplier::format_date(report.to)
+    );
+    if report.top_consumers.is_empty() {
+        println!("  No consumption.");
+    }
+    for consumer in report.top_consumers.iter() {
+        println!(
+            "  {:<24} {:>8} pcs in {} movements",
+            consumer.key, consumer.quantity, consumer.movements
+        );
+    }
+
+    println!("Slow-moving stock:");
+    if report.slow_movers.is_empty() {
+        println!("  None.");
+    }
+    for slow in report.slow_movers.iter() {
+        println!(
+            "  {:<24} {:>8} last used {}",
+            slow.key,
+            slow.number,
+            slow.last_used
+                .map_or("never".to_string(), supplier::format_date)
+        );
+    }
+
+    println!("Stockout forecast:");
+    if report.forecasts.is_empty() {
+        println!("  No consumption.");
+    }
+    for forecast in report.forecasts.iter() {
+        println!(
+            "  {:<24} {:>8} left, {:.2}/day, {:.0} days",
+            forecast.key, forecast.number, forecast.daily_rate, forecast.days_left
+        );
+    }
+    Ok(())
 }
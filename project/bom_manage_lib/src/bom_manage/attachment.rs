@@ -0,0 +1,614 @@
+/*
+ * @Description: 元件的附件和备注, 附件为数据手册/照片/封装图等本地文件, 按内容的 sha256
+ * 存放在数据库旁的目录中, 相同内容只存一份, 没有被引用的文件由 gc_attachments 删除
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\attachment.rs
+ */
+use super::ledger::now_timestamp;
+use super::{element_key, BomError, BomResult, DataBaseInfo};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// 新增附件, 元件已有相同内容的附件时忽略, id 为 NULL 时分配新的 id
+macro_rules! insert_attachment_template {
+    () => {
+        "INSERT OR IGNORE INTO attachments (id, model, manufacturer, kind, name, hash, size, added) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    };
+}
+// 读取附件
+macro_rules! get_attachment_template {
+    () => {
+        "SELECT id, model, manufacturer, kind, name, hash, size, added FROM attachments"
+    };
+}
+// 删除附件记录, 文件由 gc_attachments 删除
+macro_rules! delete_attachment_template {
+    () => {
+        "DELETE FROM attachments WHERE id = ?"
+    };
+}
+// 新增备注, id 为 NULL 时分配新的 id
+macro_rules! insert_note_template {
+    () => {
+        "INSERT INTO element_notes (id, model, manufacturer, timestamp, text, operator) VALUES (?, ?, ?, ?, ?, ?)"
+    };
+}
+// 读取备注
+macro_rules! get_note_template {
+    () => {
+        "SELECT id, model, manufacturer, timestamp, text, operator FROM element_notes"
+    };
+}
+// 删除备注
+macro_rules! delete_note_template {
+    () => {
+        "DELETE FROM element_notes WHERE id = ?"
+    };
+}
+// 删除元件的所有附件记录和备注
+macro_rules! delete_element_attachments_template {
+    () => {
+        "DELETE FROM attachments WHERE model = ? AND manufacturer = ?"
+    };
+}
+macro_rules! delete_element_notes_template {
+    () => {
+        "DELETE FROM element_notes WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 删除元件已不存在的附件记录和备注, 例如替换导入后
+macro_rules! delete_orphan_rows_template {
+    () => {
+        "DELETE FROM {1} WHERE NOT EXISTS \
+         (SELECT 1 FROM {0} e WHERE e.model = {1}.model AND e.manufacturer = {1}.manufacturer)"
+    };
+}
+// 所有被引用的文件
+macro_rules! get_hashes_template {
+    () => {
+        "SELECT DISTINCT hash FROM attachments"
+    };
+}
+
+// 写入附件文件时使用的临时文件后缀, 写完后改名, 中断时留下的临时文件由 gc 删除.
+// 写入文件和 gc 都在数据库写锁内进行, gc 时看到的临时文件一定是中断留下的
+const TEMP_SUFFIX: &str = ".tmp";
+
+// 附件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttachmentKind {
+    Datasheet, // 数据手册
+    Image,     // 照片
+    Footprint, // 封装图或封装文件
+    Other,     // 其他文件
+}
+
+impl fmt::Display for AttachmentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AttachmentKind::Datasheet => "datasheet",
+            AttachmentKind::Image => "image",
+            AttachmentKind::Footprint => "footprint",
+            AttachmentKind::Other => "other",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl AttachmentKind {
+    /**
+     * @description: 字符串转为 AttachmentKind
+     * @param {&str} s
+     * @return {*}
+     */
+    pub fn from_string(s: &str) -> BomResult<AttachmentKind> {
+        match s.to_ascii_lowercase().as_str() {
+            "datasheet" => Ok(AttachmentKind::Datasheet),
+            "image" => Ok(AttachmentKind::Image),
+            "footprint" => Ok(AttachmentKind::Footprint),
+            "other" => Ok(AttachmentKind::Other),
+            _ => Err(BomError::InvalidType(s.to_string())),
+        }
+    }
+
+    /**
+     * @description: 按文件扩展名猜测附件类型
+     * @param {&Path} path 文件路径
+     * @return {*}
+     */
+    pub fn from_path(path: &Path) -> AttachmentKind {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "pdf" => AttachmentKind::Datasheet,
+            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "svg" => AttachmentKind::Image,
+            "kicad_mod" | "kicad_sym" | "lbr" | "intlib" => AttachmentKind::Footprint,
+            _ => AttachmentKind::Other,
+        }
+    }
+}
+
+// 元件的一个附件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: i64,              // 附件 id
+    pub model: String,        // 元件型号
+    pub manufacturer: String, // 厂家
+    pub kind: AttachmentKind, // 类型
+    pub name: String,         // 添加时的文件名, 打开时用于判断文件类型
+    pub hash: String,         // 文件内容的 sha256, 十六进制
+    pub size: u64,            // 文件大小, 字节
+    pub added: i64,           // 添加时间, unix 时间戳
+}
+
+impl Attachment {
+    /**
+     * @description: 元件在 element_map 中的键
+     * @return {*}
+     */
+    pub fn key(&self) -> String {
+        element_key(&self.model, &self.manufacturer)
+    }
+}
+
+// 元件的一条备注
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub id: i64,              // 备注 id
+    pub model: String,        // 元件型号
+    pub manufacturer: String, // 厂家
+    pub timestamp: i64,       // 添加时间, unix 时间戳
+    pub text: String,         // 内容
+    pub operator: String,     // 添加人
+}
+
+// 清理附件的结果
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AttachmentGc {
+    pub rows: usize,  // 删除的元件已不存在的附件记录和备注条数
+    pub files: usize, // 删除的没有被引用的文件个数
+    pub bytes: u64,   // 释放的空间, 字节
+}
+
+/**
+ * @description: 数据库的附件目录
+ * @param {&str} filepath 数据库文件路径
+ * @return {*}
+ */
+pub fn attachment_dir(filepath: &str) -> PathBuf {
+    PathBuf::from(format!("{filepath}.attachments"))
+}
+
+/**
+ * @description: 内容的 sha256, 十六进制小写
+ * @param {&[u8]} data 文件内容
+ * @return {*}
+ */
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/**
+ * @description: 附件文件的存放路径, 按 hash 的前两位分到子目录, 避免一个目录中文件太多
+ * @param {&Path} dir 附件目录
+ * @param {&str} hash 文件内容的 sha256
+ * @return {*}
+ */
+pub fn stored_path(dir: &Path, hash: &str) -> PathBuf {
+    dir.join(&hash[..2.min(hash.len())]).join(hash)
+}
+
+/**
+ * @description: 把文件复制到附件目录, 已有相同内容的文件时不再写入
+ * @param {&Path} dir 附件目录
+ * @param {&Path} source 要添加的文件
+ * @return {(hash, 文件大小)}
+ */
+pub(crate) fn store_file(dir: &Path, source: &Path) -> BomResult<(String, u64)> {
+    let data = fs::read(source)?;
+    let hash = content_hash(&data);
+    let path = stored_path(dir, &hash);
+    if !path.is_file() {
+        fs::create_dir_all(path.parent().unwrap_or(dir))?;
+        let temp = path.with_file_name(format!("{hash}{TEMP_SUFFIX}"));
+        fs::write(&temp, &data)?;
+        fs::rename(&temp, &path)?;
+    }
+    Ok((hash, data.len() as u64))
+}
+
+/**
+ * @description: 删除元件的所有附件记录和备注, 删除元件时调用, 文件由 gc_attachments 删除
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {*}
+ */
+pub(crate) fn clear_attachments(
+    conn: &Connection,
+    model: &str,
+    manufacturer: &str,
+) -> BomResult<()> {
+    conn.execute(
+        delete_element_attachments_template!(),
+        [model, manufacturer],
+    )?;
+    conn.execute(delete_element_notes_template!(), [model, manufacturer])?;
+    Ok(())
+}
+
+/**
+ * @description: 写入附件记录, 元件已有相同内容的附件时忽略, 添加和导入时使用
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&Attachment} attachment 附件
+ * @param {Option<i64>} id 附件 id, None 时分配新的
+ * @return {*}
+ */
+pub(crate) fn write_attachment(
+    conn: &Connection,
+    attachment: &Attachment,
+    id: Option<i64>,
+) -> BomResult<()> {
+    conn.execute(
+        insert_attachment_template!(),
+        (
+            id,
+            &attachment.model,
+            &attachment.manufacturer,
+            attachment.kind.to_string(),
+            &attachment.name,
+            &attachment.hash,
+            attachment.size,
+            attachment.added,
+        ),
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 写入备注, 添加和导入时使用
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&Note} note 备注
+ * @param {Option<i64>} id 备注 id, None 时分配新的
+ * @return {备注 id}
+ */
+pub(crate) fn write_note(conn: &Connection, note: &Note, id: Option<i64>) -> BomResult<i64> {
+    conn.execute(
+        insert_note_template!(),
+        (
+            id,
+            &note.model,
+            &note.manufacturer,
+            note.timestamp,
+            &note.text,
+            &note.operator,
+        ),
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// 把 get_attachment_template 查询到的一行转为 Attachment
+fn attachment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Attachment> {
+    let kind: String = row.get(3)?;
+    Ok(Attachment {
+        id: row.get(0)?,
+        model: row.get(1)?,
+        manufacturer: row.get(2)?,
+        kind: AttachmentKind::from_string(&kind).unwrap_or(AttachmentKind::Other),
+        name: row.get(4)?,
+        hash: row.get(5)?,
+        size: row.get(6)?,
+        added: row.get(7)?,
+    })
+}
+
+// 把 get_note_template 查询到的一行转为 Note
+fn note_from_row(row: &rusqlite::Row) -> rusqlite::Result<Note> {
+    Ok(Note {
+        id: row.get(0)?,
+        model: row.get(1)?,
+        manufacturer: row.get(2)?,
+        timestamp: row.get(3)?,
+        text: row.get(4)?,
+        operator: row.get(5)?,
+    })
+}
+
+// DataBaseInfo 的附件和备注接口
+impl DataBaseInfo {
+    /**
+     * @description: 这个数据库的附件目录
+     * @return {*}
+     */
+    pub fn attachment_dir(&self) -> PathBuf {
+        attachment_dir(&self.filepath)
+    }
+
+    /**
+     * @description: 给元件添加附件, 文件复制到附件目录, 元件已有相同内容的附件时返回已有的附件
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {&Path} path 要添加的文件
+     * @param {Option<AttachmentKind>} kind 附件类型, None 时按扩展名猜测
+     * @return {*}
+     */
+    pub fn add_attachment(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        path: &Path,
+        kind: Option<AttachmentKind>,
+    ) -> BomResult<Attachment> {
+        // 先拿到写锁再写文件, 记录提交前 gc_attachments 不会删除这个文件
+        let tx = self.immediate_transaction()?;
+        self.element(model, manufacturer)?;
+        let (hash, size) = store_file(&self.attachment_dir(), path)?;
+        let name = path
+            .file_name()
+            .map_or(String::new(), |n| n.to_string_lossy().to_string());
+        let attachment = Attachment {
+            id: 0,
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            kind: kind.unwrap_or_else(|| AttachmentKind::from_path(path)),
+            name,
+            hash: hash.clone(),
+            size,
+            added: now_timestamp(),
+        };
+        write_attachment(&tx, &attachment, None)?;
+        let sql = concat!(
+            get_attachment_template!(),
+            " WHERE model = ? AND manufacturer = ? AND hash = ?"
+        );
+        let attachment = tx.query_row(sql, [model, manufacturer, &hash], attachment_from_row)?;
+        tx.commit()?;
+        Ok(attachment)
+    }
+
+    /**
+     * @description: 按 id 读取附件
+     * @param {i64} id 附件 id
+     * @return {*}
+     */
+    pub fn attachment(&self, id: i64) -> BomResult<Attachment> {
+        let sql = concat!(get_attachment_template!(), " WHERE id = ?");
+        match self.conn.query_row(sql, [id], attachment_from_row) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(BomError::NotFound(format!("attachment {id}")))
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /**
+     * @description: 元件的所有附件, 按添加顺序排序
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn attachments(&self, model: &str, manufacturer: &str) -> BomResult<Vec<Attachment>> {
+        let sql = concat!(
+            get_attachment_template!(),
+            " WHERE model = ? AND manufacturer = ? ORDER BY id"
+        );
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([model, manufacturer], attachment_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 所有元件的附件, 按 id 排序, 用于导出
+     * @return {*}
+     */
+    pub fn all_attachments(&self) -> BomResult<Vec<Attachment>> {
+        let sql = concat!(get_attachment_template!(), " ORDER BY id");
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([], attachment_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 附件文件的路径, 用于打开附件
+     * @param {i64} id 附件 id
+     * @return {文件已被删除时返回 NotFound}
+     */
+    pub fn attachment_path(&self, id: i64) -> BomResult<PathBuf> {
+        let attachment = self.attachment(id)?;
+        let path = stored_path(&self.attachment_dir(), &attachment.hash);
+        if !path.is_file() {
+            return Err(BomError::NotFound(path.to_string_lossy().to_string()));
+        }
+        Ok(path)
+    }
+
+    /**
+     * @description: 把附件复制到目录中, 使用添加时的文件名, 用于按扩展名打开附件
+     * @param {i64} id 附件 id
+     * @param {&Path} dir 目标目录
+     * @return {复制后的文件路径}
+     */
+    pub fn save_attachment(&self, id: i64, dir: &Path) -> BomResult<PathBuf> {
+        let attachment = self.attachment(id)?;
+        let source = self.attachment_path(id)?;
+        let name = match attachment.name.as_str() {
+            "" => attachment.hash,
+            name => name.to_string(),
+        };
+        fs::create_dir_all(dir)?;
+        let target = dir.join(name);
+        fs::copy(source, &target)?;
+        Ok(target)
+    }
+
+    /**
+     * @description: 删除附件记录, 没有其他元件使用的文件在 gc_attachments 时删除
+     * @param {i64} id 附件 id
+     * @return {*}
+     */
+    pub fn remove_attachment(&self, id: i64) -> BomResult<()> {
+        if self.conn.execute(delete_attachment_template!(), [id])? == 0 {
+            return Err(BomError::NotFound(format!("attachment {id}")));
+        }
+        Ok(())
+    }
+
+    /**
+     * @description: 删除元件已不存在的附件记录和备注, 再删除附件目录中没有被引用的文件.
+     * 整个过程持有写锁, 其他进程不能同时添加附件
+     * @return {*}
+     */
+    pub fn gc_attachments(&self) -> BomResult<AttachmentGc> {
+        let mut gc = AttachmentGc::default();
+        let tx = self.immediate_transaction()?;
+        for table in ["attachments", "element_notes"] {
+            gc.rows += tx.execute(
+                format!(delete_orphan_rows_template!(), self.tables, table).as_str(),
+                [],
+            )?;
+        }
+        let dir = self.attachment_dir();
+        if !dir.is_dir() {
+            tx.commit()?;
+            return Ok(gc);
+        }
+        let used = {
+            let mut stmt = tx.prepare(get_hashes_template!())?;
+            let rows = stmt.query_map([], |row| row.get::<usize, String>(0))?;
+            rows.collect::<rusqlite::Result<HashSet<String>>>()?
+        };
+        for entry in fs::read_dir(&dir)? {
+            let sub_dir = entry?.path();
+            if !sub_dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&sub_dir)? {
+                let path = file?.path();
+                let name = path
+                    .file_name()
+                    .map_or(String::new(), |n| n.to_string_lossy().to_string());
+                if path.is_file() && !used.contains(&name) {
+                    gc.bytes += fs::metadata(&path)?.len();
+                    fs::remove_file(&path)?;
+                    gc.files += 1;
+                }
+            }
+            // 子目录为空时删除, 不为空时 remove_dir 失败, 忽略
+            let _ = fs::remove_dir(&sub_dir);
+        }
+        tx.commit()?;
+        Ok(gc)
+    }
+
+    /**
+     * @description: 给元件添加一条备注
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {&str} text 内容
+     * @param {&str} operator 添加人
+     * @return {*}
+     */
+    pub fn add_note(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        text: &str,
+        operator: &str,
+    ) -> BomResult<Note> {
+        self.element(model, manufacturer)?;
+        let note = Note {
+            id: 0,
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            timestamp: now_timestamp(),
+            text: text.to_string(),
+            operator: operator.to_string(),
+        };
+        let id = write_note(&self.conn, &note, None)?;
+        Ok(Note { id, ..note })
+    }
+
+    /**
+     * @description: 元件的所有备注, 按时间排序
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn notes(&self, model: &str, manufacturer: &str) -> BomResult<Vec<Note>> {
+        let sql = concat!(
+            get_note_template!(),
+            " WHERE model = ? AND manufacturer = ? ORDER BY timestamp, id"
+        );
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([model, manufacturer], note_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 所有元件的备注, 按 id 排序, 用于导出
+     * @return {*}
+     */
+    pub fn all_notes(&self) -> BomResult<Vec<Note>> {
+        let sql = concat!(get_note_template!(), " ORDER BY id");
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([], note_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 删除备注
+     * @param {i64} id 备注 id
+     * @return {*}
+     */
+    pub fn remove_note(&self, id: i64) -> BomResult<()> {
+        if self.conn.execute(delete_note_template!(), [id])? == 0 {
+            return Err(BomError::NotFound(format!("note {id}")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_and_kinds() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let path = stored_path(Path::new("db.attachments"), &content_hash(b"abc"));
+        assert!(path.starts_with(Path::new("db.attachments").join("ba")));
+        assert_eq!(
+            AttachmentKind::from_path(Path::new("AMS1117.PDF")),
+            AttachmentKind::Datasheet
+        );
+        assert_eq!(
+            AttachmentKind::from_path(Path::new("photo.jpg")),
+            AttachmentKind::Image
+        );
+        assert_eq!(
+            AttachmentKind::from_path(Path::new("SOT-223.kicad_mod")),
+            AttachmentKind::Footprint
+        );
+        assert_eq!(
+            AttachmentKind::from_path(Path::new("notes")),
+            AttachmentKind::Other
+        );
+        assert_eq!(
+            AttachmentKind::from_string("Image").unwrap(),
+            AttachmentKind::Image
+        );
+    }
+}
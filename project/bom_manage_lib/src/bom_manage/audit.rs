@@ -0,0 +1,517 @@
+/*
+ * @Description: 盘点单, 按位置或分类开始盘点时记下账面数量, 录入实际数量后比较差异,
+ * 完成时按差异调整库存, 调整记在原因为 audit 的流水中
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\audit.rs
+ */
+use super::ledger::{self, now_timestamp, MovementNote};
+use super::location;
+use super::threshold;
+use super::{element_key, BomError, BomResult, DataBaseInfo};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// 新建盘点单, id 为 NULL 时分配新的盘点单号
+macro_rules! insert_audit_template {
+    () => {
+        "INSERT INTO audits (id, scope, scope_id, path, status, created, closed) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    };
+}
+// 新增或修改盘点单的一个元件, 已有时只修改实际数量
+macro_rules! upsert_audit_line_template {
+    () => {
+        "INSERT INTO audit_lines (audit_id, model, manufacturer, expected, counted) VALUES (?, ?, ?, ?, ?) \
+         ON CONFLICT (audit_id, model, manufacturer) DO UPDATE SET counted = excluded.counted"
+    };
+}
+// 读取盘点单
+macro_rules! get_audit_template {
+    () => {
+        "SELECT id, scope, scope_id, path, status, created, closed FROM audits"
+    };
+}
+// 读取盘点单的元件
+macro_rules! get_audit_lines_template {
+    () => {
+        "SELECT model, manufacturer, expected, counted FROM audit_lines WHERE audit_id = ? \
+         ORDER BY model, manufacturer"
+    };
+}
+// 修改盘点单状态, 只有盘点中的盘点单可以修改
+macro_rules! update_audit_status_template {
+    () => {
+        "UPDATE audits SET status = ?, closed = ? WHERE id = ? AND status = 'open'"
+    };
+}
+
+// 盘点范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditScope {
+    All,           // 所有元件, 比较元件的总数量
+    Location(i64), // 一个位置上的元件, 不含下级位置, 比较放在这个位置的数量
+    Category(i64), // 分类及其子分类下的元件, 比较元件的总数量
+}
+
+impl AuditScope {
+    // 数据库中保存的范围类型和 id
+    pub(crate) fn to_columns(self) -> (&'static str, Option<i64>) {
+        match self {
+            AuditScope::All => ("all", None),
+            AuditScope::Location(id) => ("location", Some(id)),
+            AuditScope::Category(id) => ("category", Some(id)),
+        }
+    }
+
+    // 由数据库中保存的范围类型和 id 转为 AuditScope
+    pub(crate) fn from_columns(scope: &str, id: Option<i64>) -> BomResult<AuditScope> {
+        match (scope, id) {
+            ("all", _) => Ok(AuditScope::All),
+            ("location", Some(id)) => Ok(AuditScope::Location(id)),
+            ("category", Some(id)) => Ok(AuditScope::Category(id)),
+            _ => Err(BomError::InvalidStatus(scope.to_string())),
+        }
+    }
+}
+
+// 盘点单状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditStatus {
+    Open,      // 盘点中, 可以录入实际数量
+    Applied,   // 已按差异调整库存
+    Cancelled, // 已取消, 库存没有调整
+}
+
+impl fmt::Display for AuditStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AuditStatus::Open => "open",
+            AuditStatus::Applied => "applied",
+            AuditStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl AuditStatus {
+    /**
+     * @description: 数据库中的状态字符串转为 AuditStatus
+     * @param {&str} s
+     * @return {*}
+     */
+    pub fn from_string(s: &str) -> BomResult<AuditStatus> {
+        match s {
+            "open" => Ok(AuditStatus::Open),
+            "applied" => Ok(AuditStatus::Applied),
+            "cancelled" => Ok(AuditStatus::Cancelled),
+            _ => Err(BomError::InvalidStatus(s.to_string())),
+        }
+    }
+}
+
+// 盘点单中的一个元件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditLine {
+    pub model: String,        // 元件型号
+    pub manufacturer: String, // 厂家
+    pub expected: u32,        // 开始盘点时的账面数量
+    pub counted: Option<u32>, // 实际数量, 还没有盘点时为 None
+}
+
+impl AuditLine {
+    /**
+     * @description: 元件在 element_map 中的键
+     * @return {*}
+     */
+    pub fn key(&self) -> String {
+        element_key(&self.model, &self.manufacturer)
+    }
+
+    /**
+     * @description: 实际数量和账面数量的差异, 盘盈为正, 盘亏为负
+     * @return {还没有盘点时为 None}
+     */
+    pub fn variance(&self) -> Option<i64> {
+        self.counted
+            .map(|counted| i64::from(counted) - i64::from(self.expected))
+    }
+}
+
+// 一个盘点单
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Audit {
+    pub id: i64,               // 盘点单号
+    pub scope: AuditScope,     // 盘点范围
+    pub path: String,          // 开始盘点时位置或分类的路径, 所有元件时为空
+    pub status: AuditStatus,   // 状态
+    pub created: i64,          // 开始时间, unix 时间戳
+    pub closed: Option<i64>,   // 调整库存或取消的时间
+    pub lines: Vec<AuditLine>, // 盘点的元件
+}
+
+impl Audit {
+    /**
+     * @description: 已盘点且数量有差异的元件
+     * @return {*}
+     */
+    pub fn variances(&self) -> Vec<&AuditLine> {
+        self.lines
+            .iter()
+            .filter(|l| l.variance().is_some_and(|v| v != 0))
+            .collect()
+    }
+
+    /**
+     * @description: 还没有盘点的元件, 调整库存时不修改它们
+     * @return {*}
+     */
+    pub fn uncounted(&self) -> Vec<&AuditLine> {
+        self.lines.iter().filter(|l| l.counted.is_none()).collect()
+    }
+}
+
+/**
+ * @description: 写入盘点单和它的元件, 新建和导入时使用
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&Audit} audit 盘点单
+ * @param {Option<i64>} id 盘点单号, None 时分配新的
+ * @return {盘点单号}
+ */
+pub(crate) fn write_audit(conn: &Connection, audit: &Audit, id: Option<i64>) -> BomResult<i64> {
+    let (kind, scope_id) = audit.scope.to_columns();
+    conn.execute(
+        insert_audit_template!(),
+        (
+            id,
+            kind,
+            scope_id,
+            &audit.path,
+            audit.status.to_string(),
+            audit.created,
+            audit.closed,
+        ),
+    )?;
+    let id = conn.last_insert_rowid();
+    for line in audit.lines.iter() {
+        conn.execute(
+            upsert_audit_line_template!(),
+            (
+                id,
+                &line.model,
+                &line.manufacturer,
+                line.expected,
+                line.counted,
+            ),
+        )?;
+    }
+    Ok(id)
+}
+
+/**
+ * @description: 按盘点差异调整一个元件的库存并记流水, 盘亏时数量最少减到 0,
+ * 按位置盘点时同时把这个位置的数量调整相同的差异
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {AuditScope} scope 盘点范围
+ * @param {&AuditLine} line 盘点的元件
+ * @param {i64} variance 差异
+ * @param {&MovementNote} note 流水的原因和操作人
+ * @return {*}
+ */
+fn adjust_line(
+    conn: &Connection,
+    tables: &str,
+    scope: AuditScope,
+    line: &AuditLine,
+    variance: i64,
+    note: &MovementNote,
+) -> BomResult<()> {
+    let (model, manufacturer) = (line.model.as_str(), line.manufacturer.as_str());
+    let number: u32 = match conn.query_row(
+        format!(get_number_template!(), tables).as_str(),
+        [model, manufacturer],
+        |row| row.get(0),
+    ) {
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(BomError::NotFound(line.key())),
+        res => res?,
+    };
+    let remain = (i64::from(number) + variance).clamp(0, i64::from(u32::MAX)) as u32;
+    let state = threshold::effective_thresholds(conn, tables, model, manufacturer)?.status(remain);
+    conn.execute(
+        format!(update_number_template!(), tables).as_str(),
+        (remain, state.to_string(), model, manufacturer),
+    )?;
+    if remain != number {
+        ledger::insert_movement(
+            conn,
+            &note.movement(model, manufacturer, i64::from(remain) - i64::from(number)),
+        )?;
+    }
+    if let AuditScope::Location(location_id) = scope {
+        let placed = location::location_stock(conn, location_id)?
+            .into_iter()
+            .find(|(m, f, _)| m == model && f == manufacturer)
+            .map_or(0, |(_, _, quantity)| quantity);
+        let quantity = (i64::from(placed) + variance).clamp(0, i64::from(remain)) as u32;
+        location::set_location_quantity(conn, model, manufacturer, location_id, quantity)?;
+    }
+    location::trim_locations(conn, model, manufacturer, remain)?;
+    Ok(())
+}
+
+// DataBaseInfo 的盘点接口
+impl DataBaseInfo {
+    /**
+     * @description: 开始盘点, 记下范围内每个元件的账面数量
+     * @param {AuditScope} scope 盘点范围
+     * @return {盘点单号}
+     */
+    pub fn start_audit(&self, scope: AuditScope) -> BomResult<i64> {
+        let (lines, path) = match scope {
+            AuditScope::All => {
+                let mut lines: Vec<(String, String, u32)> = self
+                    .read_hm_from_database()?
+                    .into_values()
+                    .map(|e| (e.model, e.manufacturer, e.number))
+                    .collect();
+                lines.sort();
+                (lines, String::new())
+            }
+            AuditScope::Location(id) => (
+                location::location_stock(&self.conn, id)?,
+                self.location_path(id)?,
+            ),
+            AuditScope::Category(id) => (
+                self.category_elements(id)?
+                    .into_iter()
+                    .map(|e| (e.model, e.manufacturer, e.number))
+                    .collect(),
+                self.category_path(id)?,
+            ),
+        };
+        let audit = Audit {
+            id: 0,
+            scope,
+            path,
+            status: AuditStatus::Open,
+            created: now_timestamp(),
+            closed: None,
+            lines: lines
+                .into_iter()
+                .map(|(model, manufacturer, expected)| AuditLine {
+                    model,
+                    manufacturer,
+                    expected,
+                    counted: None,
+                })
+                .collect(),
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        let id = write_audit(&tx, &audit, None)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /**
+     * @description: 按盘点单号读取盘点单
+     * @param {i64} id 盘点单号
+     * @return {*}
+     */
+    pub fn audit(&self, id: i64) -> BomResult<Audit> {
+        self.query_audits(" WHERE id = ?", &[&id])?
+            .pop()
+            .ok_or_else(|| BomError::NotFound(format!("audit {id}")))
+    }
+
+    /**
+     * @description: 读取所有盘点单, 按开始顺序排序
+     * @return {*}
+     */
+    pub fn audits(&self) -> BomResult<Vec<Audit>> {
+        self.query_audits(" ORDER BY id", &[])
+    }
+
+    // 执行 get_audit_template 加上 suffix 条件, 并读取每个盘点单的元件
+    fn query_audits(&self, suffix: &str, params: &[&dyn rusqlite::ToSql]) -> BomResult<Vec<Audit>> {
+        let sql = String::from(get_audit_template!()) + suffix;
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<usize, i64>(0)?,
+                row.get::<usize, String>(1)?,
+                row.get::<usize, Option<i64>>(2)?,
+                row.get::<usize, String>(3)?,
+                row.get::<usize, String>(4)?,
+                row.get::<usize, i64>(5)?,
+                row.get::<usize, Option<i64>>(6)?,
+            ))
+        })?;
+        let mut line_stmt = self.conn.prepare(get_audit_lines_template!())?;
+        let mut audits = Vec::new();
+        for row in rows {
+            let (id, scope, scope_id, path, status, created, closed) = row?;
+            let lines = line_stmt.query_map([id], |row| {
+                Ok(AuditLine {
+                    model: row.get(0)?,
+                    manufacturer: row.get(1)?,
+                    expected: row.get(2)?,
+                    counted: row.get(3)?,
+                })
+            })?;
+            audits.push(Audit {
+                id,
+                scope: AuditScope::from_columns(&scope, scope_id)?,
+                path,
+                status: AuditStatus::from_string(&status)?,
+                created,
+                closed,
+                lines: lines.collect::<rusqlite::Result<Vec<_>>>()?,
+            });
+        }
+        Ok(audits)
+    }
+
+    // 盘点单不是盘点中时返回 AuditClosed
+    fn open_audit(&self, id: i64) -> BomResult<Audit> {
+        let audit = self.audit(id)?;
+        if audit.status != AuditStatus::Open {
+            return Err(BomError::AuditClosed {
+                id,
+                status: audit.status.to_string(),
+            });
+        }
+        Ok(audit)
+    }
+
+    /**
+     * @description: 录入元件的实际数量, 重复录入时以最后一次为准, 不在盘点单中的元件
+     * 按当前的账面数量加入, 例如在位置上找到了没有记录的元件
+     * @param {i64} id 盘点单号
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {u32} counted 实际数量
+     * @return {*}
+     */
+    pub fn record_count(
+        &self,
+        id: i64,
+        model: &str,
+        manufacturer: &str,
+        counted: u32,
+    ) -> BomResult<()> {
+        let audit = self.open_audit(id)?;
+        let expected = match audit
+            .lines
+            .iter()
+            .find(|l| l.model == model && l.manufacturer == manufacturer)
+        {
+            Some(line) => line.expected,
+            None => {
+                let element = self.element(model, manufacturer)?;
+                match audit.scope {
+                    AuditScope::Location(location_id) => {
+                        location::location_stock(&self.conn, location_id)?
+                            .into_iter()
+                            .find(|(m, f, _)| m == model && f == manufacturer)
+                            .map_or(0, |(_, _, quantity)| quantity)
+                    }
+                    _ => element.number,
+                }
+            }
+        };
+        self.conn.execute(
+            upsert_audit_line_template!(),
+            (id, model, manufacturer, expected, counted),
+        )?;
+        Ok(())
+    }
+
+    /**
+     * @description: 按盘点差异调整库存, 所有元件在一个事务中调整并记流水, 任何一个元件失败时都不调整.
+     * 差异按开始盘点时的账面数量计算, 盘点期间的出入库不会被覆盖; 没有盘点的元件不调整
+     * @param {i64} id 盘点单号
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {调整后的盘点单}
+     */
+    pub fn apply_audit(&self, id: i64, note: &MovementNote) -> BomResult<Audit> {
+        let tx = self.immediate_transaction()?;
+        // 先改状态, 同一个盘点单只会被应用一次
+        self.close_audit(&tx, id, AuditStatus::Applied)?;
+        let audit = self.audit(id)?;
+        for line in audit.lines.iter() {
+            if let Some(variance) = line.variance().filter(|v| *v != 0) {
+                adjust_line(&tx, &self.tables, audit.scope, line, variance, note)?;
+            }
+        }
+        tx.commit()?;
+        Ok(audit)
+    }
+
+    /**
+     * @description: 取消盘点中的盘点单, 不调整库存
+     * @param {i64} id 盘点单号
+     * @return {*}
+     */
+    pub fn cancel_audit(&self, id: i64) -> BomResult<()> {
+        self.close_audit(&self.conn, id, AuditStatus::Cancelled)
+    }
+
+    // 把盘点中的盘点单改为 status, 没有修改到一行说明已经结束, 返回 AuditClosed
+    fn close_audit(&self, conn: &Connection, id: i64, status: AuditStatus) -> BomResult<()> {
+        let changed = conn.execute(
+            update_audit_status_template!(),
+            (status.to_string(), now_timestamp(), id),
+        )?;
+        if changed != 1 {
+            let audit = self.audit(id)?;
+            return Err(BomError::AuditClosed {
+                id,
+                status: audit.status.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variances_skip_uncounted_lines() {
+        let line = |model: &str, expected, counted| AuditLine {
+            model: model.to_string(),
+            manufacturer: String::new(),
+            expected,
+            counted,
+        };
+        let audit = Audit {
+            id: 1,
+            scope: AuditScope::All,
+            path: String::new(),
+            status: AuditStatus::Open,
+            created: 0,
+            closed: None,
+            lines: vec![
+                line("R10K", 100, Some(97)),
+                line("C20uF", 50, Some(50)),
+                line("R1K", 20, None),
+                line("D1", 0, Some(4)),
+            ],
+        };
+        let variances: Vec<(String, Option<i64>)> = audit
+            .variances()
+            .iter()
+            .map(|l| (l.key(), l.variance()))
+            .collect();
+        assert_eq!(
+            variances,
+            vec![("R10K".to_string(), Some(-3)), ("D1".to_string(), Some(4))]
+        );
+        assert_eq!(audit.uncounted().len(), 1);
+        assert_eq!(
+            AuditScope::from_columns("location", Some(3)).unwrap(),
+            AuditScope::Location(3)
+        );
+        assert!(AuditScope::from_columns("category", None).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+/*
+ * @Description: 数据库备份和恢复, 用 sqlite 在线备份接口复制整个文件, 备份放在数据库旁边的
+ * <文件名>.backups 目录中, 只保留最近的几份; 打开时检查数据库是否损坏
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\backup.rs
+ */
+use super::ledger::now_timestamp;
+use super::{migration, BomError, BomResult, DataBaseInfo};
+use rusqlite::{backup, Connection, OpenFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// 检查数据库文件是否完整, 没有问题时只返回一行 "ok"
+macro_rules! integrity_check_template {
+    () => {
+        "PRAGMA integrity_check"
+    };
+}
+
+// 保留的备份份数, 超出时删除最旧的
+pub const BACKUP_KEEP: usize = 10;
+
+// 自动备份的原因, 写在备份文件名中
+pub const BACKUP_MANUAL: &str = "manual"; // 手动备份
+pub const BACKUP_REMOVE_ALL: &str = "remove-all"; // 删除所有元件之前
+pub const BACKUP_IMPORT: &str = "import"; // 导入库存之前
+pub const BACKUP_REPLACE: &str = "replace"; // 整表写入之前
+pub const BACKUP_RESTORE: &str = "restore"; // 恢复备份之前
+
+// 在线备份每次复制的页数, 以及两次复制之间让出给其他进程的时间
+const BACKUP_PAGES: std::ffi::c_int = 256;
+const BACKUP_PAUSE: Duration = Duration::from_millis(10);
+
+// 一份备份
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub id: u32,        // 备份号, 越大越新
+    pub timestamp: i64, // 备份时间, unix 时间戳
+    pub reason: String, // 备份原因
+    pub path: String,   // 备份文件路径
+}
+
+impl Backup {
+    /**
+     * @description: 从备份文件名 "备份号_时间戳_原因.db" 解析备份, 其他文件返回 None
+     * @param {&Path} path 备份文件路径
+     * @return {*}
+     */
+    fn from_path(path: &Path) -> Option<Backup> {
+        let name = path.file_name()?.to_str()?.strip_suffix(".db")?;
+        let mut parts = name.splitn(3, '_');
+        Some(Backup {
+            id: parts.next()?.parse().ok()?,
+            timestamp: parts.next()?.parse().ok()?,
+            reason: parts.next()?.to_string(),
+            path: path.to_string_lossy().to_string(),
+        })
+    }
+}
+
+/**
+ * @description: 数据库的备份目录
+ * @param {&str} filepath 数据库文件路径
+ * @return {*}
+ */
+pub fn backup_dir(filepath: &str) -> PathBuf {
+    PathBuf::from(format!("{filepath}.backups"))
+}
+
+/**
+ * @description: 列出数据库的所有备份, 按备份号从旧到新排序, 数据库损坏打不开时也可以使用
+ * @param {&str} filepath 数据库文件路径
+ * @return {*}
+ */
+pub fn list_backups(filepath: &str) -> BomResult<Vec<Backup>> {
+    let dir = backup_dir(filepath);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<Backup> = fs::read_dir(dir)?
+        .filter_map(|entry| Backup::from_path(&entry.ok()?.path()))
+        .collect();
+    backups.sort_by_key(|b| b.id);
+    Ok(backups)
+}
+
+/**
+ * @description: 检查数据库是否损坏
+ * @param {&Connection} conn sqlite数据库连接
+ * @param {&str} name 出错时显示的文件名
+ * @return {损坏时返回 BomError::CorruptFile}
+ */
+pub(crate) fn integrity_check(conn: &Connection, name: &str) -> BomResult<()> {
+    let mut stmt = conn.prepare(integrity_check_template!())?;
+    let messages = stmt
+        .query_map([], |row| row.get::<usize, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if messages.len() == 1 && messages[0] == "ok" {
+        Ok(())
+    } else {
+        Err(BomError::CorruptFile(format!(
+            "{name}: {}",
+            messages.join("; ")
+        )))
+    }
+}
+
+/**
+ * @description: 在备份列表中查找备份, 并检查备份文件是否完整
+ * @param {&str} filepath 数据库文件路径
+ * @param {u32} id 备份号
+ * @return {*}
+ */
+fn checked_backup(filepath: &str, id: u32) -> BomResult<Backup> {
+    let backup = list_backups(filepath)?
+        .into_iter()
+        .find(|b| b.id == id)
+        .ok_or_else(|| BomError::NotFound(format!("backup {id}")))?;
+    let conn = Connection::open_with_flags(&backup.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    integrity_check(&conn, &backup.path)?;
+    Ok(backup)
+}
+
+/**
+ * @description: 把备份复制到 conn, 复制过程中其他进程仍然可以读写
+ * @param {&Backup} backup 备份
+ * @param {&mut Connection} conn 目标数据库
+ * @return {*}
+ */
+fn copy_backup(backup: &Backup, conn: &mut Connection) -> BomResult<()> {
+    let src = Connection::open_with_flags(&backup.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    backup::Backup::new(&src, conn)?.run_to_completion(BACKUP_PAGES, BACKUP_PAUSE, None)?;
+    Ok(())
+}
+
+/**
+ * @description: 用备份替换损坏的数据库文件, 用于数据库打不开的情况;
+ * 损坏的文件改名为 <文件名>.corrupt 保留, 不会删除
+ * @param {&str} filepath 数据库文件路径
+ * @param {u32} id 备份号
+ * @return {*}
+ */
+pub fn restore_backup_file(filepath: &str, id: u32) -> BomResult<()> {
+    let backup = checked_backup(filepath, id)?;
+    if Path::new(filepath).exists() {
+        fs::rename(filepath, format!("{filepath}.corrupt"))?;
+    }
+    // 旧文件的 WAL 日志不能合并到恢复的文件中
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{filepath}{suffix}"));
+    }
+    let mut conn = Connection::open(filepath)?;
+    copy_backup(&backup, &mut conn)
+}
+
+// 备份文件名中的原因只保留字母/数字/-
+fn file_reason(reason: &str) -> String {
+    reason
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+// DataBaseInfo 的备份接口
+impl DataBaseInfo {
+    /**
+     * @description: 备份整个数据库, 备份后只保留最近的 BACKUP_KEEP 份
+     * @param {&str} reason 备份原因, 例如 BACKUP_MANUAL
+     * @return {新的备份}
+     */
+    pub fn backup(&self, reason: &str) -> BomResult<Backup> {
+        let dir = backup_dir(&self.filepath);
+        fs::create_dir_all(&dir)?;
+        let mut backups = list_backups(&self.filepath)?;
+        let id = backups.last().map_or(1, |b| b.id + 1);
+        let path = dir.join(format!(
+            "{id:06}_{}_{}.db",
+            now_timestamp(),
+            file_reason(reason)
+        ));
+        let mut dst = Connection::open(&path)?;
+        backup::Backup::new(&self.conn, &mut dst)?.run_to_completion(
+            BACKUP_PAGES,
+            BACKUP_PAUSE,
+            None,
+        )?;
+        // 备份复制了数据库的 WAL 模式, 改回普通模式, 备份文件不需要附带 -wal/-shm 文件
+        dst.pragma_update_and_check(None, "journal_mode", "DELETE", |row| {
+            row.get::<usize, String>(0)
+        })?;
+        drop(dst);
+        let backup = Backup::from_path(&path)
+            .ok_or_else(|| BomError::CorruptFile(path.to_string_lossy().to_string()))?;
+        backups.push(backup.clone());
+        let old = backups.len().saturating_sub(BACKUP_KEEP);
+        for old in backups.drain(..old) {
+            fs::remove_file(old.path)?;
+        }
+        Ok(backup)
+    }
+
+    /**
+     * @description: 数据库的所有备份, 按备份号从旧到新排序
+     * @return {*}
+     */
+    pub fn backups(&self) -> BomResult<Vec<Backup>> {
+        list_backups(&self.filepath)
+    }
+
+    /**
+     * @description: 恢复到某一份备份, 恢复前先备份当前的数据库, 恢复错了可以再恢复回来
+     * @param {u32} id 备份号
+     * @return {*}
+     */
+    pub fn restore_backup(&mut self, id: u32) -> BomResult<()> {
+        let backup = checked_backup(&self.filepath, id)?;
+        self.backup(BACKUP_RESTORE)?;
+        copy_backup(&backup, &mut self.conn)?;
+        // 旧版本程序生成的备份升级到当前版本
+        migration::migrate(&self.conn, &self.tables)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_names_round_trip() {
+        let backup = Backup::from_path(Path::new("x.db.backups/000012_1720000000_remove-all.db"));
+        assert_eq!(
+            backup.map(|b| (b.id, b.timestamp, b.reason)),
+            Some((12, 1720000000, "remove-all".to_string()))
+        );
+        assert_eq!(Backup::from_path(Path::new("notes.txt")), None);
+        assert_eq!(file_reason("build:主板 v2"), "build----v2");
+    }
+
+    #[test]
+    fn integrity_check_accepts_healthy_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (a INTEGER)").unwrap();
+        assert!(integrity_check(&conn, "memory").is_ok());
+    }
+}
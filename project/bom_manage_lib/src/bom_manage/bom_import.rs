@@ -0,0 +1,647 @@
+/*
+ * @Description: 导入项目 BOM (KiCad 导出的 CSV/XML 或自定义列的 CSV), 和库存比对得到缺料报告
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\bom_import.rs
+ */
+use super::substitute::{parametric_score, Substitutes};
+use super::value::{ElementParams, Unit};
+use super::{element_key, BomError, BomResult, Element};
+use std::collections::HashMap;
+use std::{fs, io::Read, path::Path};
+
+// KiCad 不同版本和脚本导出的列名, 比较时忽略大小写
+const REFERENCE_COLUMNS: [&str; 6] = [
+    "reference",
+    "references",
+    "reference(s)",
+    "refs",
+    "ref",
+    "designator",
+];
+const VALUE_COLUMNS: [&str; 2] = ["value", "comment"];
+const FOOTPRINT_COLUMNS: [&str; 2] = ["footprint", "package"];
+const MODEL_COLUMNS: [&str; 5] = [
+    "mpn",
+    "model",
+    "part number",
+    "manufacturer part number",
+    "型号",
+];
+const QUANTITY_COLUMNS: [&str; 5] = ["qty", "quantity", "quantity per pcb", "qnty", "数量"];
+
+// BOM 中的一行, 同一个元件的多个位号合并为一行
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BomLine {
+    pub references: Vec<String>, // 位号, 例如 R1, R2
+    pub value: String,           // 值, 例如 10k, 100nF, AMS1117-3.3
+    pub footprint: String,       // 封装, 例如 Resistor_SMD:R_0603_1608Metric
+    pub model: String,           // 型号, 没有这一列时为空
+    pub quantity: u32,           // 每块板的用量
+}
+
+// 自定义 CSV 的列名, 空字符串表示没有这一列
+#[derive(Debug, Clone)]
+pub struct CsvColumns {
+    pub reference: String, // 位号列
+    pub value: String,     // 值列
+    pub footprint: String, // 封装列
+    pub model: String,     // 型号列
+    pub quantity: String,  // 用量列, 没有时按位号个数计算
+    pub delimiter: u8,     // 分隔符
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        CsvColumns {
+            reference: "Reference".to_string(),
+            value: "Value".to_string(),
+            footprint: "Footprint".to_string(),
+            model: "MPN".to_string(),
+            quantity: "Qty".to_string(),
+            delimiter: b',',
+        }
+    }
+}
+
+// BOM 文件格式
+#[derive(Debug, Clone)]
+pub enum BomFormat {
+    KicadCsv,        // KiCad 导出的 CSV, 自动识别列名
+    KicadXml,        // KiCad 导出的 XML (eeschema 网表格式)
+    Csv(CsvColumns), // 自定义列名的 CSV
+}
+
+impl BomFormat {
+    /**
+     * @description: 按扩展名判断 KiCad 导出的格式, .xml 为 XML, 其他为 CSV
+     * @param {&str} path 文件路径
+     * @return {*}
+     */
+    pub fn from_path(path: &str) -> BomFormat {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("xml") => BomFormat::KicadXml,
+            _ => BomFormat::KicadCsv,
+        }
+    }
+}
+
+// 一行 BOM 的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    Model,     // 型号相同
+    Alternate, // 在同一个替代料组中
+    Value,     // 解析后的数值相同, 封装/精度/耐压可以替代
+    Unmatched, // 库中没有
+}
+
+// 分配给 BOM 一行的一种元件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub element: String,       // 元件键
+    pub matched_by: MatchKind, // 这个元件的匹配方式
+    pub quantity: u32,         // 分配的数量
+}
+
+// 缺料报告中的一行
+#[derive(Debug, Clone)]
+pub struct BomCheckLine {
+    pub line: BomLine,                // BOM 中的一行
+    pub element: Option<String>,      // 匹配到的元件键, 未匹配为 None
+    pub matched_by: MatchKind,        // 匹配方式
+    pub required: u32,                // 需要的数量, 用量 × 板数
+    pub on_hand: u32,                 // 库存数量
+    pub reserved: u32,                // 已被生产单预留的数量
+    pub allocated: u32,               // 分配给这一行的数量, 可用库存不足时小于 required
+    pub shortfall: u32,               // 缺少的数量
+    pub allocations: Vec<Allocation>, // 分配的元件, 按取料顺序, 合计为 allocated
+}
+
+impl BomCheckLine {
+    /**
+     * @description: 使用的替代料, 即 element 以外的元件
+     * @return {*}
+     */
+    pub fn substitutions(&self) -> impl Iterator<Item = &Allocation> {
+        self.allocations
+            .iter()
+            .filter(|a| Some(&a.element) != self.element.as_ref())
+    }
+
+    /**
+     * @description: 这一行实际使用的元件, 替代料按分配的数量, 缺少的部分记在匹配到的元件上
+     * @return {(元件键, 需要的数量, 分配的数量) 列表, 未匹配的行为空}
+     */
+    pub fn usage(&self) -> Vec<(String, u32, u32)> {
+        let Some(key) = &self.element else {
+            return Vec::new();
+        };
+        let mut usage = vec![(key.clone(), self.shortfall, 0)];
+        for allocation in self.allocations.iter() {
+            if &allocation.element == key {
+                usage[0].1 += allocation.quantity;
+                usage[0].2 += allocation.quantity;
+            } else {
+                usage.push((
+                    allocation.element.clone(),
+                    allocation.quantity,
+                    allocation.quantity,
+                ));
+            }
+        }
+        usage.retain(|(_, required, _)| *required > 0);
+        usage
+    }
+
+    /**
+     * @description: 库中没有的元件, 生产单记下它需要的数量, 生成采购单时按它采购
+     * @return {(元件键, 需要的数量), 元件键为 BOM 中的型号, 没有型号时为值; 匹配到元件的行为 None}
+     */
+    pub fn missing(&self) -> Option<(String, u32)> {
+        if self.element.is_some() || self.required == 0 {
+            return None;
+        }
+        let name = match self.line.model.as_str() {
+            "" => &self.line.value,
+            model => model,
+        };
+        Some((element_key(name.trim(), ""), self.required))
+    }
+}
+
+// 缺料报告
+#[derive(Debug, Clone)]
+pub struct BomReport {
+    pub boards: u32,              // 板数
+    pub lines: Vec<BomCheckLine>, // 每一行的结果, 顺序和 BOM 相同
+}
+
+impl BomReport {
+    /**
+     * @description: 库中没有匹配的行
+     * @return {*}
+     */
+    pub fn unmatched(&self) -> impl Iterator<Item = &BomCheckLine> {
+        self.lines
+            .iter()
+            .filter(|l| l.matched_by == MatchKind::Unmatched)
+    }
+
+    /**
+     * @description: 缺料的行, 包括未匹配的行
+     * @return {*}
+     */
+    pub fn short(&self) -> impl Iterator<Item = &BomCheckLine> {
+        self.lines.iter().filter(|l| l.shortfall > 0)
+    }
+
+    /**
+     * @description: 库存是否够做全部板子
+     * @return {*}
+     */
+    pub fn is_buildable(&self) -> bool {
+        self.short().next().is_none()
+    }
+}
+
+/**
+ * @description: 按格式读取 BOM 文件
+ * @param {&str} path 文件路径
+ * @param {&BomFormat} format 文件格式
+ * @return {*}
+ */
+pub fn read_bom(path: &str, format: &BomFormat) -> BomResult<Vec<BomLine>> {
+    match format {
+        BomFormat::KicadCsv => parse_kicad_csv(fs::File::open(path)?),
+        BomFormat::KicadXml => parse_kicad_xml(&fs::read_to_string(path)?),
+        BomFormat::Csv(columns) => parse_csv(fs::File::open(path)?, columns),
+    }
+}
+
+/**
+ * @description: 解析 KiCad 导出的 CSV, 列名自动识别, 表头前的说明行会跳过
+ * @param {R} reader CSV 内容
+ * @return {*}
+ */
+pub fn parse_kicad_csv<R: Read>(reader: R) -> BomResult<Vec<BomLine>> {
+    parse_with(
+        reader,
+        b',',
+        |name, candidates| candidates.iter().any(|c| name.eq_ignore_ascii_case(c)),
+        &[
+            &REFERENCE_COLUMNS,
+            &VALUE_COLUMNS,
+            &FOOTPRINT_COLUMNS,
+            &MODEL_COLUMNS,
+            &QUANTITY_COLUMNS,
+        ],
+    )
+}
+
+/**
+ * @description: 按指定的列名解析 CSV
+ * @param {R} reader CSV 内容
+ * @param {&CsvColumns} columns 列名
+ * @return {*}
+ */
+pub fn parse_csv<R: Read>(reader: R, columns: &CsvColumns) -> BomResult<Vec<BomLine>> {
+    let names = [
+        [columns.reference.as_str()],
+        [columns.value.as_str()],
+        [columns.footprint.as_str()],
+        [columns.model.as_str()],
+        [columns.quantity.as_str()],
+    ];
+    parse_with(
+        reader,
+        columns.delimiter,
+        |name, candidates| {
+            candidates
+                .iter()
+                .any(|c| !c.is_empty() && name.eq_ignore_ascii_case(c))
+        },
+        &[&names[0], &names[1], &names[2], &names[3], &names[4]],
+    )
+}
+
+/**
+ * @description: 解析 CSV, 第一行能找到位号列或值列的为表头
+ * @param {R} reader CSV 内容
+ * @param {u8} delimiter 分隔符
+ * @param {F} matches 判断表头是否为某一列
+ * @param {&[&[&str]; 5]} columns 位号/值/封装/型号/用量列的候选列名
+ * @return {*}
+ */
+fn parse_with<R: Read, F: Fn(&str, &[&str]) -> bool>(
+    reader: R,
+    delimiter: u8,
+    matches: F,
+    columns: &[&[&str]; 5],
+) -> BomResult<Vec<BomLine>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+    // 每一列在 CSV 中的下标
+    let mut index: Option<[Option<usize>; 5]> = None;
+    let mut lines = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| BomError::InvalidBom(e.to_string()))?;
+        let Some(index) = index else {
+            let found = columns.map(|candidates| {
+                record
+                    .iter()
+                    .position(|name| matches(name.trim(), candidates))
+            });
+            if found[0].is_some() || found[1].is_some() {
+                index = Some(found);
+            }
+            continue;
+        };
+        let field = |i: usize| {
+            index[i]
+                .and_then(|i| record.get(i))
+                .unwrap_or("")
+                .trim()
+                .to_string()
+        };
+        let references = split_references(&field(0));
+        let value = field(1);
+        if references.is_empty() && value.is_empty() {
+            continue;
+        }
+        let quantity = match index[4] {
+            Some(_) => field(4)
+                .parse::<u32>()
+                .map_err(|_| BomError::InvalidBom(format!("invalid quantity: {}", field(4))))?,
+            None => references.len().max(1) as u32,
+        };
+        lines.push(BomLine {
+            references,
+            value,
+            footprint: field(2),
+            model: field(3),
+            quantity,
+        });
+    }
+    if index.is_none() {
+        return Err(BomError::InvalidBom("no header row found".to_string()));
+    }
+    Ok(lines)
+}
+
+// 位号可以用逗号或空格分隔, 例如 "R1, R2 R3"
+fn split_references(text: &str) -> Vec<String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|r| !r.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/**
+ * @description: 解析 KiCad 导出的 XML, 值/封装/型号相同的元件合并为一行, 标记为不贴 (DNP) 的跳过
+ * @param {&str} text XML 内容
+ * @return {*}
+ */
+pub fn parse_kicad_xml(text: &str) -> BomResult<Vec<BomLine>> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| BomError::InvalidBom(e.to_string()))?;
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|c| c.has_tag_name(name))
+            .and_then(|c| c.text())
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    };
+    let mut lines: Vec<BomLine> = Vec::new();
+    for comp in doc.descendants().filter(|n| n.has_tag_name("comp")) {
+        let dnp = comp
+            .children()
+            .any(|c| c.has_tag_name("property") && c.attribute("name") == Some("dnp"));
+        if dnp {
+            continue;
+        }
+        // 型号写在 fields 下的自定义字段中
+        let model = comp
+            .descendants()
+            .filter(|f| f.has_tag_name("field"))
+            .find(|f| {
+                f.attribute("name")
+                    .is_some_and(|n| MODEL_COLUMNS.iter().any(|c| n.eq_ignore_ascii_case(c)))
+            })
+            .and_then(|f| f.text())
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        let line = BomLine {
+            references: vec![comp.attribute("ref").unwrap_or("").to_string()],
+            value: child_text(comp, "value"),
+            footprint: child_text(comp, "footprint"),
+            model,
+            quantity: 1,
+        };
+        match lines.iter_mut().find(|l| {
+            l.value == line.value && l.footprint == line.footprint && l.model == line.model
+        }) {
+            Some(existing) => {
+                existing.references.extend(line.references);
+                existing.quantity += 1;
+            }
+            None => lines.push(line),
+        }
+    }
+    Ok(lines)
+}
+
+// 位号前缀对应的单位, 值中没写单位时使用
+fn reference_unit(references: &[String]) -> Option<Unit> {
+    let prefix: String = references
+        .first()?
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    match prefix.to_ascii_uppercase().as_str() {
+        "R" | "RN" => Some(Unit::Ohm),
+        "C" => Some(Unit::Farad),
+        "L" => Some(Unit::Henry),
+        _ => None,
+    }
+}
+
+// BOM 一行需要的参数, 封装优先使用封装列
+fn line_params(line: &BomLine) -> ElementParams {
+    let mut wanted = ElementParams::parse(&line.value, reference_unit(&line.references));
+    if let Some(package) = ElementParams::parse(&line.footprint, None).package {
+        wanted.package = Some(package);
+    }
+    wanted
+}
+
+/**
+ * @description: BOM 一行的候选元件, 按取料顺序中的来源排列, 不重复.
+ * 型号: 型号等于型号列 (没有时用值) 的元件, 库存多的在前;
+ * 替代料: 和 BOM 中的型号或型号相同的元件在同一组的元件, 按优先级和库存排列;
+ * 参数: 数值相同且封装/精度/耐压可以替代的元件, 参数越接近、库存越多越靠前
+ * @param {&HashMap<String, Element>} map 元件表
+ * @param {&BomLine} line BOM 中的一行
+ * @param {&Substitutes} substitutes 替代料组和取料顺序
+ * @return {(元件键, 匹配方式) 列表}
+ */
+pub fn candidates(
+    map: &HashMap<String, Element>,
+    line: &BomLine,
+    substitutes: &Substitutes,
+) -> Vec<(String, MatchKind)> {
+    // 排序键相同时按元件键排, 保证结果稳定
+    let sorted = |mut found: Vec<((u32, u32), &Element)>| {
+        found.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.key().cmp(&b.1.key())));
+        found.into_iter().map(|(_, e)| e.key()).collect::<Vec<_>>()
+    };
+
+    let mut models: Vec<&str> = Vec::new();
+    let mut exact = Vec::new();
+    for model in [&line.model, &line.value] {
+        if model.is_empty() {
+            continue;
+        }
+        models.push(model);
+        if exact.is_empty() {
+            exact = map
+                .values()
+                .filter(|e| e.model.eq_ignore_ascii_case(model))
+                .map(|e| ((0, u32::MAX - e.number), e))
+                .collect();
+        }
+    }
+    models.extend(exact.iter().map(|(_, e)| e.model.as_str()));
+
+    let groups: Vec<_> = substitutes
+        .groups
+        .iter()
+        .filter(|g| models.iter().any(|m| g.has_model(m)))
+        .collect();
+    let alternates = map
+        .values()
+        .filter_map(|e| {
+            let priority = groups.iter().filter_map(|g| g.priority(e)).min()?;
+            Some(((priority, u32::MAX - e.number), e))
+        })
+        .collect();
+
+    let wanted = line_params(line);
+    let similar = map
+        .values()
+        .filter_map(|e| {
+            let score = parametric_score(&wanted, &e.params())?;
+            Some(((u32::MAX - score, u32::MAX - e.number), e))
+        })
+        .collect();
+
+    let (exact, alternates, similar) = (sorted(exact), sorted(alternates), sorted(similar));
+    let mut result: Vec<(String, MatchKind)> = Vec::new();
+    for kind in substitutes.policy.order.iter() {
+        let keys = match kind {
+            MatchKind::Model => &exact,
+            MatchKind::Alternate => &alternates,
+            MatchKind::Value => &similar,
+            MatchKind::Unmatched => continue,
+        };
+        for key in keys {
+            if !result.iter().any(|(k, _)| k == key) {
+                result.push((key.clone(), *kind));
+            }
+        }
+    }
+    if !substitutes.policy.substitute {
+        result.truncate(1);
+    }
+    result
+}
+
+/**
+ * @description: 在元件表中查找 BOM 的一行, 先按型号 (型号列或值) 查找, 再按解析后的数值和封装查找,
+ * 有多个候选时优先封装相同、库存多的
+ * @param {&HashMap<String, Element>} map 元件表
+ * @param {&BomLine} line BOM 中的一行
+ * @return {元件键和匹配方式}
+ */
+pub fn match_line(map: &HashMap<String, Element>, line: &BomLine) -> Option<(String, MatchKind)> {
+    candidates(map, line, &Substitutes::default())
+        .into_iter()
+        .next()
+}
+
+/**
+ * @description: 比对 BOM 和可用库存 (库存 - 已预留), 多行匹配到同一个元件时按顺序分配库存,
+ * 第一个候选元件不够时按取料顺序从替代料中分配
+ * @param {&HashMap<String, Element>} map 元件表
+ * @param {&HashMap<String, u32>} reserved 每个元件已被生产单预留的数量
+ * @param {&[BomLine]} lines BOM
+ * @param {u32} boards 板数
+ * @param {&Substitutes} substitutes 替代料组和取料顺序
+ * @return {*}
+ */
+pub fn check_bom(
+    map: &HashMap<String, Element>,
+    reserved: &HashMap<String, u32>,
+    lines: &[BomLine],
+    boards: u32,
+    substitutes: &Substitutes,
+) -> BomReport {
+    // 每个元件已经预留和分配给前面行的数量
+    let mut allocated = reserved.clone();
+    let lines = lines
+        .iter()
+        .map(|line| {
+            let required = line.quantity.saturating_mul(boards);
+            let candidates = candidates(map, line, substitutes);
+            let (element, matched_by) = match candidates.first() {
+                Some((key, kind)) => (Some(key.clone()), *kind),
+                None => (None, MatchKind::Unmatched),
+            };
+            let on_hand = element
+                .as_ref()
+                .and_then(|key| map.get(key))
+                .map_or(0, |e| e.number);
+            let mut remaining = required;
+            let mut allocations = Vec::new();
+            for (key, kind) in candidates {
+                if remaining == 0 {
+                    break;
+                }
+                let stock = map.get(&key).map_or(0, |e| e.number);
+                let used = allocated.entry(key.clone()).or_insert(0);
+                let take = remaining.min(stock.saturating_sub(*used));
+                if take > 0 {
+                    *used += take;
+                    remaining -= take;
+                    allocations.push(Allocation {
+                        element: key,
+                        matched_by: kind,
+                        quantity: take,
+                    });
+                }
+            }
+            BomCheckLine {
+                reserved: element
+                    .as_ref()
+                    .and_then(|key| reserved.get(key))
+                    .copied()
+                    .unwrap_or(0),
+                line: line.clone(),
+                element,
+                matched_by,
+                required,
+                on_hand,
+                allocated: required - remaining,
+                shortfall: remaining,
+                allocations,
+            }
+        })
+        .collect();
+    BomReport { boards, lines }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KICAD_CSV: &str = "\
+\"Source:\",\"/home/user/board/board.kicad_sch\"
+\"Date:\",\"2024-08-20\"
+\"Tool:\",\"Eeschema 7.0.10\"
+
+\"Item\",\"Qty\",\"Reference(s)\",\"Value\",\"LibPart\",\"Footprint\",\"Datasheet\"
+\"1\",\"3\",\"R1, R2, R5\",\"10k\",\"Device:R\",\"Resistor_SMD:R_0603_1608Metric\",\"~\"
+\"2\",\"1\",\"U1\",\"AMS1117-3.3\",\"Regulator_Linear:AMS1117-3.3\",\"Package_TO_SOT_SMD:SOT-223-3_TabPin2\",\"\"
+";
+
+    const KICAD_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<export version="E">
+  <components>
+    <comp ref="C1"><value>100nF</value><footprint>Capacitor_SMD:C_0402_1005Metric</footprint></comp>
+    <comp ref="C2"><value>100nF</value><footprint>Capacitor_SMD:C_0402_1005Metric</footprint></comp>
+    <comp ref="C3"><value>100nF</value><footprint>Capacitor_SMD:C_0402_1005Metric</footprint><property name="dnp"/></comp>
+    <comp ref="Q1"><value>AO3400</value><footprint>Package_TO_SOT_SMD:SOT-23</footprint>
+      <fields><field name="MPN">AO3400A</field></fields></comp>
+  </components>
+</export>"#;
+
+    #[test]
+    fn parse_kicad_csv_skips_preamble() {
+        let lines = parse_kicad_csv(KICAD_CSV.as_bytes()).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].references, vec!["R1", "R2", "R5"]);
+        assert_eq!(lines[0].value, "10k");
+        assert_eq!(lines[0].quantity, 3);
+        assert_eq!(lines[1].footprint, "Package_TO_SOT_SMD:SOT-223-3_TabPin2");
+    }
+
+    #[test]
+    fn parse_kicad_xml_groups_components() {
+        let lines = parse_kicad_xml(KICAD_XML).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].references, vec!["C1", "C2"]);
+        assert_eq!(lines[0].quantity, 2);
+        assert_eq!(lines[1].model, "AO3400A");
+    }
+
+    #[test]
+    fn parse_csv_with_custom_columns() {
+        let text = "位号;数值;封装\nR1 R2;4K7;0805\nD1;LED;0603\n";
+        let columns = CsvColumns {
+            reference: "位号".to_string(),
+            value: "数值".to_string(),
+            footprint: "封装".to_string(),
+            model: String::new(),
+            quantity: String::new(),
+            delimiter: b';',
+        };
+        let lines = parse_csv(text.as_bytes(), &columns).unwrap();
+        assert_eq!(lines[0].quantity, 2);
+        assert_eq!(lines[1].value, "LED");
+        assert!(matches!(
+            parse_csv("a,b\n1,2\n".as_bytes(), &CsvColumns::default()),
+            Err(BomError::InvalidBom(_))
+        ));
+    }
+}
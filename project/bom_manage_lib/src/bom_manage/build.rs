@@ -0,0 +1,324 @@
+/*
+ * @Description: 生产单, 按项目 BOM 预留元件, 完成时扣减库存, 取消时释放预留
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\build.rs
+ */
+use super::ledger::{now_timestamp, MovementNote};
+use super::{element_key, reduce_number, BomError, BomResult, DataBaseInfo};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+// 写入生产单, id 为 NULL 时分配新的生产单号
+macro_rules! insert_build_template {
+    () => {
+        "INSERT INTO builds (id, name, boards, status, created, finished) VALUES (?, ?, ?, ?, ?, ?)"
+    };
+}
+// 新增生产单的一个元件
+macro_rules! insert_build_item_template {
+    () => {
+        "INSERT INTO build_items (build_id, model, manufacturer, required, reserved) VALUES (?, ?, ?, ?, ?)"
+    };
+}
+// 读取生产单
+macro_rules! get_build_template {
+    () => {
+        "SELECT id, name, boards, status, created, finished FROM builds"
+    };
+}
+// 读取生产单的元件
+macro_rules! get_build_items_template {
+    () => {
+        "SELECT model, manufacturer, required, reserved FROM build_items WHERE build_id = ? ORDER BY id"
+    };
+}
+// 修改生产单状态, 只有预留中的生产单可以修改
+macro_rules! update_build_status_template {
+    () => {
+        "UPDATE builds SET status = ?, finished = ? WHERE id = ? AND status = 'reserved'"
+    };
+}
+// 所有预留中的生产单对每个元件预留的数量
+macro_rules! get_reserved_template {
+    () => {
+        "SELECT i.model, i.manufacturer, SUM(i.reserved) FROM build_items i \
+         JOIN builds b ON b.id = i.build_id WHERE b.status = 'reserved' \
+         GROUP BY i.model, i.manufacturer"
+    };
+}
+
+// 生产单状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildStatus {
+    Reserved,  // 已预留, 等待生产
+    Committed, // 已完成, 元件已从库存扣除
+    Cancelled, // 已取消, 预留已释放
+}
+
+impl fmt::Display for BuildStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BuildStatus::Reserved => "reserved",
+            BuildStatus::Committed => "committed",
+            BuildStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl BuildStatus {
+    /**
+     * @description: 数据库中的状态字符串转为 BuildStatus
+     * @param {&str} s
+     * @return {*}
+     */
+    pub fn from_string(s: &str) -> BomResult<BuildStatus> {
+        match s {
+            "reserved" => Ok(BuildStatus::Reserved),
+            "committed" => Ok(BuildStatus::Committed),
+            "cancelled" => Ok(BuildStatus::Cancelled),
+            _ => Err(BomError::InvalidStatus(s.to_string())),
+        }
+    }
+}
+
+// 生产单中的一个元件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildItem {
+    pub model: String,        // 元件型号
+    pub manufacturer: String, // 厂家
+    pub required: u32,        // 需要的数量
+    pub reserved: u32,        // 预留的数量, 可用库存不足时小于 required
+}
+
+impl BuildItem {
+    /**
+     * @description: 元件在 element_map 中的键
+     * @return {*}
+     */
+    pub fn key(&self) -> String {
+        element_key(&self.model, &self.manufacturer)
+    }
+}
+
+// 一个生产单
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Build {
+    pub id: i64,               // 生产单号
+    pub name: String,          // 名称, 例如项目名和批次
+    pub boards: u32,           // 板数
+    pub status: BuildStatus,   // 状态
+    pub created: i64,          // 创建时间, unix 时间戳
+    pub finished: Option<i64>, // 完成或取消的时间
+    pub items: Vec<BuildItem>, // 预留的元件
+}
+
+/**
+ * @description: 写入生产单和预留的元件, 由调用者提供事务
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} name 名称
+ * @param {u32} boards 板数
+ * @param {&[BuildItem]} items 预留的元件
+ * @return {生产单号}
+ */
+pub(crate) fn insert_build(
+    conn: &Connection,
+    name: &str,
+    boards: u32,
+    items: &[BuildItem],
+) -> BomResult<i64> {
+    let build = Build {
+        id: 0,
+        name: name.to_string(),
+        boards,
+        status: BuildStatus::Reserved,
+        created: now_timestamp(),
+        finished: None,
+        items: items.to_vec(),
+    };
+    write_build(conn, &build, None)
+}
+
+/**
+ * @description: 写入生产单和它的元件, 新建和导入时使用
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&Build} build 生产单
+ * @param {Option<i64>} id 生产单号, None 时分配新的
+ * @return {生产单号}
+ */
+pub(crate) fn write_build(conn: &Connection, build: &Build, id: Option<i64>) -> BomResult<i64> {
+    conn.execute(
+        insert_build_template!(),
+        (
+            id,
+            &build.name,
+            build.boards,
+            build.status.to_string(),
+            build.created,
+            build.finished,
+        ),
+    )?;
+    let id = conn.last_insert_rowid();
+    for item in build.items.iter() {
+        conn.execute(
+            insert_build_item_template!(),
+            (
+                id,
+                &item.model,
+                &item.manufacturer,
+                item.required,
+                item.reserved,
+            ),
+        )?;
+    }
+    Ok(id)
+}
+
+// DataBaseInfo 的生产单接口
+impl DataBaseInfo {
+    /**
+     * @description: 新建生产单并预留元件
+     * @param {&str} name 名称
+     * @param {u32} boards 板数
+     * @param {&[BuildItem]} items 预留的元件
+     * @return {生产单号}
+     */
+    pub fn create_build(&self, name: &str, boards: u32, items: &[BuildItem]) -> BomResult<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let id = insert_build(&tx, name, boards, items)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /**
+     * @description: 完成预留中的生产单, 修改状态和扣减全部预留的元件在同一个 IMMEDIATE 事务中,
+     * 任何一个元件库存不足都会整体回滚, 生产单保持预留中
+     * @param {i64} id 生产单号
+     * @param {&MovementNote} note 扣减流水的原因和操作人
+     * @return {完成的生产单}
+     */
+    pub fn commit_build(&self, id: i64, note: &MovementNote) -> BomResult<Build> {
+        let tx = self.immediate_transaction()?;
+        // 先改状态, 只有一个调用者能把预留中的生产单改为完成, 重复完成不会扣两次库存
+        let changed = tx.execute(
+            update_build_status_template!(),
+            (BuildStatus::Committed.to_string(), now_timestamp(), id),
+        )?;
+        if changed != 1 {
+            let build = self.build(id)?;
+            return Err(BomError::BuildClosed {
+                id,
+                status: build.status.to_string(),
+            });
+        }
+        let build = self.build(id)?;
+        for item in build.items.iter().filter(|item| item.reserved > 0) {
+            reduce_number(
+                &tx,
+                &self.tables,
+                &item.model,
+                &item.manufacturer,
+                item.reserved,
+                false,
+                note,
+            )?;
+        }
+        tx.commit()?;
+        Ok(build)
+    }
+
+    /**
+     * @description: 读取生产单
+     * @param {i64} id 生产单号
+     * @return {*}
+     */
+    pub fn build(&self, id: i64) -> BomResult<Build> {
+        self.query_builds(" WHERE id = ?", &[&id])?
+            .pop()
+            .ok_or_else(|| BomError::NotFound(format!("build {id}")))
+    }
+
+    /**
+     * @description: 读取所有生产单, 按创建顺序排序
+     * @return {*}
+     */
+    pub fn builds(&self) -> BomResult<Vec<Build>> {
+        self.query_builds(" ORDER BY id", &[])
+    }
+
+    // 执行 get_build_template 加上 suffix 条件, 并读取每个生产单的元件
+    fn query_builds(&self, suffix: &str, params: &[&dyn rusqlite::ToSql]) -> BomResult<Vec<Build>> {
+        let sql = String::from(get_build_template!()) + suffix;
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<usize, i64>(0)?,
+                row.get::<usize, String>(1)?,
+                row.get::<usize, u32>(2)?,
+                row.get::<usize, String>(3)?,
+                row.get::<usize, i64>(4)?,
+                row.get::<usize, Option<i64>>(5)?,
+            ))
+        })?;
+        let mut item_stmt = self.conn.prepare(get_build_items_template!())?;
+        let mut builds = Vec::new();
+        for row in rows {
+            let (id, name, boards, status, created, finished) = row?;
+            let items = item_stmt.query_map([id], |row| {
+                Ok(BuildItem {
+                    model: row.get(0)?,
+                    manufacturer: row.get(1)?,
+                    required: row.get(2)?,
+                    reserved: row.get(3)?,
+                })
+            })?;
+            builds.push(Build {
+                id,
+                name,
+                boards,
+                status: BuildStatus::from_string(&status)?,
+                created,
+                finished,
+                items: items.collect::<rusqlite::Result<Vec<_>>>()?,
+            });
+        }
+        Ok(builds)
+    }
+
+    /**
+     * @description: 结束预留中的生产单, 完成或取消
+     * @param {i64} id 生产单号
+     * @param {BuildStatus} status 新状态
+     * @return {*}
+     */
+    pub fn finish_build(&self, id: i64, status: BuildStatus) -> BomResult<()> {
+        let changed = self.conn.execute(
+            update_build_status_template!(),
+            (status.to_string(), now_timestamp(), id),
+        )?;
+        if changed == 0 {
+            let build = self.build(id)?;
+            return Err(BomError::BuildClosed {
+                id,
+                status: build.status.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * @description: 所有预留中的生产单对每个元件预留的数量
+     * @return {元件键 -> 预留数量}
+     */
+    pub fn reserved_stock(&self) -> BomResult<HashMap<String, u32>> {
+        let mut stmt = self.conn.prepare(get_reserved_template!())?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                element_key(&row.get::<usize, String>(0)?, &row.get::<usize, String>(1)?),
+                row.get::<usize, u32>(2)?,
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+    }
+}
@@ -0,0 +1,355 @@
+/*
+ * @Description: 元件分类, 保存在数据库中, 支持多级分类, 例如 半导体/MOSFET/N沟道
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\category.rs
+ */
+use super::{
+    element_from_row, search, threshold, BomError, BomResult, DataBaseInfo, Element, ElementType,
+};
+
+// 新增分类, 同一父分类下已有同名分类时忽略
+macro_rules! insert_category_template {
+    () => {
+        "INSERT OR IGNORE INTO categories (name, parent_id) VALUES (?, ?)"
+    };
+}
+// 按父分类和名字查找分类, 父分类为空时 COALESCE 为 0
+macro_rules! find_category_template {
+    () => {
+        "SELECT id, name, parent_id FROM categories WHERE COALESCE(parent_id, 0) = ? AND name = ?"
+    };
+}
+// 按 id 读取分类
+macro_rules! get_category_template {
+    () => {
+        "SELECT id, name, parent_id FROM categories WHERE id = ?"
+    };
+}
+// 读取所有分类
+macro_rules! get_categories_template {
+    () => {
+        "SELECT id, name, parent_id FROM categories ORDER BY id"
+    };
+}
+// 修改分类名字
+macro_rules! rename_category_template {
+    () => {
+        "UPDATE categories SET name = ? WHERE id = ?"
+    };
+}
+// 修改元件的分类
+macro_rules! update_category_template {
+    () => {
+        "UPDATE {} SET category_id = ? WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 分类及其所有子分类下的元件
+macro_rules! get_category_elements_template {
+    () => {
+        " WHERE category_id IN (\
+         WITH RECURSIVE sub (id) AS (SELECT ? UNION ALL SELECT c.id FROM categories c JOIN sub ON c.parent_id = sub.id) \
+         SELECT id FROM sub) ORDER BY model, manufacturer"
+    };
+}
+
+// 分类路径的分隔符
+pub const PATH_SEPARATOR: char = '/';
+
+// 内置元件类型, 建立分类表时写入, id 固定
+pub const BUILTIN_TYPES: [ElementType; 6] = [
+    ElementType::Resistor,
+    ElementType::Diode,
+    ElementType::Transistor,
+    ElementType::Capacitor,
+    ElementType::Inductor,
+    ElementType::Chip,
+];
+
+// 一个元件分类
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Category {
+    pub id: i64,                // 分类 id
+    pub name: String,           // 分类名
+    pub parent_id: Option<i64>, // 父分类 id, 顶层分类为 None
+}
+
+impl ElementType {
+    /**
+     * @description: 内置类型对应的分类 id, 未知类型没有分类
+     * @return {*}
+     */
+    pub fn category_id(&self) -> Option<i64> {
+        match self {
+            ElementType::Resistor => Some(1),
+            ElementType::Diode => Some(2),
+            ElementType::Transistor => Some(3),
+            ElementType::Capacitor => Some(4),
+            ElementType::Inductor => Some(5),
+            ElementType::Chip => Some(6),
+            ElementType::Unknown => None,
+        }
+    }
+}
+
+/**
+ * @description: 把 get_category_template 查询到的一行转为 Category
+ * @param {&rusqlite::Row} row
+ * @return {*}
+ */
+fn category_from_row(row: &rusqlite::Row) -> rusqlite::Result<Category> {
+    Ok(Category {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+    })
+}
+
+/**
+ * @description: 检查分类名, 不能为空也不能包含路径分隔符
+ * @param {&str} name 分类名
+ * @return {去除两端空白后的名字}
+ */
+fn check_name(name: &str) -> BomResult<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(PATH_SEPARATOR) {
+        return Err(BomError::InvalidCategory(name.to_string()));
+    }
+    Ok(name)
+}
+
+// DataBaseInfo 的分类接口
+impl DataBaseInfo {
+    /**
+     * @description: 新增分类, 父分类下已有同名分类时返回已有的分类
+     * @param {&str} name 分类名
+     * @param {Option<i64>} parent_id 父分类 id, None 为顶层分类
+     * @return {*}
+     */
+    pub fn add_category(&self, name: &str, parent_id: Option<i64>) -> BomResult<Category> {
+        let name = check_name(name)?;
+        if let Some(parent_id) = parent_id {
+            self.category(parent_id)?;
+        }
+        self.conn
+            .execute(insert_category_template!(), (name, parent_id))?;
+        Ok(self.conn.query_row(
+            find_category_template!(),
+            (parent_id.unwrap_or(0), name),
+            category_from_row,
+        )?)
+    }
+
+    /**
+     * @description: 按路径新增分类, 路径中不存在的上级分类一并创建, 在一个事务中完成,
+     * 路径中有非法的名字时不会留下已创建的上级分类
+     * @param {&str} path 分类路径, 例如 "半导体/MOSFET/N沟道"
+     * @return {最后一级分类}
+     */
+    pub fn add_category_path(&self, path: &str) -> BomResult<Category> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut parent: Option<Category> = None;
+        for name in path.split(PATH_SEPARATOR) {
+            let parent_id = parent.as_ref().map(|c| c.id);
+            parent = Some(self.add_category(name, parent_id)?);
+        }
+        let category = parent.ok_or_else(|| BomError::InvalidCategory(path.to_string()))?;
+        tx.commit()?;
+        Ok(category)
+    }
+
+    /**
+     * @description: 按 id 读取分类
+     * @param {i64} id 分类 id
+     * @return {*}
+     */
+    pub fn category(&self, id: i64) -> BomResult<Category> {
+        match self
+            .conn
+            .query_row(get_category_template!(), [id], category_from_row)
+        {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(BomError::NotFound(format!("category {id}")))
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /**
+     * @description: 读取所有分类, 按 id 排序
+     * @return {*}
+     */
+    pub fn categories(&self) -> BomResult<Vec<Category>> {
+        let mut stmt = self.conn.prepare(get_categories_template!())?;
+        let rows = stmt.query_map([], category_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 按路径查找分类, 例如 "半导体/MOSFET"
+     * @param {&str} path 分类路径
+     * @return {*}
+     */
+    pub fn find_category(&self, path: &str) -> BomResult<Category> {
+        let mut found: Option<Category> = None;
+        for name in path.split(PATH_SEPARATOR) {
+            let parent_id = found.as_ref().map_or(0, |c| c.id);
+            found = match self.conn.query_row(
+                find_category_template!(),
+                (parent_id, name.trim()),
+                category_from_row,
+            ) {
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    return Err(BomError::NotFound(path.to_string()))
+                }
+                res => Some(res?),
+            };
+        }
+        found.ok_or_else(|| BomError::NotFound(path.to_string()))
+    }
+
+    /**
+     * @description: 分类的完整路径, 例如 "半导体/MOSFET/N沟道"
+     * @param {i64} id 分类 id
+     * @return {*}
+     */
+    pub fn category_path(&self, id: i64) -> BomResult<String> {
+        let mut names = Vec::new();
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let category = self.category(id)?;
+            names.push(category.name);
+            next = category.parent_id;
+        }
+        names.reverse();
+        Ok(names.join(&PATH_SEPARATOR.to_string()))
+    }
+
+    /**
+     * @description: 修改分类名字
+     * @param {i64} id 分类 id
+     * @param {&str} name 新名字
+     * @return {*}
+     */
+    pub fn rename_category(&self, id: i64, name: &str) -> BomResult<()> {
+        let name = check_name(name)?;
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.execute(rename_category_template!(), (name, id))? == 0 {
+            return Err(BomError::NotFound(format!("category {id}")));
+        }
+        search::reindex_all(&tx, &self.tables)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 修改元件的分类, 并按新分类的阈值更新状态
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {Option<i64>} category_id 分类 id, None 清除分类
+     * @return {*}
+     */
+    pub fn set_element_category(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        category_id: Option<i64>,
+    ) -> BomResult<()> {
+        if let Some(id) = category_id {
+            self.category(id)?;
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.execute(
+            format!(update_category_template!(), self.tables).as_str(),
+            (category_id, model, manufacturer),
+        )? == 0
+        {
+            return Err(BomError::NotFound(model.to_string()));
+        }
+        // 新分类的阈值可能不同, 状态和分类一起修改
+        threshold::refresh_state(&tx, &self.tables, model, manufacturer)?;
+        search::index_element(&tx, &self.tables, model, manufacturer)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 读取分类及其所有子分类下的元件
+     * @param {i64} id 分类 id
+     * @return {*}
+     */
+    pub fn category_elements(&self, id: i64) -> BomResult<Vec<Element>> {
+        let sql = format!(
+            concat!(get_all_template!(), get_category_elements_template!()),
+            self.tables
+        );
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map([id], element_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bom_manage::migration;
+    use rusqlite::Connection;
+
+    fn database() -> DataBaseInfo {
+        let conn = Connection::open_in_memory().unwrap();
+        migration::migrate(&conn, "bom_data").unwrap();
+        DataBaseInfo {
+            filepath: String::new(),
+            conn,
+            tables: "bom_data".to_string(),
+        }
+    }
+
+    #[test]
+    fn builtin_types_are_categories() {
+        let db = database();
+        let names: Vec<String> = db
+            .categories()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["电阻", "二极管", "三极管", "电容", "电感", "芯片"]
+        );
+        for element_type in BUILTIN_TYPES {
+            let category = db.find_category(&element_type.to_string()).unwrap();
+            assert_eq!(Some(category.id), element_type.category_id());
+        }
+    }
+
+    #[test]
+    fn nested_categories() {
+        let db = database();
+        let semi = db.add_category("半导体", None).unwrap();
+        let mosfet = db.add_category("MOSFET", Some(semi.id)).unwrap();
+        let nmos = db.add_category("N沟道", Some(mosfet.id)).unwrap();
+        // 重复新增返回已有的分类
+        assert_eq!(db.add_category(" MOSFET ", Some(semi.id)).unwrap(), mosfet);
+        // 不同父分类下可以同名
+        let other = db.add_category("MOSFET", None).unwrap();
+        assert_ne!(other.id, mosfet.id);
+
+        assert_eq!(db.category_path(nmos.id).unwrap(), "半导体/MOSFET/N沟道");
+        assert_eq!(db.find_category("半导体/MOSFET/N沟道").unwrap(), nmos);
+        assert!(matches!(
+            db.find_category("半导体/IGBT"),
+            Err(BomError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.add_category("a/b", None),
+            Err(BomError::InvalidCategory(_))
+        ));
+        assert!(matches!(
+            db.add_category("x", Some(999)),
+            Err(BomError::NotFound(_))
+        ));
+
+        db.rename_category(mosfet.id, "MOS管").unwrap();
+        assert_eq!(db.category_path(nmos.id).unwrap(), "半导体/MOS管/N沟道");
+    }
+}
@@ -0,0 +1,171 @@
+/*
+ * @Description: 元件库的错误类型
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\error.rs
+ */
+use std::{error::Error, fmt, io};
+
+// 元件库所有接口返回的错误
+#[derive(Debug)]
+pub enum BomError {
+    // 元件不存在, 内容为查找的型号
+    NotFound(String),
+    // 库存不足
+    InsufficientStock {
+        model: String,  // 元件型号
+        requested: u32, // 需要的数量
+        available: u32, // 库存数量
+    },
+    // 不支持的元件类型字符串
+    InvalidType(String),
+    // 不支持的元件状态字符串
+    InvalidStatus(String),
+    // 型号中含有分隔型号和厂家的 '@'
+    InvalidModel(String),
+    // 分类名为空或包含路径分隔符
+    InvalidCategory(String),
+    // 位置名为空或包含路径分隔符
+    InvalidLocation(String),
+    // 最低库存高于补货点
+    InvalidThreshold {
+        min_stock: u32,     // 最低库存
+        reorder_point: u32, // 补货点
+    },
+    // sqlite 读写失败, 例如数据库被锁定
+    Database(rusqlite::Error),
+    // 数据文件不是有效的 sqlite 数据库
+    CorruptFile(String),
+    // 数据库版本高于程序支持的版本
+    UnsupportedVersion {
+        found: u32,     // 数据库中的版本
+        supported: u32, // 程序支持的最新版本
+    },
+    // 文件读写失败
+    Io(io::Error),
+    // 项目 BOM 文件格式错误
+    InvalidBom(String),
+    // 导入的库存文件格式错误
+    InvalidExport(String),
+    // 元件在读取后被其他进程修改过, 写入被拒绝
+    Conflict {
+        model: String, // 元件型号
+        expected: u32, // 读取时的版本号
+        found: u32,    // 数据库中当前的版本号
+    },
+    // 生产单已经完成或取消
+    BuildClosed {
+        id: i64,        // 生产单号
+        status: String, // 当前状态
+    },
+    // 标签内容太长/标签纸或输出格式不支持
+    InvalidLabel(String),
+    // 采购单已经收货或取消
+    OrderClosed {
+        id: i64,        // 采购单号
+        status: String, // 当前状态
+    },
+    // 盘点单已经调整库存或取消
+    AuditClosed {
+        id: i64,        // 盘点单号
+        status: String, // 当前状态
+    },
+    // 报价的货币不同, 不能比较或相加
+    MixedCurrency {
+        model: String,    // 元件型号
+        expected: String, // 已有报价的货币
+        found: String,    // 这个报价的货币
+    },
+}
+
+// 元件库接口的返回类型
+pub type BomResult<T> = std::result::Result<T, BomError>;
+
+impl fmt::Display for BomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BomError::NotFound(model) => write!(f, "element not found: {model}"),
+            BomError::InsufficientStock {
+                model,
+                requested,
+                available,
+            } => write!(
+                f,
+                "insufficient stock of {model}: requested {requested}, available {available}"
+            ),
+            BomError::InvalidType(s) => write!(f, "invalid element type: {s}"),
+            BomError::InvalidStatus(s) => write!(f, "invalid element status: {s}"),
+            BomError::InvalidModel(s) => write!(
+                f,
+                "invalid element model: {s}, '@' separates the model from the manufacturer"
+            ),
+            BomError::InvalidCategory(s) => write!(f, "invalid category name: {s}"),
+            BomError::InvalidLocation(s) => write!(f, "invalid location name: {s}"),
+            BomError::InvalidThreshold {
+                min_stock,
+                reorder_point,
+            } => write!(
+                f,
+                "minimum stock {min_stock} is above reorder point {reorder_point}"
+            ),
+            BomError::Database(err) => write!(f, "database error: {err}"),
+            BomError::CorruptFile(s) => write!(f, "corrupt data file: {s}"),
+            BomError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "database schema version {found} is newer than supported version {supported}"
+            ),
+            BomError::Io(err) => write!(f, "i/o error: {err}"),
+            BomError::InvalidBom(s) => write!(f, "invalid BOM file: {s}"),
+            BomError::InvalidExport(s) => write!(f, "invalid inventory file: {s}"),
+            BomError::Conflict {
+                model,
+                expected,
+                found,
+            } => write!(
+                f,
+                "element {model} was changed by someone else (version {expected}, now {found}), reload and try again"
+            ),
+            BomError::BuildClosed { id, status } => write!(f, "build {id} is already {status}"),
+            BomError::InvalidLabel(s) => write!(f, "invalid label: {s}"),
+            BomError::OrderClosed { id, status } => {
+                write!(f, "purchase order {id} is already {status}")
+            }
+            BomError::AuditClosed { id, status } => write!(f, "audit {id} is already {status}"),
+            BomError::MixedCurrency {
+                model,
+                expected,
+                found,
+            } => write!(
+                f,
+                "price of {model} is in '{found}' but other prices are in '{expected}', set the same currency on all supplier parts"
+            ),
+        }
+    }
+}
+
+impl Error for BomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BomError::Database(err) => Some(err),
+            BomError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for BomError {
+    fn from(err: rusqlite::Error) -> Self {
+        // 打开的文件不是数据库时 sqlite 返回 NotADatabase, 文件被截断或损坏时返回 DatabaseCorrupt
+        if matches!(
+            err.sqlite_error_code(),
+            Some(rusqlite::ErrorCode::NotADatabase | rusqlite::ErrorCode::DatabaseCorrupt)
+        ) {
+            return BomError::CorruptFile(err.to_string());
+        }
+        BomError::Database(err)
+    }
+}
+
+impl From<io::Error> for BomError {
+    fn from(err: io::Error) -> Self {
+        BomError::Io(err)
+    }
+}
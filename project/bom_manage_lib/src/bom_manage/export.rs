@@ -0,0 +1,1388 @@
+/*
+ * @Description: 整个库存的导出和导入, 包括元件/分类/位置/流水/供应商/替代料组/生产单/采购单/盘点单/附件和备注,
+ * 支持 JSON/YAML 文件和 CSV 目录, 附件文件写在旁边的目录中. 导入时可以合并或替换, 并可以只生成差异报告不写入
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\export.rs
+ */
+use super::attachment::{self, Attachment, Note};
+use super::audit::{self, Audit, AuditLine, AuditScope, AuditStatus};
+use super::backup;
+use super::build::{self, Build, BuildItem, BuildStatus};
+use super::category::PATH_SEPARATOR;
+use super::ledger::{self, MovementNote, StockMovement};
+use super::purchase::{self, OrderLine, OrderStatus, PurchaseOrder};
+use super::substitute::{self, GroupMember, PartGroup};
+use super::supplier::{self, parse_supplier_csv, write_supplier_csv, PriceBreak, SupplierPart};
+use super::{
+    check_model, element_from_row, element_key, location, search, threshold, value, BomError,
+    BomResult, DataBaseInfo, Element, ElementStatus, ElementType,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// 读取元件自己设置的阈值
+macro_rules! get_own_thresholds_template {
+    () => {
+        "SELECT min_stock, reorder_point FROM {} WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 修改元件自己设置的阈值
+macro_rules! set_own_thresholds_template {
+    () => {
+        "UPDATE {} SET min_stock = ?, reorder_point = ? WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 读取分类自己设置的阈值
+macro_rules! get_category_thresholds_template {
+    () => {
+        "SELECT min_stock, reorder_point FROM categories WHERE id = ?"
+    };
+}
+// 修改分类自己设置的阈值
+macro_rules! set_category_thresholds_template {
+    () => {
+        "UPDATE categories SET min_stock = ?, reorder_point = ? WHERE id = ?"
+    };
+}
+// 读取所有流水
+macro_rules! get_all_movements_template {
+    () => {
+        "SELECT id, timestamp, model, manufacturer, delta, reason, operator FROM stock_movements ORDER BY id"
+    };
+}
+// 流水是否已经存在, 合并导入时跳过已有的流水
+macro_rules! movement_exists_template {
+    () => {
+        "SELECT EXISTS (SELECT 1 FROM stock_movements WHERE timestamp = ? AND model = ? AND manufacturer = ? \
+         AND delta = ? AND reason = ? AND operator = ?)"
+    };
+}
+// 元件流水的合计
+macro_rules! movement_sum_template {
+    () => {
+        "SELECT COALESCE(SUM(delta), 0) FROM stock_movements WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 元件在某个位置的数量
+macro_rules! insert_stock_location_template {
+    () => {
+        "INSERT INTO stock_locations (model, manufacturer, location_id, quantity) VALUES (?, ?, ?, ?) \
+         ON CONFLICT (model, manufacturer, location_id) DO UPDATE SET quantity = quantity + excluded.quantity"
+    };
+}
+
+// 导出文件的格式版本, 结构变化时增加
+pub const EXPORT_VERSION: u32 = 2;
+
+// CSV 目录中的文件名
+const CSV_ELEMENTS: &str = "elements.csv";
+const CSV_CATEGORIES: &str = "categories.csv";
+const CSV_MOVEMENTS: &str = "movements.csv";
+const CSV_SUPPLIERS: &str = "suppliers.csv";
+const CSV_PART_GROUPS: &str = "part_groups.csv";
+const CSV_LOCATIONS: &str = "locations.csv";
+const CSV_BUILDS: &str = "builds.csv";
+const CSV_BUILD_ITEMS: &str = "build_items.csv";
+const CSV_ORDERS: &str = "purchase_orders.csv";
+const CSV_ORDER_ITEMS: &str = "purchase_order_items.csv";
+const CSV_AUDITS: &str = "audits.csv";
+const CSV_AUDIT_LINES: &str = "audit_lines.csv";
+const CSV_ATTACHMENTS: &str = "attachments.csv";
+const CSV_NOTES: &str = "notes.csv";
+// CSV 目录中存放附件文件的子目录
+const CSV_ATTACHMENT_DIR: &str = "attachments";
+
+// CSV 中位置列的分隔符, 例如 "柜子A/抽屉3=100;柜子B=20"
+const LOCATION_SEPARATOR: char = ';';
+const QUANTITY_SEPARATOR: char = '=';
+
+// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json, // 单个 JSON 文件
+    Yaml, // 单个 YAML 文件
+    Csv,  // 一个目录, 每种数据一个 CSV 文件, 可以用表格软件编辑
+}
+
+impl ExportFormat {
+    /**
+     * @description: 按扩展名判断格式, .json 为 JSON, .yaml/.yml 为 YAML, 其他为 CSV 目录
+     * @param {&str} path 文件或目录路径
+     * @return {*}
+     */
+    pub fn from_path(path: &str) -> ExportFormat {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".json") {
+            ExportFormat::Json
+        } else if lower.ends_with(".yaml") || lower.ends_with(".yml") {
+            ExportFormat::Yaml
+        } else {
+            ExportFormat::Csv
+        }
+    }
+}
+
+// 导入方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    Merge,   // 合并, 文件中的元件覆盖已有的, 文件中没有的元件保留, 只追加没有的流水/单据/备注
+    Replace, // 替换, 导入后库存和单据和文件完全一致, 单据号保持不变
+}
+
+// 元件在某个位置的数量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocationRecord {
+    pub path: String,  // 位置路径
+    pub quantity: u32, // 数量
+}
+
+// 导出的一个元件, 分类和位置用路径表示, 可以导入到其他数据库
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElementRecord {
+    pub describe: String,               // 元件描述
+    pub model: String,                  // 元件型号
+    pub manufacturer: String,           // 厂家
+    pub number: u32,                    // 元件数量
+    pub element_type: ElementType,      // 元件类型
+    pub state: ElementStatus,           // 元件状态, 导入时按阈值重新计算
+    pub category: Option<String>,       // 分类路径
+    pub min_stock: Option<u32>,         // 元件自己设置的最低库存
+    pub reorder_point: Option<u32>,     // 元件自己设置的补货点
+    pub locations: Vec<LocationRecord>, // 各个位置的数量
+}
+
+impl ElementRecord {
+    /**
+     * @description: 元件在 element_map 中的键
+     * @return {*}
+     */
+    pub fn key(&self) -> String {
+        element_key(&self.model, &self.manufacturer)
+    }
+}
+
+// 导出的一个分类
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryRecord {
+    pub path: String,               // 分类路径
+    pub min_stock: Option<u32>,     // 分类自己设置的最低库存
+    pub reorder_point: Option<u32>, // 分类自己设置的补货点
+}
+
+// 导出的一个供应商料号和它的全部报价
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupplierRecord {
+    #[serde(flatten)]
+    pub part: SupplierPart, // 料号
+    pub prices: Vec<PriceBreak>, // 报价, 按日期和起订数量排序
+}
+
+// 整个库存
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Inventory {
+    pub version: u32,                        // 格式版本
+    pub elements: Vec<ElementRecord>,        // 元件
+    pub categories: Vec<CategoryRecord>,     // 分类
+    pub movements: Vec<StockMovement>,       // 出入库流水
+    pub suppliers: Vec<SupplierRecord>,      // 供应商料号
+    pub part_groups: Vec<PartGroup>,         // 替代料组
+    pub locations: Vec<String>,              // 所有位置的路径, 包括没有元件的位置
+    pub builds: Vec<Build>,                  // 生产单
+    pub purchase_orders: Vec<PurchaseOrder>, // 采购单
+    pub audits: Vec<Audit>,                  // 盘点单
+    pub attachments: Vec<Attachment>,        // 附件, 文件内容在 attachment_files_dir 目录中
+    pub notes: Vec<Note>,                    // 备注
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory {
+            version: EXPORT_VERSION,
+            elements: Vec::new(),
+            categories: Vec::new(),
+            movements: Vec::new(),
+            suppliers: Vec::new(),
+            part_groups: Vec::new(),
+            locations: Vec::new(),
+            builds: Vec::new(),
+            purchase_orders: Vec::new(),
+            audits: Vec::new(),
+            attachments: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+}
+
+// CSV 中的一个位置
+#[derive(Debug, Serialize, Deserialize)]
+struct LocationRow {
+    path: String,
+}
+
+// CSV 中的一个生产单, 元件在 build_items.csv 中
+#[derive(Debug, Serialize, Deserialize)]
+struct BuildRow {
+    id: i64,
+    name: String,
+    boards: u32,
+    status: BuildStatus,
+    created: i64,
+    #[serde(default)]
+    finished: Option<i64>,
+}
+
+// CSV 中生产单的一个元件
+#[derive(Debug, Serialize, Deserialize)]
+struct BuildItemRow {
+    build: i64,
+    model: String,
+    #[serde(default)]
+    manufacturer: String,
+    required: u32,
+    reserved: u32,
+}
+
+// CSV 中的一个采购单, 元件在 purchase_order_items.csv 中
+#[derive(Debug, Serialize, Deserialize)]
+struct OrderRow {
+    id: i64,
+    #[serde(default)]
+    supplier: String,
+    status: OrderStatus,
+    created: i64,
+    #[serde(default)]
+    received: Option<i64>,
+}
+
+// CSV 中采购单的一个元件
+#[derive(Debug, Serialize, Deserialize)]
+struct OrderItemRow {
+    order: i64,
+    model: String,
+    #[serde(default)]
+    manufacturer: String,
+    #[serde(default)]
+    sku: String,
+    #[serde(default)]
+    mpn: String,
+    needed: u32,
+    quantity: u32,
+    #[serde(default)]
+    unit_price: Option<f64>,
+}
+
+// CSV 中的一个盘点单, 元件在 audit_lines.csv 中
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditRow {
+    id: i64,
+    scope: String,
+    #[serde(default)]
+    scope_id: Option<i64>,
+    #[serde(default)]
+    path: String,
+    status: AuditStatus,
+    created: i64,
+    #[serde(default)]
+    closed: Option<i64>,
+}
+
+// CSV 中盘点单的一个元件
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditLineRow {
+    audit: i64,
+    model: String,
+    #[serde(default)]
+    manufacturer: String,
+    expected: u32,
+    #[serde(default)]
+    counted: Option<u32>,
+}
+
+// CSV 中替代料组的一个元件, 每个元件一行
+#[derive(Debug, Serialize, Deserialize)]
+struct GroupRow {
+    group: String,
+    model: String,
+    #[serde(default)]
+    manufacturer: String,
+    #[serde(default)]
+    priority: u32,
+}
+
+// CSV 中的一个元件, 位置写成一列
+#[derive(Debug, Serialize, Deserialize)]
+struct ElementRow {
+    describe: String,
+    model: String,
+    #[serde(default)]
+    manufacturer: String,
+    number: u32,
+    element_type: ElementType,
+    #[serde(default)]
+    state: ElementStatus,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    min_stock: Option<u32>,
+    #[serde(default)]
+    reorder_point: Option<u32>,
+    #[serde(default)]
+    locations: String,
+}
+
+impl From<&ElementRecord> for ElementRow {
+    fn from(record: &ElementRecord) -> Self {
+        ElementRow {
+            describe: record.describe.clone(),
+            model: record.model.clone(),
+            manufacturer: record.manufacturer.clone(),
+            number: record.number,
+            element_type: record.element_type.clone(),
+            state: record.state.clone(),
+            category: record.category.clone(),
+            min_stock: record.min_stock,
+            reorder_point: record.reorder_point,
+            locations: record
+                .locations
+                .iter()
+                .map(|l| format!("{}{QUANTITY_SEPARATOR}{}", l.path, l.quantity))
+                .collect::<Vec<_>>()
+                .join(&LOCATION_SEPARATOR.to_string()),
+        }
+    }
+}
+
+impl TryFrom<ElementRow> for ElementRecord {
+    type Error = BomError;
+
+    fn try_from(row: ElementRow) -> BomResult<Self> {
+        let mut locations = Vec::new();
+        for item in row.locations.split(LOCATION_SEPARATOR) {
+            if item.trim().is_empty() {
+                continue;
+            }
+            let (path, quantity) = item
+                .rsplit_once(QUANTITY_SEPARATOR)
+                .and_then(|(p, q)| Some((p.trim(), q.trim().parse().ok()?)))
+                .ok_or_else(|| BomError::InvalidExport(format!("invalid location: {item}")))?;
+            locations.push(LocationRecord {
+                path: path.to_string(),
+                quantity,
+            });
+        }
+        Ok(ElementRecord {
+            describe: row.describe,
+            model: row.model,
+            manufacturer: row.manufacturer,
+            number: row.number,
+            element_type: row.element_type,
+            state: row.state,
+            category: row.category.filter(|c| !c.trim().is_empty()),
+            min_stock: row.min_stock,
+            reorder_point: row.reorder_point,
+            locations,
+        })
+    }
+}
+
+// 一个元件的变化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementChange {
+    pub key: String,               // 元件键
+    pub fields: Vec<&'static str>, // 变化的字段
+}
+
+// 导入的差异报告
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportDiff {
+    pub added: Vec<String>,          // 新增的元件
+    pub changed: Vec<ElementChange>, // 修改的元件
+    pub removed: Vec<String>,        // 删除的元件, 只有替换导入时才有
+    pub unchanged: usize,            // 没有变化的元件数量
+    pub movements: usize,            // 写入的流水条数
+    pub suppliers: usize,            // 写入的供应商料号数量
+    pub part_groups: usize,          // 写入的替代料组数量
+    pub builds: usize,               // 写入的生产单数量
+    pub purchase_orders: usize,      // 写入的采购单数量
+    pub audits: usize,               // 写入的盘点单数量
+    pub attachments: usize,          // 写入的附件数量
+    pub notes: usize,                // 写入的备注条数
+}
+
+impl ImportDiff {
+    /**
+     * @description: 元件是否没有任何变化
+     * @return {*}
+     */
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/**
+ * @description: 比较两个元件, 状态由阈值决定, 不参与比较
+ * @param {&ElementRecord} old 数据库中的元件
+ * @param {&ElementRecord} new 文件中的元件
+ * @return {变化的字段}
+ */
+fn changed_fields(old: &ElementRecord, new: &ElementRecord) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    let mut check = |name: &'static str, same: bool| {
+        if !same {
+            fields.push(name);
+        }
+    };
+    check("describe", old.describe == new.describe);
+    check("number", old.number == new.number);
+    check("element_type", old.element_type == new.element_type);
+    check("category", old.category == new.category);
+    check("min_stock", old.min_stock == new.min_stock);
+    check("reorder_point", old.reorder_point == new.reorder_point);
+    check("locations", old.locations == new.locations);
+    fields
+}
+
+/**
+ * @description: 要导入的单据或备注, 替换导入时为全部, 合并导入时跳过数据库中已有的
+ * @param {&[T]} records 文件中的记录
+ * @param {&[T]} current 数据库中的记录
+ * @param {ImportMode} mode 导入方式
+ * @param {fn} same 两条记录是否为同一条, 单据号在不同数据库中不同, 按名字和时间判断
+ * @return {*}
+ */
+fn new_records<'a, T>(
+    records: &'a [T],
+    current: &[T],
+    mode: ImportMode,
+    same: fn(&T, &T) -> bool,
+) -> Vec<&'a T> {
+    records
+        .iter()
+        .filter(|r| mode == ImportMode::Replace || !current.iter().any(|c| same(c, r)))
+        .collect()
+}
+
+fn same_build(a: &Build, b: &Build) -> bool {
+    a.name == b.name && a.created == b.created
+}
+
+fn same_order(a: &PurchaseOrder, b: &PurchaseOrder) -> bool {
+    a.supplier == b.supplier && a.created == b.created
+}
+
+fn same_audit(a: &Audit, b: &Audit) -> bool {
+    a.path == b.path && a.created == b.created
+}
+
+fn same_attachment(a: &Attachment, b: &Attachment) -> bool {
+    a.model == b.model && a.manufacturer == b.manufacturer && a.hash == b.hash
+}
+
+fn same_note(a: &Note, b: &Note) -> bool {
+    a.model == b.model
+        && a.manufacturer == b.manufacturer
+        && a.timestamp == b.timestamp
+        && a.text == b.text
+}
+
+/**
+ * @description: 导出文件的附件目录, CSV 目录中为 attachments 子目录, 单个文件时为 "文件名.attachments"
+ * @param {&str} path 导出的文件或目录路径
+ * @param {ExportFormat} format 格式
+ * @return {*}
+ */
+pub fn attachment_files_dir(path: &str, format: ExportFormat) -> PathBuf {
+    match format {
+        ExportFormat::Csv => Path::new(path).join(CSV_ATTACHMENT_DIR),
+        _ => attachment::attachment_dir(path),
+    }
+}
+
+// CSV 读写错误转为 BomError
+fn csv_error(err: csv::Error) -> BomError {
+    BomError::InvalidExport(err.to_string())
+}
+
+/**
+ * @description: 把一组数据写成 CSV 文件
+ * @param {&Path} path 文件路径
+ * @param {I} rows 数据
+ * @return {*}
+ */
+fn write_csv<T: Serialize, I: IntoIterator<Item = T>>(path: &Path, rows: I) -> BomResult<()> {
+    let mut writer = csv::Writer::from_path(path).map_err(csv_error)?;
+    for row in rows {
+        writer.serialize(row).map_err(csv_error)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/**
+ * @description: 读取 CSV 文件, 文件不存在时返回空列表, 这样可以只导入部分数据
+ * @param {&Path} path 文件路径
+ * @return {*}
+ */
+fn read_csv<T: DeserializeOwned>(path: &Path) -> BomResult<Vec<T>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+    let rows = reader.deserialize().collect::<Result<Vec<T>, _>>();
+    rows.map_err(csv_error)
+}
+
+/**
+ * @description: 把 CSV 中单据的元件行按单据号分组
+ * @param {Vec<R>} rows 元件行
+ * @param {fn} parent 元件行所属的单据号
+ * @param {fn} item 元件行转为单据中的元件
+ * @return {单据号 -> 元件}
+ */
+fn group_rows<R, T>(rows: Vec<R>, parent: fn(&R) -> i64, item: fn(R) -> T) -> HashMap<i64, Vec<T>> {
+    let mut groups: HashMap<i64, Vec<T>> = HashMap::new();
+    for row in rows {
+        groups.entry(parent(&row)).or_default().push(item(row));
+    }
+    groups
+}
+
+/**
+ * @description: 把库存写到文件, CSV 格式时 path 为目录, 不存在时创建
+ * @param {&Inventory} inventory 库存
+ * @param {&str} path 文件或目录路径
+ * @param {ExportFormat} format 格式
+ * @return {*}
+ */
+pub fn write_inventory(inventory: &Inventory, path: &str, format: ExportFormat) -> BomResult<()> {
+    match format {
+        ExportFormat::Json => {
+            let text = serde_json::to_string_pretty(inventory)
+                .map_err(|e| BomError::InvalidExport(e.to_string()))?;
+            fs::write(path, text)?;
+        }
+        ExportFormat::Yaml => {
+            let text = serde_yaml::to_string(inventory)
+                .map_err(|e| BomError::InvalidExport(e.to_string()))?;
+            fs::write(path, text)?;
+        }
+        ExportFormat::Csv => {
+            let dir = Path::new(path);
+            fs::create_dir_all(dir)?;
+            write_csv(
+                &dir.join(CSV_ELEMENTS),
+                inventory.elements.iter().map(ElementRow::from),
+            )?;
+            write_csv(&dir.join(CSV_CATEGORIES), &inventory.categories)?;
+            write_csv(&dir.join(CSV_MOVEMENTS), &inventory.movements)?;
+            let rows: Vec<(SupplierPart, Option<PriceBreak>)> =
+                inventory.suppliers.iter().flat_map(supplier_rows).collect();
+            write_supplier_csv(fs::File::create(dir.join(CSV_SUPPLIERS))?, &rows)?;
+            write_csv(
+                &dir.join(CSV_PART_GROUPS),
+                inventory.part_groups.iter().flat_map(|g| {
+                    g.members.iter().map(|m| GroupRow {
+                        group: g.name.clone(),
+                        model: m.model.clone(),
+                        manufacturer: m.manufacturer.clone(),
+                        priority: m.priority,
+                    })
+                }),
+            )?;
+            write_csv(
+                &dir.join(CSV_LOCATIONS),
+                inventory
+                    .locations
+                    .iter()
+                    .map(|path| LocationRow { path: path.clone() }),
+            )?;
+            write_build_csv(dir, &inventory.builds)?;
+            write_order_csv(dir, &inventory.purchase_orders)?;
+            write_audit_csv(dir, &inventory.audits)?;
+            write_csv(&dir.join(CSV_ATTACHMENTS), &inventory.attachments)?;
+            write_csv(&dir.join(CSV_NOTES), &inventory.notes)?;
+        }
+    }
+    Ok(())
+}
+
+// 生产单写成 builds.csv 和 build_items.csv
+fn write_build_csv(dir: &Path, builds: &[Build]) -> BomResult<()> {
+    write_csv(
+        &dir.join(CSV_BUILDS),
+        builds.iter().map(|b| BuildRow {
+            id: b.id,
+            name: b.name.clone(),
+            boards: b.boards,
+            status: b.status,
+            created: b.created,
+            finished: b.finished,
+        }),
+    )?;
+    write_csv(
+        &dir.join(CSV_BUILD_ITEMS),
+        builds.iter().flat_map(|b| {
+            b.items.iter().map(|i| BuildItemRow {
+                build: b.id,
+                model: i.model.clone(),
+                manufacturer: i.manufacturer.clone(),
+                required: i.required,
+                reserved: i.reserved,
+            })
+        }),
+    )
+}
+
+// 读取 builds.csv 和 build_items.csv
+fn read_build_csv(dir: &Path) -> BomResult<Vec<Build>> {
+    let mut items = group_rows(
+        read_csv(&dir.join(CSV_BUILD_ITEMS))?,
+        |row: &BuildItemRow| row.build,
+        |row| BuildItem {
+            model: row.model,
+            manufacturer: row.manufacturer,
+            required: row.required,
+            reserved: row.reserved,
+        },
+    );
+    Ok(read_csv::<BuildRow>(&dir.join(CSV_BUILDS))?
+        .into_iter()
+        .map(|row| Build {
+            items: items.remove(&row.id).unwrap_or_default(),
+            id: row.id,
+            name: row.name,
+            boards: row.boards,
+            status: row.status,
+            created: row.created,
+            finished: row.finished,
+        })
+        .collect())
+}
+
+// 采购单写成 purchase_orders.csv 和 purchase_order_items.csv
+fn write_order_csv(dir: &Path, orders: &[PurchaseOrder]) -> BomResult<()> {
+    write_csv(
+        &dir.join(CSV_ORDERS),
+        orders.iter().map(|o| OrderRow {
+            id: o.id,
+            supplier: o.supplier.clone(),
+            status: o.status,
+            created: o.created,
+            received: o.received,
+        }),
+    )?;
+    write_csv(
+        &dir.join(CSV_ORDER_ITEMS),
+        orders.iter().flat_map(|o| {
+            o.lines.iter().map(|l| OrderItemRow {
+                order: o.id,
+                model: l.model.clone(),
+                manufacturer: l.manufacturer.clone(),
+                sku: l.sku.clone(),
+                mpn: l.mpn.clone(),
+                needed: l.needed,
+                quantity: l.quantity,
+                unit_price: l.unit_price,
+            })
+        }),
+    )
+}
+
+// 读取 purchase_orders.csv 和 purchase_order_items.csv
+fn read_order_csv(dir: &Path) -> BomResult<Vec<PurchaseOrder>> {
+    let mut lines = group_rows(
+        read_csv(&dir.join(CSV_ORDER_ITEMS))?,
+        |row: &OrderItemRow| row.order,
+        |row| OrderLine {
+            model: row.model,
+            manufacturer: row.manufacturer,
+            sku: row.sku,
+            mpn: row.mpn,
+            needed: row.needed,
+            quantity: row.quantity,
+            unit_price: row.unit_price,
+        },
+    );
+    Ok(read_csv::<OrderRow>(&dir.join(CSV_ORDERS))?
+        .into_iter()
+        .map(|row| PurchaseOrder {
+            lines: lines.remove(&row.id).unwrap_or_default(),
+            id: row.id,
+            supplier: row.supplier,
+            status: row.status,
+            created: row.created,
+            received: row.received,
+        })
+        .collect())
+}
+
+// 盘点单写成 audits.csv 和 audit_lines.csv
+fn write_audit_csv(dir: &Path, audits: &[Audit]) -> BomResult<()> {
+    write_csv(
+        &dir.join(CSV_AUDITS),
+        audits.iter().map(|a| {
+            let (scope, scope_id) = a.scope.to_columns();
+            AuditRow {
+                id: a.id,
+                scope: scope.to_string(),
+                scope_id,
+                path: a.path.clone(),
+                status: a.status,
+                created: a.created,
+                closed: a.closed,
+            }
+        }),
+    )?;
+    write_csv(
+        &dir.join(CSV_AUDIT_LINES),
+        audits.iter().flat_map(|a| {
+            a.lines.iter().map(|l| AuditLineRow {
+                audit: a.id,
+                model: l.model.clone(),
+                manufacturer: l.manufacturer.clone(),
+                expected: l.expected,
+                counted: l.counted,
+            })
+        }),
+    )
+}
+
+// 读取 audits.csv 和 audit_lines.csv
+fn read_audit_csv(dir: &Path) -> BomResult<Vec<Audit>> {
+    let mut lines = group_rows(
+        read_csv(&dir.join(CSV_AUDIT_LINES))?,
+        |row: &AuditLineRow| row.audit,
+        |row| AuditLine {
+            model: row.model,
+            manufacturer: row.manufacturer,
+            expected: row.expected,
+            counted: row.counted,
+        },
+    );
+    read_csv::<AuditRow>(&dir.join(CSV_AUDITS))?
+        .into_iter()
+        .map(|row| {
+            Ok(Audit {
+                lines: lines.remove(&row.id).unwrap_or_default(),
+                id: row.id,
+                // 位置和分类 id 导入时按路径重新查找, 这里只需要类型
+                scope: AuditScope::from_columns(&row.scope, Some(row.scope_id.unwrap_or(0)))
+                    .map_err(|_| {
+                        BomError::InvalidExport(format!("invalid audit scope: {}", row.scope))
+                    })?,
+                path: row.path,
+                status: row.status,
+                created: row.created,
+                closed: row.closed,
+            })
+        })
+        .collect()
+}
+
+/**
+ * @description: 从文件读取库存
+ * @param {&str} path 文件或目录路径
+ * @param {ExportFormat} format 格式
+ * @return {*}
+ */
+pub fn read_inventory(path: &str, format: ExportFormat) -> BomResult<Inventory> {
+    let inventory: Inventory = match format {
+        ExportFormat::Json => serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| BomError::InvalidExport(e.to_string()))?,
+        ExportFormat::Yaml => serde_yaml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| BomError::InvalidExport(e.to_string()))?,
+        ExportFormat::Csv => {
+            let dir = Path::new(path);
+            if !dir.is_dir() {
+                return Err(BomError::InvalidExport(format!("not a directory: {path}")));
+            }
+            let elements = read_csv::<ElementRow>(&dir.join(CSV_ELEMENTS))?
+                .into_iter()
+                .map(ElementRecord::try_from)
+                .collect::<BomResult<Vec<_>>>()?;
+            let suppliers_path = dir.join(CSV_SUPPLIERS);
+            let suppliers = if suppliers_path.exists() {
+                supplier_records(parse_supplier_csv(fs::File::open(suppliers_path)?)?)
+            } else {
+                Vec::new()
+            };
+            Inventory {
+                version: EXPORT_VERSION,
+                elements,
+                categories: read_csv(&dir.join(CSV_CATEGORIES))?,
+                movements: read_csv(&dir.join(CSV_MOVEMENTS))?,
+                suppliers,
+                part_groups: part_groups(read_csv(&dir.join(CSV_PART_GROUPS))?),
+                locations: read_csv::<LocationRow>(&dir.join(CSV_LOCATIONS))?
+                    .into_iter()
+                    .map(|row| row.path)
+                    .collect(),
+                builds: read_build_csv(dir)?,
+                purchase_orders: read_order_csv(dir)?,
+                audits: read_audit_csv(dir)?,
+                attachments: read_csv(&dir.join(CSV_ATTACHMENTS))?,
+                notes: read_csv(&dir.join(CSV_NOTES))?,
+            }
+        }
+    };
+    if inventory.version > EXPORT_VERSION {
+        return Err(BomError::InvalidExport(format!(
+            "version {} is newer than supported version {EXPORT_VERSION}",
+            inventory.version
+        )));
+    }
+    Ok(inventory)
+}
+
+// 料号和报价展开为 (料号, 价格) 列表, 没有报价时为一行空价格
+fn supplier_rows(record: &SupplierRecord) -> Vec<(SupplierPart, Option<PriceBreak>)> {
+    if record.prices.is_empty() {
+        return vec![(record.part.clone(), None)];
+    }
+    record
+        .prices
+        .iter()
+        .map(|p| (record.part.clone(), Some(*p)))
+        .collect()
+}
+
+// (料号, 价格) 列表按供应商 + 料号合并为 SupplierRecord, 保持原来的顺序
+fn supplier_records(rows: Vec<(SupplierPart, Option<PriceBreak>)>) -> Vec<SupplierRecord> {
+    let mut records: Vec<SupplierRecord> = Vec::new();
+    for (part, price) in rows {
+        let index = match records
+            .iter()
+            .position(|r| r.part.supplier == part.supplier && r.part.sku == part.sku)
+        {
+            Some(index) => index,
+            None => {
+                records.push(SupplierRecord {
+                    part,
+                    prices: Vec::new(),
+                });
+                records.len() - 1
+            }
+        };
+        records[index].prices.extend(price);
+    }
+    records
+}
+
+// CSV 中的组员按组名合并为 PartGroup, 保持原来的顺序
+fn part_groups(rows: Vec<GroupRow>) -> Vec<PartGroup> {
+    let mut groups: Vec<PartGroup> = Vec::new();
+    for row in rows {
+        let member = GroupMember {
+            model: row.model,
+            manufacturer: row.manufacturer,
+            priority: row.priority,
+        };
+        match groups.iter_mut().find(|g| g.name == row.group) {
+            Some(group) => group.members.push(member),
+            None => groups.push(PartGroup {
+                name: row.group,
+                members: vec![member],
+            }),
+        }
+    }
+    groups
+}
+
+// DataBaseInfo 的导出导入接口
+impl DataBaseInfo {
+    /**
+     * @description: 读取整个库存, 元件按型号和厂家排序
+     * @return {*}
+     */
+    pub fn export_inventory(&self) -> BomResult<Inventory> {
+        let sql = format!(get_all_template!(), self.tables) + " ORDER BY model, manufacturer";
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let elements = stmt
+            .query_map([], element_from_row)?
+            .collect::<rusqlite::Result<Vec<Element>>>()?;
+        let mut records = Vec::new();
+        for element in elements {
+            let (min_stock, reorder_point) = self.conn.query_row(
+                format!(get_own_thresholds_template!(), self.tables).as_str(),
+                [&element.model, &element.manufacturer],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let category = match element.category {
+                Some(id) => Some(self.category_path(id)?),
+                None => None,
+            };
+            let locations = self
+                .element_locations(&element.model, &element.manufacturer)?
+                .into_iter()
+                .map(|l| LocationRecord {
+                    path: l.path,
+                    quantity: l.quantity,
+                })
+                .collect();
+            records.push(ElementRecord {
+                describe: element.describe,
+                model: element.model,
+                manufacturer: element.manufacturer,
+                number: element.number,
+                element_type: element.element_type,
+                state: element.state,
+                category,
+                min_stock,
+                reorder_point,
+                locations,
+            });
+        }
+
+        let mut categories = Vec::new();
+        for category in self.categories()? {
+            let (min_stock, reorder_point) =
+                self.conn
+                    .query_row(get_category_thresholds_template!(), [category.id], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?;
+            categories.push(CategoryRecord {
+                path: self.category_path(category.id)?,
+                min_stock,
+                reorder_point,
+            });
+        }
+
+        let mut stmt = self.conn.prepare(get_all_movements_template!())?;
+        let movements = stmt
+            .query_map([], |row| {
+                Ok(StockMovement {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    model: row.get(2)?,
+                    manufacturer: row.get(3)?,
+                    delta: row.get(4)?,
+                    reason: row.get(5)?,
+                    operator: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Inventory {
+            version: EXPORT_VERSION,
+            elements: records,
+            categories,
+            movements,
+            suppliers: supplier_records(self.supplier_rows()?),
+            part_groups: self.part_groups()?,
+            locations: self
+                .locations()?
+                .iter()
+                .map(|l| self.location_path(l.id))
+                .collect::<BomResult<Vec<_>>>()?,
+            builds: self.builds()?,
+            purchase_orders: self.purchase_orders()?,
+            audits: self.audits()?,
+            attachments: self.all_attachments()?,
+            notes: self.all_notes()?,
+        })
+    }
+
+    /**
+     * @description: 比较文件和数据库, 生成导入的差异报告, 不写入数据库
+     * @param {&Inventory} inventory 要导入的库存
+     * @param {ImportMode} mode 导入方式
+     * @return {*}
+     */
+    pub fn diff_inventory(&self, inventory: &Inventory, mode: ImportMode) -> BomResult<ImportDiff> {
+        let current = self.export_inventory()?;
+        self.diff_with(&current, inventory, mode)
+    }
+
+    // 和已经读取的数据库内容比较
+    fn diff_with(
+        &self,
+        current: &Inventory,
+        inventory: &Inventory,
+        mode: ImportMode,
+    ) -> BomResult<ImportDiff> {
+        let mut diff = ImportDiff::default();
+        for record in inventory.elements.iter() {
+            match current.elements.iter().find(|e| e.key() == record.key()) {
+                Some(old) => {
+                    let fields = changed_fields(old, record);
+                    if fields.is_empty() {
+                        diff.unchanged += 1;
+                    } else {
+                        diff.changed.push(ElementChange {
+                            key: record.key(),
+                            fields,
+                        });
+                    }
+                }
+                None => diff.added.push(record.key()),
+            }
+        }
+        if mode == ImportMode::Replace {
+            diff.removed = current
+                .elements
+                .iter()
+                .filter(|e| !inventory.elements.iter().any(|r| r.key() == e.key()))
+                .map(ElementRecord::key)
+                .collect();
+        }
+        diff.movements = match mode {
+            ImportMode::Replace => inventory.movements.len(),
+            ImportMode::Merge => {
+                let mut count = 0;
+                for movement in inventory.movements.iter() {
+                    if !self.movement_exists(movement)? {
+                        count += 1;
+                    }
+                }
+                count
+            }
+        };
+        diff.suppliers = inventory.suppliers.len();
+        diff.part_groups = inventory.part_groups.len();
+        diff.builds = new_records(&inventory.builds, &current.builds, mode, same_build).len();
+        diff.purchase_orders = new_records(
+            &inventory.purchase_orders,
+            &current.purchase_orders,
+            mode,
+            same_order,
+        )
+        .len();
+        diff.audits = new_records(&inventory.audits, &current.audits, mode, same_audit).len();
+        diff.attachments = new_records(
+            &inventory.attachments,
+            &current.attachments,
+            mode,
+            same_attachment,
+        )
+        .len();
+        diff.notes = new_records(&inventory.notes, &current.notes, mode, same_note).len();
+        Ok(diff)
+    }
+
+    // 数据库中是否已有相同的流水
+    fn movement_exists(&self, movement: &StockMovement) -> BomResult<bool> {
+        Ok(self.conn.query_row(
+            movement_exists_template!(),
+            (
+                movement.timestamp,
+                &movement.model,
+                &movement.manufacturer,
+                movement.delta,
+                &movement.reason,
+                &movement.operator,
+            ),
+            |row| row.get(0),
+        )?)
+    }
+
+    /**
+     * @description: 按路径找到分类, 不存在时创建
+     * @param {&str} path 分类路径
+     * @return {分类 id}
+     */
+    fn ensure_category(&self, path: &str) -> BomResult<i64> {
+        let mut parent_id = None;
+        for name in path.split(PATH_SEPARATOR) {
+            parent_id = Some(self.add_category(name, parent_id)?.id);
+        }
+        parent_id.ok_or_else(|| BomError::InvalidCategory(path.to_string()))
+    }
+
+    /**
+     * @description: 按路径找到位置, 不存在时创建
+     * @param {&str} path 位置路径
+     * @return {位置 id}
+     */
+    fn ensure_location(&self, path: &str) -> BomResult<i64> {
+        let mut parent_id = None;
+        for name in path.split(PATH_SEPARATOR) {
+            parent_id = Some(self.add_location(name, parent_id)?.id);
+        }
+        parent_id.ok_or_else(|| BomError::InvalidLocation(path.to_string()))
+    }
+
+    /**
+     * @description: 导入库存, 在一个事务中完成, 失败时数据库保持导入前的内容.
+     * 元件数量和流水合计不一致时 (例如在表格中改了数量) 补记一条差额流水, 原因为 "import".
+     * 附件文件在写锁内复制, gc_attachments 不会删除还没写入记录的文件
+     * @param {&Inventory} inventory 要导入的库存
+     * @param {ImportMode} mode 导入方式
+     * @param {&Path} files 导出的附件目录, 见 attachment_files_dir
+     * @param {&MovementNote} note 差额流水的操作人
+     * @return {差异报告}
+     */
+    pub fn import_inventory(
+        &self,
+        inventory: &Inventory,
+        mode: ImportMode,
+        files: &Path,
+        note: &MovementNote,
+    ) -> BomResult<ImportDiff> {
+        let current = self.export_inventory()?;
+        let diff = self.diff_with(&current, inventory, mode)?;
+        self.backup(backup::BACKUP_IMPORT)?;
+        let tx = self.immediate_transaction()?;
+        self.import_attachment_files(&inventory.attachments, files)?;
+        if mode == ImportMode::Replace {
+            for table in [
+                self.tables.as_str(),
+                "stock_locations",
+                "stock_movements",
+                "price_breaks",
+                "supplier_parts",
+                "suppliers",
+                "part_group_members",
+                "part_groups",
+                "build_items",
+                "builds",
+                "purchase_order_items",
+                "purchase_orders",
+                "audit_lines",
+                "audits",
+                "attachments",
+                "element_notes",
+            ] {
+                tx.execute(format!(delete_all_template!(), table).as_str(), [])?;
+            }
+        }
+
+        for path in inventory.locations.iter() {
+            self.ensure_location(path)?;
+        }
+        for category in inventory.categories.iter() {
+            let id = self.ensure_category(&category.path)?;
+            tx.execute(
+                set_category_thresholds_template!(),
+                (category.min_stock, category.reorder_point, id),
+            )?;
+        }
+
+        for record in inventory.elements.iter() {
+            check_model(&record.model)?;
+            let category = match &record.category {
+                Some(path) => Some(self.ensure_category(path)?),
+                None => record.element_type.category_id(),
+            };
+            let element = Element {
+                describe: record.describe.clone(),
+                model: record.model.clone(),
+                manufacturer: record.manufacturer.clone(),
+                number: record.number,
+                element_type: record.element_type.clone(),
+                state: record.state.clone(),
+                category,
+                ..Default::default()
+            };
+            tx.execute(
+                format!(upsert_element_template!(), self.tables).as_str(),
+                (
+                    &element.describe,
+                    &element.model,
+                    &element.manufacturer,
+                    &element.number,
+                    &element.element_type.to_string(),
+                    &element.state.to_string(),
+                    &element.category_id(),
+                ),
+            )?;
+            tx.execute(
+                format!(set_own_thresholds_template!(), self.tables).as_str(),
+                (
+                    record.min_stock,
+                    record.reorder_point,
+                    &record.model,
+                    &record.manufacturer,
+                ),
+            )?;
+            value::write_params(&tx, &self.tables, &element)?;
+            location::clear_locations(&tx, &record.model, &record.manufacturer)?;
+            for item in record.locations.iter().filter(|l| l.quantity > 0) {
+                let location_id = self.ensure_location(&item.path)?;
+                tx.execute(
+                    insert_stock_location_template!(),
+                    (
+                        &record.model,
+                        &record.manufacturer,
+                        location_id,
+                        item.quantity,
+                    ),
+                )?;
+            }
+            location::trim_locations(&tx, &record.model, &record.manufacturer, record.number)?;
+        }
+
+        for movement in inventory.movements.iter() {
+            if mode == ImportMode::Replace || !self.movement_exists(movement)? {
+                ledger::insert_movement(&tx, movement)?;
+            }
+        }
+        for record in inventory.elements.iter() {
+            let sum: i64 = tx.query_row(
+                movement_sum_template!(),
+                [&record.model, &record.manufacturer],
+                |row| row.get(0),
+            )?;
+            let delta = i64::from(record.number) - sum;
+            if delta != 0 {
+                ledger::insert_movement(
+                    &tx,
+                    &note.movement(&record.model, &record.manufacturer, delta),
+                )?;
+            }
+        }
+
+        let rows: Vec<(SupplierPart, Option<PriceBreak>)> =
+            inventory.suppliers.iter().flat_map(supplier_rows).collect();
+        supplier::write_supplier_rows(&tx, &self.tables, &rows)?;
+        for group in inventory.part_groups.iter() {
+            for member in group.members.iter() {
+                substitute::write_group_member(&tx, &group.name, member)?;
+            }
+        }
+        // 替换导入时保留单据号, 合并导入时分配新的
+        let id = |id: i64| match mode {
+            ImportMode::Replace => Some(id),
+            ImportMode::Merge => None,
+        };
+        for record in new_records(&inventory.builds, &current.builds, mode, same_build) {
+            build::write_build(&tx, record, id(record.id))?;
+        }
+        for record in new_records(
+            &inventory.purchase_orders,
+            &current.purchase_orders,
+            mode,
+            same_order,
+        ) {
+            purchase::write_purchase_order(&tx, record, id(record.id))?;
+        }
+        for record in new_records(&inventory.audits, &current.audits, mode, same_audit) {
+            let scope = match record.scope {
+                AuditScope::All => AuditScope::All,
+                AuditScope::Location(_) => {
+                    AuditScope::Location(self.ensure_location(&record.path)?)
+                }
+                AuditScope::Category(_) => {
+                    AuditScope::Category(self.ensure_category(&record.path)?)
+                }
+            };
+            let record = Audit {
+                scope,
+                ..record.clone()
+            };
+            audit::write_audit(&tx, &record, id(record.id))?;
+        }
+        for record in new_records(
+            &inventory.attachments,
+            &current.attachments,
+            mode,
+            same_attachment,
+        ) {
+            attachment::write_attachment(&tx, record, id(record.id))?;
+        }
+        for record in new_records(&inventory.notes, &current.notes, mode, same_note) {
+            attachment::write_note(&tx, record, id(record.id))?;
+        }
+        threshold::refresh_states(&tx, &self.tables)?;
+        search::reindex_all(&tx, &self.tables)?;
+        tx.commit()?;
+        Ok(diff)
+    }
+
+    /**
+     * @description: 把附件文件复制到导出目录, 目录结构和附件目录相同
+     * @param {&[Attachment]} attachments 导出的附件
+     * @param {&Path} dir 导出的附件目录, 见 attachment_files_dir
+     * @return {复制的文件个数}
+     */
+    pub fn export_attachment_files(
+        &self,
+        attachments: &[Attachment],
+        dir: &Path,
+    ) -> BomResult<usize> {
+        let mut copied = 0;
+        let hashes: HashSet<&str> = attachments.iter().map(|a| a.hash.as_str()).collect();
+        for hash in hashes {
+            let source = attachment::stored_path(&self.attachment_dir(), hash);
+            if source.is_file() {
+                attachment::store_file(dir, &source)?;
+                copied += 1;
+            }
+        }
+        Ok(copied)
+    }
+
+    /**
+     * @description: 把导出目录中的附件文件复制到附件目录, 数据库中已有的文件不再复制,
+     * 文件内容和记录的 hash 不一致时返回 InvalidExport, 在 import_inventory 的事务中调用
+     * @param {&[Attachment]} attachments 要导入的附件
+     * @param {&Path} dir 导出的附件目录, 见 attachment_files_dir
+     * @return {复制的文件个数}
+     */
+    fn import_attachment_files(&self, attachments: &[Attachment], dir: &Path) -> BomResult<usize> {
+        let mut copied = 0;
+        let hashes: HashSet<&str> = attachments.iter().map(|a| a.hash.as_str()).collect();
+        for hash in hashes {
+            let source = attachment::stored_path(dir, hash);
+            if !source.is_file() || attachment::stored_path(&self.attachment_dir(), hash).is_file()
+            {
+                continue;
+            }
+            if attachment::store_file(&self.attachment_dir(), &source)?.0 != hash {
+                return Err(BomError::InvalidExport(format!(
+                    "attachment {} does not match its hash",
+                    source.to_string_lossy()
+                )));
+            }
+            copied += 1;
+        }
+        Ok(copied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_keep_locations() {
+        let record = ElementRecord {
+            describe: "电阻 10k".to_string(),
+            model: "R10K".to_string(),
+            number: 120,
+            element_type: ElementType::Resistor,
+            category: Some("电阻".to_string()),
+            locations: vec![
+                LocationRecord {
+                    path: "柜子A/抽屉3".to_string(),
+                    quantity: 100,
+                },
+                LocationRecord {
+                    path: "柜子B".to_string(),
+                    quantity: 20,
+                },
+            ],
+            ..Default::default()
+        };
+        let row = ElementRow::from(&record);
+        assert_eq!(row.locations, "柜子A/抽屉3=100;柜子B=20");
+        assert_eq!(ElementRecord::try_from(row).unwrap(), record);
+
+        let bad = ElementRow {
+            locations: "柜子A".to_string(),
+            ..ElementRow::from(&record)
+        };
+        assert!(matches!(
+            ElementRecord::try_from(bad),
+            Err(BomError::InvalidExport(_))
+        ));
+        assert_eq!(ExportFormat::from_path("backup.YML"), ExportFormat::Yaml);
+        assert_eq!(ExportFormat::from_path("backup"), ExportFormat::Csv);
+    }
+}
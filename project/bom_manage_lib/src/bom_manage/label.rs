@@ -0,0 +1,1027 @@
+/*
+ * @Description: 元件和存放位置的标签, 每张标签有一个二维码和几行文字, 输出单张 SVG/PNG 或整页 PDF 标签纸;
+ * 扫码枪扫描标签输入的字符串可以解析回元件或位置
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\label.rs
+ */
+use super::location::Location;
+use super::qr::QrCode;
+use super::{
+    element_from_row, split_key, BomError, BomResult, DataBaseInfo, Element, ElementQuery,
+};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// 读取元件在数据库中的 id, 写在二维码中
+macro_rules! get_element_id_template {
+    () => {
+        "SELECT id FROM {} WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 按 id 读取元件
+macro_rules! get_element_by_id_template {
+    () => {
+        concat!(get_all_template!(), " WHERE id = ?")
+    };
+}
+
+// 标签内容的前缀, 输入以它开头时按标签解析
+pub const LABEL_PREFIX: &str = "BOM:";
+// PNG 标签的分辨率, 常见标签打印机为 300 dpi
+pub const LABEL_DPI: f64 = 300.0;
+
+// 二维码四周的空白模块数
+const QUIET_ZONE: usize = 2;
+// 文字最大字号, mm
+const MAX_FONT_SIZE: f64 = 3.0;
+// 1 mm 等于多少 PDF 点
+const PT_PER_MM: f64 = 72.0 / 25.4;
+
+// 标签纸规格, 尺寸单位 mm
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelStock {
+    pub name: &'static str, // 名字, 例如 L7160
+    pub page_width: f64,    // 纸张宽度
+    pub page_height: f64,   // 纸张高度
+    pub label_width: f64,   // 标签宽度
+    pub label_height: f64,  // 标签高度
+    pub columns: u32,       // 每页列数
+    pub rows: u32,          // 每页行数
+    pub left: f64,          // 第一列标签到纸张左边的距离
+    pub top: f64,           // 第一行标签到纸张上边的距离
+    pub gap_x: f64,         // 标签左右间距
+    pub gap_y: f64,         // 标签上下间距
+}
+
+// 支持的标签纸, 第一个为默认
+pub const LABEL_STOCKS: [LabelStock; 5] = [
+    // 标签打印机 62x29mm 卷纸, 每页一张
+    LabelStock {
+        name: "62x29",
+        page_width: 62.0,
+        page_height: 29.0,
+        label_width: 62.0,
+        label_height: 29.0,
+        columns: 1,
+        rows: 1,
+        left: 0.0,
+        top: 0.0,
+        gap_x: 0.0,
+        gap_y: 0.0,
+    },
+    // 热敏 50x25mm 卷纸, 每页一张
+    LabelStock {
+        name: "50x25",
+        page_width: 50.0,
+        page_height: 25.0,
+        label_width: 50.0,
+        label_height: 25.0,
+        columns: 1,
+        rows: 1,
+        left: 0.0,
+        top: 0.0,
+        gap_x: 0.0,
+        gap_y: 0.0,
+    },
+    // A4 3x7, 63.5x38.1mm
+    LabelStock {
+        name: "L7160",
+        page_width: 210.0,
+        page_height: 297.0,
+        label_width: 63.5,
+        label_height: 38.1,
+        columns: 3,
+        rows: 7,
+        left: 7.2,
+        top: 15.1,
+        gap_x: 2.5,
+        gap_y: 0.0,
+    },
+    // A4 5x13, 38.1x21.2mm, 适合小抽屉
+    LabelStock {
+        name: "L7651",
+        page_width: 210.0,
+        page_height: 297.0,
+        label_width: 38.1,
+        label_height: 21.2,
+        columns: 5,
+        rows: 13,
+        left: 4.7,
+        top: 10.7,
+        gap_x: 2.5,
+        gap_y: 0.0,
+    },
+    // Letter 3x10, 2.625x1 英寸
+    LabelStock {
+        name: "5160",
+        page_width: 215.9,
+        page_height: 279.4,
+        label_width: 66.675,
+        label_height: 25.4,
+        columns: 3,
+        rows: 10,
+        left: 4.7625,
+        top: 12.7,
+        gap_x: 3.175,
+        gap_y: 0.0,
+    },
+];
+
+impl LabelStock {
+    /**
+     * @description: 按名字查找标签纸, 不区分大小写
+     * @param {&str} name 名字, 例如 L7160
+     * @return {*}
+     */
+    pub fn find(name: &str) -> BomResult<&'static LabelStock> {
+        LABEL_STOCKS
+            .iter()
+            .find(|stock| stock.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| BomError::InvalidLabel(format!("unknown label stock {name}")))
+    }
+}
+
+impl Default for LabelStock {
+    fn default() -> Self {
+        LABEL_STOCKS[0]
+    }
+}
+
+// 二维码中编码的对象, 文本形式为 "BOM:E:<id>:<型号@厂家>" 或 "BOM:L:<id>:<位置路径>"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelCode {
+    Element { id: i64, key: String },   // 元件, 数据库中的 id 和键
+    Location { id: i64, path: String }, // 存放位置, id 和路径
+}
+
+impl LabelCode {
+    /**
+     * @description: 解析扫描到的标签内容, 不是标签时返回 None
+     * @param {&str} text 扫码枪输入的字符串
+     * @return {*}
+     */
+    pub fn parse(text: &str) -> Option<LabelCode> {
+        let mut parts = text.trim().strip_prefix(LABEL_PREFIX)?.splitn(3, ':');
+        let kind = parts.next()?;
+        let id = parts.next()?.parse().ok()?;
+        let name = parts.next()?.to_string();
+        match kind {
+            "E" => Some(LabelCode::Element { id, key: name }),
+            "L" => Some(LabelCode::Location { id, path: name }),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * @description: 按 id 找到的对象是否就是标签上的那个. 行号会被重用, 导入/重写后也会重新编号,
+ * 所以只接受名字中第一个乱码字符之前的部分和对象一致的结果; 名字没有乱码或开头就是乱码时不接受
+ * @param {&str} scanned 扫描到的名字, 乱码字符为 '?' 或 U+FFFD
+ * @param {&str} name 按 id 找到的对象的名字
+ * @return {*}
+ */
+fn matches_garbled(scanned: &str, name: &str) -> bool {
+    match scanned.find(['?', '\u{FFFD}']) {
+        Some(0) | None => false,
+        Some(end) => name.starts_with(&scanned[..end]),
+    }
+}
+
+impl fmt::Display for LabelCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelCode::Element { id, key } => write!(f, "{LABEL_PREFIX}E:{id}:{key}"),
+            LabelCode::Location { id, path } => write!(f, "{LABEL_PREFIX}L:{id}:{path}"),
+        }
+    }
+}
+
+// 一张标签
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub code: LabelCode,    // 二维码内容
+    pub title: String,      // 标题, 元件的键或位置路径
+    pub lines: Vec<String>, // 其他文字, 例如 数值/封装, 存放位置
+}
+
+// 扫描标签得到的对象
+#[derive(Debug, Clone)]
+pub enum Scanned {
+    Element(Element), // 元件
+    Location {
+        location: Location, // 位置
+        path: String,       // 完整路径
+    },
+}
+
+// 标签文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelFormat {
+    Svg, // 每张标签一个 SVG 文件
+    Png, // 每张标签一个 PNG 文件, 文字只支持 ASCII
+    Pdf, // 按标签纸排版的 PDF
+}
+
+impl LabelFormat {
+    /**
+     * @description: 按扩展名判断格式
+     * @param {&str} path 文件路径
+     * @return {不支持的扩展名返回 BomError::InvalidLabel}
+     */
+    pub fn from_path(path: &str) -> BomResult<LabelFormat> {
+        let ext = Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "svg" => Ok(LabelFormat::Svg),
+            "png" => Ok(LabelFormat::Png),
+            "pdf" => Ok(LabelFormat::Pdf),
+            _ => Err(BomError::InvalidLabel(format!(
+                "{path}: use a .svg, .png or .pdf file"
+            ))),
+        }
+    }
+}
+
+// 排版后的一行文字, 坐标相对标签左上角, 单位 mm, y 为基线
+struct TextLine {
+    x: f64,
+    y: f64,
+    size: f64,
+    bold: bool,
+    text: String,
+}
+
+// 排版后的标签
+struct Layout {
+    qr: QrCode,
+    qr_x: f64,   // 二维码左上角
+    qr_y: f64,   // 二维码左上角
+    module: f64, // 模块边长
+    lines: Vec<TextLine>,
+}
+
+/**
+ * @description: 二维码靠左, 文字在右边从上到下排列, 放不下的文字截断
+ * @param {&Label} label 标签
+ * @param {f64} width 标签宽度, mm
+ * @param {f64} height 标签高度, mm
+ * @return {*}
+ */
+fn layout(label: &Label, width: f64, height: f64) -> BomResult<Layout> {
+    let qr = QrCode::encode(label.code.to_string().as_bytes())?;
+    let pad = (height * 0.06).max(0.8);
+    let side = (height - 2.0 * pad).min(width * 0.45);
+    let module = side / (qr.size() + 2 * QUIET_ZONE) as f64;
+    let text_x = pad + side;
+    let text_width = width - text_x - pad;
+
+    let texts: Vec<(&str, bool)> = std::iter::once((label.title.as_str(), true))
+        .chain(
+            label
+                .lines
+                .iter()
+                .filter(|l| !l.is_empty())
+                .map(|l| (l.as_str(), false)),
+        )
+        .collect();
+    let line_height = ((height - 2.0 * pad) / texts.len() as f64).min(MAX_FONT_SIZE * 1.25);
+    let size = line_height / 1.25;
+    let top = (height - line_height * texts.len() as f64) / 2.0;
+    let lines = texts
+        .into_iter()
+        .enumerate()
+        .map(|(i, (text, bold))| TextLine {
+            x: text_x,
+            y: top + line_height * i as f64 + size,
+            size,
+            bold,
+            text: fit_text(text, text_width, size),
+        })
+        .collect();
+    Ok(Layout {
+        qr_x: pad + QUIET_ZONE as f64 * module,
+        qr_y: (height - side) / 2.0 + QUIET_ZONE as f64 * module,
+        module,
+        qr,
+        lines,
+    })
+}
+
+/**
+ * @description: 按估计的字宽截断文字, 英文按 0.75 个字号, 其他字符按 1 个字号, 截断时以 ... 结尾
+ * @param {&str} text 文字
+ * @param {f64} width 可用宽度, mm
+ * @param {f64} size 字号, mm
+ * @return {*}
+ */
+fn fit_text(text: &str, width: f64, size: f64) -> String {
+    let char_width = |c: char| if c.is_ascii() { size * 0.75 } else { size };
+    if text.chars().map(char_width).sum::<f64>() <= width {
+        return text.to_string();
+    }
+    let mut used = size * 0.75 * 3.0;
+    let mut fitted: String = text
+        .chars()
+        .take_while(|&c| {
+            used += char_width(c);
+            used <= width
+        })
+        .collect();
+    fitted.push_str("...");
+    fitted
+}
+
+// 数字保留 3 位小数并去掉末尾的 0
+fn num(value: f64) -> String {
+    let text = format!("{value:.3}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// XML 文本转义
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/**
+ * @description: 生成一张 SVG 标签, 尺寸为实际的 mm
+ * @param {&Label} label 标签
+ * @param {&LabelStock} stock 标签纸, 只使用标签尺寸
+ * @return {*}
+ */
+pub fn label_svg(label: &Label, stock: &LabelStock) -> BomResult<String> {
+    let (width, height) = (stock.label_width, stock.label_height);
+    let layout = layout(label, width, height)?;
+    let (w, h) = (num(width), num(height));
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"#fff\"/>\n<path fill=\"#000\" d=\""
+    );
+    let m = num(layout.module);
+    for y in 0..layout.qr.size() {
+        for x in 0..layout.qr.size() {
+            if layout.qr.module(x, y) {
+                svg += &format!(
+                    "M{} {}h{m}v{m}h-{m}z",
+                    num(layout.qr_x + x as f64 * layout.module),
+                    num(layout.qr_y + y as f64 * layout.module)
+                );
+            }
+        }
+    }
+    svg += "\"/>\n";
+    for line in layout.lines.iter() {
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\"{}>{}</text>\n",
+            num(line.x),
+            num(line.y),
+            num(line.size),
+            if line.bold {
+                " font-weight=\"bold\""
+            } else {
+                ""
+            },
+            xml_escape(&line.text)
+        );
+    }
+    svg += "</svg>\n";
+    Ok(svg)
+}
+
+// 5x7 点阵字体, ASCII 0x20~0x7E, 每个字符 5 列, 每列低位在上
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x14, 0x08, 0x3E, 0x08, 0x14], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+// Ω, 电阻值常用
+const GLYPH_OHM: [u8; 5] = [0x5E, 0x61, 0x01, 0x61, 0x5E];
+
+// 字符的点阵, 没有的字符显示为 ?
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        'Ω' => GLYPH_OHM,
+        'µ' | 'μ' => FONT_5X7['u' as usize - 0x20],
+        ' '..='~' => FONT_5X7[c as usize - 0x20],
+        _ => FONT_5X7['?' as usize - 0x20],
+    }
+}
+
+// 黑白画布, true 为黑色
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Canvas {
+    fn fill(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for y in y0..y1.min(self.height) {
+            for x in x0..x1.min(self.width) {
+                self.pixels[y * self.width + x] = true;
+            }
+        }
+    }
+}
+
+/**
+ * @description: 生成一张 PNG 标签, 黑白 1 位图; 没有内置中文字体, 非 ASCII 文字显示为 ?, 需要中文时用 SVG 或 PDF
+ * @param {&Label} label 标签
+ * @param {&LabelStock} stock 标签纸, 只使用标签尺寸
+ * @param {f64} dpi 分辨率, 一般用 LABEL_DPI
+ * @return {*}
+ */
+pub fn label_png(label: &Label, stock: &LabelStock, dpi: f64) -> BomResult<Vec<u8>> {
+    let layout = layout(label, stock.label_width, stock.label_height)?;
+    let scale = dpi / 25.4;
+    let px = |mm: f64| (mm * scale).round().max(0.0) as usize;
+    let mut canvas = Canvas {
+        width: px(stock.label_width).max(1),
+        height: px(stock.label_height).max(1),
+        pixels: Vec::new(),
+    };
+    canvas.pixels = vec![false; canvas.width * canvas.height];
+    for y in 0..layout.qr.size() {
+        for x in 0..layout.qr.size() {
+            if layout.qr.module(x, y) {
+                let left = layout.qr_x + x as f64 * layout.module;
+                let top = layout.qr_y + y as f64 * layout.module;
+                canvas.fill(
+                    px(left),
+                    px(top),
+                    px(left + layout.module),
+                    px(top + layout.module),
+                );
+            }
+        }
+    }
+    for line in layout.lines.iter() {
+        // 字体 8 个点高, 包括字符下方的 1 个空点
+        let dot = ((line.size * scale) / 8.0).round().max(1.0) as usize;
+        let top = px(line.y).saturating_sub(7 * dot);
+        let mut left = px(line.x);
+        for c in line.text.chars() {
+            for (col, bits) in glyph(c).iter().enumerate() {
+                for row in 0..7 {
+                    if bits >> row & 1 != 0 {
+                        let x = left + col * dot;
+                        let y = top + row * dot;
+                        let bold = if line.bold { dot.div_ceil(3) } else { 0 };
+                        canvas.fill(x, y, x + dot + bold, y + dot);
+                    }
+                }
+            }
+            left += 6 * dot;
+        }
+    }
+    Ok(encode_png(&canvas, dpi))
+}
+
+// CRC-32, PNG 数据块校验
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Adler-32, zlib 数据校验
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+// 写入一个 PNG 数据块
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+// 画布编码为 1 位灰度 PNG, 数据用不压缩的 deflate 块, 不需要压缩库
+fn encode_png(canvas: &Canvas, dpi: f64) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((canvas.width.div_ceil(8) + 1) * canvas.height);
+    for row in canvas.pixels.chunks(canvas.width) {
+        raw.push(0); // 不使用行过滤
+        for bits in row.chunks(8) {
+            // 1 为白色, 行尾不足 8 位的补白色
+            let byte = (0..8).fold(0u8, |acc, i| {
+                acc << 1 | u8::from(!bits.get(i).copied().unwrap_or(false))
+            });
+            raw.push(byte);
+        }
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push(u8::from(i + 1 == blocks.len()));
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(*block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend((canvas.width as u32).to_be_bytes());
+    header.extend((canvas.height as u32).to_be_bytes());
+    header.extend([1, 0, 0, 0, 0]); // 1 位, 灰度, deflate, 无过滤, 不隔行
+    png_chunk(&mut png, b"IHDR", &header);
+    let ppm = (dpi / 0.0254).round() as u32;
+    let mut phys = Vec::with_capacity(9);
+    phys.extend(ppm.to_be_bytes());
+    phys.extend(ppm.to_be_bytes());
+    phys.push(1); // 单位为米
+    png_chunk(&mut png, b"pHYs", &phys);
+    png_chunk(&mut png, b"IDAT", &zlib);
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// PDF 字符串, 用 UTF-16BE 十六进制表示, 配合 UniGB-UTF16-H 编码显示中文
+fn pdf_text(text: &str) -> String {
+    let hex: String = text.encode_utf16().map(|u| format!("{u:04X}")).collect();
+    format!("<{hex}>")
+}
+
+/**
+ * @description: 按标签纸排版生成 PDF, 一页放满后换下一页; 文字使用阅读器自带的 STSong-Light 字体, 支持中文
+ * @param {&[Label]} labels 标签
+ * @param {&LabelStock} stock 标签纸
+ * @return {*}
+ */
+pub fn labels_pdf(labels: &[Label], stock: &LabelStock) -> BomResult<Vec<u8>> {
+    if labels.is_empty() {
+        return Err(BomError::InvalidLabel("no labels to print".to_string()));
+    }
+    let per_page = (stock.columns * stock.rows).max(1) as usize;
+    let pt = |mm: f64| num(mm * PT_PER_MM);
+    let mut pages = Vec::new();
+    for chunk in labels.chunks(per_page) {
+        let mut content = String::new();
+        for (i, label) in chunk.iter().enumerate() {
+            let col = (i % stock.columns.max(1) as usize) as f64;
+            let row = (i / stock.columns.max(1) as usize) as f64;
+            let left = stock.left + col * (stock.label_width + stock.gap_x);
+            let top = stock.top + row * (stock.label_height + stock.gap_y);
+            let layout = layout(label, stock.label_width, stock.label_height)?;
+            // PDF 坐标原点在左下角
+            for y in 0..layout.qr.size() {
+                for x in 0..layout.qr.size() {
+                    if layout.qr.module(x, y) {
+                        let bottom = top + layout.qr_y + (y + 1) as f64 * layout.module;
+                        content += &format!(
+                            "{} {} {} {} re\n",
+                            pt(left + layout.qr_x + x as f64 * layout.module),
+                            pt(stock.page_height - bottom),
+                            pt(layout.module),
+                            pt(layout.module)
+                        );
+                    }
+                }
+            }
+            content += "f\n";
+            for line in layout.lines.iter() {
+                content += &format!(
+                    "BT /F1 {} Tf {} 0.2 w {} {} Td {} Tj ET\n",
+                    pt(line.size),
+                    if line.bold { "2 Tr" } else { "0 Tr" },
+                    pt(left + line.x),
+                    pt(stock.page_height - top - line.y),
+                    pdf_text(&line.text)
+                );
+            }
+        }
+        pages.push(content);
+    }
+
+    // 1 目录, 2 页面树, 3~5 字体, 之后每页一个页面对象和一个内容对象
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|i| format!("{} 0 R", 6 + i * 2))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type0 /BaseFont /STSong-Light /Encoding /UniGB-UTF16-H \
+         /DescendantFonts [4 0 R] >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /CIDFontType0 /BaseFont /STSong-Light \
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (GB1) /Supplement 4 >> \
+         /FontDescriptor 5 0 R /DW 1000 /W [1 95 500] >>"
+            .to_string(),
+        "<< /Type /FontDescriptor /FontName /STSong-Light /Flags 6 /FontBBox [-25 -254 1000 880] \
+         /ItalicAngle 0 /Ascent 880 /Descent -120 /CapHeight 880 /StemV 93 >>"
+            .to_string(),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            pt(stock.page_width),
+            pt(stock.page_height),
+            7 + i * 2
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        trailer += &format!("{offset:010} 00000 n \n");
+    }
+    trailer += &format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    pdf.extend(trailer.as_bytes());
+    Ok(pdf)
+}
+
+// 多张标签写到多个文件时, 文件名后加序号, 例如 labels-1.svg
+fn numbered_path(path: &str, index: usize) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}-{index}.{ext}"))
+        .to_string_lossy()
+        .to_string()
+}
+
+/**
+ * @description: 按扩展名写标签文件, PDF 写成一个文件, SVG/PNG 每张标签一个文件
+ * @param {&[Label]} labels 标签
+ * @param {&str} path 文件路径, 以 .svg/.png/.pdf 结尾
+ * @param {&LabelStock} stock 标签纸
+ * @return {写入的文件}
+ */
+pub fn write_labels(labels: &[Label], path: &str, stock: &LabelStock) -> BomResult<Vec<String>> {
+    let format = LabelFormat::from_path(path)?;
+    if format == LabelFormat::Pdf {
+        fs::write(path, labels_pdf(labels, stock)?)?;
+        return Ok(vec![path.to_string()]);
+    }
+    let mut written = Vec::with_capacity(labels.len());
+    for (i, label) in labels.iter().enumerate() {
+        let file = if labels.len() == 1 {
+            path.to_string()
+        } else {
+            numbered_path(path, i + 1)
+        };
+        let data = match format {
+            LabelFormat::Svg => label_svg(label, stock)?.into_bytes(),
+            _ => label_png(label, stock, LABEL_DPI)?,
+        };
+        fs::write(&file, data)?;
+        written.push(file);
+    }
+    Ok(written)
+}
+
+// DataBaseInfo 的标签接口
+impl DataBaseInfo {
+    /**
+     * @description: 元件的标签, 文字为 型号@厂家, 数值/封装 (解析不到时用描述), 存放位置
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn element_label(&self, model: &str, manufacturer: &str) -> BomResult<Label> {
+        let element = self.element(model, manufacturer)?;
+        let id = self.conn.query_row(
+            format!(get_element_id_template!(), self.tables).as_str(),
+            [model, manufacturer],
+            |row| row.get(0),
+        )?;
+        let mut lines = Vec::new();
+        let params = element.params().to_string();
+        if !params.is_empty() {
+            lines.push(params);
+        } else if !element.describe.is_empty() {
+            lines.push(element.describe.clone());
+        }
+        let paths: Vec<String> = self
+            .element_locations(model, manufacturer)?
+            .into_iter()
+            .map(|l| l.path)
+            .collect();
+        if !paths.is_empty() {
+            lines.push(paths.join(", "));
+        }
+        Ok(Label {
+            code: LabelCode::Element {
+                id,
+                key: element.key(),
+            },
+            title: element.key(),
+            lines,
+        })
+    }
+
+    /**
+     * @description: 存放位置的标签, 文字为位置路径和其中存放的元件
+     * @param {&str} path 位置路径, 例如 柜子A/抽屉3
+     * @return {*}
+     */
+    pub fn location_label(&self, path: &str) -> BomResult<Label> {
+        let location = self.find_location(path)?;
+        let path = self.location_path(location.id)?;
+        let keys: Vec<String> = self
+            .query_elements(&ElementQuery::new().location(&path))?
+            .iter()
+            .map(Element::key)
+            .collect();
+        Ok(Label {
+            code: LabelCode::Location {
+                id: location.id,
+                path: path.clone(),
+            },
+            title: path,
+            lines: vec![keys.join(", ")],
+        })
+    }
+
+    /**
+     * @description: 解析扫描到的字符串: 标签按名字查找, 名字对不上时再按 id 查找
+     * (扫码枪模拟键盘输入时中文可能变成乱码), 按 id 找到的对象名字和乱码前的部分不一致时
+     * 返回 NotFound; 不是标签时当作元件的键查找
+     * @param {&str} text 扫码枪输入的字符串
+     * @return {*}
+     */
+    pub fn scan_label(&self, text: &str) -> BomResult<Scanned> {
+        match LabelCode::parse(text) {
+            Some(LabelCode::Element { id, key }) => {
+                let (model, manufacturer) = split_key(&key);
+                match self.element(model, manufacturer) {
+                    Err(BomError::NotFound(_)) => match self.conn.query_row(
+                        format!(get_element_by_id_template!(), self.tables).as_str(),
+                        [id],
+                        element_from_row,
+                    ) {
+                        Err(rusqlite::Error::QueryReturnedNoRows) => Err(BomError::NotFound(key)),
+                        Ok(element) if !matches_garbled(&key, &element.key()) => {
+                            Err(BomError::NotFound(key))
+                        }
+                        res => Ok(Scanned::Element(res?)),
+                    },
+                    res => Ok(Scanned::Element(res?)),
+                }
+            }
+            Some(LabelCode::Location { id, path }) => {
+                let location = match self.find_location(&path) {
+                    Err(BomError::NotFound(_)) => self.location(id)?,
+                    res => res?,
+                };
+                let found = self.location_path(location.id)?;
+                if found != path && !matches_garbled(&path, &found) {
+                    return Err(BomError::NotFound(path));
+                }
+                Ok(Scanned::Location {
+                    path: found,
+                    location,
+                })
+            }
+            None => {
+                let (model, manufacturer) = split_key(text.trim());
+                Ok(Scanned::Element(self.element(model, manufacturer)?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_label() -> Label {
+        Label {
+            code: LabelCode::Element {
+                id: 7,
+                key: "R10K@Yageo".to_string(),
+            },
+            title: "R10K@Yageo".to_string(),
+            lines: vec!["10kΩ 1% 0603".to_string(), "柜子A & B".to_string()],
+        }
+    }
+
+    #[test]
+    fn garbled_names_match_only_their_readable_prefix() {
+        assert!(matches_garbled("R1??", "R10K@Yageo"));
+        assert!(matches_garbled("柜子A/抽屉\u{FFFD}", "柜子A/抽屉3"));
+        assert!(!matches_garbled("R1??", "X5"));
+        assert!(!matches_garbled("??", "R10K"));
+        assert!(!matches_garbled("R22K", "R10K"));
+    }
+
+    #[test]
+    fn label_codes_round_trip() {
+        let code = LabelCode::Element {
+            id: 42,
+            key: "STM32F103C8T6@ST".to_string(),
+        };
+        assert_eq!(code.to_string(), "BOM:E:42:STM32F103C8T6@ST");
+        assert_eq!(LabelCode::parse(" BOM:E:42:STM32F103C8T6@ST\n"), Some(code));
+        assert_eq!(
+            LabelCode::parse("BOM:L:3:柜子A/抽屉3:左"),
+            Some(LabelCode::Location {
+                id: 3,
+                path: "柜子A/抽屉3:左".to_string()
+            })
+        );
+        assert_eq!(LabelCode::parse("R10K"), None);
+        assert_eq!(LabelCode::parse("BOM:X:1:R10K"), None);
+        assert_eq!(LabelCode::parse("BOM:E:abc:R10K"), None);
+    }
+
+    #[test]
+    fn text_is_truncated_to_fit() {
+        assert_eq!(fit_text("R10K", 20.0, 2.0), "R10K");
+        let fitted = fit_text("a very long location name", 10.0, 2.0);
+        assert!(fitted.ends_with("...") && fitted.len() < 10, "{fitted}");
+        assert_eq!(num(2.5000), "2.5");
+        assert_eq!(num(3.0), "3");
+    }
+
+    #[test]
+    fn svg_label_has_qr_and_text() {
+        let svg = label_svg(&test_label(), &LabelStock::default()).unwrap();
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert!(svg.contains("width=\"62mm\" height=\"29mm\""));
+        assert!(svg.contains(">R10K@Yageo</text>"));
+        assert!(svg.contains("&amp;"));
+        assert!(svg.contains("h-"));
+    }
+
+    #[test]
+    fn png_label_is_valid() {
+        let png = label_png(&test_label(), &LabelStock::default(), LABEL_DPI).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        // 62x29mm 在 300 dpi 下为 732x343 像素
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 732);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 343);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert!(png.ends_with(&[0xAE, 0x42, 0x60, 0x82]));
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn pdf_sheet_pages() {
+        let stock = LabelStock::find("l7160").unwrap();
+        let labels = vec![test_label(); 22];
+        let pdf = labels_pdf(&labels, stock).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.ends_with("%%EOF\n"));
+        // xref 中的偏移指向对象开头
+        let start: usize = text
+            .split("startxref\n")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let xref = String::from_utf8_lossy(&pdf[start..]);
+        let offset: usize = xref.lines().nth(3).unwrap()[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with(b"1 0 obj"));
+        assert_eq!(pdf_text("R1Ω"), "<0052003103A9>");
+        assert!(LabelStock::find("A0").is_err());
+        assert!(labels_pdf(&[], stock).is_err());
+    }
+
+    #[test]
+    fn label_formats_by_extension() {
+        assert_eq!(LabelFormat::from_path("a.SVG").unwrap(), LabelFormat::Svg);
+        assert_eq!(LabelFormat::from_path("a.png").unwrap(), LabelFormat::Png);
+        assert_eq!(LabelFormat::from_path("a.pdf").unwrap(), LabelFormat::Pdf);
+        assert!(LabelFormat::from_path("a.txt").is_err());
+        assert_eq!(numbered_path("out/labels.svg", 3), "out/labels-3.svg");
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * @Description: 出入库流水, 每次数量变化记录一条, 可以查看历史和回放任意时刻的数量
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\ledger.rs
+ */
+use super::{BomResult, DataBaseInfo};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 插入一条流水
+macro_rules! insert_movement_template {
+    () => {
+        "INSERT INTO stock_movements (timestamp, model, manufacturer, delta, reason, operator) VALUES (?, ?, ?, ?, ?, ?)"
+    };
+}
+// 按时间顺序读取一个元件的流水
+macro_rules! get_history_template {
+    () => {
+        "SELECT id, timestamp, model, manufacturer, delta, reason, operator FROM stock_movements \
+         WHERE model = ? AND manufacturer = ? ORDER BY timestamp, id"
+    };
+}
+// 回放流水得到某一时刻的数量
+macro_rules! get_quantity_at_template {
+    () => {
+        "SELECT COALESCE(SUM(delta), 0) FROM stock_movements WHERE model = ? AND manufacturer = ? AND timestamp <= ?"
+    };
+}
+
+// 程序自动生成的流水原因
+pub const REASON_OPENING: &str = "opening"; // 建立流水表时已有的库存
+pub const REASON_REMOVE: &str = "remove"; // 删除元件
+pub const REASON_IMPORT: &str = "import"; // 导入的数量和流水合计不一致时补记的差额
+pub const REASON_AUDIT: &str = "audit"; // 盘点时按实际数量调整的差额
+
+// 一条出入库流水
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StockMovement {
+    pub id: i64,              // 流水号, 写入时忽略
+    pub timestamp: i64,       // unix 时间戳, 秒
+    pub model: String,        // 元件型号
+    pub manufacturer: String, // 厂家
+    pub delta: i64,           // 数量变化, 入库为正, 出库为负
+    pub reason: String,       // 原因或项目
+    pub operator: String,     // 操作人
+}
+
+// 数量变化时附带的说明
+#[derive(Debug, Clone, Default)]
+pub struct MovementNote {
+    pub reason: String,   // 原因或项目
+    pub operator: String, // 操作人
+}
+
+impl MovementNote {
+    /**
+     * @description: 生成当前时间的流水
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {i64} delta 数量变化
+     * @return {*}
+     */
+    pub fn movement(&self, model: &str, manufacturer: &str, delta: i64) -> StockMovement {
+        StockMovement {
+            id: 0,
+            timestamp: now_timestamp(),
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            delta,
+            reason: self.reason.clone(),
+            operator: self.operator.clone(),
+        }
+    }
+}
+
+/**
+ * @description: 当前 unix 时间戳, 秒
+ * @return {*}
+ */
+pub fn now_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/**
+ * @description: 写入一条流水, conn 可以是事务, 和数量修改一起提交
+ * @param {&Connection} conn sqlite数据库连接
+ * @param {&StockMovement} movement 流水
+ * @return {*}
+ */
+pub(crate) fn insert_movement(conn: &Connection, movement: &StockMovement) -> BomResult<()> {
+    conn.execute(
+        insert_movement_template!(),
+        (
+            movement.timestamp,
+            &movement.model,
+            &movement.manufacturer,
+            movement.delta,
+            &movement.reason,
+            &movement.operator,
+        ),
+    )?;
+    Ok(())
+}
+
+// DataBaseInfo 的流水接口
+impl DataBaseInfo {
+    /**
+     * @description: 直接写入一条流水, 不修改元件数量, 用于导入历史记录
+     * @param {&StockMovement} movement 流水
+     * @return {*}
+     */
+    pub fn record_movement(&self, movement: &StockMovement) -> BomResult<()> {
+        insert_movement(&self.conn, movement)
+    }
+
+    /**
+     * @description: 读取一个元件的全部流水, 按时间排序
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn element_history(
+        &self,
+        model: &str,
+        manufacturer: &str,
+    ) -> BomResult<Vec<StockMovement>> {
+        let mut stmt = self.conn.prepare(get_history_template!())?;
+        let rows = stmt.query_map([model, manufacturer], |row| {
+            Ok(StockMovement {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                model: row.get(2)?,
+                manufacturer: row.get(3)?,
+                delta: row.get(4)?,
+                reason: row.get(5)?,
+                operator: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 回放流水, 计算元件在某一时刻的数量
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {i64} timestamp unix 时间戳, 包含该时刻的流水
+     * @return {*}
+     */
+    pub fn quantity_at(&self, model: &str, manufacturer: &str, timestamp: i64) -> BomResult<i64> {
+        Ok(self.conn.query_row(
+            get_quantity_at_template!(),
+            (model, manufacturer, timestamp),
+            |row| row.get(0),
+        )?)
+    }
+}
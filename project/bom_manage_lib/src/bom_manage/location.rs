@@ -0,0 +1,460 @@
+/*
+ * @Description: 存放位置, 例如 柜子A/抽屉3/格子12, 一个元件的库存可以分放在多个位置
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\location.rs
+ */
+use super::category::PATH_SEPARATOR;
+use super::{add_number, BomError, BomResult, DataBaseInfo, Element, MovementNote};
+use rusqlite::Connection;
+
+// 新增位置, 同一上级位置下已有同名位置时忽略
+macro_rules! insert_location_template {
+    () => {
+        "INSERT OR IGNORE INTO locations (name, parent_id) VALUES (?, ?)"
+    };
+}
+// 按上级位置和名字查找位置, 上级为空时 COALESCE 为 0
+macro_rules! find_location_template {
+    () => {
+        "SELECT id, name, parent_id FROM locations WHERE COALESCE(parent_id, 0) = ? AND name = ?"
+    };
+}
+// 按 id 读取位置
+macro_rules! get_location_template {
+    () => {
+        "SELECT id, name, parent_id FROM locations WHERE id = ?"
+    };
+}
+// 读取所有位置
+macro_rules! get_locations_template {
+    () => {
+        "SELECT id, name, parent_id FROM locations ORDER BY id"
+    };
+}
+// 读取元件在各个位置的数量
+macro_rules! get_stock_locations_template {
+    () => {
+        "SELECT location_id, quantity FROM stock_locations WHERE model = ? AND manufacturer = ? ORDER BY location_id"
+    };
+}
+// 增加元件在某个位置的数量
+macro_rules! add_stock_location_template {
+    () => {
+        "INSERT INTO stock_locations (model, manufacturer, location_id, quantity) VALUES (?, ?, ?, ?) \
+         ON CONFLICT (model, manufacturer, location_id) DO UPDATE SET quantity = quantity + excluded.quantity"
+    };
+}
+// 修改元件在某个位置的数量
+macro_rules! update_stock_location_template {
+    () => {
+        "UPDATE stock_locations SET quantity = ? WHERE model = ? AND manufacturer = ? AND location_id = ?"
+    };
+}
+// 设置元件在某个位置的数量
+macro_rules! set_stock_location_template {
+    () => {
+        "INSERT INTO stock_locations (model, manufacturer, location_id, quantity) VALUES (?, ?, ?, ?) \
+         ON CONFLICT (model, manufacturer, location_id) DO UPDATE SET quantity = excluded.quantity"
+    };
+}
+// 读取一个位置上的所有元件
+macro_rules! get_location_stock_template {
+    () => {
+        "SELECT model, manufacturer, quantity FROM stock_locations WHERE location_id = ? \
+         ORDER BY model, manufacturer"
+    };
+}
+// 删除数量为 0 的位置记录
+macro_rules! delete_empty_locations_template {
+    () => {
+        "DELETE FROM stock_locations WHERE model = ? AND manufacturer = ? AND quantity = 0"
+    };
+}
+// 删除元件时删除它的位置记录
+macro_rules! delete_stock_locations_template {
+    () => {
+        "DELETE FROM stock_locations WHERE model = ? AND manufacturer = ?"
+    };
+}
+
+// 一个存放位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub id: i64,                // 位置 id
+    pub name: String,           // 名字, 例如 抽屉3
+    pub parent_id: Option<i64>, // 上级位置 id, 顶层为 None
+}
+
+// 元件在一个位置的数量
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StockLocation {
+    pub location_id: i64, // 位置 id
+    pub path: String,     // 位置路径, 例如 柜子A/抽屉3/格子12
+    pub quantity: u32,    // 数量
+}
+
+// 把 get_location_template 查询到的一行转为 Location
+fn location_from_row(row: &rusqlite::Row) -> rusqlite::Result<Location> {
+    Ok(Location {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+    })
+}
+
+/**
+ * @description: 读取元件在各个位置的数量, 不含路径
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {(位置 id, 数量)}
+ */
+fn located(conn: &Connection, model: &str, manufacturer: &str) -> BomResult<Vec<(i64, u32)>> {
+    let mut stmt = conn.prepare(get_stock_locations_template!())?;
+    let rows = stmt.query_map([model, manufacturer], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/**
+ * @description: 元件数量减少后, 位置上的总数不能超过库存, 超出的部分从数量少的位置开始扣除
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @param {u32} number 元件现在的数量
+ * @return {*}
+ */
+pub(crate) fn trim_locations(
+    conn: &Connection,
+    model: &str,
+    manufacturer: &str,
+    number: u32,
+) -> BomResult<()> {
+    let mut located = located(conn, model, manufacturer)?;
+    let mut excess = located
+        .iter()
+        .map(|(_, q)| *q)
+        .sum::<u32>()
+        .saturating_sub(number);
+    if excess == 0 {
+        return Ok(());
+    }
+    located.sort_by_key(|(id, q)| (*q, *id));
+    for (id, quantity) in located {
+        let take = quantity.min(excess);
+        conn.execute(
+            update_stock_location_template!(),
+            (quantity - take, model, manufacturer, id),
+        )?;
+        excess -= take;
+        if excess == 0 {
+            break;
+        }
+    }
+    conn.execute(delete_empty_locations_template!(), [model, manufacturer])?;
+    Ok(())
+}
+
+/**
+ * @description: 读取一个位置上的所有元件, 不含下级位置
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {i64} location_id 位置 id
+ * @return {(型号, 厂家, 数量)}
+ */
+pub(crate) fn location_stock(
+    conn: &Connection,
+    location_id: i64,
+) -> BomResult<Vec<(String, String, u32)>> {
+    let mut stmt = conn.prepare(get_location_stock_template!())?;
+    let rows = stmt.query_map([location_id], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/**
+ * @description: 设置元件在某个位置的数量, 为 0 时删除记录, 调用者保证位置上的总数不超过库存
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @param {i64} location_id 位置 id
+ * @param {u32} quantity 数量
+ * @return {*}
+ */
+pub(crate) fn set_location_quantity(
+    conn: &Connection,
+    model: &str,
+    manufacturer: &str,
+    location_id: i64,
+    quantity: u32,
+) -> BomResult<()> {
+    conn.execute(
+        set_stock_location_template!(),
+        (model, manufacturer, location_id, quantity),
+    )?;
+    conn.execute(delete_empty_locations_template!(), [model, manufacturer])?;
+    Ok(())
+}
+
+/**
+ * @description: 删除元件的所有位置记录
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {*}
+ */
+pub(crate) fn clear_locations(conn: &Connection, model: &str, manufacturer: &str) -> BomResult<()> {
+    conn.execute(delete_stock_locations_template!(), [model, manufacturer])?;
+    Ok(())
+}
+
+// DataBaseInfo 的存放位置接口
+impl DataBaseInfo {
+    /**
+     * @description: 新增位置, 上级位置下已有同名位置时返回已有的位置
+     * @param {&str} name 位置名
+     * @param {Option<i64>} parent_id 上级位置 id, None 为顶层
+     * @return {*}
+     */
+    pub fn add_location(&self, name: &str, parent_id: Option<i64>) -> BomResult<Location> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(PATH_SEPARATOR) {
+            return Err(BomError::InvalidLocation(name.to_string()));
+        }
+        if let Some(parent_id) = parent_id {
+            self.location(parent_id)?;
+        }
+        self.conn
+            .execute(insert_location_template!(), (name, parent_id))?;
+        Ok(self.conn.query_row(
+            find_location_template!(),
+            (parent_id.unwrap_or(0), name),
+            location_from_row,
+        )?)
+    }
+
+    /**
+     * @description: 按 id 读取位置
+     * @param {i64} id 位置 id
+     * @return {*}
+     */
+    pub fn location(&self, id: i64) -> BomResult<Location> {
+        match self
+            .conn
+            .query_row(get_location_template!(), [id], location_from_row)
+        {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(BomError::NotFound(format!("location {id}")))
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /**
+     * @description: 所有位置, 按 id 排序, 上级位置总在下级之前
+     * @return {*}
+     */
+    pub fn locations(&self) -> BomResult<Vec<Location>> {
+        let mut stmt = self.conn.prepare(get_locations_template!())?;
+        let rows = stmt.query_map([], location_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 按路径查找位置, 例如 "柜子A/抽屉3/格子12"
+     * @param {&str} path 位置路径
+     * @return {*}
+     */
+    pub fn find_location(&self, path: &str) -> BomResult<Location> {
+        let mut found: Option<Location> = None;
+        for name in path.split(PATH_SEPARATOR) {
+            let parent_id = found.as_ref().map_or(0, |l| l.id);
+            found = match self.conn.query_row(
+                find_location_template!(),
+                (parent_id, name.trim()),
+                location_from_row,
+            ) {
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    return Err(BomError::NotFound(path.to_string()))
+                }
+                res => Some(res?),
+            };
+        }
+        found.ok_or_else(|| BomError::NotFound(path.to_string()))
+    }
+
+    /**
+     * @description: 位置的完整路径
+     * @param {i64} id 位置 id
+     * @return {*}
+     */
+    pub fn location_path(&self, id: i64) -> BomResult<String> {
+        let mut names = Vec::new();
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let location = self.location(id)?;
+            names.push(location.name);
+            next = location.parent_id;
+        }
+        names.reverse();
+        Ok(names.join(&PATH_SEPARATOR.to_string()))
+    }
+
+    /**
+     * @description: 元件在各个位置的数量
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn element_locations(
+        &self,
+        model: &str,
+        manufacturer: &str,
+    ) -> BomResult<Vec<StockLocation>> {
+        located(&self.conn, model, manufacturer)?
+            .into_iter()
+            .map(|(location_id, quantity)| {
+                Ok(StockLocation {
+                    location_id,
+                    path: self.location_path(location_id)?,
+                    quantity,
+                })
+            })
+            .collect()
+    }
+
+    /**
+     * @description: 按路径新增位置, 路径中不存在的上级位置一并创建, 整个路径在一个事务中写入
+     * @param {&str} path 位置路径, 例如 "柜子A/抽屉3/格子12"
+     * @return {最后一级位置}
+     */
+    pub fn add_location_path(&self, path: &str) -> BomResult<Location> {
+        let tx = self.conn.unchecked_transaction()?;
+        let location = self.create_location_path(path)?;
+        tx.commit()?;
+        Ok(location)
+    }
+
+    /**
+     * @description: 移动元件, from 为 None 时从未放置的库存中取, to 为 None 时取下后不放到任何位置.
+     * 先检查库存, 够用时才创建新位置, 检查/创建/移动在同一个事务中完成
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {Option<i64>} from 原位置 id
+     * @param {Option<&str>} to 新位置路径, 不存在时创建
+     * @param {u32} quantity 数量
+     * @return {*}
+     */
+    pub fn move_stock(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        from: Option<i64>,
+        to: Option<&str>,
+        quantity: u32,
+    ) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.place_stock(model, manufacturer, from, to, quantity)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 增加元件数量并把增加的部分放到指定位置, 位置不存在时创建, 在同一个事务中完成
+     * @param {&Element} element 要增加的元件, number 为增加的数量
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @param {&str} path 位置路径
+     * @return {写入后的元件}
+     */
+    pub fn add_element_number_at(
+        &self,
+        element: &Element,
+        note: &MovementNote,
+        path: &str,
+    ) -> BomResult<Element> {
+        let tx = self.conn.unchecked_transaction()?;
+        add_number(&tx, &self.tables, element, note)?;
+        self.place_stock(
+            &element.model,
+            &element.manufacturer,
+            None,
+            Some(path),
+            element.number,
+        )?;
+        let stored = self.element(&element.model, &element.manufacturer)?;
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    /**
+     * @description: 逐级创建路径上的位置, 调用方负责开启事务
+     * @param {&str} path 位置路径
+     * @return {最后一级位置}
+     */
+    fn create_location_path(&self, path: &str) -> BomResult<Location> {
+        let mut parent: Option<Location> = None;
+        for name in path.split(PATH_SEPARATOR) {
+            let parent_id = parent.as_ref().map(|l| l.id);
+            parent = Some(self.add_location(name, parent_id)?);
+        }
+        parent.ok_or_else(|| BomError::InvalidLocation(path.to_string()))
+    }
+
+    /**
+     * @description: 检查库存后移动元件, 调用方负责开启事务
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {Option<i64>} from 原位置 id
+     * @param {Option<&str>} to 新位置路径, 库存足够时才创建
+     * @param {u32} quantity 数量
+     * @return {*}
+     */
+    fn place_stock(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        from: Option<i64>,
+        to: Option<&str>,
+        quantity: u32,
+    ) -> BomResult<()> {
+        let number: u32 = match self.conn.query_row(
+            format!(get_number_template!(), self.tables).as_str(),
+            [model, manufacturer],
+            |row| row.get(0),
+        ) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(BomError::NotFound(model.to_string()))
+            }
+            res => res?,
+        };
+        let located = located(&self.conn, model, manufacturer)?;
+        let available = match from {
+            Some(from) => located
+                .iter()
+                .find(|(id, _)| *id == from)
+                .map_or(0, |(_, q)| *q),
+            None => number.saturating_sub(located.iter().map(|(_, q)| *q).sum()),
+        };
+        if quantity > available {
+            return Err(BomError::InsufficientStock {
+                model: model.to_string(),
+                requested: quantity,
+                available,
+            });
+        }
+        if let Some(from) = from {
+            self.conn.execute(
+                update_stock_location_template!(),
+                (available - quantity, model, manufacturer, from),
+            )?;
+        }
+        if let Some(to) = to {
+            let to = self.create_location_path(to)?;
+            self.conn.execute(
+                add_stock_location_template!(),
+                (model, manufacturer, to.id, quantity),
+            )?;
+        }
+        self.conn
+            .execute(delete_empty_locations_template!(), [model, manufacturer])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1788 @@
+/*
+ * @Description: 数据库版本升级, 版本号记录在 PRAGMA user_version 中
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\migration.rs
+ */
+use super::ledger::{now_timestamp, REASON_OPENING};
+use super::{BomError, BomResult};
+use rusqlite::{Connection, Transaction};
+
+// 升级函数, 参数为事务和元件表名
+pub type MigrationFn = fn(&Transaction, &str) -> BomResult<()>;
+
+// 一个升级步骤, 把数据库从 version - 1 升级到 version
+pub struct Migration {
+    pub version: u32,           // 升级后的版本号
+    pub describe: &'static str, // 升级内容
+    pub up: MigrationFn,        // 升级函数
+}
+
+// 按版本号顺序排列的升级步骤, 新增列/表时在末尾追加, 不要修改已有的步骤,
+// 步骤中的 SQL 写死在步骤里, 不引用会随版本变化的模板和函数
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        describe: "创建元件表",
+        up: create_bom_table,
+    },
+    Migration {
+        version: 2,
+        describe: "增加厂家列, 合并重复型号, 型号 + 厂家唯一",
+        up: unique_model,
+    },
+    Migration {
+        version: 3,
+        describe: "增加出入库流水表, 已有库存记为期初",
+        up: stock_movements,
+    },
+    Migration {
+        version: 4,
+        describe: "增加数值/单位/精度/耐压/封装列, 解析已有元件",
+        up: element_params,
+    },
+    Migration {
+        version: 5,
+        describe: "增加分类表, 内置元件类型写入分类表, 元件增加分类列",
+        up: categories,
+    },
+    Migration {
+        version: 6,
+        describe: "元件和分类增加最低库存和补货点",
+        up: thresholds,
+    },
+    Migration {
+        version: 7,
+        describe: "增加生产单和生产单预留的元件",
+        up: builds,
+    },
+    Migration {
+        version: 8,
+        describe: "增加存放位置, 元件库存可以分放在多个位置",
+        up: locations,
+    },
+    Migration {
+        version: 9,
+        describe: "增加供应商, 供应商料号和带日期的价格梯度",
+        up: suppliers,
+    },
+    Migration {
+        version: 10,
+        describe: "增加元件全文搜索索引",
+        up: element_search,
+    },
+    Migration {
+        version: 11,
+        describe: "元件增加行版本号, 每次修改加 1",
+        up: row_version,
+    },
+    Migration {
+        version: 12,
+        describe: "增加替代料组, 组内元件可以互相替代",
+        up: part_groups,
+    },
+    Migration {
+        version: 13,
+        describe: "供应商料号增加首选标记, 增加采购单和采购单的元件",
+        up: purchase_orders,
+    },
+    Migration {
+        version: 14,
+        describe: "增加盘点单和盘点的元件",
+        up: audits,
+    },
+    Migration {
+        version: 15,
+        describe: "增加元件的附件和备注",
+        up: attachments,
+    },
+    Migration {
+        version: 16,
+        describe: "供应商料号增加报价货币",
+        up: supplier_currency,
+    },
+];
+
+/**
+ * @description: 当前程序支持的最新版本号
+ * @return {*}
+ */
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/**
+ * @description: 读取数据库的版本号, 没有升级过的数据库为 0
+ * @param {&Connection} conn sqlite数据库连接
+ * @return {*}
+ */
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/**
+ * @description: 把数据库升级到最新版本
+ * @param {&Connection} conn sqlite数据库连接
+ * @param {&str} tables 元件表名
+ * @return {升级后的版本号}
+ */
+pub fn migrate(conn: &Connection, tables: &str) -> BomResult<u32> {
+    migrate_to(conn, tables, latest_version())
+}
+
+/**
+ * @description: 依次执行版本号大于当前版本且不超过 target 的升级步骤,
+ * 每个步骤和版本号的修改在同一个事务中, 失败时停在上一个版本
+ * @param {&Connection} conn sqlite数据库连接
+ * @param {&str} tables 元件表名
+ * @param {u32} target 目标版本号
+ * @return {升级后的版本号}
+ */
+pub fn migrate_to(conn: &Connection, tables: &str, target: u32) -> BomResult<u32> {
+    let mut version = schema_version(conn)?;
+    if version > latest_version() {
+        return Err(BomError::UnsupportedVersion {
+            found: version,
+            supported: latest_version(),
+        });
+    }
+    let current = version;
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+    {
+        let tx = conn.unchecked_transaction()?;
+        // 其他进程可能同时打开同一个文件, 拿到写锁后再确认一次版本号
+        if schema_version(&tx)? >= migration.version {
+            version = migration.version;
+            continue;
+        }
+        if let Err(err) = (migration.up)(&tx, tables) {
+            info_log!("升级到版本 {} 失败: {err}", migration.version);
+            return Err(err);
+        }
+        // PRAGMA 不支持参数绑定
+        tx.execute_batch(format!("PRAGMA user_version = {}", migration.version).as_str())?;
+        tx.commit()?;
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+/**
+ * @description: 版本 1, 创建元件表, 已有的表保持不变
+ */
+fn create_bom_table(tx: &Transaction, tables: &str) -> BomResult<()> {
+    tx.execute(
+        format!(
+            "CREATE TABLE IF NOT EXISTS {tables} (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, \
+             model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL)"
+        )
+        .as_str(),
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 2, 给表加上厂家列和 (model, manufacturer) 唯一索引,
+ * 已有的重复型号合并为一行, 数量相加, 保留最早的一行
+ */
+fn unique_model(tx: &Transaction, tables: &str) -> BomResult<()> {
+    let has_manufacturer: bool = tx.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = 'manufacturer'",
+        [tables],
+        |row| row.get(0),
+    )?;
+    if !has_manufacturer {
+        tx.execute(
+            format!("ALTER TABLE {tables} ADD COLUMN manufacturer TEXT NOT NULL DEFAULT ''")
+                .as_str(),
+            [],
+        )?;
+    }
+
+    let duplicates = {
+        let mut stmt = tx.prepare(
+            format!(
+                "SELECT model, manufacturer, SUM(number), MIN(id) FROM {tables} \
+                 GROUP BY model, manufacturer HAVING COUNT(*) > 1"
+            )
+            .as_str(),
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<usize, String>(0)?,
+                row.get::<usize, String>(1)?,
+                row.get::<usize, u32>(2)?,
+                row.get::<usize, i64>(3)?,
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (model, manufacturer, number, id) in duplicates {
+        info_log!("合并重复型号 {model}, 总数 {number}");
+        // 状态按当时固定的划分: 0 未知, 不超过 50 缺货, 不超过 100 一般, 其余丰富
+        tx.execute(
+            format!(
+                "UPDATE {tables} SET number = ?1, state = CASE WHEN ?1 = 0 THEN '未知' \
+                 WHEN ?1 <= 50 THEN '缺货' WHEN ?1 <= 100 THEN '一般' ELSE '丰富' END WHERE id = ?2"
+            )
+            .as_str(),
+            (number, id),
+        )?;
+        tx.execute(
+            format!("DELETE FROM {tables} WHERE model = ? AND manufacturer = ? AND id <> ?")
+                .as_str(),
+            (&model, &manufacturer, id),
+        )?;
+    }
+    tx.execute(
+        format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {tables}_model_manufacturer ON {tables} (model, manufacturer)"
+        )
+        .as_str(),
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 3, 创建出入库流水表, 已有的库存写一条期初流水, 保证回放结果和当前数量一致
+ */
+fn stock_movements(tx: &Transaction, tables: &str) -> BomResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+         CREATE INDEX IF NOT EXISTS stock_movements_model ON stock_movements (model, manufacturer, timestamp);",
+    )?;
+    tx.execute(
+        format!(
+            "INSERT INTO stock_movements (timestamp, model, manufacturer, delta, reason, operator) \
+             SELECT ?, model, manufacturer, number, ?, '' FROM {tables} WHERE number > 0"
+        )
+        .as_str(),
+        (now_timestamp(), REASON_OPENING),
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 4, 增加解析后的参数列, 并解析已有元件的型号和描述
+ */
+fn element_params(tx: &Transaction, tables: &str) -> BomResult<()> {
+    for column in [
+        "value REAL",
+        "unit TEXT",
+        "tolerance REAL",
+        "voltage REAL",
+        "package TEXT",
+    ] {
+        tx.execute(
+            format!("ALTER TABLE {tables} ADD COLUMN {column}").as_str(),
+            [],
+        )?;
+    }
+    tx.execute(
+        format!("CREATE INDEX IF NOT EXISTS {tables}_unit_value ON {tables} (unit, value)")
+            .as_str(),
+        [],
+    )?;
+    // 列和解析规则都写死在这里, 后续版本修改 Element 或 value.rs 不影响这一步
+    let elements: Vec<(String, String, String, String)> = {
+        let mut stmt = tx.prepare(
+            format!("SELECT describe, model, manufacturer, element_type FROM {tables}").as_str(),
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get(0).unwrap_or_else(|_| "Unknown".to_string()),
+                row.get(1)?,
+                row.get(2).unwrap_or_default(),
+                row.get(3).unwrap_or_default(),
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (describe, model, manufacturer, element_type) in elements {
+        let hint = match element_type.as_str() {
+            "电阻" => Some(v4_params::OHM),
+            "电容" => Some(v4_params::FARAD),
+            "电感" => Some(v4_params::HENRY),
+            _ => None,
+        };
+        let params = v4_params::Params::parse(&format!("{model} {describe}"), hint);
+        tx.execute(
+            format!(
+                "UPDATE {tables} SET value = ?, unit = ?, tolerance = ?, voltage = ?, package = ? \
+                 WHERE model = ? AND manufacturer = ?"
+            )
+            .as_str(),
+            (
+                params.value,
+                params.unit,
+                params.tolerance,
+                params.voltage,
+                params.package,
+                &model,
+                &manufacturer,
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/**
+ * @description: 版本 5, 创建分类表并写入内置元件类型, 已有元件按 element_type 归入对应分类
+ */
+fn categories(tx: &Transaction, tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, \
+         parent_id INTEGER REFERENCES categories (id))",
+        [],
+    )?;
+    // 顶层分类 parent_id 为 NULL, UNIQUE 不会比较 NULL, 所以用 COALESCE
+    tx.execute(
+        "CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name)",
+        [],
+    )?;
+    // id 和 ElementType::category_id 对应
+    tx.execute(
+        "INSERT INTO categories (id, name) VALUES \
+         (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片')",
+        [],
+    )?;
+    tx.execute(
+        format!("ALTER TABLE {tables} ADD COLUMN category_id INTEGER REFERENCES categories (id)")
+            .as_str(),
+        [],
+    )?;
+    tx.execute(
+        format!(
+            "UPDATE {tables} SET category_id = \
+             (SELECT id FROM categories WHERE parent_id IS NULL AND name = {tables}.element_type)"
+        )
+        .as_str(),
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 6, 元件和分类增加最低库存和补货点, 为空时继承上级或使用默认值
+ */
+fn thresholds(tx: &Transaction, tables: &str) -> BomResult<()> {
+    for table in [tables, "categories"] {
+        for column in ["min_stock INTEGER", "reorder_point INTEGER"] {
+            tx.execute(
+                format!("ALTER TABLE {table} ADD COLUMN {column}").as_str(),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * @description: 版本 7, 创建生产单表, 预留中的生产单占用库存
+ */
+fn builds(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, \
+         status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), \
+         model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', \
+         required INTEGER NOT NULL, reserved INTEGER NOT NULL)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX build_items_build ON build_items (build_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 8, 创建存放位置表和元件在各个位置的数量
+ */
+fn locations(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, \
+         parent_id INTEGER REFERENCES locations (id))",
+        [],
+    )?;
+    tx.execute(
+        "CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', \
+         location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, \
+         PRIMARY KEY (model, manufacturer, location_id))",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 9, 创建供应商表, 供应商料号表和价格梯度表
+ */
+fn suppliers(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, \
+         supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, \
+         model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', \
+         pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, \
+         UNIQUE (supplier_id, sku))",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), \
+         min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX price_breaks_part ON price_breaks (part_id, effective)",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 10, 创建型号/描述/分类/厂家料号的 FTS5 全文索引, 并为已有元件建索引
+ */
+fn element_search(tx: &Transaction, tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, \
+         tokenize = 'unicode61 remove_diacritics 2')",
+        [],
+    )?;
+    // 分类名从自己到顶层用空格连接, 再加上元件类型的英文名, 和 search::index_element 写入的内容一致
+    tx.execute(
+        format!(
+            "WITH RECURSIVE paths (origin, names, parent_id) AS (\
+             SELECT id, name, parent_id FROM categories \
+             UNION ALL SELECT p.origin, p.names || ' ' || c.name, c.parent_id FROM paths p \
+             JOIN categories c ON c.id = p.parent_id) \
+             INSERT INTO element_search (key, model, describe, category, mpn) \
+             SELECT CASE WHEN e.manufacturer = '' THEN e.model ELSE e.model || '@' || e.manufacturer END, \
+             e.model || ' ' || e.manufacturer, e.describe, \
+             TRIM(COALESCE(p.names, '') || ' ' || CASE e.element_type \
+             WHEN '电阻' THEN 'resistor' WHEN '二极管' THEN 'diode' WHEN '三极管' THEN 'transistor' \
+             WHEN '电容' THEN 'capacitor' WHEN '电感' THEN 'inductor' WHEN '芯片' THEN 'chip ic' ELSE '' END), \
+             (SELECT COALESCE(group_concat(s.mpn || ' ' || s.sku, ' '), '') FROM supplier_parts s \
+             WHERE s.model = e.model AND s.manufacturer = e.manufacturer) \
+             FROM {tables} e LEFT JOIN paths p ON p.origin = e.category_id AND p.parent_id IS NULL"
+        )
+        .as_str(),
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 11, 元件增加版本号列, 触发器保证任何修改都让版本号加 1,
+ * 已经自己加了版本号的修改不会再加
+ */
+fn row_version(tx: &Transaction, tables: &str) -> BomResult<()> {
+    tx.execute(
+        format!("ALTER TABLE {tables} ADD COLUMN version INTEGER NOT NULL DEFAULT 0").as_str(),
+        [],
+    )?;
+    tx.execute(
+        format!(
+            "CREATE TRIGGER {tables}_version AFTER UPDATE ON {tables} FOR EACH ROW \
+             WHEN NEW.version = OLD.version \
+             BEGIN UPDATE {tables} SET version = OLD.version + 1 WHERE rowid = NEW.rowid; END"
+        )
+        .as_str(),
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 12, 创建替代料组和组内元件, 优先级小的先用
+ */
+fn part_groups(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE part_groups (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE part_group_members (group_id INTEGER NOT NULL REFERENCES part_groups (id), \
+         model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', priority INTEGER NOT NULL DEFAULT 0, \
+         PRIMARY KEY (group_id, model, manufacturer))",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX part_group_members_model ON part_group_members (model)",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 13, 供应商料号增加首选标记, 创建采购单表, 收货前的采购单数量算作在途
+ */
+fn purchase_orders(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "ALTER TABLE supplier_parts ADD COLUMN preferred INTEGER NOT NULL DEFAULT 0",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE purchase_orders (id INTEGER PRIMARY KEY, supplier TEXT NOT NULL, \
+         status TEXT NOT NULL, created INTEGER NOT NULL, received INTEGER)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE purchase_order_items (id INTEGER PRIMARY KEY, \
+         order_id INTEGER NOT NULL REFERENCES purchase_orders (id), \
+         model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', sku TEXT NOT NULL DEFAULT '', \
+         mpn TEXT NOT NULL DEFAULT '', needed INTEGER NOT NULL, quantity INTEGER NOT NULL, unit_price REAL)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX purchase_order_items_order ON purchase_order_items (order_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 14, 创建盘点单表, 开始盘点时记下账面数量, 完成时按差异调整库存
+ */
+fn audits(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE audits (id INTEGER PRIMARY KEY, scope TEXT NOT NULL, scope_id INTEGER, \
+         path TEXT NOT NULL DEFAULT '', status TEXT NOT NULL, created INTEGER NOT NULL, closed INTEGER)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE TABLE audit_lines (audit_id INTEGER NOT NULL REFERENCES audits (id), \
+         model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', expected INTEGER NOT NULL, \
+         counted INTEGER, PRIMARY KEY (audit_id, model, manufacturer))",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 15, 创建附件表和备注表, 附件文件按内容的 sha256 存放在数据库旁的目录中
+ */
+fn attachments(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "CREATE TABLE attachments (id INTEGER PRIMARY KEY, model TEXT NOT NULL, \
+         manufacturer TEXT NOT NULL DEFAULT '', kind TEXT NOT NULL, name TEXT NOT NULL, \
+         hash TEXT NOT NULL, size INTEGER NOT NULL, added INTEGER NOT NULL, \
+         UNIQUE (model, manufacturer, hash))",
+        [],
+    )?;
+    tx.execute("CREATE INDEX attachments_hash ON attachments (hash)", [])?;
+    tx.execute(
+        "CREATE TABLE element_notes (id INTEGER PRIMARY KEY, model TEXT NOT NULL, \
+         manufacturer TEXT NOT NULL DEFAULT '', timestamp INTEGER NOT NULL, text TEXT NOT NULL, \
+         operator TEXT NOT NULL DEFAULT '')",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX element_notes_model ON element_notes (model, manufacturer)",
+        [],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 版本 16, 供应商料号增加报价货币, 已有的料号为空字符串表示未指定
+ */
+fn supplier_currency(tx: &Transaction, _tables: &str) -> BomResult<()> {
+    tx.execute(
+        "ALTER TABLE supplier_parts ADD COLUMN currency TEXT NOT NULL DEFAULT ''",
+        [],
+    )?;
+    Ok(())
+}
+
+// 版本 4 写入时的参数解析, 从当时的 value.rs 复制, 单位直接用数据库中的字符串;
+// 以后解析规则改进也不要改这里, 已经升级过的数据库不会再解析一次
+mod v4_params {
+    pub const OHM: &str = "ohm";
+    pub const FARAD: &str = "F";
+    pub const HENRY: &str = "H";
+
+    const CHIP_PACKAGES: [&str; 10] = [
+        "01005", "0201", "0402", "0603", "0805", "1206", "1210", "1812", "2010", "2512",
+    ];
+    const PACKAGE_PREFIXES: [&str; 22] = [
+        "TSSOP", "MSOP", "SSOP", "SOIC", "LQFP", "TQFP", "ESOP", "SOT", "SOD", "SOP", "QFN", "DFN",
+        "QFP", "BGA", "LGA", "DIP", "SIP", "SMA", "SMB", "SMC", "TO", "DO",
+    ];
+
+    // 解析出的参数, 没有解析到的为 None
+    #[derive(Default)]
+    pub struct Params {
+        pub value: Option<f64>,
+        pub unit: Option<&'static str>,
+        pub tolerance: Option<f64>,
+        pub voltage: Option<f64>,
+        pub package: Option<String>,
+    }
+
+    impl Params {
+        pub fn parse(text: &str, hint: Option<&'static str>) -> Params {
+            let mut params = Params::default();
+            let tokens = text
+                .split(|c: char| c.is_whitespace() || ",;/_:：，".contains(c))
+                .filter(|t| !t.is_empty());
+            for token in tokens {
+                if let Some(package) = parse_package(token) {
+                    params.package.get_or_insert(package);
+                    continue;
+                }
+                for part in token.split('-').filter(|t| !t.is_empty()) {
+                    params.parse_token(part, hint);
+                }
+            }
+            params
+        }
+
+        fn parse_token(&mut self, token: &str, hint: Option<&'static str>) {
+            if let Some(package) = parse_package(token) {
+                self.package.get_or_insert(package);
+            } else if let Some(tolerance) = parse_tolerance(token) {
+                self.tolerance.get_or_insert(tolerance);
+            } else if let Some(voltage) = parse_voltage(token) {
+                self.voltage.get_or_insert(voltage);
+            } else if self.value.is_none() {
+                if let Some((value, unit)) = parse_value(token) {
+                    let unit = unit.or(hint);
+                    if unit.is_some() {
+                        self.value = Some(value);
+                        self.unit = unit;
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_value(token: &str) -> Option<(f64, Option<&'static str>)> {
+        let mut chars = token.chars();
+        if let (Some(designator), Some(next)) = (chars.next(), chars.next()) {
+            let hint = match designator {
+                'R' => Some(OHM),
+                'C' => Some(FARAD),
+                'L' => Some(HENRY),
+                _ => None,
+            };
+            if hint.is_some() && next.is_ascii_digit() {
+                if let Some((value, unit, marked)) = parse_number(&token[1..]) {
+                    if marked {
+                        return Some((value, unit.or(hint)));
+                    }
+                }
+            }
+        }
+        parse_number(token).map(|(value, unit, _)| (value, unit))
+    }
+
+    fn parse_number(token: &str) -> Option<(f64, Option<&'static str>, bool)> {
+        let (body, mut unit) = strip_unit(token);
+        let mut int_part = String::new();
+        let mut frac_part = String::new();
+        let mut tail = String::new();
+        let mut multiplier = None;
+        let mut seen_dot = false;
+        for c in body.chars() {
+            if c.is_ascii_digit() {
+                if multiplier.is_some() {
+                    tail.push(c);
+                } else if seen_dot {
+                    frac_part.push(c);
+                } else {
+                    int_part.push(c);
+                }
+            } else if c == '.' && !seen_dot && multiplier.is_none() {
+                seen_dot = true;
+            } else if multiplier.is_none() {
+                multiplier = Some((c, prefix_exponent(c)?));
+            } else {
+                return None;
+            }
+        }
+        if (seen_dot && !tail.is_empty()) || tail.len() > 3 {
+            return None;
+        }
+        if !seen_dot {
+            frac_part = tail;
+        }
+        if int_part.is_empty() && frac_part.is_empty() {
+            return None;
+        }
+        let exponent = multiplier.map_or(0, |(_, e)| e);
+        if matches!(multiplier, Some(('R', _)) | Some(('r', _))) {
+            unit = unit.or(Some(OHM));
+        }
+        let text = format!(
+            "{}.{}e{}",
+            if int_part.is_empty() { "0" } else { &int_part },
+            if frac_part.is_empty() {
+                "0"
+            } else {
+                &frac_part
+            },
+            exponent
+        );
+        let value = text.parse::<f64>().ok()?;
+        Some((value, unit, multiplier.is_some() || unit.is_some()))
+    }
+
+    fn strip_unit(token: &str) -> (&str, Option<&'static str>) {
+        if let Some(body) = token.strip_suffix('Ω') {
+            return (body, Some(OHM));
+        }
+        let lower = token.to_ascii_lowercase();
+        for (suffix, unit) in [("ohms", OHM), ("ohm", OHM), ("f", FARAD), ("h", HENRY)] {
+            if lower.ends_with(suffix) && lower.len() > suffix.len() {
+                return (&token[..token.len() - suffix.len()], Some(unit));
+            }
+        }
+        (token, None)
+    }
+
+    fn prefix_exponent(c: char) -> Option<i32> {
+        match c {
+            'p' | 'P' => Some(-12),
+            'n' | 'N' => Some(-9),
+            'u' | 'U' | 'µ' | 'μ' => Some(-6),
+            'm' => Some(-3),
+            'R' | 'r' => Some(0),
+            'k' | 'K' => Some(3),
+            'M' => Some(6),
+            'G' => Some(9),
+            _ => None,
+        }
+    }
+
+    fn parse_tolerance(token: &str) -> Option<f64> {
+        token
+            .strip_suffix('%')?
+            .trim_start_matches('±')
+            .parse::<f64>()
+            .ok()
+    }
+
+    fn parse_voltage(token: &str) -> Option<f64> {
+        if let Some(body) = token.strip_suffix(['V', 'v']) {
+            let (value, unit, _) = parse_number(body)?;
+            return match unit {
+                None => Some(value),
+                Some(_) => None,
+            };
+        }
+        let (int_part, frac_part) = token.split_once(['V', 'v'])?;
+        if !int_part.is_empty()
+            && int_part.chars().all(|c| c.is_ascii_digit())
+            && frac_part.chars().all(|c| c.is_ascii_digit())
+        {
+            return format!("{int_part}.{frac_part}").parse::<f64>().ok();
+        }
+        None
+    }
+
+    fn parse_package(token: &str) -> Option<String> {
+        if CHIP_PACKAGES.contains(&token) {
+            return Some(token.to_string());
+        }
+        let upper = token.to_uppercase();
+        for prefix in PACKAGE_PREFIXES {
+            if let Some(rest) = upper.strip_prefix(prefix) {
+                let rest = rest.trim_start_matches('-');
+                if rest.is_empty() && matches!(prefix, "SMA" | "SMB" | "SMC") {
+                    return Some(prefix.to_string());
+                }
+                if rest.starts_with(|c: char| c.is_ascii_digit())
+                    && rest.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    return Some(format!("{prefix}-{rest}"));
+                }
+                return None;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 版本 0: 程序加入版本号之前生成的数据库文件
+    const FIXTURE_V0: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL);
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 30, '电阻', '缺货');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电容', 'C10uF', 5, '电容', '缺货');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 80, '电阻', '一般');";
+
+    // 版本 2: 型号 + 厂家唯一, 还没有流水表
+    const FIXTURE_V2: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '');
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 110, '电阻', '丰富');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电容', 'C10uF', 0, '电容', '未知');
+        PRAGMA user_version = 2;";
+
+    // 版本 1: 只有元件表, 型号可以重复
+    const FIXTURE_V1: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL);
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 30, '电阻', '缺货');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 80, '电阻', '一般');
+        PRAGMA user_version = 1;";
+
+    fn fixture(sql: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(sql).unwrap();
+        conn
+    }
+
+    fn rows(conn: &Connection) -> Vec<(String, String, u32)> {
+        let mut stmt = conn
+            .prepare("SELECT model, manufacturer, number FROM bom_data ORDER BY id")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn versions_are_ordered() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1, "{}", migration.describe);
+        }
+    }
+
+    #[test]
+    fn empty_database_to_v1() {
+        let conn = fixture("");
+        assert_eq!(migrate_to(&conn, "bom_data", 1).unwrap(), 1);
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        assert!(table_exists(&conn, "bom_data"));
+    }
+
+    #[test]
+    fn v0_keeps_data_at_v1() {
+        let conn = fixture(FIXTURE_V0);
+        assert_eq!(migrate_to(&conn, "bom_data", 1).unwrap(), 1);
+        let count: u32 = conn
+            .query_row("SELECT COUNT(*) FROM bom_data", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn v1_to_v2_merges_duplicates() {
+        let conn = fixture(FIXTURE_V1);
+        assert_eq!(migrate_to(&conn, "bom_data", 2).unwrap(), 2);
+        assert_eq!(rows(&conn), vec![("R10K".to_string(), String::new(), 110)]);
+        let state: String = conn
+            .query_row("SELECT state FROM bom_data", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(state, "丰富");
+        // 唯一索引生效
+        assert!(conn
+            .execute(
+                "INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('', 'R10K', 1, '', '')",
+                [],
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn v2_to_v3_records_opening_stock() {
+        let conn = fixture(FIXTURE_V2);
+        assert_eq!(migrate_to(&conn, "bom_data", 3).unwrap(), 3);
+        let movements: Vec<(String, i64, String)> = {
+            let mut stmt = conn
+                .prepare("SELECT model, delta, reason FROM stock_movements ORDER BY id")
+                .unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect()
+        };
+        // 数量为 0 的元件不写期初
+        assert_eq!(
+            movements,
+            vec![("R10K".to_string(), 110, REASON_OPENING.to_string())]
+        );
+    }
+
+    // 版本 3: 还没有参数列
+    const FIXTURE_V3: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '');
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻 封装:0805', 'R10K', 110, '电阻', '丰富');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电容 25V', 'C20uF', 5, '电容', '缺货');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('稳压芯片', 'AMS1117', 5, '芯片', '缺货');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('贴片电阻 ±1% SOT-23', '2R2', 60, '电阻', '一般');
+        PRAGMA user_version = 3;";
+
+    #[test]
+    fn v3_to_v4_parses_existing_elements() {
+        let conn = fixture(FIXTURE_V3);
+        assert_eq!(migrate_to(&conn, "bom_data", 4).unwrap(), 4);
+        // 用 quote() 把参数列拼成文本比较, 空列显示为 NULL
+        let mut stmt = conn
+            .prepare(
+                "SELECT model, quote(value) || ' ' || quote(unit) || ' ' || quote(tolerance) || ' ' || quote(voltage) || ' ' || quote(package) \
+                 FROM bom_data ORDER BY id",
+            )
+            .unwrap();
+        let rows: Vec<(String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "R10K".to_string(),
+                    "10000.0 'ohm' NULL NULL '0805'".to_string()
+                ),
+                (
+                    "C20uF".to_string(),
+                    "2.0e-05 'F' NULL 25.0 NULL".to_string()
+                ),
+                (
+                    "AMS1117".to_string(),
+                    "NULL NULL NULL NULL NULL".to_string()
+                ),
+                ("2R2".to_string(), "2.2 'ohm' 1.0 NULL 'SOT-23'".to_string()),
+            ]
+        );
+    }
+
+    // 版本 4: 有参数列, 还没有分类表
+    const FIXTURE_V4: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        INSERT INTO bom_data (describe, model, number, element_type, state, value, unit, package) VALUES ('电阻 封装:0805', 'R10K', 110, '电阻', '丰富', 10000.0, 'ohm', '0805');
+        INSERT INTO bom_data (describe, model, number, element_type, state, value, unit, voltage) VALUES ('电容 25V', 'C20uF', 5, '电容', '缺货', 2.0e-05, 'F', 25.0);
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('稳压芯片', 'AMS1117', 5, '芯片', '缺货');
+        INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('未知元件', 'X1', 1, '未知', '缺货');
+        PRAGMA user_version = 4;";
+
+    #[test]
+    fn v4_to_v5_moves_types_into_categories() {
+        let conn = fixture(FIXTURE_V4);
+        assert_eq!(migrate_to(&conn, "bom_data", 5).unwrap(), 5);
+        let mut stmt = conn
+            .prepare(
+                "SELECT model, COALESCE(c.name, '') FROM bom_data \
+                 LEFT JOIN categories c ON c.id = bom_data.category_id ORDER BY bom_data.id",
+            )
+            .unwrap();
+        let rows: Vec<(String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("R10K".to_string(), "电阻".to_string()),
+                ("C20uF".to_string(), "电容".to_string()),
+                ("AMS1117".to_string(), "芯片".to_string()),
+                ("X1".to_string(), String::new()),
+            ]
+        );
+    }
+
+    // 版本 5: 有分类表, 包含一个自定义子分类
+    const FIXTURE_V5: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id));
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id));
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        PRAGMA user_version = 5;";
+
+    // 表/索引/触发器的定义, 连续空白合并为一个空格, 用来比较两个数据库的结构
+    fn schema(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT type || ' ' || name || ' ' || COALESCE(sql, '') FROM sqlite_master ORDER BY type, name",
+            )
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|r| r.unwrap().split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn fixtures_match_migrated_schema() {
+        // 每个版本的快照和上一个版本的快照升级后的结构相同, 快照和升级步骤不会各改各的
+        let fixtures = [
+            FIXTURE_V0,
+            FIXTURE_V1,
+            FIXTURE_V2,
+            FIXTURE_V3,
+            FIXTURE_V4,
+            FIXTURE_V5,
+            FIXTURE_V6,
+            FIXTURE_V7,
+            FIXTURE_V8,
+            FIXTURE_V9,
+            FIXTURE_V10,
+            FIXTURE_V11,
+            FIXTURE_V12,
+            FIXTURE_V13,
+            FIXTURE_V14,
+            FIXTURE_V15,
+        ];
+        for (i, pair) in fixtures.windows(2).enumerate() {
+            let version = i as u32 + 1;
+            let conn = fixture(pair[0]);
+            assert_eq!(migrate_to(&conn, "bom_data", version).unwrap(), version);
+            assert_eq!(schema(&conn), schema(&fixture(pair[1])), "v{version}");
+        }
+    }
+
+    #[test]
+    fn v5_to_v6_adds_empty_thresholds() {
+        let conn = fixture(FIXTURE_V5);
+        assert_eq!(migrate_to(&conn, "bom_data", 6).unwrap(), 6);
+        // 已有的元件和分类没有阈值, 使用上级或默认值
+        let empty: u32 = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM bom_data WHERE min_stock IS NULL AND reorder_point IS NULL) \
+                 + (SELECT COUNT(*) FROM categories WHERE min_stock IS NULL AND reorder_point IS NULL)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(empty, 2 + 7);
+        conn.execute(
+            "UPDATE categories SET min_stock = 2, reorder_point = 5 WHERE id = 7",
+            [],
+        )
+        .unwrap();
+        assert_eq!(rows(&conn).len(), 2);
+    }
+
+    // 版本 6: 元件和分类有阈值列, 都没有设置
+    const FIXTURE_V6: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        PRAGMA user_version = 6;";
+
+    #[test]
+    fn v6_to_v7_creates_build_tables() {
+        let conn = fixture(FIXTURE_V6);
+        assert_eq!(migrate_to(&conn, "bom_data", 7).unwrap(), 7);
+        assert!(table_exists(&conn, "builds"));
+        conn.execute(
+            "INSERT INTO builds (name, boards, status, created) VALUES ('主板', 2, '预留', 0)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20)",
+            [],
+        )
+        .unwrap();
+        let (manufacturer, finished): (String, Option<i64>) = conn
+            .query_row(
+                "SELECT i.manufacturer, b.finished FROM build_items i JOIN builds b ON b.id = i.build_id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((manufacturer, finished), (String::new(), None));
+        // 元件不受影响
+        assert_eq!(rows(&conn).len(), 2);
+    }
+
+    // 版本 7: 有生产单表, 包含一个预留中的生产单
+    const FIXTURE_V7: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        PRAGMA user_version = 7;";
+
+    #[test]
+    fn v7_to_v8_creates_location_tables() {
+        let conn = fixture(FIXTURE_V7);
+        assert_eq!(migrate_to(&conn, "bom_data", 8).unwrap(), 8);
+        conn.execute("INSERT INTO locations (id, name) VALUES (1, '柜子A')", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1)",
+            [],
+        )
+        .unwrap();
+        // 同一个上级下不能重名, 顶层也一样
+        assert!(conn
+            .execute("INSERT INTO locations (name) VALUES ('柜子A')", [])
+            .is_err());
+        assert!(conn
+            .execute(
+                "INSERT INTO locations (name, parent_id) VALUES ('抽屉1', 1)",
+                []
+            )
+            .is_err());
+        conn.execute(
+            "INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60)",
+            [],
+        )
+        .unwrap();
+        assert!(conn
+            .execute(
+                "INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 1)",
+                []
+            )
+            .is_err());
+    }
+
+    // 版本 8: 有位置表, 部分库存放在位置上
+    const FIXTURE_V8: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        PRAGMA user_version = 8;";
+
+    #[test]
+    fn v8_to_v9_creates_supplier_tables() {
+        let conn = fixture(FIXTURE_V8);
+        assert_eq!(migrate_to(&conn, "bom_data", 9).unwrap(), 9);
+        conn.execute("INSERT INTO suppliers (id, name) VALUES (1, 'LCSC')", [])
+            .unwrap();
+        assert!(conn
+            .execute("INSERT INTO suppliers (name) VALUES ('LCSC')", [])
+            .is_err());
+        conn.execute(
+            "INSERT INTO supplier_parts (id, supplier_id, sku, model) VALUES (1, 1, 'C25804', 'R10K')",
+            [],
+        )
+        .unwrap();
+        // 同一个供应商的料号唯一
+        assert!(conn
+            .execute(
+                "INSERT INTO supplier_parts (supplier_id, sku, model) VALUES (1, 'C25804', 'R1K')",
+                []
+            )
+            .is_err());
+        conn.execute(
+            "INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 100, 0.002, 0)",
+            [],
+        )
+        .unwrap();
+        let (pack_size, moq, mpn): (u32, u32, String) = conn
+            .query_row(
+                "SELECT pack_size, moq, mpn FROM supplier_parts WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((pack_size, moq, mpn), (1, 1, String::new()));
+    }
+
+    // 版本 9: 有供应商表, 包含一个料号和报价
+    const FIXTURE_V9: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        PRAGMA user_version = 9;";
+
+    #[test]
+    fn v9_to_v10_indexes_existing_elements() {
+        let conn = fixture(FIXTURE_V9);
+        assert_eq!(migrate_to(&conn, "bom_data", 10).unwrap(), 10);
+        let index = |conn: &Connection| -> Vec<(String, String, String, String, String)> {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT key, model, describe, category, mpn FROM element_search ORDER BY key",
+                )
+                .unwrap();
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+        };
+        let migrated = index(&conn);
+        assert_eq!(
+            migrated,
+            vec![
+                (
+                    "AMS1117@AMS".to_string(),
+                    "AMS1117 AMS".to_string(),
+                    "稳压芯片".to_string(),
+                    "稳压 芯片 chip ic".to_string(),
+                    "AMS1117-3.3 C6186".to_string()
+                ),
+                (
+                    "R10K".to_string(),
+                    "R10K ".to_string(),
+                    "电阻 封装:0805".to_string(),
+                    "电阻 resistor".to_string(),
+                    String::new()
+                ),
+            ]
+        );
+        // 和程序重建的索引一致
+        super::super::search::reindex_all(&conn, "bom_data").unwrap();
+        assert_eq!(index(&conn), migrated);
+    }
+
+    // 版本 10: 有全文搜索索引
+    const FIXTURE_V10: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, tokenize = 'unicode61 remove_diacritics 2');
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        INSERT INTO element_search (key, model, describe, category, mpn) VALUES ('AMS1117@AMS', 'AMS1117 AMS', '稳压芯片', '稳压 芯片 chip ic', 'AMS1117-3.3 C6186'), ('R10K', 'R10K ', '电阻 封装:0805', '电阻 resistor', '');
+        PRAGMA user_version = 10;";
+
+    #[test]
+    fn v10_to_v11_versions_every_update() {
+        let conn = fixture(FIXTURE_V10);
+        assert_eq!(migrate_to(&conn, "bom_data", 11).unwrap(), 11);
+        let version = |conn: &Connection| -> u32 {
+            conn.query_row(
+                "SELECT version FROM bom_data WHERE model = 'R10K'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        // 已有的元件从 0 开始
+        assert_eq!(version(&conn), 0);
+        conn.execute("UPDATE bom_data SET number = 100 WHERE model = 'R10K'", [])
+            .unwrap();
+        assert_eq!(version(&conn), 1);
+        // 自己加了版本号的修改不会再加
+        conn.execute(
+            "UPDATE bom_data SET number = 90, version = version + 1 WHERE model = 'R10K'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(version(&conn), 2);
+    }
+
+    // 版本 11: 元件有版本号和自动加 1 的触发器
+    const FIXTURE_V11: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER, version INTEGER NOT NULL DEFAULT 0);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, tokenize = 'unicode61 remove_diacritics 2');
+        CREATE TRIGGER bom_data_version AFTER UPDATE ON bom_data FOR EACH ROW WHEN NEW.version = OLD.version BEGIN UPDATE bom_data SET version = OLD.version + 1 WHERE rowid = NEW.rowid; END;
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        INSERT INTO element_search (key, model, describe, category, mpn) VALUES ('AMS1117@AMS', 'AMS1117 AMS', '稳压芯片', '稳压 芯片 chip ic', 'AMS1117-3.3 C6186'), ('R10K', 'R10K ', '电阻 封装:0805', '电阻 resistor', '');
+        PRAGMA user_version = 11;";
+
+    #[test]
+    fn v11_to_v12_creates_part_group_tables() {
+        let conn = fixture(FIXTURE_V11);
+        assert_eq!(migrate_to(&conn, "bom_data", 12).unwrap(), 12);
+        conn.execute(
+            "INSERT INTO part_groups (id, name) VALUES (1, 'LDO 3.3V')",
+            [],
+        )
+        .unwrap();
+        assert!(conn
+            .execute("INSERT INTO part_groups (name) VALUES ('LDO 3.3V')", [])
+            .is_err());
+        conn.execute(
+            "INSERT INTO part_group_members (group_id, model, manufacturer) VALUES (1, 'AMS1117', 'AMS')",
+            [],
+        )
+        .unwrap();
+        // 同一个元件在组内只能出现一次
+        assert!(conn
+            .execute(
+                "INSERT INTO part_group_members (group_id, model, manufacturer, priority) VALUES (1, 'AMS1117', 'AMS', 1)",
+                []
+            )
+            .is_err());
+        let priority: u32 = conn
+            .query_row("SELECT priority FROM part_group_members", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(priority, 0);
+    }
+
+    // 版本 12: 有替代料组
+    const FIXTURE_V12: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER, version INTEGER NOT NULL DEFAULT 0);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, tokenize = 'unicode61 remove_diacritics 2');
+        CREATE TRIGGER bom_data_version AFTER UPDATE ON bom_data FOR EACH ROW WHEN NEW.version = OLD.version BEGIN UPDATE bom_data SET version = OLD.version + 1 WHERE rowid = NEW.rowid; END;
+        CREATE TABLE part_groups (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE part_group_members (group_id INTEGER NOT NULL REFERENCES part_groups (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', priority INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (group_id, model, manufacturer));
+        CREATE INDEX part_group_members_model ON part_group_members (model);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        INSERT INTO element_search (key, model, describe, category, mpn) VALUES ('AMS1117@AMS', 'AMS1117 AMS', '稳压芯片', '稳压 芯片 chip ic', 'AMS1117-3.3 C6186'), ('R10K', 'R10K ', '电阻 封装:0805', '电阻 resistor', '');
+        INSERT INTO part_groups (id, name) VALUES (1, 'LDO 3.3V');
+        INSERT INTO part_group_members (group_id, model, manufacturer) VALUES (1, 'AMS1117', 'AMS');
+        PRAGMA user_version = 12;";
+
+    #[test]
+    fn v12_to_v13_adds_preferred_and_purchase_orders() {
+        let conn = fixture(FIXTURE_V12);
+        assert_eq!(migrate_to(&conn, "bom_data", 13).unwrap(), 13);
+        // 已有的供应商料号都不是首选
+        let preferred: bool = conn
+            .query_row("SELECT preferred FROM supplier_parts", [], |row| row.get(0))
+            .unwrap();
+        assert!(!preferred);
+        conn.execute(
+            "INSERT INTO purchase_orders (id, supplier, status, created) VALUES (1, 'LCSC', '草稿', 0)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO purchase_order_items (order_id, model, needed, quantity) VALUES (1, 'R10K', 40, 100)",
+            [],
+        )
+        .unwrap();
+        let (sku, unit_price): (String, Option<f64>) = conn
+            .query_row(
+                "SELECT sku, unit_price FROM purchase_order_items",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((sku, unit_price), (String::new(), None));
+    }
+
+    // 版本 13: 料号有首选标记, 有采购单表
+    const FIXTURE_V13: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER, version INTEGER NOT NULL DEFAULT 0);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, preferred INTEGER NOT NULL DEFAULT 0, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, tokenize = 'unicode61 remove_diacritics 2');
+        CREATE TRIGGER bom_data_version AFTER UPDATE ON bom_data FOR EACH ROW WHEN NEW.version = OLD.version BEGIN UPDATE bom_data SET version = OLD.version + 1 WHERE rowid = NEW.rowid; END;
+        CREATE TABLE part_groups (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE part_group_members (group_id INTEGER NOT NULL REFERENCES part_groups (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', priority INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (group_id, model, manufacturer));
+        CREATE INDEX part_group_members_model ON part_group_members (model);
+        CREATE TABLE purchase_orders (id INTEGER PRIMARY KEY, supplier TEXT NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, received INTEGER);
+        CREATE TABLE purchase_order_items (id INTEGER PRIMARY KEY, order_id INTEGER NOT NULL REFERENCES purchase_orders (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', sku TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', needed INTEGER NOT NULL, quantity INTEGER NOT NULL, unit_price REAL);
+        CREATE INDEX purchase_order_items_order ON purchase_order_items (order_id);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        INSERT INTO element_search (key, model, describe, category, mpn) VALUES ('AMS1117@AMS', 'AMS1117 AMS', '稳压芯片', '稳压 芯片 chip ic', 'AMS1117-3.3 C6186'), ('R10K', 'R10K ', '电阻 封装:0805', '电阻 resistor', '');
+        INSERT INTO part_groups (id, name) VALUES (1, 'LDO 3.3V');
+        INSERT INTO part_group_members (group_id, model, manufacturer) VALUES (1, 'AMS1117', 'AMS');
+        INSERT INTO purchase_orders (id, supplier, status, created) VALUES (1, 'LCSC', '草稿', 0);
+        INSERT INTO purchase_order_items (order_id, model, manufacturer, sku, mpn, needed, quantity, unit_price) VALUES (1, 'AMS1117', 'AMS', 'C6186', 'AMS1117-3.3', 5, 10, 0.35);
+        PRAGMA user_version = 13;";
+
+    #[test]
+    fn v13_to_v14_creates_audit_tables() {
+        let conn = fixture(FIXTURE_V13);
+        assert_eq!(migrate_to(&conn, "bom_data", 14).unwrap(), 14);
+        conn.execute(
+            "INSERT INTO audits (id, scope, status, created) VALUES (1, '全部', '进行中', 0)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO audit_lines (audit_id, model, expected) VALUES (1, 'R10K', 110)",
+            [],
+        )
+        .unwrap();
+        // 每个元件在一次盘点中只有一行
+        assert!(conn
+            .execute(
+                "INSERT INTO audit_lines (audit_id, model, expected) VALUES (1, 'R10K', 110)",
+                []
+            )
+            .is_err());
+        let (path, counted): (String, Option<u32>) = conn
+            .query_row(
+                "SELECT a.path, l.counted FROM audit_lines l JOIN audits a ON a.id = l.audit_id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((path, counted), (String::new(), None));
+    }
+
+    // 版本 14: 有盘点单表
+    const FIXTURE_V14: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER, version INTEGER NOT NULL DEFAULT 0);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, preferred INTEGER NOT NULL DEFAULT 0, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, tokenize = 'unicode61 remove_diacritics 2');
+        CREATE TRIGGER bom_data_version AFTER UPDATE ON bom_data FOR EACH ROW WHEN NEW.version = OLD.version BEGIN UPDATE bom_data SET version = OLD.version + 1 WHERE rowid = NEW.rowid; END;
+        CREATE TABLE part_groups (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE part_group_members (group_id INTEGER NOT NULL REFERENCES part_groups (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', priority INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (group_id, model, manufacturer));
+        CREATE INDEX part_group_members_model ON part_group_members (model);
+        CREATE TABLE purchase_orders (id INTEGER PRIMARY KEY, supplier TEXT NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, received INTEGER);
+        CREATE TABLE purchase_order_items (id INTEGER PRIMARY KEY, order_id INTEGER NOT NULL REFERENCES purchase_orders (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', sku TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', needed INTEGER NOT NULL, quantity INTEGER NOT NULL, unit_price REAL);
+        CREATE INDEX purchase_order_items_order ON purchase_order_items (order_id);
+        CREATE TABLE audits (id INTEGER PRIMARY KEY, scope TEXT NOT NULL, scope_id INTEGER, path TEXT NOT NULL DEFAULT '', status TEXT NOT NULL, created INTEGER NOT NULL, closed INTEGER);
+        CREATE TABLE audit_lines (audit_id INTEGER NOT NULL REFERENCES audits (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', expected INTEGER NOT NULL, counted INTEGER, PRIMARY KEY (audit_id, model, manufacturer));
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        INSERT INTO element_search (key, model, describe, category, mpn) VALUES ('AMS1117@AMS', 'AMS1117 AMS', '稳压芯片', '稳压 芯片 chip ic', 'AMS1117-3.3 C6186'), ('R10K', 'R10K ', '电阻 封装:0805', '电阻 resistor', '');
+        INSERT INTO part_groups (id, name) VALUES (1, 'LDO 3.3V');
+        INSERT INTO part_group_members (group_id, model, manufacturer) VALUES (1, 'AMS1117', 'AMS');
+        INSERT INTO purchase_orders (id, supplier, status, created) VALUES (1, 'LCSC', '草稿', 0);
+        INSERT INTO purchase_order_items (order_id, model, manufacturer, sku, mpn, needed, quantity, unit_price) VALUES (1, 'AMS1117', 'AMS', 'C6186', 'AMS1117-3.3', 5, 10, 0.35);
+        INSERT INTO audits (id, scope, status, created) VALUES (1, '全部', '进行中', 0);
+        INSERT INTO audit_lines (audit_id, model, expected) VALUES (1, 'R10K', 110);
+        PRAGMA user_version = 14;";
+
+    #[test]
+    fn v14_to_v15_creates_attachment_tables() {
+        let conn = fixture(FIXTURE_V14);
+        assert_eq!(migrate_to(&conn, "bom_data", 15).unwrap(), 15);
+        let insert = "INSERT INTO attachments (model, kind, name, hash, size, added) \
+                      VALUES ('R10K', '数据手册', 'r.pdf', 'abc', 3, 0)";
+        conn.execute(insert, []).unwrap();
+        // 同一个元件的同一个文件只存一次
+        assert!(conn.execute(insert, []).is_err());
+        conn.execute(
+            "INSERT INTO element_notes (model, timestamp, text) VALUES ('R10K', 0, '换了新批次')",
+            [],
+        )
+        .unwrap();
+        let (manufacturer, operator): (String, String) = conn
+            .query_row(
+                "SELECT manufacturer, operator FROM element_notes",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((manufacturer, operator), (String::new(), String::new()));
+    }
+
+    // 版本 15: 有附件和备注表
+    const FIXTURE_V15: &str = "
+        CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', value REAL, unit TEXT, tolerance REAL, voltage REAL, package TEXT, category_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER, version INTEGER NOT NULL DEFAULT 0);
+        CREATE UNIQUE INDEX bom_data_model_manufacturer ON bom_data (model, manufacturer);
+        CREATE INDEX bom_data_unit_value ON bom_data (unit, value);
+        CREATE TABLE stock_movements (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', delta INTEGER NOT NULL, reason TEXT NOT NULL DEFAULT '', operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX stock_movements_model ON stock_movements (model, manufacturer, timestamp);
+        CREATE TABLE categories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES categories (id), min_stock INTEGER, reorder_point INTEGER);
+        CREATE UNIQUE INDEX categories_parent_name ON categories (COALESCE(parent_id, 0), name);
+        CREATE TABLE builds (id INTEGER PRIMARY KEY, name TEXT NOT NULL, boards INTEGER NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, finished INTEGER);
+        CREATE TABLE build_items (id INTEGER PRIMARY KEY, build_id INTEGER NOT NULL REFERENCES builds (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', required INTEGER NOT NULL, reserved INTEGER NOT NULL);
+        CREATE INDEX build_items_build ON build_items (build_id);
+        CREATE TABLE locations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, parent_id INTEGER REFERENCES locations (id));
+        CREATE UNIQUE INDEX locations_parent_name ON locations (COALESCE(parent_id, 0), name);
+        CREATE TABLE stock_locations (model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', location_id INTEGER NOT NULL REFERENCES locations (id), quantity INTEGER NOT NULL, PRIMARY KEY (model, manufacturer, location_id));
+        CREATE TABLE suppliers (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE supplier_parts (id INTEGER PRIMARY KEY, supplier_id INTEGER NOT NULL REFERENCES suppliers (id), sku TEXT NOT NULL, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', pack_size INTEGER NOT NULL DEFAULT 1, moq INTEGER NOT NULL DEFAULT 1, preferred INTEGER NOT NULL DEFAULT 0, UNIQUE (supplier_id, sku));
+        CREATE INDEX supplier_parts_element ON supplier_parts (model, manufacturer);
+        CREATE TABLE price_breaks (part_id INTEGER NOT NULL REFERENCES supplier_parts (id), min_quantity INTEGER NOT NULL, unit_price REAL NOT NULL, effective INTEGER NOT NULL);
+        CREATE INDEX price_breaks_part ON price_breaks (part_id, effective);
+        CREATE VIRTUAL TABLE element_search USING fts5 (key UNINDEXED, model, describe, category, mpn, tokenize = 'unicode61 remove_diacritics 2');
+        CREATE TRIGGER bom_data_version AFTER UPDATE ON bom_data FOR EACH ROW WHEN NEW.version = OLD.version BEGIN UPDATE bom_data SET version = OLD.version + 1 WHERE rowid = NEW.rowid; END;
+        CREATE TABLE part_groups (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE part_group_members (group_id INTEGER NOT NULL REFERENCES part_groups (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', priority INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (group_id, model, manufacturer));
+        CREATE INDEX part_group_members_model ON part_group_members (model);
+        CREATE TABLE purchase_orders (id INTEGER PRIMARY KEY, supplier TEXT NOT NULL, status TEXT NOT NULL, created INTEGER NOT NULL, received INTEGER);
+        CREATE TABLE purchase_order_items (id INTEGER PRIMARY KEY, order_id INTEGER NOT NULL REFERENCES purchase_orders (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', sku TEXT NOT NULL DEFAULT '', mpn TEXT NOT NULL DEFAULT '', needed INTEGER NOT NULL, quantity INTEGER NOT NULL, unit_price REAL);
+        CREATE INDEX purchase_order_items_order ON purchase_order_items (order_id);
+        CREATE TABLE audits (id INTEGER PRIMARY KEY, scope TEXT NOT NULL, scope_id INTEGER, path TEXT NOT NULL DEFAULT '', status TEXT NOT NULL, created INTEGER NOT NULL, closed INTEGER);
+        CREATE TABLE audit_lines (audit_id INTEGER NOT NULL REFERENCES audits (id), model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', expected INTEGER NOT NULL, counted INTEGER, PRIMARY KEY (audit_id, model, manufacturer));
+        CREATE TABLE attachments (id INTEGER PRIMARY KEY, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', kind TEXT NOT NULL, name TEXT NOT NULL, hash TEXT NOT NULL, size INTEGER NOT NULL, added INTEGER NOT NULL, UNIQUE (model, manufacturer, hash));
+        CREATE INDEX attachments_hash ON attachments (hash);
+        CREATE TABLE element_notes (id INTEGER PRIMARY KEY, model TEXT NOT NULL, manufacturer TEXT NOT NULL DEFAULT '', timestamp INTEGER NOT NULL, text TEXT NOT NULL, operator TEXT NOT NULL DEFAULT '');
+        CREATE INDEX element_notes_model ON element_notes (model, manufacturer);
+        INSERT INTO categories (id, name) VALUES (1, '电阻'), (2, '二极管'), (3, '三极管'), (4, '电容'), (5, '电感'), (6, '芯片');
+        INSERT INTO categories (id, name, parent_id) VALUES (7, '稳压', 6);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, value, unit, package, category_id) VALUES ('电阻 封装:0805', 'R10K', '', 110, '电阻', '丰富', 10000.0, 'ohm', '0805', 1);
+        INSERT INTO bom_data (describe, model, manufacturer, number, element_type, state, category_id) VALUES ('稳压芯片', 'AMS1117', 'AMS', 5, '芯片', '缺货', 7);
+        INSERT INTO builds (id, name, boards, status, created) VALUES (1, '主板', 2, '预留', 0);
+        INSERT INTO build_items (build_id, model, required, reserved) VALUES (1, 'R10K', 20, 20);
+        INSERT INTO locations (id, name) VALUES (1, '柜子A');
+        INSERT INTO locations (id, name, parent_id) VALUES (2, '抽屉1', 1);
+        INSERT INTO stock_locations (model, location_id, quantity) VALUES ('R10K', 2, 60);
+        INSERT INTO suppliers (id, name) VALUES (1, 'LCSC');
+        INSERT INTO supplier_parts (id, supplier_id, sku, model, manufacturer, mpn) VALUES (1, 1, 'C6186', 'AMS1117', 'AMS', 'AMS1117-3.3');
+        INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (1, 10, 0.35, 0);
+        INSERT INTO element_search (key, model, describe, category, mpn) VALUES ('AMS1117@AMS', 'AMS1117 AMS', '稳压芯片', '稳压 芯片 chip ic', 'AMS1117-3.3 C6186'), ('R10K', 'R10K ', '电阻 封装:0805', '电阻 resistor', '');
+        INSERT INTO part_groups (id, name) VALUES (1, 'LDO 3.3V');
+        INSERT INTO part_group_members (group_id, model, manufacturer) VALUES (1, 'AMS1117', 'AMS');
+        INSERT INTO purchase_orders (id, supplier, status, created) VALUES (1, 'LCSC', '草稿', 0);
+        INSERT INTO purchase_order_items (order_id, model, manufacturer, sku, mpn, needed, quantity, unit_price) VALUES (1, 'AMS1117', 'AMS', 'C6186', 'AMS1117-3.3', 5, 10, 0.35);
+        INSERT INTO audits (id, scope, status, created) VALUES (1, '全部', '进行中', 0);
+        INSERT INTO audit_lines (audit_id, model, expected) VALUES (1, 'R10K', 110);
+        INSERT INTO attachments (model, kind, name, hash, size, added) VALUES ('R10K', '数据手册', 'r.pdf', 'abc', 3, 0);
+        INSERT INTO element_notes (model, timestamp, text) VALUES ('R10K', 0, '换了新批次');
+        PRAGMA user_version = 15;";
+
+    #[test]
+    fn v15_to_v16_adds_supplier_currency() {
+        let conn = fixture(FIXTURE_V15);
+        assert_eq!(migrate_to(&conn, "bom_data", 16).unwrap(), 16);
+        // 已有的料号没有指定货币
+        let currency: String = conn
+            .query_row("SELECT currency FROM supplier_parts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(currency, "");
+    }
+
+    #[test]
+    fn v0_to_latest() {
+        let conn = fixture(FIXTURE_V0);
+        assert_eq!(migrate(&conn, "bom_data").unwrap(), latest_version());
+        assert_eq!(
+            rows(&conn),
+            vec![
+                ("R10K".to_string(), String::new(), 110),
+                ("C10uF".to_string(), String::new(), 5)
+            ]
+        );
+        // 再次执行没有变化
+        assert_eq!(migrate(&conn, "bom_data").unwrap(), latest_version());
+    }
+
+    #[test]
+    fn newer_database_is_rejected() {
+        let conn = fixture(format!("PRAGMA user_version = {};", latest_version() + 1).as_str());
+        assert!(matches!(
+            migrate(&conn, "bom_data"),
+            Err(BomError::UnsupportedVersion { .. })
+        ));
+    }
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [table],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+}
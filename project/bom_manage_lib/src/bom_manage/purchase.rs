@@ -0,0 +1,716 @@
+/*
+ * @Description: 采购单, 汇总到达补货点的元件和生产单缺的料, 按首选供应商分组,
+ * 数量按包装数量和最小起订量取整, 可以导出为 CSV 或 Markdown, 收货时一次入库
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\purchase.rs
+ */
+use super::ledger::{now_timestamp, MovementNote};
+use super::supplier::{price_for, SupplierPart};
+use super::threshold::{query_thresholds, Thresholds};
+use super::{add_number, element_key, split_key, BomError, BomResult, DataBaseInfo, Element};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::{fs, io::Write, path::Path};
+
+// 新建采购单
+macro_rules! insert_order_template {
+    () => {
+        "INSERT INTO purchase_orders (supplier, status, created) VALUES (?, ?, ?)"
+    };
+}
+// 按导出的内容写入采购单, id 为 NULL 时分配新的采购单号
+macro_rules! insert_order_record_template {
+    () => {
+        "INSERT INTO purchase_orders (id, supplier, status, created, received) VALUES (?, ?, ?, ?, ?)"
+    };
+}
+// 新增采购单的一个元件
+macro_rules! insert_order_item_template {
+    () => {
+        "INSERT INTO purchase_order_items (order_id, model, manufacturer, sku, mpn, needed, quantity, unit_price) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    };
+}
+// 读取采购单
+macro_rules! get_order_template {
+    () => {
+        "SELECT id, supplier, status, created, received FROM purchase_orders"
+    };
+}
+// 读取采购单的元件
+macro_rules! get_order_items_template {
+    () => {
+        "SELECT model, manufacturer, sku, mpn, needed, quantity, unit_price FROM purchase_order_items \
+         WHERE order_id = ? ORDER BY id"
+    };
+}
+// 修改采购单状态, 只有未收货的采购单可以修改
+macro_rules! update_order_status_template {
+    () => {
+        "UPDATE purchase_orders SET status = ?, received = ? WHERE id = ? AND status = 'open'"
+    };
+}
+// 所有未收货的采购单对每个元件采购的数量
+macro_rules! get_on_order_template {
+    () => {
+        "SELECT i.model, i.manufacturer, SUM(i.quantity) FROM purchase_order_items i \
+         JOIN purchase_orders o ON o.id = i.order_id WHERE o.status = 'open' \
+         GROUP BY i.model, i.manufacturer"
+    };
+}
+// 所有预留中的生产单对每个元件缺少的数量
+macro_rules! get_build_shortfall_template {
+    () => {
+        "SELECT i.model, i.manufacturer, SUM(i.required - i.reserved) FROM build_items i \
+         JOIN builds b ON b.id = i.build_id WHERE b.status = 'reserved' AND i.required > i.reserved \
+         GROUP BY i.model, i.manufacturer"
+    };
+}
+
+// 采购单状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Open,      // 已下单, 等待收货
+    Received,  // 已收货, 元件已入库
+    Cancelled, // 已取消
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OrderStatus::Open => "open",
+            OrderStatus::Received => "received",
+            OrderStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl OrderStatus {
+    /**
+     * @description: 数据库中的状态字符串转为 OrderStatus
+     * @param {&str} s
+     * @return {*}
+     */
+    pub fn from_string(s: &str) -> BomResult<OrderStatus> {
+        match s {
+            "open" => Ok(OrderStatus::Open),
+            "received" => Ok(OrderStatus::Received),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            _ => Err(BomError::InvalidStatus(s.to_string())),
+        }
+    }
+}
+
+// 采购单中的一个元件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub model: String,           // 元件型号
+    pub manufacturer: String,    // 厂家
+    pub sku: String,             // 供应商料号, 没有供应商料号时为空
+    pub mpn: String,             // 厂家料号
+    pub needed: u32,             // 需要的数量
+    pub quantity: u32,           // 采购数量, 按包装数量和最小起订量取整
+    pub unit_price: Option<f64>, // 单价, 没有报价时为 None
+}
+
+impl OrderLine {
+    /**
+     * @description: 元件在 element_map 中的键
+     * @return {*}
+     */
+    pub fn key(&self) -> String {
+        element_key(&self.model, &self.manufacturer)
+    }
+
+    /**
+     * @description: 这一行的金额
+     * @return {没有报价时为 None}
+     */
+    pub fn total(&self) -> Option<f64> {
+        self.unit_price.map(|p| p * f64::from(self.quantity))
+    }
+}
+
+// 一个采购单, 一个供应商一张
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub id: i64,               // 采购单号, 没有保存的草稿为 0
+    pub supplier: String,      // 供应商, 元件没有供应商料号时为空
+    pub status: OrderStatus,   // 状态
+    pub created: i64,          // 创建时间, unix 时间戳
+    pub received: Option<i64>, // 收货或取消的时间
+    pub lines: Vec<OrderLine>, // 采购的元件
+}
+
+impl PurchaseOrder {
+    /**
+     * @description: 有报价的元件的总金额
+     * @return {*}
+     */
+    pub fn total(&self) -> f64 {
+        // 空迭代器的 sum 为 -0.0, 从 0.0 开始累加
+        self.lines
+            .iter()
+            .filter_map(OrderLine::total)
+            .fold(0.0, |total, t| total + t)
+    }
+}
+
+// 采购单导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderFormat {
+    Csv,      // 每个元件一行, 多个采购单写在同一个文件中
+    Markdown, // 每个采购单一个表格
+}
+
+impl OrderFormat {
+    /**
+     * @description: 按扩展名判断格式, .md/.markdown 为 Markdown, 其他为 CSV
+     * @param {&str} path 文件路径
+     * @return {*}
+     */
+    pub fn from_path(path: &str) -> OrderFormat {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown") => {
+                OrderFormat::Markdown
+            }
+            _ => OrderFormat::Csv,
+        }
+    }
+}
+
+/**
+ * @description: 采购数量, 不少于最小起订量, 并向上取整到包装数量的整数倍
+ * @param {u32} needed 需要的数量
+ * @param {u32} pack_size 包装数量
+ * @param {u32} moq 最小起订量
+ * @return {*}
+ */
+pub fn order_quantity(needed: u32, pack_size: u32, moq: u32) -> u32 {
+    let pack_size = pack_size.max(1);
+    needed.max(moq).div_ceil(pack_size) * pack_size
+}
+
+/**
+ * @description: 元件需要补充的数量, 让库存扣除生产单预留和缺料、加上在途数量后超过补货点
+ * @param {u32} number 库存数量
+ * @param {&Thresholds} thresholds 生效的阈值
+ * @param {u32} committed 生产单预留和缺少的数量
+ * @param {u32} on_order 未收货的采购单数量
+ * @return {不需要补充时为 0}
+ */
+pub fn reorder_quantity(
+    number: u32,
+    thresholds: &Thresholds,
+    committed: u32,
+    on_order: u32,
+) -> u32 {
+    (thresholds.reorder_point + 1 + committed).saturating_sub(number.saturating_add(on_order))
+}
+
+// 报价单元格, 没有报价时为空
+fn price_text(price: Option<f64>) -> String {
+    price.map_or_else(String::new, |p| format!("{p:.4}"))
+}
+
+// 写入采购单的一个元件
+fn insert_order_line(conn: &Connection, id: i64, line: &OrderLine) -> BomResult<()> {
+    conn.execute(
+        insert_order_item_template!(),
+        (
+            id,
+            &line.model,
+            &line.manufacturer,
+            &line.sku,
+            &line.mpn,
+            line.needed,
+            line.quantity,
+            line.unit_price,
+        ),
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 按导出的内容写入采购单和它的元件, 用于导入
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&PurchaseOrder} order 采购单
+ * @param {Option<i64>} id 采购单号, None 时分配新的
+ * @return {采购单号}
+ */
+pub(crate) fn write_purchase_order(
+    conn: &Connection,
+    order: &PurchaseOrder,
+    id: Option<i64>,
+) -> BomResult<i64> {
+    conn.execute(
+        insert_order_record_template!(),
+        (
+            id,
+            &order.supplier,
+            order.status.to_string(),
+            order.created,
+            order.received,
+        ),
+    )?;
+    let id = conn.last_insert_rowid();
+    for line in order.lines.iter() {
+        insert_order_line(conn, id, line)?;
+    }
+    Ok(id)
+}
+
+/**
+ * @description: 写出 CSV 格式的采购单
+ * @param {W} writer 输出
+ * @param {&[PurchaseOrder]} orders 采购单
+ * @return {*}
+ */
+pub fn write_orders_csv<W: Write>(writer: W, orders: &[PurchaseOrder]) -> BomResult<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let invalid = |e: csv::Error| BomError::InvalidExport(e.to_string());
+    writer
+        .write_record([
+            "order",
+            "supplier",
+            "sku",
+            "mpn",
+            "model",
+            "manufacturer",
+            "needed",
+            "quantity",
+            "unit_price",
+            "total",
+        ])
+        .map_err(invalid)?;
+    for order in orders {
+        for line in order.lines.iter() {
+            writer
+                .write_record([
+                    order.id.to_string().as_str(),
+                    &order.supplier,
+                    &line.sku,
+                    &line.mpn,
+                    &line.model,
+                    &line.manufacturer,
+                    &line.needed.to_string(),
+                    &line.quantity.to_string(),
+                    &price_text(line.unit_price),
+                    &price_text(line.total()),
+                ])
+                .map_err(invalid)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Markdown 表格单元格中的 '|' 需要转义
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/**
+ * @description: 生成 Markdown 格式的采购单, 每个采购单一个标题和表格
+ * @param {&[PurchaseOrder]} orders 采购单
+ * @return {*}
+ */
+pub fn orders_markdown(orders: &[PurchaseOrder]) -> String {
+    let mut text = String::new();
+    for order in orders {
+        let id = match order.id {
+            0 => "draft".to_string(),
+            id => format!("#{id}"),
+        };
+        let supplier = match order.supplier.as_str() {
+            "" => "(no supplier)",
+            supplier => supplier,
+        };
+        text += &format!("# Purchase order {id}: {}\n\n", markdown_cell(supplier));
+        text += &format!("Status: {}\n\n", order.status);
+        text += "| SKU | MPN | Model | Manufacturer | Needed | Quantity | Unit price | Total |\n";
+        text += "|---|---|---|---|---:|---:|---:|---:|\n";
+        for line in order.lines.iter() {
+            text += &format!(
+                "| {} | {} | {} | {} | {} | {} | {} | {} |\n",
+                markdown_cell(&line.sku),
+                markdown_cell(&line.mpn),
+                markdown_cell(&line.model),
+                markdown_cell(&line.manufacturer),
+                line.needed,
+                line.quantity,
+                price_text(line.unit_price),
+                price_text(line.total())
+            );
+        }
+        text += &format!("\nTotal: {:.2}\n\n", order.total());
+    }
+    text
+}
+
+/**
+ * @description: 把采购单写到文件, 格式由扩展名决定, 见 OrderFormat::from_path
+ * @param {&[PurchaseOrder]} orders 采购单
+ * @param {&str} path 文件路径
+ * @return {*}
+ */
+pub fn write_orders(orders: &[PurchaseOrder], path: &str) -> BomResult<()> {
+    match OrderFormat::from_path(path) {
+        OrderFormat::Csv => write_orders_csv(fs::File::create(path)?, orders),
+        OrderFormat::Markdown => Ok(fs::write(path, orders_markdown(orders))?),
+    }
+}
+
+// DataBaseInfo 的采购单接口
+impl DataBaseInfo {
+    // 执行按元件汇总数量的查询, 返回元件键 -> 数量
+    fn quantities(&self, sql: &str) -> BomResult<HashMap<String, u32>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                element_key(&row.get::<usize, String>(0)?, &row.get::<usize, String>(1)?),
+                row.get::<usize, u32>(2)?,
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+    }
+
+    /**
+     * @description: 选择元件的供应商料号, 优先首选料号, 其次按采购数量报价最低的, 都没有报价时用第一个
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {u32} needed 需要的数量
+     * @param {i64} at 报价时间
+     * @return {料号和单价, 没有供应商料号时为 None}
+     */
+    fn order_part(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        needed: u32,
+        at: i64,
+    ) -> BomResult<Option<(SupplierPart, Option<f64>)>> {
+        let mut best: Option<(SupplierPart, Option<f64>)> = None;
+        for part in self.supplier_parts(model, manufacturer)? {
+            let quantity = order_quantity(needed, part.pack_size, part.moq);
+            let price = price_for(&self.price_breaks(part.id, at)?, quantity);
+            if part.preferred {
+                return Ok(Some((part, price)));
+            }
+            let better = match (&best, price) {
+                (None, _) => true,
+                (Some((_, None)), Some(_)) => true,
+                (Some((_, Some(b))), Some(p)) => p < *b,
+                _ => false,
+            };
+            if better {
+                best = Some((part, price));
+            }
+        }
+        Ok(best)
+    }
+
+    // 元件的采购行和选择的供应商, 没有供应商料号时供应商为空, 采购数量为需要的数量
+    fn order_line(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        needed: u32,
+        at: i64,
+    ) -> BomResult<(String, OrderLine)> {
+        let line = OrderLine {
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            sku: String::new(),
+            mpn: String::new(),
+            needed,
+            quantity: needed,
+            unit_price: None,
+        };
+        Ok(match self.order_part(model, manufacturer, needed, at)? {
+            Some((part, unit_price)) => (
+                part.supplier,
+                OrderLine {
+                    sku: part.sku,
+                    mpn: part.mpn,
+                    quantity: order_quantity(needed, part.pack_size, part.moq),
+                    unit_price,
+                    ..line
+                },
+            ),
+            None => (String::new(), line),
+        })
+    }
+
+    /**
+     * @description: 生成采购单草稿, 不写入数据库. 元件的可用数量 (库存 - 生产单预留和缺料 + 在途)
+     * 不超过补货点时采购, 生产单中库中没有的元件按缺少的数量采购,
+     * 按选择的供应商分组, 没有供应商料号的元件放在最后一张
+     * @return {每个供应商一张采购单, 按供应商名排序}
+     */
+    pub fn draft_purchase_orders(&self) -> BomResult<Vec<PurchaseOrder>> {
+        let now = now_timestamp();
+        let reserved = self.reserved_stock()?;
+        let shortfall = self.quantities(get_build_shortfall_template!())?;
+        let on_order = self.quantities(get_on_order_template!())?;
+        let elements = query_thresholds(
+            &self.conn,
+            &self.tables,
+            " ORDER BY t.model, t.manufacturer",
+            &[],
+        )?;
+        let mut wanted: Vec<(String, String, u32)> = Vec::new();
+        let mut stocked = HashSet::new();
+        for (element, thresholds) in elements {
+            let key = element.key();
+            let committed = reserved.get(&key).unwrap_or(&0) + shortfall.get(&key).unwrap_or(&0);
+            let needed = reorder_quantity(
+                element.number,
+                &thresholds,
+                committed,
+                *on_order.get(&key).unwrap_or(&0),
+            );
+            stocked.insert(key);
+            if needed > 0 {
+                wanted.push((element.model, element.manufacturer, needed));
+            }
+        }
+        // 库中没有的元件只补生产单缺的数量
+        let mut missing: Vec<(&String, &u32)> = shortfall
+            .iter()
+            .filter(|(key, _)| !stocked.contains(*key))
+            .collect();
+        missing.sort();
+        for (key, short) in missing {
+            let needed = short.saturating_sub(*on_order.get(key).unwrap_or(&0));
+            if needed > 0 {
+                let (model, manufacturer) = split_key(key);
+                wanted.push((model.to_string(), manufacturer.to_string(), needed));
+            }
+        }
+
+        let mut orders: Vec<PurchaseOrder> = Vec::new();
+        for (model, manufacturer, needed) in wanted {
+            let (supplier, line) = self.order_line(&model, &manufacturer, needed, now)?;
+            match orders.iter_mut().find(|o| o.supplier == supplier) {
+                Some(order) => order.lines.push(line),
+                None => orders.push(PurchaseOrder {
+                    id: 0,
+                    supplier,
+                    status: OrderStatus::Open,
+                    created: now,
+                    received: None,
+                    lines: vec![line],
+                }),
+            }
+        }
+        orders.sort_by(|a, b| {
+            (a.supplier.is_empty(), &a.supplier).cmp(&(b.supplier.is_empty(), &b.supplier))
+        });
+        Ok(orders)
+    }
+
+    /**
+     * @description: 保存采购单, 在一个事务中完成, 保存后数量算作在途
+     * @param {&[PurchaseOrder]} orders 采购单, 一般为 draft_purchase_orders 的结果
+     * @return {采购单号}
+     */
+    pub fn create_purchase_orders(&self, orders: &[PurchaseOrder]) -> BomResult<Vec<i64>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let now = now_timestamp();
+        let mut ids = Vec::new();
+        for order in orders {
+            tx.execute(
+                insert_order_template!(),
+                (&order.supplier, OrderStatus::Open.to_string(), now),
+            )?;
+            let id = tx.last_insert_rowid();
+            for line in order.lines.iter() {
+                insert_order_line(&tx, id, line)?;
+            }
+            ids.push(id);
+        }
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /**
+     * @description: 读取采购单
+     * @param {i64} id 采购单号
+     * @return {*}
+     */
+    pub fn purchase_order(&self, id: i64) -> BomResult<PurchaseOrder> {
+        self.query_orders(" WHERE id = ?", &[&id])?
+            .pop()
+            .ok_or_else(|| BomError::NotFound(format!("purchase order {id}")))
+    }
+
+    /**
+     * @description: 读取所有采购单, 按创建顺序排序
+     * @return {*}
+     */
+    pub fn purchase_orders(&self) -> BomResult<Vec<PurchaseOrder>> {
+        self.query_orders(" ORDER BY id", &[])
+    }
+
+    // 执行 get_order_template 加上 suffix 条件, 并读取每个采购单的元件
+    fn query_orders(
+        &self,
+        suffix: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> BomResult<Vec<PurchaseOrder>> {
+        let sql = String::from(get_order_template!()) + suffix;
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<usize, i64>(0)?,
+                row.get::<usize, String>(1)?,
+                row.get::<usize, String>(2)?,
+                row.get::<usize, i64>(3)?,
+                row.get::<usize, Option<i64>>(4)?,
+            ))
+        })?;
+        let mut item_stmt = self.conn.prepare(get_order_items_template!())?;
+        let mut orders = Vec::new();
+        for row in rows {
+            let (id, supplier, status, created, received) = row?;
+            let lines = item_stmt.query_map([id], |row| {
+                Ok(OrderLine {
+                    model: row.get(0)?,
+                    manufacturer: row.get(1)?,
+                    sku: row.get(2)?,
+                    mpn: row.get(3)?,
+                    needed: row.get(4)?,
+                    quantity: row.get(5)?,
+                    unit_price: row.get(6)?,
+                })
+            })?;
+            orders.push(PurchaseOrder {
+                id,
+                supplier,
+                status: OrderStatus::from_string(&status)?,
+                created,
+                received,
+                lines: lines.collect::<rusqlite::Result<Vec<_>>>()?,
+            });
+        }
+        Ok(orders)
+    }
+
+    // 结束未收货的采购单, 已经结束时返回 BomError::OrderClosed
+    fn close_order(&self, conn: &Connection, id: i64, status: OrderStatus) -> BomResult<()> {
+        let changed = conn.execute(
+            update_order_status_template!(),
+            (status.to_string(), now_timestamp(), id),
+        )?;
+        if changed == 0 {
+            let order = self.purchase_order(id)?;
+            return Err(BomError::OrderClosed {
+                id,
+                status: order.status.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * @description: 采购单收货, 所有元件在一个事务中入库并记流水, 任何一个元件失败时都不入库,
+     * 库中没有的元件 (生产单缺的新料) 收货时新增, 描述为厂家料号
+     * @param {i64} id 采购单号
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {收货的采购单}
+     */
+    pub fn receive_purchase_order(&self, id: i64, note: &MovementNote) -> BomResult<PurchaseOrder> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.close_order(&tx, id, OrderStatus::Received)?;
+        let order = self.purchase_order(id)?;
+        for line in order.lines.iter().filter(|l| l.quantity > 0) {
+            let element = match self.element(&line.model, &line.manufacturer) {
+                Err(BomError::NotFound(_)) => Element {
+                    describe: line.mpn.clone(),
+                    model: line.model.clone(),
+                    manufacturer: line.manufacturer.clone(),
+                    ..Default::default()
+                },
+                res => res?,
+            };
+            add_number(
+                &tx,
+                &self.tables,
+                &Element {
+                    number: line.quantity,
+                    ..element
+                },
+                note,
+            )?;
+        }
+        tx.commit()?;
+        Ok(order)
+    }
+
+    /**
+     * @description: 取消未收货的采购单, 数量不再算作在途
+     * @param {i64} id 采购单号
+     * @return {*}
+     */
+    pub fn cancel_purchase_order(&self, id: i64) -> BomResult<()> {
+        self.close_order(&self.conn, id, OrderStatus::Cancelled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantities_round_to_pack_and_moq() {
+        assert_eq!(order_quantity(7, 1, 1), 7);
+        assert_eq!(order_quantity(7, 100, 1), 100);
+        assert_eq!(order_quantity(120, 100, 1), 200);
+        assert_eq!(order_quantity(7, 1, 20), 20);
+        assert_eq!(order_quantity(30, 4000, 5000), 8000);
+        assert_eq!(order_quantity(0, 0, 0), 0);
+
+        let thresholds = Thresholds::new(10, 100).unwrap();
+        assert_eq!(reorder_quantity(150, &thresholds, 0, 0), 0);
+        assert_eq!(reorder_quantity(100, &thresholds, 0, 0), 1);
+        assert_eq!(reorder_quantity(150, &thresholds, 80, 0), 31);
+        assert_eq!(reorder_quantity(40, &thresholds, 0, 100), 0);
+    }
+
+    #[test]
+    fn order_documents() {
+        let orders = vec![PurchaseOrder {
+            id: 3,
+            supplier: "LCSC".to_string(),
+            status: OrderStatus::Open,
+            created: 0,
+            received: None,
+            lines: vec![OrderLine {
+                model: "R10K".to_string(),
+                manufacturer: String::new(),
+                sku: "C25804".to_string(),
+                mpn: "0603WAF1002T5E|R".to_string(),
+                needed: 61,
+                quantity: 100,
+                unit_price: Some(0.004),
+            }],
+        }];
+        let mut csv = Vec::new();
+        write_orders_csv(&mut csv, &orders).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("3,LCSC,C25804,0603WAF1002T5E|R,R10K,,61,100,0.0040,0.4000")
+        );
+        let markdown = orders_markdown(&orders);
+        assert!(markdown.starts_with("# Purchase order #3: LCSC\n"));
+        assert!(markdown
+            .contains("| C25804 | 0603WAF1002T5E\\|R | R10K |  | 61 | 100 | 0.0040 | 0.4000 |"));
+        assert!(markdown.contains("Total: 0.40"));
+        assert_eq!(OrderFormat::from_path("po.MD"), OrderFormat::Markdown);
+        assert_eq!(OrderFormat::from_path("po.csv"), OrderFormat::Csv);
+    }
+}
@@ -0,0 +1,656 @@
+/*
+ * @Description: 二维码编码, 只支持字节模式和纠错等级 M, 版本 1~10, 最多 213 字节, 标签内容足够用
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\qr.rs
+ */
+use super::{BomError, BomResult};
+
+// 支持的最大版本, 57x57 模块
+const MAX_VERSION: usize = 10;
+// 纠错等级 M 每块的纠错码字数, 下标为版本
+const ECC_PER_BLOCK: [usize; MAX_VERSION + 1] = [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26];
+// 纠错等级 M 的分块数, 下标为版本
+const NUM_BLOCKS: [usize; MAX_VERSION + 1] = [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5];
+// 格式信息中纠错等级 M 的编码
+const ECL_M: u32 = 0;
+
+// 二维码, 模块按行保存, true 为深色
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrCode {
+    size: usize,        // 边长, 模块数
+    modules: Vec<bool>, // 所有模块
+}
+
+impl QrCode {
+    /**
+     * @description: 用字节模式编码数据, 自动选择能放下数据的最小版本和惩罚分最低的掩码
+     * @param {&[u8]} data 数据, 例如标签内容的 UTF-8 字节
+     * @return {数据太长时返回 BomError::InvalidLabel}
+     */
+    pub fn encode(data: &[u8]) -> BomResult<QrCode> {
+        let version = (1..=MAX_VERSION)
+            .find(|&v| 4 + count_bits(v) + data.len() * 8 <= data_codewords(v) * 8)
+            .ok_or_else(|| {
+                BomError::InvalidLabel(format!("{} bytes do not fit in a QR code", data.len()))
+            })?;
+        Ok(QrCode::encode_with(data, version, None))
+    }
+
+    /**
+     * @description: 用指定的版本编码数据, 调用前要确认数据放得下
+     * @param {&[u8]} data 数据
+     * @param {usize} version 版本
+     * @param {Option<u32>} mask 掩码, None 时选择惩罚分最低的掩码
+     * @return {*}
+     */
+    fn encode_with(data: &[u8], version: usize, mask: Option<u32>) -> QrCode {
+        // 模式 + 长度 + 数据 + 结束符, 补齐到整字节后用 0xEC/0x11 填满
+        let capacity = data_codewords(version) * 8;
+        let mut bits = Vec::with_capacity(capacity);
+        push_bits(&mut bits, 0b0100, 4);
+        push_bits(&mut bits, data.len() as u32, count_bits(version));
+        for &byte in data {
+            push_bits(&mut bits, byte.into(), 8);
+        }
+        let terminator = (capacity - bits.len()).min(4);
+        push_bits(&mut bits, 0, terminator);
+        let padding = (8 - bits.len() % 8) % 8;
+        push_bits(&mut bits, 0, padding);
+        for pad in [0xEC, 0x11].into_iter().cycle() {
+            if bits.len() >= capacity {
+                break;
+            }
+            push_bits(&mut bits, pad, 8);
+        }
+        let codewords: Vec<u8> = bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+            .collect();
+
+        let mut builder = Builder::new(version);
+        builder.draw_function_patterns();
+        builder.draw_codewords(&add_ecc_and_interleave(version, &codewords));
+        let mask = mask.unwrap_or_else(|| {
+            (0..8)
+                .min_by_key(|&mask| {
+                    builder.apply_mask(mask);
+                    builder.draw_format_bits(mask);
+                    let penalty = builder.penalty();
+                    builder.apply_mask(mask);
+                    penalty
+                })
+                .unwrap_or(0)
+        });
+        builder.apply_mask(mask);
+        builder.draw_format_bits(mask);
+        QrCode {
+            size: builder.size,
+            modules: builder.modules,
+        }
+    }
+
+    /**
+     * @description: 边长, 模块数, 不包括四周的空白区
+     * @return {*}
+     */
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /**
+     * @description: 模块是否为深色, 超出范围的坐标为浅色
+     * @param {usize} x 列
+     * @param {usize} y 行
+     * @return {*}
+     */
+    pub fn module(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y * self.size + x]
+    }
+}
+
+// 生成二维码时的中间状态, function 标记定位图形等不能放数据的模块
+struct Builder {
+    size: usize,
+    version: usize,
+    modules: Vec<bool>,
+    function: Vec<bool>,
+}
+
+impl Builder {
+    fn new(version: usize) -> Builder {
+        let size = version * 4 + 17;
+        Builder {
+            size,
+            version,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        }
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    // 定位图形/时序图形/校正图形/格式信息/版本信息
+    fn draw_function_patterns(&mut self) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        self.draw_finder(3, 3);
+        self.draw_finder(size - 4, 3);
+        self.draw_finder(3, size - 4);
+        // 校正图形不能和三个定位图形重叠
+        let positions = alignment_positions(self.version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                let corner = match (i, j) {
+                    (0, _) | (_, 0) => i + j == 0 || i + j == last,
+                    _ => false,
+                };
+                if !corner {
+                    self.draw_alignment(x, y);
+                }
+            }
+        }
+        // 先占住格式信息的位置, 选择掩码后再写入
+        self.draw_format_bits(0);
+        self.draw_version();
+    }
+
+    // 7x7 的定位图形和周围一圈浅色分隔符
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if (0..self.size as i32).contains(&xx) && (0..self.size as i32).contains(&yy) {
+                    let dist = dx.abs().max(dy.abs());
+                    self.set_function(xx as usize, yy as usize, dist != 2 && dist != 4);
+                }
+            }
+        }
+    }
+
+    // 5x5 的校正图形
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                let dist = dx.abs().max(dy.abs());
+                self.set_function(
+                    (x as i32 + dx) as usize,
+                    (y as i32 + dy) as usize,
+                    dist != 1,
+                );
+            }
+        }
+    }
+
+    // 纠错等级和掩码组成的 15 位格式信息, 左上角一份, 右上角和左下角分开放一份
+    fn draw_format_bits(&mut self, mask: u32) {
+        let size = self.size;
+        let bits = format_bits(ECL_M, mask);
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // 固定的深色模块
+        self.set_function(8, size - 8, true);
+    }
+
+    // 版本 7 以上在右上角和左下角各放一份 18 位版本信息
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let bits = version_bits(self.version as u32);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    // 从右下角开始每两列一组上下来回放置码字, 跳过时序图形所在的第 6 列
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let size = self.size as isize;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert } as usize;
+                    let index = y * self.size + x;
+                    if !self.function[index] && i < data.len() * 8 {
+                        self.modules[index] = (data[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    // 对数据模块异或掩码, 执行两次恢复原样
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if invert && !self.function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    // 掩码惩罚分: 同色连续/同色 2x2/类似定位图形/深浅比例, 越低越容易识别
+    fn penalty(&self) -> u32 {
+        let size = self.size;
+        let at = |x: usize, y: usize| self.modules[y * size + x];
+        let mut penalty = 0;
+        for transpose in [false, true] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size)
+                    .map(|b| if transpose { at(a, b) } else { at(b, a) })
+                    .collect();
+                let mut run = 1;
+                for b in 1..=size {
+                    if b < size && line[b] == line[b - 1] {
+                        run += 1;
+                        continue;
+                    }
+                    if run >= 5 {
+                        penalty += run - 2;
+                    }
+                    run = 1;
+                }
+                // 同色段的长度, 浅色开始深浅交替, 两端各加 size 个浅色模块当作空白区
+                let mut runs = Vec::new();
+                let (mut color, mut len) = (false, size);
+                for &dark in &line {
+                    if dark == color {
+                        len += 1;
+                    } else {
+                        runs.push(len);
+                        (color, len) = (dark, 1);
+                    }
+                }
+                if color {
+                    runs.push(len);
+                    len = 0;
+                }
+                runs.push(len + size);
+                // 深浅深浅深为 1:1:3:1:1, 任意一侧有 4 倍宽的浅色时各算一次
+                for w in runs.windows(7).step_by(2) {
+                    let n = w[1];
+                    if w[2] == n && w[3] == n * 3 && w[4] == n && w[5] == n {
+                        penalty += 40 * u32::from(w[0] >= n * 4 && w[6] >= n);
+                        penalty += 40 * u32::from(w[6] >= n * 4 && w[0] >= n);
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = at(x, y);
+                if at(x + 1, y) == color && at(x, y + 1) == color && at(x + 1, y + 1) == color {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let total = size * size;
+        penalty += (dark * 20)
+            .abs_diff(total * 10)
+            .div_ceil(total)
+            .saturating_sub(1) as u32
+            * 10;
+        penalty
+    }
+}
+
+// 字节模式长度字段的位数
+fn count_bits(version: usize) -> usize {
+    if version < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+// 除去定位图形等之后可以放码字的模块数
+fn raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let n = version / 7 + 2;
+        result -= (25 * n - 10) * n - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+// 数据码字数, 总码字数减去纠错码字数
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_PER_BLOCK[version] * NUM_BLOCKS[version]
+}
+
+// 校正图形中心的坐标, 行列相同
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return vec![];
+    }
+    let size = version * 4 + 17;
+    let n = version / 7 + 2;
+    let step = (version * 8 + n * 3 + 5) / (n * 4 - 4) * 2;
+    let mut result: Vec<usize> = (0..n - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+// 在 bits 后追加 value 的低 len 位, 高位在前
+fn push_bits(bits: &mut Vec<bool>, value: u32, len: usize) {
+    bits.extend((0..len).rev().map(|i| (value >> i) & 1 != 0));
+}
+
+// 15 位格式信息, BCH(15,5) 纠错后异或 0x5412
+fn format_bits(ecl: u32, mask: u32) -> u32 {
+    let data = ecl << 3 | mask;
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    (data << 10 | rem) ^ 0x5412
+}
+
+// 18 位版本信息, BCH(18,6)
+fn version_bits(version: u32) -> u32 {
+    let mut rem = version;
+    for _ in 0..12 {
+        rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+    }
+    version << 12 | rem
+}
+
+// 数据分块计算纠错码, 再按列交错排列
+fn add_ecc_and_interleave(version: usize, data: &[u8]) -> Vec<u8> {
+    let num_blocks = NUM_BLOCKS[version];
+    let ecc_len = ECC_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let num_short = num_blocks - raw_codewords % num_blocks;
+    let short_len = raw_codewords / num_blocks;
+    let divisor = reed_solomon_divisor(ecc_len);
+    let mut blocks = Vec::with_capacity(num_blocks);
+    let mut k = 0;
+    for i in 0..num_blocks {
+        let len = short_len - ecc_len + usize::from(i >= num_short);
+        let mut block = data[k..k + len].to_vec();
+        k += len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        // 短块补一个占位字节, 交错时跳过
+        if i < num_short {
+            block.push(0);
+        }
+        block.extend(ecc);
+        blocks.push(block);
+    }
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..blocks[0].len() {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_len - ecc_len || j >= num_short {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+// GF(256) 乘法, 本原多项式 0x11D
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((u32::from(y) >> i) & 1) * u32::from(x);
+    }
+    z as u8
+}
+
+// Reed-Solomon 生成多项式, 不含最高次项的系数
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+// 数据多项式除以生成多项式的余数, 就是纠错码字
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reed_solomon_matches_reference() {
+        // 版本 1-M 的 "HELLO WORLD" 数据码字和纠错码字
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        let ecc = reed_solomon_remainder(&data, &reed_solomon_divisor(10));
+        assert_eq!(ecc, [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+    }
+
+    #[test]
+    fn format_and_version_bits() {
+        assert_eq!(format_bits(ECL_M, 0), 0b101010000010010);
+        assert_eq!(format_bits(1, 0), 0b111011111000100); // L
+        assert_eq!(format_bits(2, 0), 0b001011010001001); // H
+        assert_eq!(version_bits(7), 0x07C94);
+        assert_eq!(alignment_positions(7), [6, 22, 38]);
+        assert_eq!(data_codewords(1), 16);
+        assert_eq!(data_codewords(10), 216);
+    }
+
+    #[test]
+    fn encode_picks_smallest_version() {
+        let qr = QrCode::encode(b"BOM:E:1:R10K").unwrap();
+        assert_eq!(qr.size(), 21);
+        // 三个定位图形的角和中心
+        for (x, y) in [(0, 0), (3, 3), (20, 0), (17, 3), (0, 20), (3, 17)] {
+            assert!(qr.module(x, y));
+        }
+        assert!(!qr.module(1, 1));
+        assert!(qr.module(8, 13));
+        assert_eq!(QrCode::encode(&[b'x'; 20]).unwrap().size(), 25);
+        assert_eq!(QrCode::encode(&[b'x'; 213]).unwrap().size(), 57);
+        assert!(matches!(
+            QrCode::encode(&[b'x'; 214]),
+            Err(BomError::InvalidLabel(_))
+        ));
+    }
+
+    /**
+     * @description: 用参考编码器 qrcodegen 生成同样内容的二维码
+     * @param {&[u8]} data 数据
+     * @param {usize} version 版本, 0 时自动选择
+     * @param {Option<u32>} mask 掩码, None 时自动选择
+     * @return {*}
+     */
+    fn reference(data: &[u8], version: usize, mask: Option<u32>) -> QrCode {
+        use qrcodegen::{Mask, QrCodeEcc, QrSegment, Version};
+        let (min, max) = match version {
+            0 => (1, MAX_VERSION as u8),
+            v => (v as u8, v as u8),
+        };
+        let qr = qrcodegen::QrCode::encode_segments_advanced(
+            &[QrSegment::make_bytes(data)],
+            QrCodeEcc::Medium,
+            Version::new(min),
+            Version::new(max),
+            mask.map(|m| Mask::new(m as u8)),
+            false,
+        )
+        .unwrap();
+        let size = qr.size() as usize;
+        QrCode {
+            size,
+            modules: (0..size * size)
+                .map(|i| qr.get_module((i % size) as i32, (i / size) as i32))
+                .collect(),
+        }
+    }
+
+    /**
+     * @description: 把二维码画成文本, 比较失败时容易看出差异
+     * @param {&QrCode} qr
+     * @return {*}
+     */
+    fn render(qr: &QrCode) -> String {
+        (0..qr.size())
+            .map(|y| {
+                (0..qr.size())
+                    .map(|x| if qr.module(x, y) { '#' } else { '.' })
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn modules_match_reference_encoder() {
+        // 覆盖单块/多块/有版本信息/16 位长度字段的版本, 每个版本都试所有掩码
+        let text = "BOM:E:42:R10K 电阻 0805 1% 100V ";
+        for version in [1, 2, 5, 7, 10] {
+            let data: Vec<u8> = text
+                .bytes()
+                .cycle()
+                .take(data_codewords(version) - 2 - count_bits(version) / 8)
+                .collect();
+            for mask in 0..8 {
+                assert_eq!(
+                    render(&QrCode::encode_with(&data, version, Some(mask))),
+                    render(&reference(&data, version, Some(mask))),
+                    "version {version} mask {mask}"
+                );
+            }
+        }
+        // 自动选择的版本和掩码也一致
+        for len in [1, 13, 40, 100, 213] {
+            let data: Vec<u8> = text.bytes().cycle().take(len).collect();
+            assert_eq!(
+                render(&QrCode::encode(&data).unwrap()),
+                render(&reference(&data, 0, None)),
+                "{len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn version_1_reads_back() {
+        // 按标准的读取顺序取回格式信息和数据, 检查掩码和码字位置
+        let text = b"BOM:E:42:R10K";
+        let qr = QrCode::encode(text).unwrap();
+        let mut format = 0;
+        for i in (9..15).rev() {
+            format = format << 1 | u32::from(qr.module(14 - i, 8));
+        }
+        format = format << 1 | u32::from(qr.module(7, 8));
+        format = format << 1 | u32::from(qr.module(8, 8));
+        format = format << 1 | u32::from(qr.module(8, 7));
+        for i in (0..=5).rev() {
+            format = format << 1 | u32::from(qr.module(8, i));
+        }
+        let mask = (0..8).find(|&m| format_bits(ECL_M, m) == format).unwrap();
+
+        // 版本 1 的定位图形/分隔符/格式信息/时序图形, 不放数据
+        let reserved = |x: usize, y: usize| {
+            x == 6 || y == 6 || (x <= 8 && y <= 8) || (x >= 13 && y <= 8) || (x <= 8 && y >= 13)
+        };
+        let mut unmasked = Builder::new(1);
+        unmasked.draw_function_patterns();
+        unmasked.modules = qr.modules.clone();
+        unmasked.apply_mask(mask);
+        let mut bits = Vec::new();
+        let mut upward = true;
+        let mut right = 20;
+        loop {
+            for vert in 0..21 {
+                let y = if upward { 20 - vert } else { vert };
+                for x in [right, right - 1] {
+                    if !reserved(x, y) {
+                        bits.push(unmasked.modules[y * 21 + x]);
+                    }
+                }
+            }
+            upward = !upward;
+            match right {
+                1 => break,
+                8 => right = 5,
+                _ => right -= 2,
+            }
+        }
+        assert_eq!(bits.len(), 26 * 8);
+        let bytes: Vec<u8> = bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+            .collect();
+        assert_eq!(bytes[0] >> 4, 0b0100);
+        assert_eq!(usize::from(bytes[0] << 4 | bytes[1] >> 4), text.len());
+        let payload: Vec<u8> = (0..text.len())
+            .map(|i| bytes[i + 1] << 4 | bytes[i + 2] >> 4)
+            .collect();
+        assert_eq!(payload, text);
+        let ecc = reed_solomon_remainder(&bytes[..16], &reed_solomon_divisor(10));
+        assert_eq!(&bytes[16..], ecc.as_slice());
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * @Description: 元件查询, 按类型/状态/数量/位置/文字过滤, 排序和分页都在 SQL 中完成, 不需要把整个库存读到内存
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\query.rs
+ */
+use super::search::{fts_query, search_terms};
+use super::{
+    element_from_row, element_key, BomError, BomResult, DataBaseInfo, Element, ElementStatus,
+    ElementType,
+};
+use rusqlite::types::Value;
+
+// 查询元件, 列的顺序和 get_all_template 一致, 用 element_from_row 读取
+macro_rules! query_elements_template {
+    () => {
+        "SELECT t.describe, t.model, t.manufacturer, t.number, t.element_type, t.state, t.category_id, t.version FROM {} t"
+    };
+}
+// 元件的键, 和 element_key 一致, 用于关联搜索索引
+macro_rules! element_key_sql {
+    () => {
+        "CASE WHEN t.manufacturer = '' THEN t.model ELSE t.model || '@' || t.manufacturer END"
+    };
+}
+// 元件在位置及其所有下级位置中有库存
+macro_rules! in_location_template {
+    () => {
+        "EXISTS (SELECT 1 FROM stock_locations s WHERE s.model = t.model AND s.manufacturer = t.manufacturer \
+         AND s.location_id IN (WITH RECURSIVE sub (id) AS (SELECT ? \
+         UNION ALL SELECT l.id FROM locations l JOIN sub ON l.parent_id = sub.id) SELECT id FROM sub))"
+    };
+}
+// 元件的索引命中全文搜索
+macro_rules! match_text_template {
+    () => {
+        concat!(
+            element_key_sql!(),
+            " IN (SELECT key FROM element_search WHERE element_search MATCH ?)"
+        )
+    };
+}
+
+// 排序字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortField {
+    #[default]
+    Model, // 型号
+    Describe, // 描述
+    Number,   // 数量
+    Type,     // 类型
+    Status,   // 状态, 按 缺货/一般/丰富 排序, 未知排在最前
+    Value,    // 解析后的数值, 例如电阻值
+}
+
+impl SortField {
+    // 排序字段对应的列
+    fn column(&self) -> &'static str {
+        match self {
+            SortField::Model => "t.model",
+            SortField::Describe => "t.describe",
+            SortField::Number => "t.number",
+            SortField::Type => "t.element_type",
+            SortField::Status => {
+                "CASE t.state WHEN '缺货' THEN 1 WHEN '一般' THEN 2 WHEN '丰富' THEN 3 ELSE 0 END"
+            }
+            SortField::Value => "t.value",
+        }
+    }
+}
+
+// 排序方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc, // 升序
+    Desc, // 降序
+}
+
+// 元件查询条件, 通过链式调用设置, 没有设置的条件不过滤
+#[derive(Debug, Clone, Default)]
+pub struct ElementQuery {
+    element_type: Option<ElementType>, // 元件类型
+    status: Option<ElementStatus>,     // 元件状态
+    min_number: Option<u32>,           // 最小数量 (含)
+    max_number: Option<u32>,           // 最大数量 (含)
+    location: Option<String>,          // 位置路径, 包含下级位置
+    text: Option<String>,              // 文字, 匹配型号/描述/分类/厂家料号
+    sort: SortField,                   // 排序字段
+    order: SortOrder,                  // 排序方向
+    limit: Option<u32>,                // 最多返回的条数
+    offset: u32,                       // 跳过的条数
+}
+
+impl ElementQuery {
+    /**
+     * @description: 不带条件的查询, 返回所有元件, 按型号升序
+     * @return {*}
+     */
+    pub fn new() -> ElementQuery {
+        ElementQuery::default()
+    }
+
+    /**
+     * @description: 只查询某种类型
+     * @param {ElementType} element_type
+     * @return {*}
+     */
+    pub fn element_type(mut self, element_type: ElementType) -> Self {
+        self.element_type = Some(element_type);
+        self
+    }
+
+    /**
+     * @description: 只查询某种状态
+     * @param {ElementStatus} status
+     * @return {*}
+     */
+    pub fn status(mut self, status: ElementStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /**
+     * @description: 数量范围, 两端都包含
+     * @param {Option<u32>} min 最小数量, None 不限制
+     * @param {Option<u32>} max 最大数量, None 不限制
+     * @return {*}
+     */
+    pub fn number_range(mut self, min: Option<u32>, max: Option<u32>) -> Self {
+        self.min_number = min;
+        self.max_number = max;
+        self
+    }
+
+    /**
+     * @description: 只查询在某个位置 (含下级位置) 有库存的元件
+     * @param {&str} path 位置路径, 例如 柜子A/抽屉3
+     * @return {*}
+     */
+    pub fn location(mut self, path: &str) -> Self {
+        self.location = Some(path.to_string());
+        self
+    }
+
+    /**
+     * @description: 按文字过滤, 每个词按前缀匹配型号/描述/分类/厂家料号
+     * @param {&str} text 例如 "10k 0603"
+     * @return {*}
+     */
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /**
+     * @description: 排序方式, 相同时再按型号和厂家排序
+     * @param {SortField} sort 排序字段
+     * @param {SortOrder} order 排序方向
+     * @return {*}
+     */
+    pub fn sort_by(mut self, sort: SortField, order: SortOrder) -> Self {
+        self.sort = sort;
+        self.order = order;
+        self
+    }
+
+    /**
+     * @description: 最多返回的条数
+     * @param {u32} limit
+     * @return {*}
+     */
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /**
+     * @description: 跳过前面的条数, 和 limit 一起用于分页
+     * @param {u32} offset
+     * @return {*}
+     */
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /**
+     * @description: 生成 WHERE 条件和参数, 位置已经解析为 id
+     * @param {Option<i64>} location_id 位置 id
+     * @return {(条件, 参数)}
+     */
+    fn conditions(&self, location_id: Option<i64>) -> (String, Vec<Value>) {
+        let mut conditions: Vec<&str> = vec![];
+        let mut values: Vec<Value> = vec![];
+        if let Some(element_type) = &self.element_type {
+            conditions.push("t.element_type = ?");
+            values.push(Value::Text(element_type.to_string()));
+        }
+        if let Some(status) = &self.status {
+            conditions.push("t.state = ?");
+            values.push(Value::Text(status.to_string()));
+        }
+        if let Some(min) = self.min_number {
+            conditions.push("t.number >= ?");
+            values.push(Value::Integer(min.into()));
+        }
+        if let Some(max) = self.max_number {
+            conditions.push("t.number <= ?");
+            values.push(Value::Integer(max.into()));
+        }
+        if let Some(id) = location_id {
+            conditions.push(in_location_template!());
+            values.push(Value::Integer(id));
+        }
+        let terms = self.text.as_deref().map(search_terms).unwrap_or_default();
+        if !terms.is_empty() {
+            conditions.push(match_text_template!());
+            values.push(Value::Text(fts_query(&terms)));
+        }
+        if conditions.is_empty() {
+            (String::new(), values)
+        } else {
+            (format!(" WHERE {}", conditions.join(" AND ")), values)
+        }
+    }
+}
+
+// DataBaseInfo 的查询接口
+impl DataBaseInfo {
+    // 位置路径解析为 id, 位置不存在时返回 NotFound
+    fn query_location(&self, query: &ElementQuery) -> BomResult<Option<i64>> {
+        match &query.location {
+            Some(path) => Ok(Some(self.find_location(path)?.id)),
+            None => Ok(None),
+        }
+    }
+
+    /**
+     * @description: 按型号 + 厂家读取元件
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn element(&self, model: &str, manufacturer: &str) -> BomResult<Element> {
+        let sql = format!(query_elements_template!(), self.tables)
+            + " WHERE t.model = ? AND t.manufacturer = ?";
+        match self
+            .conn
+            .query_row(sql.as_str(), [model, manufacturer], element_from_row)
+        {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(BomError::NotFound(element_key(model, manufacturer)))
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /**
+     * @description: 按条件查询元件
+     * @param {&ElementQuery} query 查询条件
+     * @return {*}
+     */
+    pub fn query_elements(&self, query: &ElementQuery) -> BomResult<Vec<Element>> {
+        let (conditions, mut values) = query.conditions(self.query_location(query)?);
+        let direction = match query.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        let sql = format!(query_elements_template!(), self.tables)
+            + &format!(
+                "{conditions} ORDER BY {} {direction}, t.model, t.manufacturer LIMIT ? OFFSET ?",
+                query.sort.column()
+            );
+        values.push(Value::Integer(query.limit.map_or(-1, i64::from)));
+        values.push(Value::Integer(query.offset.into()));
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), element_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 符合条件的元件数量, 不受 limit 和 offset 影响, 用于计算页数
+     * @param {&ElementQuery} query 查询条件
+     * @return {*}
+     */
+    pub fn count_elements(&self, query: &ElementQuery) -> BomResult<u32> {
+        let (conditions, values) = query.conditions(self.query_location(query)?);
+        let sql = format!("SELECT COUNT(*) FROM {} t{conditions}", self.tables);
+        Ok(self
+            .conn
+            .query_row(sql.as_str(), rusqlite::params_from_iter(values), |row| {
+                row.get(0)
+            })?)
+    }
+}
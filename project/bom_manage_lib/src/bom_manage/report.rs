@@ -0,0 +1,411 @@
+/*
+ * @Description: 库存统计报表, 按类型和状态统计元件数量, 库存金额, 按流水统计消耗排行,
+ * 呆滞库存和按消耗速度估算的缺货天数
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\report.rs
+ */
+use super::category::BUILTIN_TYPES;
+use super::ledger::{self, REASON_AUDIT, REASON_IMPORT, REASON_REMOVE};
+use super::supplier::Costing;
+use super::{element_key, BomResult, DataBaseInfo, Element, ElementStatus, ElementType};
+use std::collections::HashMap;
+
+// 一段时间内每个元件的消耗, 删除元件, 导入补记和盘点调整的差额不算消耗
+macro_rules! get_consumption_template {
+    () => {
+        "SELECT model, manufacturer, -SUM(delta), COUNT(*) FROM stock_movements \
+         WHERE delta < 0 AND reason NOT IN (?, ?, ?) AND timestamp >= ? AND timestamp <= ? \
+         GROUP BY model, manufacturer"
+    };
+}
+// 每个元件最后一次消耗的时间
+macro_rules! get_last_used_template {
+    () => {
+        "SELECT model, manufacturer, MAX(timestamp) FROM stock_movements \
+         WHERE delta < 0 AND reason NOT IN (?, ?, ?) GROUP BY model, manufacturer"
+    };
+}
+
+// 一天的秒数
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+// 默认统计最近多少天的消耗
+pub const DEFAULT_REPORT_DAYS: u32 = 90;
+// 默认每个列表最多显示的元件个数
+pub const DEFAULT_REPORT_TOP: usize = 10;
+
+// 报表的统计范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportOptions {
+    pub from: i64,  // 统计消耗的开始时间, unix 时间戳
+    pub to: i64,    // 统计消耗的结束时间, unix 时间戳
+    pub top: usize, // 消耗排行, 呆滞库存和缺货预测最多的行数, 0 不限制
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions::last_days(DEFAULT_REPORT_DAYS)
+    }
+}
+
+impl ReportOptions {
+    /**
+     * @description: 统计到当前时间为止最近几天的消耗
+     * @param {u32} days 天数
+     * @return {*}
+     */
+    pub fn last_days(days: u32) -> ReportOptions {
+        let to = ledger::now_timestamp();
+        ReportOptions {
+            from: to - i64::from(days) * SECONDS_PER_DAY,
+            to,
+            top: DEFAULT_REPORT_TOP,
+        }
+    }
+
+    /**
+     * @description: 统计范围的天数, 不足一天按一天计
+     * @return {*}
+     */
+    pub fn days(&self) -> f64 {
+        ((self.to - self.from) as f64 / SECONDS_PER_DAY as f64).max(1.0)
+    }
+
+    // 按 top 截断列表
+    fn truncate<T>(&self, list: &mut Vec<T>) {
+        if self.top > 0 {
+            list.truncate(self.top);
+        }
+    }
+}
+
+// 一组元件的个数和总数量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StockCount {
+    pub parts: u32,    // 元件种类数
+    pub quantity: u64, // 总数量
+}
+
+impl StockCount {
+    /**
+     * @description: 计入一个元件
+     * @param {&Element} element 元件
+     * @return {*}
+     */
+    pub fn add(&mut self, element: &Element) {
+        self.parts += 1;
+        self.quantity += u64::from(element.number);
+    }
+}
+
+// 按类型和状态分组的库存统计
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StockSummary {
+    pub total: StockCount,                           // 全部元件
+    pub by_type: Vec<(ElementType, StockCount)>,     // 按类型, 包括没有元件的类型
+    pub by_status: Vec<(ElementStatus, StockCount)>, // 按状态, 包括没有元件的状态
+}
+
+impl StockSummary {
+    /**
+     * @description: 统计元件, 类型和状态按固定顺序排列, 便于比较两次的报表
+     * @param {I} elements 元件
+     * @return {*}
+     */
+    pub fn new<'a, I: IntoIterator<Item = &'a Element>>(elements: I) -> StockSummary {
+        let mut summary = StockSummary {
+            total: StockCount::default(),
+            by_type: BUILTIN_TYPES
+                .iter()
+                .chain([&ElementType::Unknown])
+                .map(|t| (t.clone(), StockCount::default()))
+                .collect(),
+            by_status: [
+                ElementStatus::ALOT,
+                ElementStatus::NORMAL,
+                ElementStatus::SHORTAGE,
+                ElementStatus::Unknown,
+            ]
+            .into_iter()
+            .map(|s| (s, StockCount::default()))
+            .collect(),
+        };
+        for element in elements {
+            summary.total.add(element);
+            if let Some((_, count)) = summary
+                .by_type
+                .iter_mut()
+                .find(|(t, _)| *t == element.element_type)
+            {
+                count.add(element);
+            }
+            if let Some((_, count)) = summary
+                .by_status
+                .iter_mut()
+                .find(|(s, _)| *s == element.state)
+            {
+                count.add(element);
+            }
+        }
+        summary
+    }
+}
+
+// 一个元件在统计范围内的消耗
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consumption {
+    pub key: String,    // 元件键
+    pub quantity: u64,  // 消耗数量
+    pub movements: u32, // 出库次数
+}
+
+// 统计范围内没有消耗的库存
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowMover {
+    pub key: String,            // 元件键
+    pub number: u32,            // 当前数量
+    pub last_used: Option<i64>, // 最后一次消耗的时间, 从未消耗为 None
+}
+
+// 按消耗速度估算的缺货时间
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forecast {
+    pub key: String,     // 元件键
+    pub number: u32,     // 当前数量
+    pub daily_rate: f64, // 统计范围内平均每天的消耗
+    pub days_left: f64,  // 按这个速度还能用的天数
+}
+
+// 库存统计报表
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryReport {
+    pub from: i64,                       // 统计消耗的开始时间
+    pub to: i64,                         // 统计消耗的结束时间
+    pub stock: StockSummary,             // 按类型和状态的库存统计
+    pub value: Costing,                  // 按当前价格计算的库存金额
+    pub top_consumers: Vec<Consumption>, // 消耗最多的元件, 按消耗数量降序
+    pub slow_movers: Vec<SlowMover>,     // 有库存但没有消耗的元件, 最久没用的在前
+    pub forecasts: Vec<Forecast>,        // 有消耗的元件的缺货预测, 最快缺货的在前
+}
+
+/**
+ * @description: 按平均每天的消耗估算还能用的天数
+ * @param {u32} number 当前数量
+ * @param {f64} daily_rate 平均每天的消耗
+ * @return {没有消耗时返回 None}
+ */
+pub fn days_until_stockout(number: u32, daily_rate: f64) -> Option<f64> {
+    (daily_rate > 0.0).then(|| f64::from(number) / daily_rate)
+}
+
+/**
+ * @description: 由当前库存和流水统计生成报表中的消耗排行, 呆滞库存和缺货预测
+ * @param {&HashMap<String, Element>} elements 当前所有元件
+ * @param {HashMap<String, Consumption>} consumption 统计范围内每个元件的消耗
+ * @param {&HashMap<String, i64>} last_used 每个元件最后一次消耗的时间
+ * @param {&ReportOptions} options 统计范围
+ * @return {(消耗排行, 呆滞库存, 缺货预测)}
+ */
+pub fn usage_report(
+    elements: &HashMap<String, Element>,
+    consumption: HashMap<String, Consumption>,
+    last_used: &HashMap<String, i64>,
+    options: &ReportOptions,
+) -> (Vec<Consumption>, Vec<SlowMover>, Vec<Forecast>) {
+    let mut slow_movers: Vec<SlowMover> = elements
+        .iter()
+        .filter(|(key, e)| e.number > 0 && !consumption.contains_key(*key))
+        .map(|(key, e)| SlowMover {
+            key: key.clone(),
+            number: e.number,
+            last_used: last_used.get(key).copied(),
+        })
+        .collect();
+    slow_movers.sort_by(|a, b| {
+        a.last_used
+            .cmp(&b.last_used)
+            .then(b.number.cmp(&a.number))
+            .then(a.key.cmp(&b.key))
+    });
+
+    let days = options.days();
+    let mut forecasts: Vec<Forecast> = consumption
+        .values()
+        .filter_map(|c| {
+            // 已删除的元件不需要预测
+            let element = elements.get(&c.key)?;
+            let daily_rate = c.quantity as f64 / days;
+            Some(Forecast {
+                key: c.key.clone(),
+                number: element.number,
+                daily_rate,
+                days_left: days_until_stockout(element.number, daily_rate)?,
+            })
+        })
+        .collect();
+    forecasts.sort_by(|a, b| a.days_left.total_cmp(&b.days_left).then(a.key.cmp(&b.key)));
+
+    let mut top_consumers: Vec<Consumption> = consumption.into_values().collect();
+    top_consumers.sort_by(|a, b| b.quantity.cmp(&a.quantity).then(a.key.cmp(&b.key)));
+
+    options.truncate(&mut top_consumers);
+    options.truncate(&mut slow_movers);
+    options.truncate(&mut forecasts);
+    (top_consumers, slow_movers, forecasts)
+}
+
+// DataBaseInfo 的统计报表接口
+impl DataBaseInfo {
+    /**
+     * @description: 统计范围内每个元件的消耗, 包括已删除的元件
+     * @param {i64} from 开始时间
+     * @param {i64} to 结束时间
+     * @return {键为元件键}
+     */
+    pub fn consumption(&self, from: i64, to: i64) -> BomResult<HashMap<String, Consumption>> {
+        let mut stmt = self.conn.prepare(get_consumption_template!())?;
+        let rows = stmt
+            .query_map(
+                (REASON_REMOVE, REASON_IMPORT, REASON_AUDIT, from, to),
+                |row| {
+                    let model: String = row.get(0)?;
+                    let manufacturer: String = row.get(1)?;
+                    Ok(Consumption {
+                        key: element_key(&model, &manufacturer),
+                        quantity: row.get::<usize, i64>(2)?.max(0) as u64,
+                        movements: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows.into_iter().map(|c| (c.key.clone(), c)).collect())
+    }
+
+    /**
+     * @description: 生成库存统计报表
+     * @param {&ReportOptions} options 统计范围
+     * @return {*}
+     */
+    pub fn inventory_report(&self, options: &ReportOptions) -> BomResult<InventoryReport> {
+        let elements = self.read_hm_from_database()?;
+        let consumption = self.consumption(options.from, options.to)?;
+        let mut stmt = self.conn.prepare(get_last_used_template!())?;
+        let last_used = stmt
+            .query_map((REASON_REMOVE, REASON_IMPORT, REASON_AUDIT), |row| {
+                let model: String = row.get(0)?;
+                let manufacturer: String = row.get(1)?;
+                Ok((element_key(&model, &manufacturer), row.get(2)?))
+            })?
+            .collect::<rusqlite::Result<HashMap<String, i64>>>()?;
+        let value = self.inventory_value(ledger::now_timestamp())?;
+        let (top_consumers, slow_movers, forecasts) =
+            usage_report(&elements, consumption, &last_used, options);
+        Ok(InventoryReport {
+            from: options.from,
+            to: options.to,
+            stock: StockSummary::new(elements.values()),
+            value,
+            top_consumers,
+            slow_movers,
+            forecasts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(model: &str, number: u32, element_type: ElementType) -> Element {
+        Element {
+            model: model.to_string(),
+            number,
+            element_type,
+            state: ElementStatus::from_number(number).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summary_counts_by_type_and_status() {
+        let elements = [
+            element("R1", 100, ElementType::Resistor),
+            element("R2", 5, ElementType::Resistor),
+            element("C1", 30, ElementType::Capacitor),
+        ];
+        let summary = StockSummary::new(&elements);
+        assert_eq!(
+            summary.total,
+            StockCount {
+                parts: 3,
+                quantity: 135
+            }
+        );
+        assert_eq!(summary.by_type.len(), 7);
+        assert_eq!(
+            summary.by_type[0],
+            (
+                ElementType::Resistor,
+                StockCount {
+                    parts: 2,
+                    quantity: 105
+                }
+            )
+        );
+        let shortage = summary
+            .by_status
+            .iter()
+            .find(|(s, _)| *s == ElementStatus::SHORTAGE)
+            .unwrap();
+        assert_eq!(
+            shortage.1,
+            StockCount {
+                parts: 2,
+                quantity: 35
+            }
+        );
+    }
+
+    #[test]
+    fn usage_ranks_consumers_and_forecasts_stockout() {
+        let elements: HashMap<String, Element> = [
+            element("R1", 100, ElementType::Resistor),
+            element("R2", 10, ElementType::Resistor),
+            element("C1", 30, ElementType::Capacitor),
+            element("C2", 0, ElementType::Capacitor),
+        ]
+        .into_iter()
+        .map(|e| (e.key(), e))
+        .collect();
+        let consumption: HashMap<String, Consumption> =
+            [("R1", 60, 3), ("R2", 20, 1), ("D1", 90, 2)]
+                .into_iter()
+                .map(|(key, quantity, movements)| {
+                    (
+                        key.to_string(),
+                        Consumption {
+                            key: key.to_string(),
+                            quantity,
+                            movements,
+                        },
+                    )
+                })
+                .collect();
+        let last_used = HashMap::from([("C1".to_string(), 5)]);
+        let options = ReportOptions {
+            from: 0,
+            to: 20 * SECONDS_PER_DAY,
+            top: 2,
+        };
+        let (top, slow, forecasts) = usage_report(&elements, consumption, &last_used, &options);
+        let keys: Vec<&str> = top.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, ["D1", "R1"]);
+        // 没有库存的 C2 不算呆滞
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].last_used, Some(5));
+        // R2 每天 1 个还剩 10 天, R1 每天 3 个还剩 33 天, 已删除的 D1 不预测
+        assert_eq!(forecasts.len(), 2);
+        assert_eq!(forecasts[0].key, "R2");
+        assert_eq!(forecasts[0].days_left, 10.0);
+        assert!((forecasts[1].days_left - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(days_until_stockout(5, 0.0), None);
+    }
+}
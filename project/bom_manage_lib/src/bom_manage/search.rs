@@ -0,0 +1,355 @@
+/*
+ * @Description: 元件搜索, 用 SQLite FTS5 对型号/描述/分类/厂家料号建全文索引, 全文没有命中时按编辑距离模糊匹配
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\search.rs
+ */
+use super::{element_from_row, element_key, BomResult, DataBaseInfo, Element, ElementType};
+use rusqlite::{Connection, OptionalExtension};
+
+// 删除元件的索引
+macro_rules! delete_search_template {
+    () => {
+        "DELETE FROM element_search WHERE key = ?"
+    };
+}
+// 写入元件的索引
+macro_rules! insert_search_template {
+    () => {
+        "INSERT INTO element_search (key, model, describe, category, mpn) VALUES (?, ?, ?, ?, ?)"
+    };
+}
+// 读取建索引需要的元件字段
+macro_rules! get_search_source_template {
+    () => {
+        "SELECT describe, element_type, category_id FROM {} WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 分类及其所有上级分类的名字
+macro_rules! get_category_names_template {
+    () => {
+        "WITH RECURSIVE up (id, name, parent_id) AS (\
+         SELECT id, name, parent_id FROM categories WHERE id = ? \
+         UNION ALL SELECT c.id, c.name, c.parent_id FROM categories c JOIN up ON c.id = up.parent_id) \
+         SELECT COALESCE(group_concat(name, ' '), '') FROM up"
+    };
+}
+// 元件所有供应商料号的厂家料号和供应商料号
+macro_rules! get_part_numbers_template {
+    () => {
+        "SELECT COALESCE(group_concat(mpn || ' ' || sku, ' '), '') FROM supplier_parts \
+         WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 全文搜索, 按 bm25 排序, 型号和厂家料号的权重更高
+macro_rules! full_text_search_template {
+    () => {
+        "SELECT key, bm25(element_search, 0.0, 10.0, 2.0, 1.0, 5.0) AS score FROM element_search \
+         WHERE element_search MATCH ? ORDER BY score LIMIT ?"
+    };
+}
+// 读取所有索引内容, 用于模糊匹配
+macro_rules! get_search_all_template {
+    () => {
+        "SELECT key, model, describe, category, mpn FROM element_search"
+    };
+}
+
+// 一条搜索结果
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub element: Element, // 命中的元件
+    pub score: f64,       // 越小越靠前, 全文命中为 bm25 分数, 模糊命中为编辑距离之和
+    pub fuzzy: bool,      // 是否为模糊命中
+}
+
+/**
+ * @description: 元件类型的英文名, 写入索引后可以用英文搜索, 例如 "10k resistor"
+ * @param {&ElementType} element_type
+ * @return {*}
+ */
+fn type_alias(element_type: &ElementType) -> &'static str {
+    match element_type {
+        ElementType::Resistor => "resistor",
+        ElementType::Diode => "diode",
+        ElementType::Transistor => "transistor",
+        ElementType::Capacitor => "capacitor",
+        ElementType::Inductor => "inductor",
+        ElementType::Chip => "chip ic",
+        ElementType::Unknown => "",
+    }
+}
+
+/**
+ * @description: 重建一个元件的索引, 元件已删除时只删除索引
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {*}
+ */
+pub(crate) fn index_element(
+    conn: &Connection,
+    tables: &str,
+    model: &str,
+    manufacturer: &str,
+) -> BomResult<()> {
+    let key = element_key(model, manufacturer);
+    conn.execute(delete_search_template!(), [&key])?;
+    let Some((describe, element_type, category_id)) = conn
+        .query_row(
+            format!(get_search_source_template!(), tables).as_str(),
+            [model, manufacturer],
+            |row| {
+                Ok((
+                    row.get::<usize, Option<String>>(0)?.unwrap_or_default(),
+                    row.get::<usize, Option<String>>(1)?.unwrap_or_default(),
+                    row.get::<usize, Option<i64>>(2)?,
+                ))
+            },
+        )
+        .optional()?
+    else {
+        return Ok(());
+    };
+    let mut category: String = match category_id {
+        Some(id) => conn.query_row(get_category_names_template!(), [id], |row| row.get(0))?,
+        None => String::new(),
+    };
+    let element_type = ElementType::from_string(&element_type).unwrap_or_default();
+    category = format!("{category} {}", type_alias(&element_type));
+    let mpn: String =
+        conn.query_row(get_part_numbers_template!(), [model, manufacturer], |row| {
+            row.get(0)
+        })?;
+    conn.execute(
+        insert_search_template!(),
+        (
+            &key,
+            format!("{model} {manufacturer}"),
+            describe,
+            category.trim(),
+            mpn,
+        ),
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 删除一个元件的索引
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {*}
+ */
+pub(crate) fn unindex_element(conn: &Connection, model: &str, manufacturer: &str) -> BomResult<()> {
+    conn.execute(
+        delete_search_template!(),
+        [element_key(model, manufacturer)],
+    )?;
+    Ok(())
+}
+
+/**
+ * @description: 重建所有元件的索引, 分类改名等影响多个元件时使用
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @return {*}
+ */
+pub(crate) fn reindex_all(conn: &Connection, tables: &str) -> BomResult<()> {
+    conn.execute("DELETE FROM element_search", [])?;
+    let mut stmt = conn.prepare(format!("SELECT model, manufacturer FROM {tables}").as_str())?;
+    let keys = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<usize, String>(0)?,
+                row.get::<usize, Option<String>>(1)?.unwrap_or_default(),
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (model, manufacturer) in keys {
+        index_element(conn, tables, &model, &manufacturer)?;
+    }
+    Ok(())
+}
+
+/**
+ * @description: 把用户输入拆成搜索词, 按非字母数字字符分开并转为小写
+ * @param {&str} text
+ * @return {*}
+ */
+pub fn search_terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/**
+ * @description: 搜索词转为 FTS5 查询, 每个词都按前缀匹配, 所有词都要命中
+ * @param {&[String]} terms 搜索词
+ * @return {*}
+ */
+pub(crate) fn fts_query(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/**
+ * @description: 两个字符串的编辑距离 (Levenshtein)
+ * @param {&[char]} a
+ * @param {&[char]} b
+ * @return {*}
+ */
+pub fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/**
+ * @description: 搜索词允许的编辑距离, 3 个字符以内必须完全一致
+ * @param {usize} len 搜索词的字符数
+ * @return {*}
+ */
+fn allowed_distance(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+/**
+ * @description: 模糊匹配, 每个搜索词都要和某个索引词足够接近, 索引词可以只比较和搜索词等长的前缀
+ * @param {&[String]} terms 搜索词
+ * @param {&str} text 元件的索引内容
+ * @return {编辑距离之和, 不匹配时为 None}
+ */
+fn fuzzy_score(terms: &[String], text: &str) -> Option<usize> {
+    let tokens: Vec<Vec<char>> = search_terms(text)
+        .iter()
+        .map(|t| t.chars().collect())
+        .collect();
+    let mut total = 0;
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        let best = tokens
+            .iter()
+            .map(|token| {
+                let prefix = &token[..token.len().min(term.len())];
+                edit_distance(&term, token).min(edit_distance(&term, prefix))
+            })
+            .min()?;
+        if best > allowed_distance(term.len()) {
+            return None;
+        }
+        total += best;
+    }
+    Some(total)
+}
+
+// DataBaseInfo 的搜索接口
+impl DataBaseInfo {
+    /**
+     * @description: 搜索元件, 先做全文搜索, 全文没有命中时再模糊匹配
+     * @param {&str} text 搜索内容, 例如 "r10k", "10k resistor"
+     * @param {usize} limit 最多返回的条数
+     * @return {按相关度排序}
+     */
+    pub fn search(&self, text: &str, limit: usize) -> BomResult<Vec<SearchHit>> {
+        let terms = search_terms(text);
+        if terms.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        let mut ranked: Vec<(String, f64, bool)> = {
+            let mut stmt = self.conn.prepare(full_text_search_template!())?;
+            let rows = stmt.query_map((fts_query(&terms), limit as i64), |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, f64>(1)?,
+                    false,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        if ranked.is_empty() {
+            let mut stmt = self.conn.prepare(get_search_all_template!())?;
+            let rows = stmt.query_map([], |row| {
+                let text = (1..5)
+                    .map(|i| row.get::<usize, String>(i))
+                    .collect::<rusqlite::Result<Vec<_>>>()?
+                    .join(" ");
+                Ok((row.get::<usize, String>(0)?, text))
+            })?;
+            let mut fuzzy = Vec::new();
+            for row in rows {
+                let (key, text) = row?;
+                if let Some(score) = fuzzy_score(&terms, &text) {
+                    fuzzy.push((key, score as f64, true));
+                }
+            }
+            fuzzy.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+            ranked.extend(fuzzy.into_iter().take(limit));
+        }
+        let sql =
+            format!(get_all_template!(), self.tables) + " WHERE model = ? AND manufacturer = ?";
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let mut hits = Vec::new();
+        for (key, score, fuzzy) in ranked {
+            let (model, manufacturer) = super::split_key(&key);
+            if let Some(element) = stmt
+                .query_row([model, manufacturer], element_from_row)
+                .optional()?
+            {
+                hits.push(SearchHit {
+                    element,
+                    score,
+                    fuzzy,
+                });
+            }
+        }
+        Ok(hits)
+    }
+
+    /**
+     * @description: 重建所有元件的搜索索引
+     * @return {*}
+     */
+    pub fn rebuild_search_index(&self) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        reindex_all(&tx, &self.tables)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_terms() {
+        assert_eq!(search_terms("10k, Resistor"), vec!["10k", "resistor"]);
+        assert_eq!(fts_query(&search_terms("r10k 电阻")), "\"r10k\"* \"电阻\"*");
+
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("resistr"), &chars("resistor")), 1);
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+
+        let text = "R10K 电阻 10k 0603 1% 电阻 resistor";
+        assert_eq!(fuzzy_score(&search_terms("10k resistr"), text), Some(1));
+        assert_eq!(fuzzy_score(&search_terms("resis"), text), Some(0));
+        // 短词不做模糊匹配
+        assert_eq!(fuzzy_score(&search_terms("r1k"), text), None);
+        assert_eq!(fuzzy_score(&search_terms("capacitor"), text), None);
+    }
+}
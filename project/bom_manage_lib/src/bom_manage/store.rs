@@ -0,0 +1,721 @@
+/*
+ * @Description: 元件存储后端, BomManageCtrl 通过 BomStore 读写元件和流水,
+ * 可以用 sqlite 数据库, 内存或者单个 JSON 文件保存
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\store.rs
+ */
+use super::category::{BUILTIN_TYPES, PATH_SEPARATOR};
+use super::export::{
+    read_inventory, write_inventory, CategoryRecord, ElementRecord, ExportFormat, Inventory,
+    LocationRecord,
+};
+use super::ledger::{MovementNote, StockMovement};
+use super::threshold::{DEFAULT_MIN_STOCK, DEFAULT_REORDER_POINT};
+use super::{
+    check_model, element_key, BomError, BomResult, DataBaseInfo, Element, ReduceReport, Thresholds,
+};
+use std::collections::HashMap;
+use std::{fs, path::Path};
+
+// 元件的持久化操作, 数量的修改同时写入一条流水
+pub trait BomStore {
+    /**
+     * @description: 读取所有元件, 键为 element_key
+     * @return {*}
+     */
+    fn read_all(&self) -> BomResult<HashMap<String, Element>>;
+
+    /**
+     * @description: 按型号 + 厂家读取元件, 不存在时返回 BomError::NotFound
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    fn element(&self, model: &str, manufacturer: &str) -> BomResult<Element>;
+
+    /**
+     * @description: 增加元件数量, 不存在就新增, 并记一条入库流水
+     * @param {&Element} element 要增加的元件, number 为增加的数量
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {写入后的元件}
+     */
+    fn add_element_number(&mut self, element: &Element, note: &MovementNote) -> BomResult<Element>;
+
+    /**
+     * @description: 减少元件数量, 并记一条出库流水
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {u32} number 要减少的数量
+     * @param {bool} take_available 库存不足时 true 取出全部库存, false 返回库存不足错误
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {*}
+     */
+    fn reduce_element_number(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        number: u32,
+        take_available: bool,
+        note: &MovementNote,
+    ) -> BomResult<ReduceReport>;
+
+    /**
+     * @description: 修改元件的描述/类型/分类, 版本号和存储中不同时返回 BomError::Conflict
+     * @param {&Element} element 修改后的元件
+     * @return {修改后的元件}
+     */
+    fn update_element(&mut self, element: &Element) -> BomResult<Element>;
+
+    /**
+     * @description: 删除单个元件, 剩余库存记一条出库流水
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {删除的个数}
+     */
+    fn delete_element(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        note: &MovementNote,
+    ) -> BomResult<usize>;
+
+    /**
+     * @description: 删除所有元件, 每个元件的剩余库存记一条出库流水
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {*}
+     */
+    fn delete_all(&mut self, note: &MovementNote) -> BomResult<()>;
+
+    /**
+     * @description: 读取一个元件的全部流水, 按时间排序
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    fn element_history(&self, model: &str, manufacturer: &str) -> BomResult<Vec<StockMovement>>;
+}
+
+// sqlite 数据库, 支持分类/位置/供应商等全部功能, 多个进程可以同时使用
+impl BomStore for DataBaseInfo {
+    fn read_all(&self) -> BomResult<HashMap<String, Element>> {
+        self.read_hm_from_database()
+    }
+
+    fn element(&self, model: &str, manufacturer: &str) -> BomResult<Element> {
+        DataBaseInfo::element(self, model, manufacturer)
+    }
+
+    fn add_element_number(&mut self, element: &Element, note: &MovementNote) -> BomResult<Element> {
+        DataBaseInfo::add_element_number(self, element, note)
+    }
+
+    fn reduce_element_number(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        number: u32,
+        take_available: bool,
+        note: &MovementNote,
+    ) -> BomResult<ReduceReport> {
+        DataBaseInfo::reduce_element_number(self, model, manufacturer, number, take_available, note)
+    }
+
+    fn update_element(&mut self, element: &Element) -> BomResult<Element> {
+        DataBaseInfo::update_element(self, element)
+    }
+
+    fn delete_element(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        note: &MovementNote,
+    ) -> BomResult<usize> {
+        DataBaseInfo::delete_element(self, model, manufacturer, note)
+    }
+
+    fn delete_all(&mut self, note: &MovementNote) -> BomResult<()> {
+        DataBaseInfo::delete_all(self, note)
+    }
+
+    fn element_history(&self, model: &str, manufacturer: &str) -> BomResult<Vec<StockMovement>> {
+        DataBaseInfo::element_history(self, model, manufacturer)
+    }
+}
+
+// 内存中的元件和流水, 程序退出后丢失, 用于测试;
+// 状态和数据库一样按元件/分类的阈值计算, 都没有设置时使用默认值
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    elements: HashMap<String, Element>, // 元件, 键为 element_key
+    movements: Vec<StockMovement>,      // 流水, 按写入顺序
+    element_thresholds: HashMap<String, (Option<u32>, Option<u32>)>, // 元件自己的 (最低库存, 补货点)
+    category_thresholds: HashMap<i64, (Option<u32>, Option<u32>)>, // 分类生效的阈值, 已继承上级分类
+}
+
+impl MemoryStore {
+    // 元件生效的阈值, 元件没有设置时用分类的, 都没有时使用默认值
+    fn thresholds(&self, key: &str, category: Option<i64>) -> Thresholds {
+        let own = self
+            .element_thresholds
+            .get(key)
+            .copied()
+            .unwrap_or_default();
+        let inherited = category
+            .and_then(|id| self.category_thresholds.get(&id))
+            .copied()
+            .unwrap_or_default();
+        Thresholds {
+            min_stock: own.0.or(inherited.0).unwrap_or(DEFAULT_MIN_STOCK),
+            reorder_point: own.1.or(inherited.1).unwrap_or(DEFAULT_REORDER_POINT),
+        }
+    }
+
+    // 写入一条流水, 流水号按顺序生成
+    fn push_movement(&mut self, mut movement: StockMovement) {
+        movement.id = self.movements.len() as i64 + 1;
+        self.movements.push(movement);
+    }
+}
+
+impl BomStore for MemoryStore {
+    fn read_all(&self) -> BomResult<HashMap<String, Element>> {
+        Ok(self.elements.clone())
+    }
+
+    fn element(&self, model: &str, manufacturer: &str) -> BomResult<Element> {
+        let key = element_key(model, manufacturer);
+        self.elements
+            .get(&key)
+            .cloned()
+            .ok_or(BomError::NotFound(key))
+    }
+
+    fn add_element_number(&mut self, element: &Element, note: &MovementNote) -> BomResult<Element> {
+        let thresholds = self.thresholds(&element.key(), element.category_id());
+        let stored = self
+            .elements
+            .entry(element.key())
+            .and_modify(|stored| {
+                stored.describe = element.describe.clone();
+                stored.number = stored.number.saturating_add(element.number);
+                stored.element_type = element.element_type.clone();
+                stored.version += 1;
+            })
+            .or_insert_with(|| Element {
+                version: 0,
+                ..element.clone()
+            });
+        stored.category = element.category_id();
+        stored.state = thresholds.status(stored.number);
+        let stored = stored.clone();
+        if element.number > 0 {
+            self.push_movement(note.movement(
+                &element.model,
+                &element.manufacturer,
+                element.number.into(),
+            ));
+        }
+        Ok(stored)
+    }
+
+    fn reduce_element_number(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        number: u32,
+        take_available: bool,
+        note: &MovementNote,
+    ) -> BomResult<ReduceReport> {
+        let key = element_key(model, manufacturer);
+        let thresholds = match self.elements.get(&key) {
+            Some(element) => self.thresholds(&key, element.category),
+            None => return Err(BomError::NotFound(model.to_string())),
+        };
+        let element = self.elements.get_mut(&key).unwrap();
+        let available = element.number;
+        if number > available && !take_available {
+            return Err(BomError::InsufficientStock {
+                model: model.to_string(),
+                requested: number,
+                available,
+            });
+        }
+        let taken = number.min(available);
+        let remain = available - taken;
+        element.number = remain;
+        element.state = thresholds.status(remain);
+        element.version += 1;
+        if taken > 0 {
+            self.push_movement(note.movement(model, manufacturer, -i64::from(taken)));
+        }
+        Ok(ReduceReport {
+            taken,
+            shortfall: number - taken,
+            remain,
+        })
+    }
+
+    fn update_element(&mut self, element: &Element) -> BomResult<Element> {
+        let key = element.key();
+        let Some(stored) = self.elements.get_mut(&key) else {
+            return Err(BomError::NotFound(key));
+        };
+        if stored.version != element.version {
+            return Err(BomError::Conflict {
+                model: key,
+                expected: element.version,
+                found: stored.version,
+            });
+        }
+        stored.describe = element.describe.clone();
+        stored.element_type = element.element_type.clone();
+        stored.category = element.category_id();
+        stored.version += 1;
+        Ok(stored.clone())
+    }
+
+    fn delete_element(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        note: &MovementNote,
+    ) -> BomResult<usize> {
+        let key = element_key(model, manufacturer);
+        // 和数据库一样, 元件的阈值随元件删除
+        self.element_thresholds.remove(&key);
+        match self.elements.remove(&key) {
+            Some(element) => {
+                if element.number > 0 {
+                    self.push_movement(note.movement(
+                        model,
+                        manufacturer,
+                        -i64::from(element.number),
+                    ));
+                }
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn delete_all(&mut self, note: &MovementNote) -> BomResult<()> {
+        self.element_thresholds.clear();
+        let mut removed: Vec<Element> = self.elements.drain().map(|(_, e)| e).collect();
+        removed.sort_by_key(|e| e.key());
+        for element in removed.iter().filter(|e| e.number > 0) {
+            self.push_movement(note.movement(
+                &element.model,
+                &element.manufacturer,
+                -i64::from(element.number),
+            ));
+        }
+        Ok(())
+    }
+
+    fn element_history(&self, model: &str, manufacturer: &str) -> BomResult<Vec<StockMovement>> {
+        let mut history: Vec<StockMovement> = self
+            .movements
+            .iter()
+            .filter(|m| m.model == model && m.manufacturer == manufacturer)
+            .cloned()
+            .collect();
+        history.sort_by_key(|m| (m.timestamp, m.id));
+        Ok(history)
+    }
+}
+
+// 单个 JSON 文件, 格式和 export 导出的 JSON 相同, 可以用 import 导入到数据库;
+// 每次修改后写回整个文件, 只适合单个进程和少量元件, 例如小型嵌入式板子
+#[derive(Debug)]
+pub struct JsonStore {
+    path: String,                    // 文件路径
+    memory: MemoryStore,             // 文件中的元件和流水, 修改在这里完成后写回文件
+    categories: Vec<CategoryRecord>, // 文件中的分类, 下标 + 1 为元件的分类 id, 前几个为内置分类
+    inventory: Inventory, // 文件的其他内容, 例如供应商/单据/元件的阈值和位置, 写回时保持不变
+}
+
+impl JsonStore {
+    /**
+     * @description: 打开 JSON 文件, 文件不存在时创建一个空库存
+     * @param {&str} path 文件路径
+     * @return {*}
+     */
+    pub fn open(path: &str) -> BomResult<JsonStore> {
+        let store = Self::load(path)?;
+        if !Path::new(path).exists() {
+            store.save()?;
+        }
+        Ok(store)
+    }
+
+    /**
+     * @description: 文件路径
+     * @return {*}
+     */
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // 读取文件内容, 文件不存在时为空; 文件中没有版本号, 读取后从 0 开始;
+    // 分类按路径读取, 依次编号, 和数据库一样内置类型的分类 id 不变
+    fn load(path: &str) -> BomResult<JsonStore> {
+        let mut store = JsonStore {
+            path: path.to_string(),
+            memory: MemoryStore::default(),
+            categories: BUILTIN_TYPES
+                .iter()
+                .map(|t| CategoryRecord {
+                    path: t.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            inventory: Inventory::default(),
+        };
+        if !Path::new(path).exists() {
+            return Ok(store);
+        }
+        store.inventory = read_inventory(path, ExportFormat::Json)?;
+        for category in std::mem::take(&mut store.inventory.categories) {
+            let id = store.category_id(&category.path);
+            store.categories[id as usize - 1] = category;
+        }
+        // 先登记元件用到的分类, 再计算每个分类生效的阈值
+        let categories: Vec<Option<i64>> = store
+            .inventory
+            .elements
+            .clone()
+            .iter()
+            .map(|record| match &record.category {
+                Some(path) => Some(store.category_id(path)),
+                None => record.element_type.category_id(),
+            })
+            .collect();
+        store.memory.category_thresholds = (1..=store.categories.len() as i64)
+            .map(|id| (id, store.inherited_thresholds(id)))
+            .collect();
+        for (record, category) in store.inventory.elements.iter().zip(categories) {
+            // 和 add_element/import 一样检查型号, 键必须能拆回型号和厂家
+            check_model(&record.model)?;
+            let key = record.key();
+            store
+                .memory
+                .element_thresholds
+                .insert(key.clone(), (record.min_stock, record.reorder_point));
+            let element = Element {
+                category,
+                state: store
+                    .memory
+                    .thresholds(&key, category)
+                    .status(record.number),
+                describe: record.describe.clone(),
+                model: record.model.clone(),
+                manufacturer: record.manufacturer.clone(),
+                number: record.number,
+                element_type: record.element_type.clone(),
+                version: 0,
+            };
+            if store.memory.elements.insert(key.clone(), element).is_some() {
+                return Err(BomError::InvalidExport(format!("duplicate element: {key}")));
+            }
+        }
+        for movement in std::mem::take(&mut store.inventory.movements) {
+            store.memory.push_movement(movement);
+        }
+        Ok(store)
+    }
+
+    // 分类路径对应的 id, 文件中没有的分类添加到最后
+    fn category_id(&mut self, path: &str) -> i64 {
+        match self.categories.iter().position(|c| c.path == path) {
+            Some(index) => index as i64 + 1,
+            None => {
+                self.categories.push(CategoryRecord {
+                    path: path.to_string(),
+                    ..Default::default()
+                });
+                self.categories.len() as i64
+            }
+        }
+    }
+
+    // 分类生效的阈值, 分类没有设置时沿路径继承上级分类的
+    fn inherited_thresholds(&self, id: i64) -> (Option<u32>, Option<u32>) {
+        let path = &self.categories[id as usize - 1].path;
+        let mut inherited = (None, None);
+        let mut prefix = String::new();
+        for name in path.split(PATH_SEPARATOR) {
+            if !prefix.is_empty() {
+                prefix.push(PATH_SEPARATOR);
+            }
+            prefix.push_str(name);
+            if let Some(c) = self.categories.iter().find(|c| c.path == prefix) {
+                inherited = (c.min_stock.or(inherited.0), c.reorder_point.or(inherited.1));
+            }
+        }
+        inherited
+    }
+
+    // 分类 id 对应的路径, 不是从文件读到的分类返回 BomError::NotFound
+    fn category_path(&self, id: i64) -> BomResult<String> {
+        usize::try_from(id - 1)
+            .ok()
+            .and_then(|index| self.categories.get(index))
+            .map(|c| c.path.clone())
+            .ok_or_else(|| BomError::NotFound(format!("category {id}")))
+    }
+
+    // 写回整个文件, 先写临时文件再改名, 写到一半断电时原文件不受影响;
+    // 只替换元件和流水, 元件原有的阈值/位置和文件的其他内容原样写回
+    fn save(&self) -> BomResult<()> {
+        let mut elements: Vec<ElementRecord> = self
+            .memory
+            .elements
+            .values()
+            .map(|e| {
+                let mut record = self
+                    .inventory
+                    .elements
+                    .iter()
+                    .find(|r| r.key() == e.key())
+                    .cloned()
+                    .unwrap_or_default();
+                record.describe = e.describe.clone();
+                record.model = e.model.clone();
+                record.manufacturer = e.manufacturer.clone();
+                record.number = e.number;
+                record.element_type = e.element_type.clone();
+                record.state = e.state.clone();
+                record.category = e.category.map(|id| self.category_path(id)).transpose()?;
+                trim_locations(&mut record.locations, e.number);
+                Ok(record)
+            })
+            .collect::<BomResult<_>>()?;
+        elements.sort_by_key(|e| e.key());
+        let inventory = Inventory {
+            elements,
+            categories: self.categories.clone(),
+            movements: self.memory.movements.clone(),
+            ..self.inventory.clone()
+        };
+        let temp = format!("{}.tmp", self.path);
+        write_inventory(&inventory, &temp, ExportFormat::Json)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    // 修改后写回文件, 修改或写回失败时从文件恢复, 内存和文件保持一致
+    fn commit<T>(&mut self, result: BomResult<T>) -> BomResult<T> {
+        match result.and_then(|value| self.save().map(|_| value)) {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                *self = Self::load(&self.path)?;
+                Err(err)
+            }
+        }
+    }
+}
+
+/**
+ * @description: 和数据库一样, 位置上的总数不能超过库存, 超出的部分从数量少的位置开始扣除
+ * @param {&mut Vec<LocationRecord>} locations 元件各个位置的数量
+ * @param {u32} number 元件现在的数量
+ * @return {*}
+ */
+fn trim_locations(locations: &mut Vec<LocationRecord>, number: u32) {
+    let mut excess = locations
+        .iter()
+        .map(|l| l.quantity)
+        .sum::<u32>()
+        .saturating_sub(number);
+    let mut order: Vec<usize> = (0..locations.len()).collect();
+    order.sort_by_key(|&i| locations[i].quantity);
+    for i in order {
+        let take = locations[i].quantity.min(excess);
+        locations[i].quantity -= take;
+        excess -= take;
+    }
+    locations.retain(|l| l.quantity > 0);
+}
+
+impl BomStore for JsonStore {
+    fn read_all(&self) -> BomResult<HashMap<String, Element>> {
+        self.memory.read_all()
+    }
+
+    fn element(&self, model: &str, manufacturer: &str) -> BomResult<Element> {
+        self.memory.element(model, manufacturer)
+    }
+
+    fn add_element_number(&mut self, element: &Element, note: &MovementNote) -> BomResult<Element> {
+        let result = self.memory.add_element_number(element, note);
+        self.commit(result)
+    }
+
+    fn reduce_element_number(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        number: u32,
+        take_available: bool,
+        note: &MovementNote,
+    ) -> BomResult<ReduceReport> {
+        let result =
+            self.memory
+                .reduce_element_number(model, manufacturer, number, take_available, note);
+        self.commit(result)
+    }
+
+    fn update_element(&mut self, element: &Element) -> BomResult<Element> {
+        let result = self.memory.update_element(element);
+        self.commit(result)
+    }
+
+    fn delete_element(
+        &mut self,
+        model: &str,
+        manufacturer: &str,
+        note: &MovementNote,
+    ) -> BomResult<usize> {
+        let result = self.memory.delete_element(model, manufacturer, note);
+        // 删除的元件原有的阈值和位置不再写回
+        let key = element_key(model, manufacturer);
+        self.inventory.elements.retain(|r| r.key() != key);
+        self.commit(result)
+    }
+
+    fn delete_all(&mut self, note: &MovementNote) -> BomResult<()> {
+        let result = self.memory.delete_all(note);
+        self.inventory.elements.clear();
+        self.commit(result)
+    }
+
+    fn element_history(&self, model: &str, manufacturer: &str) -> BomResult<Vec<StockMovement>> {
+        self.memory.element_history(model, manufacturer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bom_manage::{ElementStatus, ElementType};
+
+    fn resistor(model: &str, number: u32) -> Element {
+        Element {
+            describe: "电阻".to_string(),
+            model: model.to_string(),
+            number,
+            element_type: ElementType::Resistor,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn memory_store_counts_and_records_movements() {
+        let mut store = MemoryStore::default();
+        let note = MovementNote::default();
+        store
+            .add_element_number(&resistor("R10K", 10), &note)
+            .unwrap();
+        let stored = store
+            .add_element_number(&resistor("R10K", 90), &note)
+            .unwrap();
+        assert_eq!(stored.number, 100);
+        assert_eq!(stored.state, ElementStatus::NORMAL);
+
+        let report = store
+            .reduce_element_number("R10K", "", 150, true, &note)
+            .unwrap();
+        assert_eq!(
+            (report.taken, report.shortfall, report.remain),
+            (100, 50, 0)
+        );
+        assert!(matches!(
+            store.reduce_element_number("R10K", "", 1, false, &note),
+            Err(BomError::InsufficientStock { .. })
+        ));
+        let deltas: Vec<i64> = store
+            .element_history("R10K", "")
+            .unwrap()
+            .iter()
+            .map(|m| m.delta)
+            .collect();
+        assert_eq!(deltas, vec![10, 90, -100]);
+
+        // 旧版本的修改被拒绝
+        let mut stale = store.element("R10K", "").unwrap();
+        store
+            .add_element_number(&resistor("R10K", 1), &note)
+            .unwrap();
+        stale.describe = "贴片电阻".to_string();
+        assert!(matches!(
+            store.update_element(&stale),
+            Err(BomError::Conflict { .. })
+        ));
+        assert_eq!(store.delete_element("R10K", "", &note).unwrap(), 1);
+        assert_eq!(store.delete_element("R10K", "", &note).unwrap(), 0);
+    }
+
+    #[test]
+    fn json_store_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("bom_store_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let _ = fs::remove_file(&path);
+        let note = MovementNote::default();
+
+        let mut store = JsonStore::open(&path).unwrap();
+        assert!(Path::new(&path).exists());
+        store
+            .add_element_number(&resistor("R10K", 30), &note)
+            .unwrap();
+        store
+            .reduce_element_number("R10K", "", 5, false, &note)
+            .unwrap();
+        // 失败的修改不写回文件
+        assert!(store
+            .reduce_element_number("R10K", "", 50, false, &note)
+            .is_err());
+
+        let store = JsonStore::open(&path).unwrap();
+        assert_eq!(store.element("R10K", "").unwrap().number, 25);
+        assert_eq!(store.element_history("R10K", "").unwrap().len(), 2);
+        // 文件和 export 的格式相同
+        let inventory = read_inventory(&path, ExportFormat::Json).unwrap();
+        assert_eq!(inventory.elements[0].model, "R10K");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn json_store_rejects_ambiguous_keys() {
+        let path = std::env::temp_dir().join(format!("bom_store_keys_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let record = |model: &str, manufacturer: &str| ElementRecord {
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            number: 10,
+            ..Default::default()
+        };
+        let write = |elements: Vec<ElementRecord>| {
+            let inventory = Inventory {
+                elements,
+                ..Default::default()
+            };
+            write_inventory(&inventory, &path, ExportFormat::Json).unwrap();
+        };
+
+        // 型号中有 '@' 时键拆不回型号和厂家
+        write(vec![record("R10K@0603", "")]);
+        assert!(matches!(
+            JsonStore::open(&path),
+            Err(BomError::InvalidModel(model)) if model == "R10K@0603"
+        ));
+        // 重复的元件不能只保留一个
+        write(vec![record("R10K", "国巨"), record("R10K", "国巨")]);
+        assert!(matches!(
+            JsonStore::open(&path),
+            Err(BomError::InvalidExport(_))
+        ));
+        write(vec![record("R10K", "国巨"), record("R10K", "")]);
+        assert_eq!(JsonStore::open(&path).unwrap().read_all().unwrap().len(), 2);
+        let _ = fs::remove_file(&path);
+    }
+}
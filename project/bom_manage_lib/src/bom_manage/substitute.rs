@@ -0,0 +1,350 @@
+/*
+ * @Description: 替代料, 包括手动维护的替代料组和按数值/封装/精度/耐压判断的参数替代,
+ * 比对 BOM 和生产单预留时按设置的顺序从替代料中取料
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\substitute.rs
+ */
+use super::bom_import::MatchKind;
+use super::value::ElementParams;
+use super::{BomError, BomResult, DataBaseInfo, Element};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+// 新增替代料组, 已存在时忽略
+macro_rules! insert_group_template {
+    () => {
+        "INSERT OR IGNORE INTO part_groups (name) VALUES (?)"
+    };
+}
+// 新增或修改组内元件的优先级
+macro_rules! upsert_member_template {
+    () => {
+        "INSERT INTO part_group_members (group_id, model, manufacturer, priority) \
+         VALUES ((SELECT id FROM part_groups WHERE name = ?), ?, ?, ?) \
+         ON CONFLICT (group_id, model, manufacturer) DO UPDATE SET priority = excluded.priority"
+    };
+}
+// 组内下一个优先级, 新加入的元件排在最后
+macro_rules! next_priority_template {
+    () => {
+        "SELECT COALESCE(MAX(m.priority) + 1, 0) FROM part_group_members m \
+         JOIN part_groups g ON g.id = m.group_id WHERE g.name = ?"
+    };
+}
+// 从组中删除元件
+macro_rules! delete_member_template {
+    () => {
+        "DELETE FROM part_group_members WHERE group_id = (SELECT id FROM part_groups WHERE name = ?) \
+         AND model = ? AND manufacturer = ?"
+    };
+}
+// 删除没有元件的组
+macro_rules! delete_empty_groups_template {
+    () => {
+        "DELETE FROM part_groups WHERE id NOT IN (SELECT group_id FROM part_group_members)"
+    };
+}
+// 读取所有组和组内元件
+macro_rules! get_all_members_template {
+    () => {
+        "SELECT g.name, m.model, m.manufacturer, m.priority FROM part_groups g \
+         JOIN part_group_members m ON m.group_id = g.id \
+         ORDER BY g.name, m.priority, m.model, m.manufacturer"
+    };
+}
+
+// 替代料组中的一个元件
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupMember {
+    pub model: String,        // 型号
+    pub manufacturer: String, // 厂家, 为空时匹配这个型号的所有厂家
+    pub priority: u32,        // 优先级, 小的先用
+}
+
+impl GroupMember {
+    /**
+     * @description: 元件是否为这个组员, 型号忽略大小写
+     * @param {&Element} element 元件
+     * @return {*}
+     */
+    pub fn matches(&self, element: &Element) -> bool {
+        element.model.eq_ignore_ascii_case(&self.model)
+            && (self.manufacturer.is_empty()
+                || element
+                    .manufacturer
+                    .eq_ignore_ascii_case(&self.manufacturer))
+    }
+}
+
+// 替代料组, 组内元件可以互相替代
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartGroup {
+    pub name: String,              // 组名
+    pub members: Vec<GroupMember>, // 组内元件, 按优先级排序
+}
+
+impl PartGroup {
+    /**
+     * @description: 元件在组中的优先级, 不在组中返回 None
+     * @param {&Element} element 元件
+     * @return {*}
+     */
+    pub fn priority(&self, element: &Element) -> Option<u32> {
+        self.members
+            .iter()
+            .filter(|m| m.matches(element))
+            .map(|m| m.priority)
+            .min()
+    }
+
+    /**
+     * @description: 组中是否有这个型号, 用于按 BOM 中写的型号查找替代料
+     * @param {&str} model 型号
+     * @return {*}
+     */
+    pub fn has_model(&self, model: &str) -> bool {
+        self.members
+            .iter()
+            .any(|m| m.model.eq_ignore_ascii_case(model))
+    }
+}
+
+// 取料顺序
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubstitutePolicy {
+    pub order: Vec<MatchKind>, // 候选元件的来源顺序, 不在其中的来源不使用
+    pub substitute: bool,      // 第一个候选元件不够时是否从其他候选元件取料
+}
+
+impl Default for SubstitutePolicy {
+    fn default() -> Self {
+        SubstitutePolicy {
+            order: vec![MatchKind::Model, MatchKind::Alternate, MatchKind::Value],
+            substitute: true,
+        }
+    }
+}
+
+impl SubstitutePolicy {
+    /**
+     * @description: 不使用替代料, 每行只用匹配到的第一个元件
+     * @return {*}
+     */
+    pub fn exact() -> SubstitutePolicy {
+        SubstitutePolicy {
+            substitute: false,
+            ..Default::default()
+        }
+    }
+}
+
+// 比对 BOM 时使用的替代料组和取料顺序
+#[derive(Debug, Clone, Default)]
+pub struct Substitutes {
+    pub groups: Vec<PartGroup>,   // 替代料组
+    pub policy: SubstitutePolicy, // 取料顺序
+}
+
+// 数值相对误差小于百万分之一认为相同
+fn same_value(a: f64, b: f64) -> bool {
+    (a - b).abs() <= a.abs().max(b.abs()) * 1e-6
+}
+
+/**
+ * @description: 判断元件参数能否替代需要的参数: 数值和单位必须相同, 封装都已知时必须相同,
+ * 精度都已知时不能更差, 耐压都已知时不能更低
+ * @param {&ElementParams} wanted 需要的参数, 必须有数值和单位
+ * @param {&ElementParams} params 元件的参数
+ * @return {不能替代返回 None, 否则返回确认相同的封装/精度/耐压个数, 越大越接近}
+ */
+pub fn parametric_score(wanted: &ElementParams, params: &ElementParams) -> Option<u32> {
+    let (Some(value), Some(unit)) = (wanted.value, wanted.unit) else {
+        return None;
+    };
+    if params.unit != Some(unit) || !params.value.is_some_and(|v| same_value(v, value)) {
+        return None;
+    }
+    let mut score = 0;
+    if let (Some(a), Some(b)) = (&wanted.package, &params.package) {
+        if a != b {
+            return None;
+        }
+        score += 1;
+    }
+    if let (Some(a), Some(b)) = (wanted.tolerance, params.tolerance) {
+        if b > a * (1.0 + 1e-6) {
+            return None;
+        }
+        score += 1;
+    }
+    if let (Some(a), Some(b)) = (wanted.voltage, params.voltage) {
+        if b < a * (1.0 - 1e-6) {
+            return None;
+        }
+        score += 1;
+    }
+    Some(score)
+}
+
+/**
+ * @description: 把元件加入替代料组, 组不存在时创建, 已在组中时修改优先级
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} group 组名
+ * @param {&GroupMember} member 组内元件
+ * @return {*}
+ */
+pub(crate) fn write_group_member(
+    conn: &Connection,
+    group: &str,
+    member: &GroupMember,
+) -> BomResult<()> {
+    conn.execute(insert_group_template!(), [group])?;
+    conn.execute(
+        upsert_member_template!(),
+        (group, &member.model, &member.manufacturer, member.priority),
+    )?;
+    Ok(())
+}
+
+// DataBaseInfo 的替代料接口
+impl DataBaseInfo {
+    /**
+     * @description: 把元件加入替代料组
+     * @param {&str} group 组名
+     * @param {&str} model 型号
+     * @param {&str} manufacturer 厂家, 为空时匹配这个型号的所有厂家
+     * @param {Option<u32>} priority 优先级, 为 None 时排在组内最后
+     * @return {*}
+     */
+    pub fn add_group_member(
+        &self,
+        group: &str,
+        model: &str,
+        manufacturer: &str,
+        priority: Option<u32>,
+    ) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let priority = match priority {
+            Some(priority) => priority,
+            None => tx.query_row(next_priority_template!(), [group], |row| row.get(0))?,
+        };
+        let member = GroupMember {
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            priority,
+        };
+        write_group_member(&tx, group, &member)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 从替代料组中删除元件, 组内没有元件时删除组
+     * @param {&str} group 组名
+     * @param {&str} model 型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn remove_group_member(
+        &self,
+        group: &str,
+        model: &str,
+        manufacturer: &str,
+    ) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let count = tx.execute(delete_member_template!(), [group, model, manufacturer])?;
+        if count == 0 {
+            return Err(BomError::NotFound(format!("{group}: {model}")));
+        }
+        tx.execute(delete_empty_groups_template!(), [])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 所有替代料组, 按组名排序, 组内按优先级排序
+     * @return {*}
+     */
+    pub fn part_groups(&self) -> BomResult<Vec<PartGroup>> {
+        let mut stmt = self.conn.prepare(get_all_members_template!())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    GroupMember {
+                        model: row.get(1)?,
+                        manufacturer: row.get(2)?,
+                        priority: row.get(3)?,
+                    },
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut groups: Vec<PartGroup> = Vec::new();
+        for (name, member) in rows {
+            match groups.last_mut() {
+                Some(group) if group.name == name => group.members.push(member),
+                _ => groups.push(PartGroup {
+                    name,
+                    members: vec![member],
+                }),
+            }
+        }
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(text: &str) -> ElementParams {
+        ElementParams::parse(text, None)
+    }
+
+    #[test]
+    fn parametric_score_checks_package_tolerance_and_voltage() {
+        let wanted = params("100nF 0603 10% 25V");
+        assert_eq!(
+            parametric_score(&wanted, &params("0.1uF 0603 5% 50V")),
+            Some(3)
+        );
+        assert_eq!(parametric_score(&wanted, &params("100nF")), Some(0));
+        assert_eq!(parametric_score(&wanted, &params("100nF 0805")), None);
+        assert_eq!(parametric_score(&wanted, &params("100nF 20%")), None);
+        assert_eq!(parametric_score(&wanted, &params("100nF 16V")), None);
+        assert_eq!(parametric_score(&wanted, &params("10nF 0603")), None);
+        assert_eq!(
+            parametric_score(&params("0603"), &params("100nF 0603")),
+            None
+        );
+    }
+
+    #[test]
+    fn group_members_match_any_manufacturer_when_empty() {
+        let group = PartGroup {
+            name: "LDO 3.3V".to_string(),
+            members: vec![
+                GroupMember {
+                    model: "AMS1117-3.3".to_string(),
+                    manufacturer: String::new(),
+                    priority: 0,
+                },
+                GroupMember {
+                    model: "LM1117-3.3".to_string(),
+                    manufacturer: "TI".to_string(),
+                    priority: 1,
+                },
+            ],
+        };
+        let element = |model: &str, manufacturer: &str| Element {
+            model: model.to_string(),
+            manufacturer: manufacturer.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(group.priority(&element("ams1117-3.3", "AMS")), Some(0));
+        assert_eq!(group.priority(&element("LM1117-3.3", "TI")), Some(1));
+        assert_eq!(group.priority(&element("LM1117-3.3", "NS")), None);
+        assert!(group.has_model("lm1117-3.3"));
+    }
+}
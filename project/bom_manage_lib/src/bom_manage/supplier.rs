@@ -0,0 +1,711 @@
+/*
+ * @Description: 供应商料号/包装数量/价格梯度, 价格带日期, 可以按当前或历史价格计算库存金额和 BOM 成本
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\supplier.rs
+ */
+use super::ledger::now_timestamp;
+use super::{element_key, search, BomError, BomResult, DataBaseInfo};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::{
+    fs,
+    io::{Read, Write},
+};
+
+// 新增供应商, 已存在时忽略
+macro_rules! insert_supplier_template {
+    () => {
+        "INSERT OR IGNORE INTO suppliers (name) VALUES (?)"
+    };
+}
+// 新增或更新供应商料号, 供应商 + 料号唯一
+macro_rules! upsert_supplier_part_template {
+    () => {
+        "INSERT INTO supplier_parts (supplier_id, sku, model, manufacturer, mpn, pack_size, moq, preferred, currency) \
+         VALUES ((SELECT id FROM suppliers WHERE name = ?), ?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT (supplier_id, sku) DO UPDATE SET model = excluded.model, manufacturer = excluded.manufacturer, \
+         mpn = excluded.mpn, pack_size = excluded.pack_size, moq = excluded.moq, preferred = excluded.preferred, \
+         currency = excluded.currency RETURNING id"
+    };
+}
+// 一个元件只有一个首选料号, 设置首选时清除其他料号的标记
+macro_rules! clear_preferred_template {
+    () => {
+        "UPDATE supplier_parts SET preferred = 0 WHERE model = ? AND manufacturer = ? AND id != ?"
+    };
+}
+// 读取元件的供应商料号
+macro_rules! get_supplier_parts_template {
+    () => {
+        "SELECT p.id, s.name, p.sku, p.model, p.manufacturer, p.mpn, p.pack_size, p.moq, p.preferred, p.currency \
+         FROM supplier_parts p JOIN suppliers s ON s.id = p.supplier_id \
+         WHERE p.model = ? AND p.manufacturer = ? ORDER BY s.name, p.sku"
+    };
+}
+// 读取所有供应商料号
+macro_rules! get_all_supplier_parts_template {
+    () => {
+        "SELECT p.id, s.name, p.sku, p.model, p.manufacturer, p.mpn, p.pack_size, p.moq, p.preferred, p.currency \
+         FROM supplier_parts p JOIN suppliers s ON s.id = p.supplier_id ORDER BY p.id"
+    };
+}
+// 读取料号的所有报价
+macro_rules! get_price_history_template {
+    () => {
+        "SELECT min_quantity, unit_price, effective FROM price_breaks WHERE part_id = ? ORDER BY effective, min_quantity"
+    };
+}
+// 删除同一天的旧价格, 重复导入时覆盖
+macro_rules! delete_price_breaks_template {
+    () => {
+        "DELETE FROM price_breaks WHERE part_id = ? AND effective = ?"
+    };
+}
+// 新增一档价格
+macro_rules! insert_price_break_template {
+    () => {
+        "INSERT INTO price_breaks (part_id, min_quantity, unit_price, effective) VALUES (?, ?, ?, ?)"
+    };
+}
+// 读取某一时刻生效的价格梯度, 即该时刻之前最近一次报价
+macro_rules! get_price_breaks_template {
+    () => {
+        "SELECT min_quantity, unit_price, effective FROM price_breaks \
+         WHERE part_id = ?1 AND effective = (SELECT MAX(effective) FROM price_breaks WHERE part_id = ?1 AND effective <= ?2) \
+         ORDER BY min_quantity"
+    };
+}
+
+// 一天的秒数
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// 供应商的一个料号
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupplierPart {
+    pub id: i64,              // 料号 id, 写入时忽略
+    pub supplier: String,     // 供应商, 例如 LCSC, Mouser, 淘宝店铺名
+    pub sku: String,          // 供应商料号, 例如 C25804
+    pub model: String,        // 对应的元件型号
+    pub manufacturer: String, // 对应的元件厂家
+    pub mpn: String,          // 厂家料号
+    pub pack_size: u32,       // 包装数量, 购买数量为它的整数倍
+    pub moq: u32,             // 最小起订量
+    pub preferred: bool,      // 首选料号, 生成采购单时优先使用
+    pub currency: String,     // 报价的货币, 例如 CNY, USD, 空字符串表示未指定
+}
+
+// 价格梯度中的一档
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriceBreak {
+    pub min_quantity: u32, // 购买数量不少于它时使用这一档
+    pub unit_price: f64,   // 单价
+    pub effective: i64,    // 报价日期, unix 时间戳
+}
+
+// 一个元件的报价
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub part: SupplierPart, // 报价的供应商料号
+    pub unit_price: f64,    // 单价
+}
+
+// 成本明细中的一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostLine {
+    pub key: String,      // 元件键
+    pub quantity: u32,    // 数量
+    pub unit_price: f64,  // 单价
+    pub supplier: String, // 报价的供应商
+    pub currency: String, // 报价的货币
+}
+
+// 库存金额或 BOM 成本, 所有行的货币必须相同, 不做汇率换算
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Costing {
+    pub lines: Vec<CostLine>,  // 有报价的元件
+    pub unpriced: Vec<String>, // 没有报价或没有匹配到元件的行
+    pub total: f64,            // 总金额
+    pub currency: String,      // 总金额的货币, 第一行的货币
+}
+
+impl Costing {
+    /**
+     * @description: 增加一行, 同时累加总金额
+     * @param {CostLine} line
+     * @return {货币和已有的行不同时返回 BomError::MixedCurrency}
+     */
+    pub fn push(&mut self, line: CostLine) -> BomResult<()> {
+        if self.lines.is_empty() {
+            self.currency = line.currency.clone();
+        } else if line.currency != self.currency {
+            return Err(BomError::MixedCurrency {
+                model: line.key,
+                expected: self.currency.clone(),
+                found: line.currency,
+            });
+        }
+        self.total += line.unit_price * f64::from(line.quantity);
+        self.lines.push(line);
+        Ok(())
+    }
+}
+
+/**
+ * @description: 在价格梯度中找到数量对应的单价, 数量少于最低一档时按最低一档计
+ * @param {&[PriceBreak]} breaks 按 min_quantity 升序排列的价格梯度
+ * @param {u32} quantity 购买数量
+ * @return {*}
+ */
+pub fn price_for(breaks: &[PriceBreak], quantity: u32) -> Option<f64> {
+    breaks
+        .iter()
+        .rev()
+        .find(|b| b.min_quantity <= quantity)
+        .or(breaks.first())
+        .map(|b| b.unit_price)
+}
+
+/**
+ * @description: 日期转为当天 0 点 (UTC) 的 unix 时间戳, 也接受直接写的时间戳
+ * @param {&str} text 日期, 例如 2024-08-20
+ * @return {*}
+ */
+pub fn parse_date(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if let Ok(timestamp) = text.parse::<i64>() {
+        return Some(timestamp);
+    }
+    let mut parts = text.splitn(3, ['-', '/']);
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    // 公历日期到 1970-01-01 的天数
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some((era * 146_097 + doe - 719_468) * SECONDS_PER_DAY)
+}
+
+/**
+ * @description: unix 时间戳转为日期 (UTC), parse_date 的逆过程
+ * @param {i64} timestamp unix 时间戳
+ * @return {例如 2024-08-20}
+ */
+pub fn format_date(timestamp: i64) -> String {
+    // 1970-01-01 起的天数到公历日期
+    let z = timestamp.div_euclid(SECONDS_PER_DAY) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+// 供应商 CSV 的列, 比较时忽略大小写
+const CSV_COLUMNS: [&str; 12] = [
+    "supplier",
+    "sku",
+    "model",
+    "manufacturer",
+    "mpn",
+    "pack_size",
+    "moq",
+    "min_quantity",
+    "unit_price",
+    "date",
+    "preferred",
+    "currency",
+];
+
+/**
+ * @description: 解析供应商 CSV, 每行一档价格, 列名见 CSV_COLUMNS, 必须有 supplier/sku/model/unit_price,
+ * unit_price 为空的行只有料号没有价格, 没有 date 列时使用当前时间, preferred 为 1/true/yes 时为首选料号,
+ * currency 为报价的货币, 转为大写
+ * @param {R} reader CSV 内容
+ * @return {(料号, 价格) 列表}
+ */
+pub fn parse_supplier_csv<R: Read>(
+    reader: R,
+) -> BomResult<Vec<(SupplierPart, Option<PriceBreak>)>> {
+    let invalid = |e: &dyn std::fmt::Display| BomError::InvalidBom(e.to_string());
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let headers = reader.headers().map_err(|e| invalid(&e))?.clone();
+    let index = CSV_COLUMNS.map(|name| {
+        headers
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    });
+    for required in [0, 1, 2, 8] {
+        if index[required].is_none() {
+            return Err(BomError::InvalidBom(format!(
+                "missing column: {}",
+                CSV_COLUMNS[required]
+            )));
+        }
+    }
+    let now = now_timestamp();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid(&e))?;
+        let field = |i: usize| index[i].and_then(|i| record.get(i)).unwrap_or("").trim();
+        let number = |i: usize, default: u32| -> BomResult<u32> {
+            match field(i) {
+                "" => Ok(default),
+                text => text
+                    .parse()
+                    .map_err(|_| BomError::InvalidBom(format!("invalid number: {text}"))),
+            }
+        };
+        let unit_price: Option<f64> = match field(8) {
+            "" => None,
+            text => Some(
+                text.parse()
+                    .map_err(|_| BomError::InvalidBom(format!("invalid price: {text}")))?,
+            ),
+        };
+        let effective = match field(9) {
+            "" => now,
+            text => parse_date(text)
+                .ok_or_else(|| BomError::InvalidBom(format!("invalid date: {text}")))?,
+        };
+        rows.push((
+            SupplierPart {
+                id: 0,
+                supplier: field(0).to_string(),
+                sku: field(1).to_string(),
+                model: field(2).to_string(),
+                manufacturer: field(3).to_string(),
+                mpn: field(4).to_string(),
+                pack_size: number(5, 1)?.max(1),
+                moq: number(6, 1)?.max(1),
+                preferred: matches!(
+                    field(10).to_ascii_lowercase().as_str(),
+                    "1" | "true" | "yes" | "y"
+                ),
+                currency: field(11).to_ascii_uppercase(),
+            },
+            match unit_price {
+                Some(unit_price) => Some(PriceBreak {
+                    min_quantity: number(7, 1)?,
+                    unit_price,
+                    effective,
+                }),
+                None => None,
+            },
+        ));
+    }
+    Ok(rows)
+}
+
+/**
+ * @description: 写出供应商 CSV, 格式和 parse_supplier_csv 一致, 没有价格的料号写一行空价格
+ * @param {W} writer 输出
+ * @param {&[(SupplierPart, Option<PriceBreak>)]} rows (料号, 价格) 列表
+ * @return {*}
+ */
+pub fn write_supplier_csv<W: Write>(
+    writer: W,
+    rows: &[(SupplierPart, Option<PriceBreak>)],
+) -> BomResult<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let invalid = |e: csv::Error| BomError::InvalidBom(e.to_string());
+    writer.write_record(CSV_COLUMNS).map_err(invalid)?;
+    for (part, price) in rows {
+        let (min_quantity, unit_price, date) = match price {
+            Some(p) => (
+                p.min_quantity.to_string(),
+                p.unit_price.to_string(),
+                p.effective.to_string(),
+            ),
+            None => Default::default(),
+        };
+        writer
+            .write_record([
+                part.supplier.as_str(),
+                &part.sku,
+                &part.model,
+                &part.manufacturer,
+                &part.mpn,
+                &part.pack_size.to_string(),
+                &part.moq.to_string(),
+                &min_quantity,
+                &unit_price,
+                &date,
+                if part.preferred { "1" } else { "" },
+                &part.currency,
+            ])
+            .map_err(invalid)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/**
+ * @description: 新增或更新供应商料号, 供应商不存在时创建, 并更新元件的搜索索引
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {&SupplierPart} part 料号
+ * @return {料号 id}
+ */
+pub(crate) fn write_supplier_part(
+    conn: &Connection,
+    tables: &str,
+    part: &SupplierPart,
+) -> BomResult<i64> {
+    conn.execute(insert_supplier_template!(), [&part.supplier])?;
+    let id = conn.query_row(
+        upsert_supplier_part_template!(),
+        (
+            &part.supplier,
+            &part.sku,
+            &part.model,
+            &part.manufacturer,
+            &part.mpn,
+            part.pack_size.max(1),
+            part.moq.max(1),
+            part.preferred,
+            &part.currency,
+        ),
+        |row| row.get(0),
+    )?;
+    if part.preferred {
+        conn.execute(
+            clear_preferred_template!(),
+            (&part.model, &part.manufacturer, id),
+        )?;
+    }
+    search::index_element(conn, tables, &part.model, &part.manufacturer)?;
+    Ok(id)
+}
+
+/**
+ * @description: 写入一次报价的价格梯度, 同一料号同一时间的旧报价被覆盖
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {i64} part_id 料号 id
+ * @param {i64} effective 报价日期, unix 时间戳
+ * @param {&[(u32, f64)]} breaks (起订数量, 单价) 列表
+ * @return {*}
+ */
+pub(crate) fn write_price_breaks(
+    conn: &Connection,
+    part_id: i64,
+    effective: i64,
+    breaks: &[(u32, f64)],
+) -> BomResult<()> {
+    conn.execute(delete_price_breaks_template!(), (part_id, effective))?;
+    for (min_quantity, unit_price) in breaks {
+        conn.execute(
+            insert_price_break_template!(),
+            (part_id, min_quantity, unit_price, effective),
+        )?;
+    }
+    Ok(())
+}
+
+/**
+ * @description: 写入 (料号, 价格) 列表, 同一料号同一日期的多档价格一起写入
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {&[(SupplierPart, Option<PriceBreak>)]} rows
+ * @return {*}
+ */
+pub(crate) fn write_supplier_rows(
+    conn: &Connection,
+    tables: &str,
+    rows: &[(SupplierPart, Option<PriceBreak>)],
+) -> BomResult<()> {
+    let mut groups: BTreeMap<(i64, i64), Vec<(u32, f64)>> = BTreeMap::new();
+    for (part, price) in rows.iter() {
+        let part_id = write_supplier_part(conn, tables, part)?;
+        if let Some(price) = price {
+            groups
+                .entry((part_id, price.effective))
+                .or_default()
+                .push((price.min_quantity, price.unit_price));
+        }
+    }
+    for ((part_id, effective), breaks) in groups {
+        write_price_breaks(conn, part_id, effective, &breaks)?;
+    }
+    Ok(())
+}
+
+// 把 get_supplier_parts_template 查询到的一行转为 SupplierPart
+fn part_from_row(row: &rusqlite::Row) -> rusqlite::Result<SupplierPart> {
+    Ok(SupplierPart {
+        id: row.get(0)?,
+        supplier: row.get(1)?,
+        sku: row.get(2)?,
+        model: row.get(3)?,
+        manufacturer: row.get(4)?,
+        mpn: row.get(5)?,
+        pack_size: row.get(6)?,
+        moq: row.get(7)?,
+        preferred: row.get(8)?,
+        currency: row.get(9)?,
+    })
+}
+
+// DataBaseInfo 的供应商接口
+impl DataBaseInfo {
+    /**
+     * @description: 新增或更新供应商料号, 供应商不存在时创建
+     * @param {&SupplierPart} part 料号
+     * @return {料号 id}
+     */
+    pub fn add_supplier_part(&self, part: &SupplierPart) -> BomResult<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let id = write_supplier_part(&tx, &self.tables, part)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /**
+     * @description: 写入一次报价的价格梯度, 同一料号同一时间的旧报价被覆盖
+     * @param {i64} part_id 料号 id
+     * @param {i64} effective 报价日期, unix 时间戳
+     * @param {&[(u32, f64)]} breaks (起订数量, 单价) 列表
+     * @return {*}
+     */
+    pub fn set_price_breaks(
+        &self,
+        part_id: i64,
+        effective: i64,
+        breaks: &[(u32, f64)],
+    ) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        write_price_breaks(&tx, part_id, effective, breaks)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 元件的所有供应商料号
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn supplier_parts(&self, model: &str, manufacturer: &str) -> BomResult<Vec<SupplierPart>> {
+        let mut stmt = self.conn.prepare(get_supplier_parts_template!())?;
+        let rows = stmt.query_map([model, manufacturer], part_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 料号在某一时刻生效的价格梯度
+     * @param {i64} part_id 料号 id
+     * @param {i64} at unix 时间戳
+     * @return {按起订数量升序}
+     */
+    pub fn price_breaks(&self, part_id: i64, at: i64) -> BomResult<Vec<PriceBreak>> {
+        let mut stmt = self.conn.prepare(get_price_breaks_template!())?;
+        let rows = stmt.query_map((part_id, at), |row| {
+            Ok(PriceBreak {
+                min_quantity: row.get(0)?,
+                unit_price: row.get(1)?,
+                effective: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /**
+     * @description: 元件在某一时刻买 quantity 个的最低报价, 不同货币的报价不能比较
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {u32} quantity 购买数量
+     * @param {i64} at unix 时间戳
+     * @return {没有报价时为 None, 报价的货币不同时返回 BomError::MixedCurrency}
+     */
+    pub fn best_quote(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        quantity: u32,
+        at: i64,
+    ) -> BomResult<Option<Quote>> {
+        let mut best: Option<Quote> = None;
+        for part in self.supplier_parts(model, manufacturer)? {
+            let Some(unit_price) = price_for(&self.price_breaks(part.id, at)?, quantity) else {
+                continue;
+            };
+            if let Some(b) = best.as_ref().filter(|b| b.part.currency != part.currency) {
+                return Err(BomError::MixedCurrency {
+                    model: element_key(model, manufacturer),
+                    expected: b.part.currency.clone(),
+                    found: part.currency,
+                });
+            }
+            if best.as_ref().is_none_or(|b| unit_price < b.unit_price) {
+                best = Some(Quote { part, unit_price });
+            }
+        }
+        Ok(best)
+    }
+
+    /**
+     * @description: 按数量计算一组元件的成本
+     * @param {I} items (元件键, 数量) 列表
+     * @param {i64} at unix 时间戳
+     * @return {*}
+     */
+    pub fn cost_items<'a, I: IntoIterator<Item = (&'a str, u32)>>(
+        &self,
+        items: I,
+        at: i64,
+    ) -> BomResult<Costing> {
+        let mut costing = Costing::default();
+        for (key, quantity) in items {
+            let (model, manufacturer) = super::split_key(key);
+            match self.best_quote(model, manufacturer, quantity, at)? {
+                Some(quote) => costing.push(CostLine {
+                    key: element_key(model, manufacturer),
+                    quantity,
+                    unit_price: quote.unit_price,
+                    supplier: quote.part.supplier,
+                    currency: quote.part.currency,
+                })?,
+                None => costing.unpriced.push(key.to_string()),
+            }
+        }
+        Ok(costing)
+    }
+
+    /**
+     * @description: 库存金额, 每个元件按库存数量对应的价格档计算
+     * @param {i64} at 按这个时间的价格, unix 时间戳
+     * @return {*}
+     */
+    pub fn inventory_value(&self, at: i64) -> BomResult<Costing> {
+        let elements = self.read_hm_from_database()?;
+        let mut items: Vec<(&str, u32)> = elements
+            .iter()
+            .filter(|(_, e)| e.number > 0)
+            .map(|(key, e)| (key.as_str(), e.number))
+            .collect();
+        items.sort();
+        self.cost_items(items, at)
+    }
+
+    /**
+     * @description: 导入供应商 CSV 文件, 见 parse_supplier_csv
+     * @param {&str} path 文件路径
+     * @return {导入的行数}
+     */
+    pub fn import_supplier_csv(&self, path: &str) -> BomResult<usize> {
+        let rows = parse_supplier_csv(fs::File::open(path)?)?;
+        let tx = self.conn.unchecked_transaction()?;
+        write_supplier_rows(&tx, &self.tables, &rows)?;
+        tx.commit()?;
+        Ok(rows.len())
+    }
+
+    /**
+     * @description: 所有供应商料号和它们的全部报价, 按料号 id 排序
+     * @return {(料号, 价格) 列表, 没有报价的料号价格为 None}
+     */
+    pub fn supplier_rows(&self) -> BomResult<Vec<(SupplierPart, Option<PriceBreak>)>> {
+        let mut stmt = self.conn.prepare(get_all_supplier_parts_template!())?;
+        let parts = stmt
+            .query_map([], part_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut price_stmt = self.conn.prepare(get_price_history_template!())?;
+        let mut rows = Vec::new();
+        for part in parts {
+            let prices = price_stmt
+                .query_map([part.id], |row| {
+                    Ok(PriceBreak {
+                        min_quantity: row.get(0)?,
+                        unit_price: row.get(1)?,
+                        effective: row.get(2)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            if prices.is_empty() {
+                rows.push((part, None));
+            } else {
+                rows.extend(prices.into_iter().map(|p| (part.clone(), Some(p))));
+            }
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_and_price_breaks() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2024-08-20"), Some(1_724_112_000));
+        assert_eq!(parse_date("2024/03/01"), Some(1_709_251_200));
+        assert_eq!(parse_date("1724112000"), Some(1_724_112_000));
+        assert_eq!(parse_date("2024-13-01"), None);
+        // 按月份和闰年检查日期
+        assert_eq!(parse_date("2024-02-29"), Some(1_709_164_800));
+        assert_eq!(parse_date("2024-02-30"), None);
+        assert_eq!(parse_date("2024-02-31"), None);
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("2000-02-29"), Some(951_782_400));
+        assert_eq!(parse_date("2024-04-31"), None);
+        assert_eq!(parse_date("2024-12-31"), Some(1_735_603_200));
+        assert_eq!(format_date(1_724_112_000 + 3600), "2024-08-20");
+        assert_eq!(format_date(1_709_251_200), "2024-03-01");
+        assert_eq!(format_date(-1), "1969-12-31");
+
+        let breaks = [
+            PriceBreak {
+                min_quantity: 10,
+                unit_price: 0.05,
+                effective: 0,
+            },
+            PriceBreak {
+                min_quantity: 100,
+                unit_price: 0.02,
+                effective: 0,
+            },
+        ];
+        assert_eq!(price_for(&breaks, 1), Some(0.05));
+        assert_eq!(price_for(&breaks, 99), Some(0.05));
+        assert_eq!(price_for(&breaks, 100), Some(0.02));
+        assert_eq!(price_for(&[], 100), None);
+    }
+
+    #[test]
+    fn parse_supplier_rows() {
+        let text = "Supplier,SKU,Model,MPN,Pack_Size,Min_Quantity,Unit_Price,Date,Currency\n\
+                    LCSC,C25804,R10K,0603WAF1002T5E,100,100,0.0021,2024-08-20,cny\n\
+                    Mouser,603-RC0603FR-0710KL,R10K,RC0603FR-0710KL,,1,0.1,,USD\n";
+        let rows = parse_supplier_csv(text.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0.pack_size, 100);
+        assert_eq!(rows[0].0.currency, "CNY");
+        assert_eq!(rows[0].1.unwrap().effective, 1_724_112_000);
+        assert_eq!(rows[1].0.pack_size, 1);
+        assert!(matches!(
+            parse_supplier_csv("sku,model\n".as_bytes()),
+            Err(BomError::InvalidBom(_))
+        ));
+    }
+}
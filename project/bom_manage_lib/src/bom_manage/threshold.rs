@@ -0,0 +1,316 @@
+/*
+ * @Description: 库存阈值, 元件和分类可以分别设置最低库存和补货点, 元件状态由阈值决定
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\threshold.rs
+ */
+use super::{element_from_row, BomError, BomResult, DataBaseInfo, Element, ElementStatus};
+use rusqlite::Connection;
+
+// 读取元件和生效的阈值, 元件没有设置时沿分类向上查找, 都没有时使用默认值
+// eff 为每个分类生效的阈值, 子分类没有设置时继承父分类
+macro_rules! get_thresholds_template {
+    () => {
+        "WITH RECURSIVE eff (id, min_stock, reorder_point) AS (\
+         SELECT id, min_stock, reorder_point FROM categories WHERE parent_id IS NULL \
+         UNION ALL SELECT c.id, COALESCE(c.min_stock, eff.min_stock), COALESCE(c.reorder_point, eff.reorder_point) \
+         FROM categories c JOIN eff ON c.parent_id = eff.id) \
+         SELECT t.describe, t.model, t.manufacturer, t.number, t.element_type, t.state, t.category_id, t.version, \
+         COALESCE(t.min_stock, eff.min_stock, ?) AS min_level, COALESCE(t.reorder_point, eff.reorder_point, ?) AS reorder_level \
+         FROM {} t LEFT JOIN eff ON eff.id = t.category_id"
+    };
+}
+// 修改元件的阈值
+macro_rules! update_element_thresholds_template {
+    () => {
+        "UPDATE {} SET min_stock = ?, reorder_point = ? WHERE model = ? AND manufacturer = ?"
+    };
+}
+// 修改分类的阈值
+macro_rules! update_category_thresholds_template {
+    () => {
+        "UPDATE categories SET min_stock = ?, reorder_point = ? WHERE id = ?"
+    };
+}
+
+// 没有设置阈值时的默认值, 和以前固定的 50 / 100 一致
+pub const DEFAULT_MIN_STOCK: u32 = 50;
+pub const DEFAULT_REORDER_POINT: u32 = 100;
+
+// 库存阈值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    pub min_stock: u32,     // 最低库存, 数量不超过它时为缺货
+    pub reorder_point: u32, // 补货点, 数量不超过它时需要补货, 超过时为丰富
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            min_stock: DEFAULT_MIN_STOCK,
+            reorder_point: DEFAULT_REORDER_POINT,
+        }
+    }
+}
+
+impl Thresholds {
+    /**
+     * @description: 创建阈值, 最低库存不能高于补货点
+     * @param {u32} min_stock 最低库存
+     * @param {u32} reorder_point 补货点
+     * @return {*}
+     */
+    pub fn new(min_stock: u32, reorder_point: u32) -> BomResult<Thresholds> {
+        if min_stock > reorder_point {
+            return Err(BomError::InvalidThreshold {
+                min_stock,
+                reorder_point,
+            });
+        }
+        Ok(Thresholds {
+            min_stock,
+            reorder_point,
+        })
+    }
+
+    /**
+     * @description: 根据数量得到元件状态, 数量为 0 时为未知
+     * @param {u32} number 元件数量
+     * @return {*}
+     */
+    pub fn status(&self, number: u32) -> ElementStatus {
+        if number == 0 {
+            ElementStatus::Unknown
+        } else if number <= self.min_stock {
+            ElementStatus::SHORTAGE
+        } else if number <= self.reorder_point {
+            ElementStatus::NORMAL
+        } else {
+            ElementStatus::ALOT
+        }
+    }
+
+    /**
+     * @description: 数量是否到达补货点
+     * @param {u32} number 元件数量
+     * @return {*}
+     */
+    pub fn needs_reorder(&self, number: u32) -> bool {
+        number <= self.reorder_point
+    }
+}
+
+/**
+ * @description: 执行 get_thresholds_template 加上 suffix 条件, 返回元件和生效的阈值
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {&str} suffix 追加在查询后的条件
+ * @param {&[&dyn rusqlite::ToSql]} params 条件的参数
+ * @return {*}
+ */
+pub(crate) fn query_thresholds(
+    conn: &Connection,
+    tables: &str,
+    suffix: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> BomResult<Vec<(Element, Thresholds)>> {
+    let sql = format!(get_thresholds_template!(), tables) + suffix;
+    let mut stmt = conn.prepare(sql.as_str())?;
+    let defaults: [&dyn rusqlite::ToSql; 2] = [&DEFAULT_MIN_STOCK, &DEFAULT_REORDER_POINT];
+    let all: Vec<&dyn rusqlite::ToSql> = defaults.iter().chain(params).copied().collect();
+    let rows = stmt.query_map(all.as_slice(), |row| {
+        Ok((
+            element_from_row(row)?,
+            Thresholds {
+                min_stock: row.get(8)?,
+                reorder_point: row.get(9)?,
+            },
+        ))
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/**
+ * @description: 元件生效的阈值, 在事务中修改数量时用来计算状态
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {*}
+ */
+pub(crate) fn effective_thresholds(
+    conn: &Connection,
+    tables: &str,
+    model: &str,
+    manufacturer: &str,
+) -> BomResult<Thresholds> {
+    query_thresholds(
+        conn,
+        tables,
+        " WHERE t.model = ? AND t.manufacturer = ?",
+        &[&model, &manufacturer],
+    )?
+    .pop()
+    .map(|(_, thresholds)| thresholds)
+    .ok_or_else(|| BomError::NotFound(model.to_string()))
+}
+
+/**
+ * @description: 按生效的阈值重新计算一个元件的状态, 元件换分类后使用
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @param {&str} model 元件型号
+ * @param {&str} manufacturer 厂家
+ * @return {*}
+ */
+pub(crate) fn refresh_state(
+    conn: &Connection,
+    tables: &str,
+    model: &str,
+    manufacturer: &str,
+) -> BomResult<()> {
+    let (element, thresholds) = query_thresholds(
+        conn,
+        tables,
+        " WHERE t.model = ? AND t.manufacturer = ?",
+        &[&model, &manufacturer],
+    )?
+    .pop()
+    .ok_or_else(|| BomError::NotFound(model.to_string()))?;
+    let state = thresholds.status(element.number);
+    if state != element.state {
+        conn.execute(
+            format!(update_state_template!(), tables).as_str(),
+            [&state.to_string(), model, manufacturer],
+        )?;
+    }
+    Ok(())
+}
+
+/**
+ * @description: 阈值修改后重新计算所有元件的状态
+ * @param {&Connection} conn sqlite数据库连接, 可以是事务
+ * @param {&str} tables 元件表名
+ * @return {*}
+ */
+pub(crate) fn refresh_states(conn: &Connection, tables: &str) -> BomResult<()> {
+    for (element, thresholds) in query_thresholds(conn, tables, "", &[])? {
+        let state = thresholds.status(element.number);
+        if state != element.state {
+            conn.execute(
+                format!(update_state_template!(), tables).as_str(),
+                [&state.to_string(), &element.model, &element.manufacturer],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// DataBaseInfo 的阈值接口
+impl DataBaseInfo {
+    /**
+     * @description: 设置元件自己的阈值, 并更新状态
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {Option<Thresholds>} thresholds 阈值, None 清除后使用分类或默认阈值
+     * @return {*}
+     */
+    pub fn set_element_thresholds(
+        &self,
+        model: &str,
+        manufacturer: &str,
+        thresholds: Option<Thresholds>,
+    ) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.execute(
+            format!(update_element_thresholds_template!(), self.tables).as_str(),
+            (
+                thresholds.map(|t| t.min_stock),
+                thresholds.map(|t| t.reorder_point),
+                model,
+                manufacturer,
+            ),
+        )? == 0
+        {
+            return Err(BomError::NotFound(model.to_string()));
+        }
+        refresh_states(&tx, &self.tables)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 设置分类的阈值, 子分类和分类下没有自己阈值的元件继承它, 并更新状态
+     * @param {i64} category_id 分类 id
+     * @param {Option<Thresholds>} thresholds 阈值, None 清除后继承父分类
+     * @return {*}
+     */
+    pub fn set_category_thresholds(
+        &self,
+        category_id: i64,
+        thresholds: Option<Thresholds>,
+    ) -> BomResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.execute(
+            update_category_thresholds_template!(),
+            (
+                thresholds.map(|t| t.min_stock),
+                thresholds.map(|t| t.reorder_point),
+                category_id,
+            ),
+        )? == 0
+        {
+            return Err(BomError::NotFound(format!("category {category_id}")));
+        }
+        refresh_states(&tx, &self.tables)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /**
+     * @description: 元件生效的阈值
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn element_thresholds(&self, model: &str, manufacturer: &str) -> BomResult<Thresholds> {
+        effective_thresholds(&self.conn, &self.tables, model, manufacturer)
+    }
+
+    /**
+     * @description: 数量不超过补货点的元件, 缺得越多越靠前
+     * @return {元件和生效的阈值}
+     */
+    pub fn below_reorder_point(&self) -> BomResult<Vec<(Element, Thresholds)>> {
+        query_thresholds(
+            &self.conn,
+            &self.tables,
+            " WHERE t.number <= reorder_level ORDER BY reorder_level - t.number DESC, t.model",
+            &[],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_follows_thresholds() {
+        // 默认阈值和以前固定的划分一致
+        for number in [0, 1, 50, 51, 100, 101, 1000] {
+            assert_eq!(
+                Thresholds::default().status(number),
+                ElementStatus::from_number(number).unwrap()
+            );
+        }
+        let mcu = Thresholds::new(2, 5).unwrap();
+        assert_eq!(mcu.status(2), ElementStatus::SHORTAGE);
+        assert_eq!(mcu.status(5), ElementStatus::NORMAL);
+        assert_eq!(mcu.status(50), ElementStatus::ALOT);
+        assert!(mcu.needs_reorder(5));
+        assert!(!mcu.needs_reorder(6));
+        assert!(matches!(
+            Thresholds::new(10, 5),
+            Err(BomError::InvalidThreshold { .. })
+        ));
+    }
+}
@@ -0,0 +1,564 @@
+/*
+ * @Description: 元件参数解析, 从型号和描述中解析阻值/容值/感值, 精度, 耐压和封装
+ * @FilePath: \rust\project\bom_manage_lib\src\bom_manage\value.rs
+ */
+use super::{element_from_row, BomResult, DataBaseInfo, Element, ElementType};
+use rusqlite::{types::Value, Connection};
+use std::fmt;
+
+// 写入解析后的参数
+macro_rules! update_params_template {
+    () => {
+        "UPDATE {} SET value = ?, unit = ?, tolerance = ?, voltage = ?, package = ? WHERE model = ? AND manufacturer = ?"
+    };
+}
+
+// 贴片元件的英制尺寸封装
+const CHIP_PACKAGES: [&str; 10] = [
+    "01005", "0201", "0402", "0603", "0805", "1206", "1210", "1812", "2010", "2512",
+];
+// 带引脚数的封装前缀, 长的放前面保证优先匹配
+const PACKAGE_PREFIXES: [&str; 22] = [
+    "TSSOP", "MSOP", "SSOP", "SOIC", "LQFP", "TQFP", "ESOP", "SOT", "SOD", "SOP", "QFN", "DFN",
+    "QFP", "BGA", "LGA", "DIP", "SIP", "SMA", "SMB", "SMC", "TO", "DO",
+];
+
+// 数值的单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Ohm,   // 欧姆
+    Farad, // 法拉
+    Henry, // 亨利
+}
+
+impl Unit {
+    /**
+     * @description: 数据库中保存的单位字符串
+     * @return {*}
+     */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Ohm => "ohm",
+            Unit::Farad => "F",
+            Unit::Henry => "H",
+        }
+    }
+
+    /**
+     * @description: 数据库中的单位字符串转为 Unit
+     * @param {&str} s
+     * @return {*}
+     */
+    pub fn from_db(s: &str) -> Option<Unit> {
+        match s {
+            "ohm" => Some(Unit::Ohm),
+            "F" => Some(Unit::Farad),
+            "H" => Some(Unit::Henry),
+            _ => None,
+        }
+    }
+
+    /**
+     * @description: 元件类型对应的默认单位, 型号中没写单位时使用
+     * @param {&ElementType} element_type
+     * @return {*}
+     */
+    pub fn from_element_type(element_type: &ElementType) -> Option<Unit> {
+        match element_type {
+            ElementType::Resistor => Some(Unit::Ohm),
+            ElementType::Capacitor => Some(Unit::Farad),
+            ElementType::Inductor => Some(Unit::Henry),
+            _ => None,
+        }
+    }
+
+    // 显示用的符号
+    fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Ohm => "Ω",
+            Unit::Farad => "F",
+            Unit::Henry => "H",
+        }
+    }
+}
+
+// 解析出的元件参数, 没有解析到的为 None
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ElementParams {
+    pub value: Option<f64>,      // 数值, 基本单位 (Ω, F, H)
+    pub unit: Option<Unit>,      // 单位
+    pub tolerance: Option<f64>,  // 精度, 百分比
+    pub voltage: Option<f64>,    // 额定电压, V
+    pub package: Option<String>, // 封装, 例如 0603, SOT-23
+}
+
+impl ElementParams {
+    /**
+     * @description: 从文本中解析参数, 文本可以是型号和描述的组合, 例如 "C10uF-25V-0805 10%"
+     * @param {&str} text 要解析的文本
+     * @param {Option<Unit>} hint 文本中没写单位时使用的单位, 一般由元件类型决定
+     * @return {*}
+     */
+    pub fn parse(text: &str, hint: Option<Unit>) -> ElementParams {
+        let mut params = ElementParams::default();
+        let tokens = text
+            .split(|c: char| c.is_whitespace() || ",;/_:：，".contains(c))
+            .filter(|t| !t.is_empty());
+        for token in tokens {
+            // 封装中可能带 '-', 先整体匹配再按 '-' 拆开
+            if let Some(package) = parse_package(token) {
+                params.package.get_or_insert(package);
+                continue;
+            }
+            for part in token.split('-').filter(|t| !t.is_empty()) {
+                params.parse_token(part, hint);
+            }
+        }
+        params
+    }
+
+    /**
+     * @description: 解析元件的型号和描述
+     * @param {&Element} element
+     * @return {*}
+     */
+    pub fn from_element(element: &Element) -> ElementParams {
+        ElementParams::parse(
+            format!("{} {}", element.model, element.describe).as_str(),
+            Unit::from_element_type(&element.element_type),
+        )
+    }
+
+    // 解析一个不含分隔符的片段, 已经解析到的参数不覆盖
+    fn parse_token(&mut self, token: &str, hint: Option<Unit>) {
+        if let Some(package) = parse_package(token) {
+            self.package.get_or_insert(package);
+        } else if let Some(tolerance) = parse_tolerance(token) {
+            self.tolerance.get_or_insert(tolerance);
+        } else if let Some(voltage) = parse_voltage(token) {
+            self.voltage.get_or_insert(voltage);
+        } else if self.value.is_none() {
+            if let Some((value, unit)) = parse_value(token) {
+                let unit = unit.or(hint);
+                if unit.is_some() {
+                    self.value = Some(value);
+                    self.unit = unit;
+                }
+            }
+        }
+    }
+
+    /**
+     * @description: 数值显示为带前缀的字符串, 例如 4.7kΩ, 10uF
+     * @return {没有数值返回 None}
+     */
+    pub fn value_text(&self) -> Option<String> {
+        let value = self.value?;
+        let symbol = self.unit.map_or("", |u| u.symbol());
+        Some(format_value(value, symbol))
+    }
+}
+
+impl fmt::Display for ElementParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(value) = self.value_text() {
+            parts.push(value);
+        }
+        if let Some(tolerance) = self.tolerance {
+            parts.push(format!("{tolerance}%"));
+        }
+        if let Some(voltage) = self.voltage {
+            parts.push(format!("{voltage}V"));
+        }
+        if let Some(package) = &self.package {
+            parts.push(package.clone());
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/**
+ * @description: 解析带 SI 前缀或 RKM 写法的数值, 例如 4K7, 10uF, 100nH, 2R2, 0.1u, R47
+ * @param {&str} token 不含分隔符的片段
+ * @return {(基本单位的数值, 片段中写明的单位)}
+ */
+pub fn parse_value(token: &str) -> Option<(f64, Option<Unit>)> {
+    // 型号前面的位号字母, R10K C20uF L10uH, 去掉后必须带前缀或单位
+    let mut chars = token.chars();
+    if let (Some(designator), Some(next)) = (chars.next(), chars.next()) {
+        let hint = match designator {
+            'R' => Some(Unit::Ohm),
+            'C' => Some(Unit::Farad),
+            'L' => Some(Unit::Henry),
+            _ => None,
+        };
+        if hint.is_some() && next.is_ascii_digit() {
+            if let Some((value, unit, marked)) = parse_number(&token[1..]) {
+                if marked {
+                    return Some((value, unit.or(hint)));
+                }
+            }
+        }
+    }
+    parse_number(token).map(|(value, unit, _)| (value, unit))
+}
+
+/**
+ * @description: 解析数值本体
+ * @param {&str} token
+ * @return {(数值, 单位, 是否带有前缀或单位)}
+ */
+fn parse_number(token: &str) -> Option<(f64, Option<Unit>, bool)> {
+    let (body, mut unit) = strip_unit(token);
+    let mut int_part = String::new(); // 整数部分
+    let mut frac_part = String::new(); // 小数点后的数字
+    let mut tail = String::new(); // 前缀后的数字, RKM 写法的小数部分
+    let mut multiplier = None;
+    let mut seen_dot = false;
+    for c in body.chars() {
+        if c.is_ascii_digit() {
+            if multiplier.is_some() {
+                tail.push(c);
+            } else if seen_dot {
+                frac_part.push(c);
+            } else {
+                int_part.push(c);
+            }
+        } else if c == '.' && !seen_dot && multiplier.is_none() {
+            seen_dot = true;
+        } else if multiplier.is_none() {
+            multiplier = Some((c, prefix_exponent(c)?));
+        } else {
+            return None;
+        }
+    }
+    // 4.7K7 不合法; 2N2222, 1N4148 这类型号不是数值
+    if (seen_dot && !tail.is_empty()) || tail.len() > 3 {
+        return None;
+    }
+    if !seen_dot {
+        frac_part = tail;
+    }
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let exponent = multiplier.map_or(0, |(_, e)| e);
+    if matches!(multiplier, Some(('R', _)) | Some(('r', _))) {
+        unit = unit.or(Some(Unit::Ohm));
+    }
+    let text = format!(
+        "{}.{}e{}",
+        if int_part.is_empty() { "0" } else { &int_part },
+        if frac_part.is_empty() {
+            "0"
+        } else {
+            &frac_part
+        },
+        exponent
+    );
+    let value = text.parse::<f64>().ok()?;
+    Some((value, unit, multiplier.is_some() || unit.is_some()))
+}
+
+// 去掉末尾的单位
+fn strip_unit(token: &str) -> (&str, Option<Unit>) {
+    if let Some(body) = token.strip_suffix('Ω') {
+        return (body, Some(Unit::Ohm));
+    }
+    let lower = token.to_ascii_lowercase();
+    for (suffix, unit) in [
+        ("ohms", Unit::Ohm),
+        ("ohm", Unit::Ohm),
+        ("f", Unit::Farad),
+        ("h", Unit::Henry),
+    ] {
+        if lower.ends_with(suffix) && lower.len() > suffix.len() {
+            return (&token[..token.len() - suffix.len()], Some(unit));
+        }
+    }
+    (token, None)
+}
+
+// SI 前缀对应的指数, R 表示小数点
+fn prefix_exponent(c: char) -> Option<i32> {
+    match c {
+        'p' | 'P' => Some(-12),
+        'n' | 'N' => Some(-9),
+        'u' | 'U' | 'µ' | 'μ' => Some(-6),
+        'm' => Some(-3),
+        'R' | 'r' => Some(0),
+        'k' | 'K' => Some(3),
+        'M' => Some(6),
+        'G' => Some(9),
+        _ => None,
+    }
+}
+
+/**
+ * @description: 解析精度, 例如 1%, ±5%
+ * @param {&str} token
+ * @return {*}
+ */
+pub fn parse_tolerance(token: &str) -> Option<f64> {
+    token
+        .strip_suffix('%')?
+        .trim_start_matches('±')
+        .parse::<f64>()
+        .ok()
+}
+
+/**
+ * @description: 解析额定电压, 例如 25V, 6.3V, 6V3, 1kV
+ * @param {&str} token
+ * @return {*}
+ */
+pub fn parse_voltage(token: &str) -> Option<f64> {
+    if let Some(body) = token.strip_suffix(['V', 'v']) {
+        let (value, unit, _) = parse_number(body)?;
+        return match unit {
+            None => Some(value),
+            Some(_) => None,
+        };
+    }
+    // 6V3 写法
+    let (int_part, frac_part) = token.split_once(['V', 'v'])?;
+    if !int_part.is_empty()
+        && int_part.chars().all(|c| c.is_ascii_digit())
+        && frac_part.chars().all(|c| c.is_ascii_digit())
+    {
+        return format!("{int_part}.{frac_part}").parse::<f64>().ok();
+    }
+    None
+}
+
+/**
+ * @description: 解析封装, 贴片尺寸 0603/0805 等, 或者 SOT-23, SOIC8 这类带引脚数的封装
+ * @param {&str} token
+ * @return {统一为大写, 前缀和引脚数之间用 '-' 连接}
+ */
+pub fn parse_package(token: &str) -> Option<String> {
+    if CHIP_PACKAGES.contains(&token) {
+        return Some(token.to_string());
+    }
+    let upper = token.to_uppercase();
+    for prefix in PACKAGE_PREFIXES {
+        if let Some(rest) = upper.strip_prefix(prefix) {
+            let rest = rest.trim_start_matches('-');
+            if rest.is_empty() && matches!(prefix, "SMA" | "SMB" | "SMC") {
+                return Some(prefix.to_string());
+            }
+            if rest.starts_with(|c: char| c.is_ascii_digit())
+                && rest.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Some(format!("{prefix}-{rest}"));
+            }
+            return None;
+        }
+    }
+    None
+}
+
+// 数值转为带前缀的字符串
+fn format_value(value: f64, symbol: &str) -> String {
+    const PREFIXES: [(f64, &str); 7] = [
+        (1e9, "G"),
+        (1e6, "M"),
+        (1e3, "k"),
+        (1.0, ""),
+        (1e-3, "m"),
+        (1e-6, "u"),
+        (1e-9, "n"),
+    ];
+    for (scale, prefix) in PREFIXES {
+        // 留一点余量, 避免 1e-6 这类数值因为浮点误差落到下一档
+        if value.abs() >= scale * 0.999_999 {
+            return format!("{}{prefix}{symbol}", round_text(value / scale));
+        }
+    }
+    if value == 0.0 {
+        return format!("0{symbol}");
+    }
+    format!("{}p{symbol}", round_text(value / 1e-12))
+}
+
+// 最多保留 3 位小数并去掉末尾的 0
+fn round_text(value: f64) -> String {
+    let text = format!("{value:.3}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/**
+ * @description: 写入元件解析后的参数, conn 可以是事务
+ * @param {&Connection} conn sqlite数据库连接
+ * @param {&str} tables 元件表名
+ * @param {&Element} element
+ * @return {*}
+ */
+pub(crate) fn write_params(conn: &Connection, tables: &str, element: &Element) -> BomResult<()> {
+    let params = ElementParams::from_element(element);
+    conn.execute(
+        format!(update_params_template!(), tables).as_str(),
+        (
+            params.value,
+            params.unit.map(|u| u.as_str()),
+            params.tolerance,
+            params.voltage,
+            params.package,
+            &element.model,
+            &element.manufacturer,
+        ),
+    )?;
+    Ok(())
+}
+
+// 参数查询条件, None 表示不限制
+#[derive(Debug, Clone, Default)]
+pub struct ParamFilter {
+    pub element_type: Option<ElementType>, // 元件类型
+    pub unit: Option<Unit>,                // 单位
+    pub min_value: Option<f64>,            // 最小数值 (含)
+    pub max_value: Option<f64>,            // 最大数值 (含)
+    pub max_tolerance: Option<f64>,        // 最大精度百分比 (含)
+    pub min_voltage: Option<f64>,          // 最小额定电压 (含)
+    pub package: Option<String>,           // 封装
+}
+
+// 浮点比较的相对余量, 避免 10uF 和 1e-5 因为误差比较失败
+const EPSILON: f64 = 1e-9;
+
+// DataBaseInfo 的参数查询接口
+impl DataBaseInfo {
+    /**
+     * @description: 按解析后的参数查询元件, 例如所有 >= 10uF 且耐压 >= 25V 的电容, 结果按数值排序
+     * @param {&ParamFilter} filter 查询条件
+     * @return {*}
+     */
+    pub fn query_params(&self, filter: &ParamFilter) -> BomResult<Vec<Element>> {
+        let mut conditions: Vec<&str> = vec![];
+        let mut values: Vec<Value> = vec![];
+        if let Some(element_type) = &filter.element_type {
+            conditions.push("element_type = ?");
+            values.push(Value::Text(element_type.to_string()));
+        }
+        if let Some(unit) = filter.unit {
+            conditions.push("unit = ?");
+            values.push(Value::Text(unit.as_str().to_string()));
+        }
+        if let Some(min) = filter.min_value {
+            conditions.push("value >= ?");
+            values.push(Value::Real(min - min.abs() * EPSILON));
+        }
+        if let Some(max) = filter.max_value {
+            conditions.push("value <= ?");
+            values.push(Value::Real(max + max.abs() * EPSILON));
+        }
+        if let Some(tolerance) = filter.max_tolerance {
+            conditions.push("tolerance <= ?");
+            values.push(Value::Real(tolerance + tolerance.abs() * EPSILON));
+        }
+        if let Some(voltage) = filter.min_voltage {
+            conditions.push("voltage >= ?");
+            values.push(Value::Real(voltage - voltage.abs() * EPSILON));
+        }
+        if let Some(package) = &filter.package {
+            conditions.push("package = ?");
+            values.push(Value::Text(
+                parse_package(package).unwrap_or(package.to_uppercase()),
+            ));
+        }
+        let mut sql = format!(get_all_template!(), self.tables);
+        if !conditions.is_empty() {
+            sql = format!("{sql} WHERE {}", conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY value, model");
+
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), element_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(token: &str) -> Option<(f64, Option<Unit>)> {
+        parse_value(token)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= b.abs() * 1e-12
+    }
+
+    #[test]
+    fn si_prefixes_and_rkm_codes() {
+        let cases = [
+            ("4K7", 4700.0, None),
+            ("4.7k", 4700.0, None),
+            ("10K", 10_000.0, None),
+            ("2R2", 2.2, Some(Unit::Ohm)),
+            ("R47", 0.47, Some(Unit::Ohm)),
+            ("10R", 10.0, Some(Unit::Ohm)),
+            ("1M", 1e6, None),
+            ("10uF", 10e-6, Some(Unit::Farad)),
+            ("0.1uF", 1e-7, Some(Unit::Farad)),
+            ("4n7", 4.7e-9, None),
+            ("100nH", 100e-9, Some(Unit::Henry)),
+            ("22pF", 22e-12, Some(Unit::Farad)),
+            ("100NF", 100e-9, Some(Unit::Farad)),
+            ("10kohm", 10e3, Some(Unit::Ohm)),
+            ("R10K", 10e3, Some(Unit::Ohm)),
+            ("C20uF", 20e-6, Some(Unit::Farad)),
+            ("L10uH", 10e-6, Some(Unit::Henry)),
+        ];
+        for (token, expect, unit) in cases {
+            let (v, u) = value(token).unwrap_or_else(|| panic!("{token}"));
+            assert!(close(v, expect), "{token}: {v} != {expect}");
+            assert_eq!(u, unit, "{token}");
+        }
+        for token in ["2N2222", "1N4148", "STM32F103", "K", "", "10K7K"] {
+            assert_eq!(value(token), None, "{token}");
+        }
+    }
+
+    #[test]
+    fn tolerance_voltage_and_package() {
+        assert_eq!(parse_tolerance("1%"), Some(1.0));
+        assert_eq!(parse_tolerance("±0.5%"), Some(0.5));
+        assert_eq!(parse_voltage("25V"), Some(25.0));
+        assert_eq!(parse_voltage("6.3v"), Some(6.3));
+        assert_eq!(parse_voltage("6V3"), Some(6.3));
+        assert_eq!(parse_voltage("1kV"), Some(1000.0));
+        assert_eq!(parse_voltage("10uF"), None);
+        assert_eq!(parse_package("0603"), Some("0603".to_string()));
+        assert_eq!(parse_package("SOT-23"), Some("SOT-23".to_string()));
+        assert_eq!(parse_package("sot23"), Some("SOT-23".to_string()));
+        assert_eq!(parse_package("SOIC-8"), Some("SOIC-8".to_string()));
+        assert_eq!(parse_package("TO-220"), Some("TO-220".to_string()));
+        assert_eq!(parse_package("SOTA"), None);
+        assert_eq!(parse_package("0604"), None);
+    }
+
+    #[test]
+    fn parse_model_and_describe() {
+        let params = ElementParams::parse("C10uF-25V-0805 10%", None);
+        assert!(close(params.value.unwrap(), 10e-6));
+        assert_eq!(params.unit, Some(Unit::Farad));
+        assert_eq!(params.voltage, Some(25.0));
+        assert_eq!(params.tolerance, Some(10.0));
+        assert_eq!(params.package.as_deref(), Some("0805"));
+        assert_eq!(params.to_string(), "10uF 10% 25V 0805");
+
+        // 没有单位时使用元件类型的单位
+        let params = ElementParams::parse("4K7 1% 电阻 封装:0603", Some(Unit::Ohm));
+        assert_eq!(params.value_text().as_deref(), Some("4.7kΩ"));
+        assert_eq!(params.package.as_deref(), Some("0603"));
+
+        // 芯片没有数值
+        let params = ElementParams::parse("AMS1117-3.3 SOT-223", None);
+        assert_eq!(params.value, None);
+        assert_eq!(params.voltage, None);
+        assert_eq!(params.package.as_deref(), Some("SOT-223"));
+    }
+}
@@ -7,11 +7,13 @@
  * @FilePath: \rust\project\bom_manage_lib\src\lib.rs
  */
 pub mod bom_manage {
-    use rusqlite::{Connection, Result};
-    use std::collections::HashMap;
+    use rusqlite::{Connection, Transaction, TransactionBehavior};
+    use std::collections::{HashMap, HashSet};
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
     // use serde_json;
-    use std::error::Error;
-    use std::{file, fs, io::*, line};
+    use serde::{Deserialize, Serialize};
+    use std::{file, fmt, fs, io::*, line};
 
     /**
      * @name: info_log
@@ -30,13 +32,19 @@ pub mod bom_manage {
     // 数据库表头宏
     macro_rules! insert_into_template {
         () => {
-            "INSERT OR IGNORE INTO {} (describe, model, number, element_type, state) VALUES (?, ?, ?, ?, ?)"
+            "INSERT OR IGNORE INTO {} (describe, model, manufacturer, number, element_type, state, category_id, version) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
         };
     }
     // 读取数据库中所有数据命令
     macro_rules! get_all_template {
         () => {
-            "SELECT describe, model, number, element_type, state FROM {}"
+            "SELECT describe, model, manufacturer, number, element_type, state, category_id, version FROM {}"
+        };
+    }
+    // 读取数据库中所有元件的型号和厂家
+    macro_rules! get_keys_template {
+        () => {
+            "SELECT model, manufacturer FROM {}"
         };
     }
     // 读取数据库中所有数据命令
@@ -51,10 +59,61 @@ pub mod bom_manage {
             "SELECT 0"
         };
     }
-    // 创建表头宏命令
-    macro_rules! create_table_template {
+    // 新增元件, 已存在就在 SQL 中累加数量, 返回累加后的数量
+    macro_rules! add_number_template {
+        () => {
+            "INSERT INTO {} (describe, model, manufacturer, number, element_type, state, category_id) VALUES (?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (model, manufacturer) DO UPDATE SET describe = excluded.describe, number = number + excluded.number, element_type = excluded.element_type, \
+             category_id = excluded.category_id \
+             RETURNING number"
+        };
+    }
+    // 写入元件, 已存在就覆盖
+    macro_rules! upsert_element_template {
+        () => {
+            "INSERT INTO {} (describe, model, manufacturer, number, element_type, state, category_id) VALUES (?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (model, manufacturer) DO UPDATE SET describe = excluded.describe, number = excluded.number, element_type = excluded.element_type, state = excluded.state, \
+             category_id = excluded.category_id"
+        };
+    }
+    // 写入元件, 已存在时只有版本号和读取时一致才覆盖, 覆盖后版本号加 1
+    macro_rules! write_element_template {
+        () => {
+            "INSERT INTO {0} (describe, model, manufacturer, number, element_type, state, category_id, version) VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (model, manufacturer) DO UPDATE SET describe = excluded.describe, number = excluded.number, element_type = excluded.element_type, state = excluded.state, \
+             category_id = excluded.category_id, version = {0}.version + 1 \
+             WHERE {0}.version = excluded.version"
+        };
+    }
+    // 修改元件的描述/类型/分类, 版本号和读取时一致才修改
+    macro_rules! update_element_template {
+        () => {
+            "UPDATE {} SET describe = ?, element_type = ?, category_id = ?, version = version + 1 \
+             WHERE model = ? AND manufacturer = ? AND version = ?"
+        };
+    }
+    // 读取一个元件的版本号
+    macro_rules! get_version_template {
+        () => {
+            "SELECT version FROM {} WHERE model = ? AND manufacturer = ?"
+        };
+    }
+    // 读取一个元件的数量
+    macro_rules! get_number_template {
+        () => {
+            "SELECT number FROM {} WHERE model = ? AND manufacturer = ?"
+        };
+    }
+    // 修改元件数量和状态
+    macro_rules! update_number_template {
+        () => {
+            "UPDATE {} SET number = ?, state = ? WHERE model = ? AND manufacturer = ?"
+        };
+    }
+    // 更新元件状态
+    macro_rules! update_state_template {
         () => {
-            "CREATE TABLE IF NOT EXISTS bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL)"
+            "UPDATE {} SET state = ? WHERE model = ? AND manufacturer = ?"
         };
     }
     // 删除表内所有内容
@@ -63,9 +122,68 @@ pub mod bom_manage {
             "DELETE FROM {}"
         };
     }
+    // 删除元件前把剩余库存记为出库流水
+    macro_rules! remove_movement_template {
+        () => {
+            "INSERT INTO stock_movements (timestamp, model, manufacturer, delta, reason, operator) \
+             SELECT ?, model, manufacturer, -number, ?, ? FROM {} WHERE number > 0"
+        };
+    }
+    // 按型号 + 厂家删除一行数据
+    macro_rules! delete_element_template {
+        () => {
+            "DELETE FROM {} WHERE model = ? AND manufacturer = ?"
+        };
+    }
+
+    pub mod attachment;
+    pub mod audit;
+    pub mod backup;
+    pub mod bom_import;
+    pub mod build;
+    pub mod category;
+    mod error;
+    pub mod export;
+    pub mod label;
+    pub mod ledger;
+    pub mod location;
+    pub mod migration;
+    pub mod purchase;
+    pub mod qr;
+    pub mod query;
+    pub mod report;
+    pub mod search;
+    pub mod store;
+    pub mod substitute;
+    pub mod supplier;
+    pub mod threshold;
+    pub mod value;
+
+    pub use attachment::{Attachment, AttachmentGc, AttachmentKind, Note};
+    pub use audit::{Audit, AuditLine, AuditScope, AuditStatus};
+    pub use backup::Backup;
+    pub use bom_import::{Allocation, BomFormat, BomLine, BomReport, CsvColumns, MatchKind};
+    pub use build::{Build, BuildItem, BuildStatus};
+    pub use category::Category;
+    pub use error::{BomError, BomResult};
+    pub use export::{ElementChange, ExportFormat, ImportDiff, ImportMode, Inventory};
+    pub use label::{Label, LabelCode, LabelFormat, LabelStock, Scanned};
+    pub use ledger::{MovementNote, StockMovement};
+    pub use location::{Location, StockLocation};
+    pub use purchase::{OrderFormat, OrderLine, OrderStatus, PurchaseOrder};
+    pub use query::{ElementQuery, SortField, SortOrder};
+    pub use report::{
+        Consumption, Forecast, InventoryReport, ReportOptions, SlowMover, StockCount, StockSummary,
+    };
+    pub use search::SearchHit;
+    pub use store::{BomStore, JsonStore, MemoryStore};
+    pub use substitute::{GroupMember, PartGroup, SubstitutePolicy, Substitutes};
+    pub use supplier::{CostLine, Costing, PriceBreak, Quote, SupplierPart};
+    pub use threshold::Thresholds;
+    pub use value::{ElementParams, ParamFilter, Unit};
 
     // 元件类别
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
     pub enum ElementType {
         Resistor,   // 电阻
         Diode,      // 二极管
@@ -73,39 +191,43 @@ pub mod bom_manage {
         Capacitor,  // 电容
         Inductor,   // 电感
         Chip,       // 芯片
-        Unknown,    // 未知
+        #[default]
+        Unknown, // 未知
     }
-    // ElementType 的方法
-    impl ElementType {
-        /**
-         * @name: to_string
-         * @msg: 类型转为字符串
-         * @param {*} self
-         * @return {*}
-         * @author: TOTHTOT
-         * @Date: 2024-07-31 09:14:58
-         */
-        pub fn to_string(&self) -> String {
-            match self {
-                ElementType::Resistor => "电阻".to_string(),
-                ElementType::Diode => "二极管".to_string(),
-                ElementType::Transistor => "三极管".to_string(),
-                ElementType::Capacitor => "电容".to_string(),
-                ElementType::Inductor => "电感".to_string(),
-                ElementType::Chip => "芯片".to_string(),
-                ElementType::Unknown => "未知".to_string(),
-            }
+    /**
+     * @name: fmt
+     * @msg: 类型转为字符串, 通过 to_string() 使用
+     * @param {*} self
+     * @return {*}
+     * @author: TOTHTOT
+     * @Date: 2024-07-31 09:14:58
+     */
+    impl fmt::Display for ElementType {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                ElementType::Resistor => "电阻",
+                ElementType::Diode => "二极管",
+                ElementType::Transistor => "三极管",
+                ElementType::Capacitor => "电容",
+                ElementType::Inductor => "电感",
+                ElementType::Chip => "芯片",
+                ElementType::Unknown => "未知",
+            };
+            write!(f, "{name}")
         }
+    }
 
+    // ElementType 的方法
+    impl ElementType {
         /**
          * @name: from_string
          * @msg: 字符串转为ElementType类型
          * @param { &str } s 字符串
-         * @return {返回ElementType类型, 不支持的类型返回ErrType}
+         * @return {返回ElementType类型, 不支持的类型返回 BomError::InvalidType}
          * @author: TOTHTOT
          * @Date: 2024-07-31 09:15:41
          */
-        pub fn from_string(s: &str) -> Result<ElementType, String> {
+        pub fn from_string(s: &str) -> BomResult<ElementType> {
             match s {
                 "电阻" => Ok(ElementType::Resistor),
                 "二极管" => Ok(ElementType::Diode),
@@ -113,75 +235,88 @@ pub mod bom_manage {
                 "电容" => Ok(ElementType::Capacitor),
                 "电感" => Ok(ElementType::Inductor),
                 "芯片" => Ok(ElementType::Chip),
-                _ => Err("Error ElementType".to_string()),
+                _ => Err(BomError::InvalidType(s.to_string())),
             }
         }
     }
 
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
     pub enum ElementStatus {
         ALOT,     // 丰富
         NORMAL,   // 一般
         SHORTAGE, // 缺货
-        Unknown,  // 未知
+        #[default]
+        Unknown, // 未知
     }
-    // ElementStatus 的方法
-    impl ElementStatus {
-        /**
-         * @name: to_string
-         * @msg: 类型转字符串
-         * @param {*} self
-         * @return {转译后的字符串, 类型错误返回空字符串}
-         * @author: TOTHTOT
-         * @Date: 2024-07-31 09:22:04
-         */
-        pub fn to_string(&self) -> String {
-            match self {
-                ElementStatus::ALOT => "丰富".to_string(),
-                ElementStatus::NORMAL => "一般".to_string(),
-                ElementStatus::SHORTAGE => "缺货".to_string(),
-                ElementStatus::Unknown => "未知".to_string(),
-            }
+    /**
+     * @name: fmt
+     * @msg: 类型转字符串, 通过 to_string() 使用
+     * @param {*} self
+     * @return {转译后的字符串}
+     * @author: TOTHTOT
+     * @Date: 2024-07-31 09:22:04
+     */
+    impl fmt::Display for ElementStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                ElementStatus::ALOT => "丰富",
+                ElementStatus::NORMAL => "一般",
+                ElementStatus::SHORTAGE => "缺货",
+                ElementStatus::Unknown => "未知",
+            };
+            write!(f, "{name}")
         }
+    }
 
+    // ElementStatus 的方法
+    impl ElementStatus {
         /**
          * @name: from_string
          * @msg: 字符串转为 ElementStatus 类型, 需要解包
          * @param {*} s
-         * @return {Ok(ElementStatus) 匹配的类型; Err(&str) 失败的字符串}
+         * @return {Ok(ElementStatus) 匹配的类型; Err(BomError::InvalidStatus) 失败的字符串}
          * @author: TOTHTOT
          * @Date: 2024-07-31 09:25:47
          */
-        pub fn from_string(s: &str) -> Result<ElementStatus, String> {
+        pub fn from_string(s: &str) -> BomResult<ElementStatus> {
             match s {
                 "丰富" => Ok(ElementStatus::ALOT),
                 "一般" => Ok(ElementStatus::NORMAL),
                 "缺货" => Ok(ElementStatus::SHORTAGE),
-                _ => Err("ErrorNumber".to_string()),
+                _ => Err(BomError::InvalidStatus(s.to_string())),
             }
         }
 
-        pub fn from_number(num: u32) -> Result<ElementStatus, String> {
-            if num > 100 {
-                Ok(ElementStatus::ALOT)
-            } else if num > 50 && num <= 100 {
-                Ok(ElementStatus::NORMAL)
-            } else if num > 0 && num <= 50 {
-                Ok(ElementStatus::SHORTAGE)
-            } else {
-                Ok(ElementStatus::Unknown)
-            }
+        /**
+         * @description: 按默认阈值把数量转为状态, 元件设置了阈值时使用 Thresholds::status
+         * @param {u32} num 元件数量
+         * @return {*}
+         */
+        pub fn from_number(num: u32) -> BomResult<ElementStatus> {
+            Ok(Thresholds::default().status(num))
         }
     }
 
     // 元件信息
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
     pub struct Element {
         pub describe: String,          // 元件描述
         pub model: String,             // 元件型号
+        pub manufacturer: String,      // 厂家, 空字符串表示未指定
         pub number: u32,               // 元件数量
         pub element_type: ElementType, // 元件类型
         pub state: ElementStatus,      // 元件状态
+        pub category: Option<i64>,     // 分类 id, None 时按 element_type 归入内置分类
+        pub version: u32,              // 行版本号, 每次修改加 1, 写入时用来发现别人的修改
+    }
+
+    // 减少元件数量的结果
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ReduceReport {
+        pub taken: u32,     // 实际取出的数量
+        pub shortfall: u32, // 缺少的数量, 按需取出时为 0
+        pub remain: u32,    // 取出后剩余的数量
     }
 
     // 保存bom信息的数据库信息
@@ -193,42 +328,237 @@ pub mod bom_manage {
 
     // DataBaseInfo 的方法
     impl DataBaseInfo {
+        /**
+         * @description: 开始一个 IMMEDIATE 事务, 开始时就拿到写锁, 读取后再修改的过程中不会被其他连接插入写入
+         * @return {*}
+         */
+        pub(crate) fn immediate_transaction(&self) -> BomResult<Transaction<'_>> {
+            Ok(Transaction::new_unchecked(
+                &self.conn,
+                TransactionBehavior::Immediate,
+            )?)
+        }
+
         /**
          * @name: write_hm_to_database
-         * @msg: 写入数据到sqlite数据库, 先删除所有数据再写入数据
+         * @msg: 写入数据到sqlite数据库, 先删除所有数据再写入数据, 整个过程在一个事务中完成,
+         * 中途失败会回滚, 数据库保持写入前的内容. 哈希表中的元件在读取后被别人修改过时
+         * 返回 BomError::Conflict, 不会用旧数据覆盖
          * @param {&HashMap<String, Element>} map
-         * @param {*} conn 连接的sqlite 数据库
-         * @param {*} tables 表名
          * @return {*}
          * @author: TOTHTOT
          * @Date: 2024-07-31 09:48:28
          */
-        pub fn write_hm_to_database(
-            self: &Self,
-            map: &HashMap<String, Element>,
-        ) -> Result<(), Box<dyn Error>> {
-            if let Err(e) = self
-                .conn
-                .execute(format!(delete_all_template!(), self.tables).as_str(), [])
-            {
+        pub fn write_hm_to_database(&self, map: &HashMap<String, Element>) -> BomResult<()> {
+            self.backup(backup::BACKUP_REPLACE)?;
+            // 事务在 drop 时未 commit 会自动回滚
+            let tx = self.conn.unchecked_transaction()?;
+            for value in map.values() {
+                check_version(&tx, &self.tables, value)?;
+            }
+            if let Err(e) = tx.execute(format!(delete_all_template!(), self.tables).as_str(), []) {
                 info_log!("Failed to delete data: {}", e);
-                return Err("Failed to delete data".into());
+                return Err(e.into());
             };
             // 将哈希表写入数据库
-            for (_key, value) in map.iter() {
-                self.conn
-                    .execute(
-                        format!(insert_into_template!(), self.tables).as_str(),
-                        &[
-                            &value.describe,
-                            &value.model,
-                            &value.number.to_string(),
-                            &value.element_type.to_string(),
-                            &value.state.to_string(),
-                        ],
-                    )
-                    .expect("Failed to insert data");
+            for value in map.values() {
+                if let Err(e) = tx.execute(
+                    format!(insert_into_template!(), self.tables).as_str(),
+                    (
+                        &value.describe,
+                        &value.model,
+                        &value.manufacturer,
+                        &value.number,
+                        &value.element_type.to_string(),
+                        &value.state.to_string(),
+                        &value.category_id(),
+                        value.version + 1,
+                    ),
+                ) {
+                    info_log!("Failed to insert data: {}", e);
+                    return Err(e.into());
+                }
+                value::write_params(&tx, &self.tables, value)?;
+            }
+            search::reindex_all(&tx, &self.tables)?;
+            tx.commit()?;
+            Ok(())
+        }
+
+        /**
+         * @description: 写入单个元件, 数据库中已有该型号 + 厂家就覆盖, 没有就插入.
+         * 已有的元件在读取后被别人修改过 (版本号不同) 时返回 BomError::Conflict
+         * @param {&Element} element 要写入的元件
+         * @return {*}
+         */
+        pub fn write_element(&self, element: &Element) -> BomResult<()> {
+            let tx = self.conn.unchecked_transaction()?;
+            let changed = tx.execute(
+                format!(write_element_template!(), self.tables).as_str(),
+                (
+                    &element.describe,
+                    &element.model,
+                    &element.manufacturer,
+                    &element.number,
+                    &element.element_type.to_string(),
+                    &element.state.to_string(),
+                    &element.category_id(),
+                    element.version,
+                ),
+            )?;
+            if changed == 0 {
+                check_version(&tx, &self.tables, element)?;
+            }
+            value::write_params(&tx, &self.tables, element)?;
+            search::index_element(&tx, &self.tables, &element.model, &element.manufacturer)?;
+            tx.commit()?;
+            Ok(())
+        }
+
+        /**
+         * @description: 增加元件数量, 数量在 SQL 中累加, 不存在就插入, 并根据新数量更新状态,
+         * 同时写入一条入库流水
+         * @param {&Element} element 要增加的元件, number 为增加的数量
+         * @param {&MovementNote} note 流水的原因和操作人
+         * @return {写入后的元件}
+         */
+        pub fn add_element_number(
+            &self,
+            element: &Element,
+            note: &MovementNote,
+        ) -> BomResult<Element> {
+            let tx = self.conn.unchecked_transaction()?;
+            add_number(&tx, &self.tables, element, note)?;
+            // 在事务中读取, 拿到的是这次写入后的版本号
+            let stored = self.element(&element.model, &element.manufacturer)?;
+            tx.commit()?;
+            Ok(stored)
+        }
+
+        /**
+         * @description: 修改元件的描述/类型/分类, 数量请用 add_element_number 和 reduce_element_number 修改.
+         * element 的版本号必须和数据库中一致, 读取后被别人修改过时返回 BomError::Conflict
+         * @param {&Element} element 修改后的元件
+         * @return {修改后的元件}
+         */
+        pub fn update_element(&self, element: &Element) -> BomResult<Element> {
+            let tx = self.conn.unchecked_transaction()?;
+            let changed = tx.execute(
+                format!(update_element_template!(), self.tables).as_str(),
+                (
+                    &element.describe,
+                    &element.element_type.to_string(),
+                    &element.category_id(),
+                    &element.model,
+                    &element.manufacturer,
+                    element.version,
+                ),
+            )?;
+            if changed == 0 {
+                check_version(&tx, &self.tables, element)?;
+                return Err(BomError::NotFound(element.key()));
+            }
+            value::write_params(&tx, &self.tables, element)?;
+            search::index_element(&tx, &self.tables, &element.model, &element.manufacturer)?;
+            let stored = self.element(&element.model, &element.manufacturer)?;
+            tx.commit()?;
+            Ok(stored)
+        }
+
+        /**
+         * @description: 减少元件数量, 读取和修改在同一个事务中完成, 数量不会出现下溢
+         * @param {&str} model 元件型号
+         * @param {&str} manufacturer 厂家
+         * @param {u32} number 要减少的数量
+         * @param {bool} take_available 库存不足时 true 取出全部库存并报告缺少的数量, false 返回库存不足错误
+         * @param {&MovementNote} note 流水的原因和操作人
+         * @return {*}
+         */
+        pub fn reduce_element_number(
+            &self,
+            model: &str,
+            manufacturer: &str,
+            number: u32,
+            take_available: bool,
+            note: &MovementNote,
+        ) -> BomResult<ReduceReport> {
+            let tx = self.conn.unchecked_transaction()?;
+            let report = reduce_number(
+                &tx,
+                &self.tables,
+                model,
+                manufacturer,
+                number,
+                take_available,
+                note,
+            )?;
+            tx.commit()?;
+            Ok(report)
+        }
+
+        /**
+         * @description: 按型号 + 厂家删除单个元件, 剩余库存记一条出库流水
+         * @param {&str} model 元件型号
+         * @param {&str} manufacturer 厂家
+         * @param {&MovementNote} note 流水的原因和操作人
+         * @return {删除的行数}
+         */
+        pub fn delete_element(
+            &self,
+            model: &str,
+            manufacturer: &str,
+            note: &MovementNote,
+        ) -> BomResult<usize> {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute(
+                format!(
+                    concat!(
+                        remove_movement_template!(),
+                        " AND model = ? AND manufacturer = ?"
+                    ),
+                    self.tables
+                )
+                .as_str(),
+                (
+                    ledger::now_timestamp(),
+                    &note.reason,
+                    &note.operator,
+                    model,
+                    manufacturer,
+                ),
+            )?;
+            let changed = tx.execute(
+                format!(delete_element_template!(), self.tables).as_str(),
+                [model, manufacturer],
+            )?;
+            location::clear_locations(&tx, model, manufacturer)?;
+            attachment::clear_attachments(&tx, model, manufacturer)?;
+            search::unindex_element(&tx, model, manufacturer)?;
+            tx.commit()?;
+            Ok(changed)
+        }
+
+        /**
+         * @description: 删除表中所有元件, 每个元件的剩余库存记一条出库流水, 删除前先备份
+         * @param {&MovementNote} note 流水的原因和操作人
+         * @return {*}
+         */
+        pub fn delete_all(&self, note: &MovementNote) -> BomResult<()> {
+            self.backup(backup::BACKUP_REMOVE_ALL)?;
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute(
+                format!(remove_movement_template!(), self.tables).as_str(),
+                (ledger::now_timestamp(), &note.reason, &note.operator),
+            )?;
+            tx.execute(format!(delete_all_template!(), self.tables).as_str(), [])?;
+            tx.execute(
+                format!(delete_all_template!(), "stock_locations").as_str(),
+                [],
+            )?;
+            for table in ["element_search", "attachments", "element_notes"] {
+                tx.execute(format!(delete_all_template!(), table).as_str(), [])?;
             }
+            tx.commit()?;
             Ok(())
         }
 
@@ -240,49 +570,19 @@ pub mod bom_manage {
          * @author: TOTHTOT
          * @Date: 2024-07-31 10:33:07
          */
-        pub fn read_hm_from_database(
-            self: &Self,
-        ) -> Result<HashMap<String, Element>, Box<dyn Error>> {
+        pub fn read_hm_from_database(&self) -> BomResult<HashMap<String, Element>> {
             // 准备 SQL 查询语句
             let mut stmt = self
                 .conn
-                .prepare(format!(get_all_template!(), self.tables).as_str())
-                .expect("Failed to prepare statement");
+                .prepare(format!(get_all_template!(), self.tables).as_str())?;
             // 执行查询语句，迭代处理每一行结果, data_iter 是个迭代器
-            let data_iter = stmt.query_map([], |row| {
-                Ok(Element {
-                    describe: match row.get(0) {
-                        Ok(describe) => {
-                            // info_log!("describe: {}", describe);
-                            describe
-                        }
-                        Err(_) => "Unknown".to_string(),
-                    },
-                    model: row.get(1).expect("Failed to get model"),
-                    number: row.get(2).expect("Failed to get number"),
-                    // 字符串转枚举
-                    element_type: match row.get::<usize, String>(3) {
-                        Ok(element_type_str) => ElementType::from_string(&element_type_str)
-                            .unwrap_or(ElementType::Unknown),
-                        Err(_) => ElementType::Unknown,
-                    },
-                    // 字符串转枚举
-                    state: match row.get::<usize, String>(4) {
-                        Ok(element_status_str) => ElementStatus::from_string(&element_status_str)
-                            .unwrap_or(ElementStatus::Unknown),
-                        Err(err) => {
-                            info_log!("Failed to get element status: {}", err);
-                            ElementStatus::Unknown
-                        }
-                    },
-                })
-            })?; // 为什么可以用?, Box<dyn Error> 什么意思
+            let data_iter = stmt.query_map([], element_from_row)?; // rusqlite::Error 通过 From 转为 BomError
 
             let mut map: HashMap<String, Element> = HashMap::new();
             for result in data_iter {
                 match result {
                     Ok(element) => {
-                        map.insert(element.model.clone(), element);
+                        map.insert(element.key(), element);
                     }
                     Err(_) => {
                         info_log!("Failed to get element");
@@ -292,36 +592,218 @@ pub mod bom_manage {
             }
             Ok(map)
         }
+
+        /**
+         * @description: 读取数据库中所有元件的键, 只读型号和厂家
+         * @return {*}
+         */
+        pub fn element_keys(&self) -> BomResult<Vec<String>> {
+            let mut stmt = self
+                .conn
+                .prepare(format!(get_keys_template!(), self.tables).as_str())?;
+            let rows = stmt.query_map([], |row| {
+                Ok(element_key(
+                    &row.get::<_, String>(0)?,
+                    &row.get::<_, String>(1)?,
+                ))
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        }
+    }
+
+    /**
+     * @description: 增加元件数量并记流水, 同时更新状态/参数/搜索索引, 元件不存在时新增
+     * @param {&Connection} conn sqlite数据库连接, 可以是事务
+     * @param {&str} tables 元件表名
+     * @param {&Element} element 要增加的元件, number 为增加的数量
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {*}
+     */
+    pub(crate) fn add_number(
+        conn: &Connection,
+        tables: &str,
+        element: &Element,
+        note: &MovementNote,
+    ) -> BomResult<()> {
+        let number: u32 = conn.query_row(
+            format!(add_number_template!(), tables).as_str(),
+            (
+                &element.describe,
+                &element.model,
+                &element.manufacturer,
+                &element.number,
+                &element.element_type.to_string(),
+                &element.state.to_string(),
+                &element.category_id(),
+            ),
+            |row| row.get(0),
+        )?;
+        let state =
+            threshold::effective_thresholds(conn, tables, &element.model, &element.manufacturer)?
+                .status(number);
+        conn.execute(
+            format!(update_state_template!(), tables).as_str(),
+            [&state.to_string(), &element.model, &element.manufacturer],
+        )?;
+        value::write_params(conn, tables, element)?;
+        search::index_element(conn, tables, &element.model, &element.manufacturer)?;
+        if element.number > 0 {
+            ledger::insert_movement(
+                conn,
+                &note.movement(&element.model, &element.manufacturer, element.number.into()),
+            )?;
+        }
+        Ok(())
+    }
+
+    /**
+     * @description: 减少元件数量并记流水, 同时更新状态并裁剪库位
+     * @param {&Connection} conn sqlite数据库连接, 可以是事务
+     * @param {&str} tables 元件表名
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @param {u32} number 要减少的数量
+     * @param {bool} take_available 库存不足时是否取走现有的全部
+     * @param {&MovementNote} note 流水的原因和操作人
+     * @return {*}
+     */
+    pub(crate) fn reduce_number(
+        conn: &Connection,
+        tables: &str,
+        model: &str,
+        manufacturer: &str,
+        number: u32,
+        take_available: bool,
+        note: &MovementNote,
+    ) -> BomResult<ReduceReport> {
+        let available: u32 = match conn.query_row(
+            format!(get_number_template!(), tables).as_str(),
+            [model, manufacturer],
+            |row| row.get(0),
+        ) {
+            Ok(available) => available,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(BomError::NotFound(model.to_string()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if number > available && !take_available {
+            return Err(BomError::InsufficientStock {
+                model: model.to_string(),
+                requested: number,
+                available,
+            });
+        }
+        let taken = number.min(available);
+        let remain = available - taken;
+        let state =
+            threshold::effective_thresholds(conn, tables, model, manufacturer)?.status(remain);
+        conn.execute(
+            format!(update_number_template!(), tables).as_str(),
+            (remain, state.to_string(), model, manufacturer),
+        )?;
+        if taken > 0 {
+            ledger::insert_movement(conn, &note.movement(model, manufacturer, -i64::from(taken)))?;
+        }
+        location::trim_locations(conn, model, manufacturer, remain)?;
+        Ok(ReduceReport {
+            taken,
+            shortfall: number - taken,
+            remain,
+        })
+    }
+
+    /**
+     * @description: 把 get_all_template 查询到的一行转为 Element
+     * @param {&rusqlite::Row} row
+     * @return {*}
+     */
+    fn element_from_row(row: &rusqlite::Row) -> rusqlite::Result<Element> {
+        Ok(Element {
+            describe: match row.get(0) {
+                Ok(describe) => {
+                    // info_log!("describe: {}", describe);
+                    describe
+                }
+                Err(_) => "Unknown".to_string(),
+            },
+            model: row.get(1)?,
+            manufacturer: row.get(2).unwrap_or_default(),
+            number: row.get(3)?,
+            // 字符串转枚举
+            element_type: match row.get::<usize, String>(4) {
+                Ok(element_type_str) => {
+                    ElementType::from_string(&element_type_str).unwrap_or(ElementType::Unknown)
+                }
+                Err(_) => ElementType::Unknown,
+            },
+            // 字符串转枚举
+            state: match row.get::<usize, String>(5) {
+                Ok(element_status_str) => ElementStatus::from_string(&element_status_str)
+                    .unwrap_or(ElementStatus::Unknown),
+                Err(err) => {
+                    info_log!("Failed to get element status: {}", err);
+                    ElementStatus::Unknown
+                }
+            },
+            category: row.get(6).unwrap_or_default(),
+            version: row.get(7).unwrap_or_default(),
+        })
+    }
+
+    /**
+     * @description: 检查元件在读取后有没有被别人修改过, 数据库中没有该元件时不算冲突
+     * @param {&Connection} conn sqlite数据库连接
+     * @param {&str} tables 元件表名
+     * @param {&Element} element 读取到的元件, version 为读取时的版本号
+     * @return {版本号不同时返回 BomError::Conflict}
+     */
+    fn check_version(conn: &Connection, tables: &str, element: &Element) -> BomResult<()> {
+        match conn.query_row(
+            format!(get_version_template!(), tables).as_str(),
+            [&element.model, &element.manufacturer],
+            |row| row.get::<usize, u32>(0),
+        ) {
+            Ok(found) if found != element.version => Err(BomError::Conflict {
+                model: element.key(),
+                expected: element.version,
+                found,
+            }),
+            Err(rusqlite::Error::QueryReturnedNoRows) | Ok(_) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
     }
 
+    // 写锁被其他进程占用时的最长等待时间
+    const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
     // bom控制结构体
-    pub struct BomManageCtrl {
-        pub database: DataBaseInfo,
-        pub element_map: HashMap<String, Element>,
+    pub struct BomManageCtrl<S = DataBaseInfo> {
+        pub database: S,                                  // 存储后端, 默认为 sqlite 数据库
+        pub(crate) element_map: HashMap<String, Element>, // 元件缓存, 其他进程修改后不会更新, 读取请用 query/element
+        pub operator: String,                             // 写入流水的操作人
+        pub substitute_policy: SubstitutePolicy,          // 比对 BOM 时的取料顺序
     }
 
     // BomManageCtrl 的方法
     impl BomManageCtrl {
         /**
          * @name: new
-         * @msg: 创建时判断是否有数据文件,
-         * 1. 有的话就读取并创建哈希表, 将数据写入,
-         * 2. 没数据文件就创建哈希表等待写入数据到表中.
+         * @msg: 创建时判断是否有数据文件:
+         * 有的话就读取并创建哈希表, 将数据写入;
+         * 没数据文件就创建哈希表等待写入数据到表中.
          * @param {&'a str} data_filepath 数据库地址
          * @param {&'a str} table_name 数据库表名
          * @return {*}
          * @author: TOTHTOT
          * @Date: 2024-07-29 11:11:17
          */
-        pub fn new<'a>(
-            data_filepath: &'a str,
-            table_name: &'a str,
-        ) -> Result<BomManageCtrl, String> {
+        pub fn new<'a>(data_filepath: &'a str, table_name: &'a str) -> BomResult<BomManageCtrl> {
             // 判断文件是否存在且数据有效
             match check_datafile(data_filepath) {
                 Ok(_) => {
                     // 文件存在且有效, 读取文件内容
-                    match open_or_create_data_file(data_filepath) {
+                    match open_or_create_data_file(data_filepath, table_name) {
                         Ok(content) => {
                             let mut map: HashMap<String, Element> = HashMap::new();
                             // 行数, 根据行数判断是否需要读取数据到哈希表中, 先借用 content 避免所有权问题
@@ -338,10 +820,7 @@ pub mod bom_manage {
                                     Ok(map) => map,
                                     Err(err) => {
                                         info_log!("{err}");
-                                        return Err(format!(
-                                            "Error reading from database: {}",
-                                            err
-                                        ));
+                                        return Err(err);
                                     }
                                 };
                             } else {
@@ -351,18 +830,24 @@ pub mod bom_manage {
                             Ok(BomManageCtrl {
                                 database: baseinof,
                                 element_map: map,
+                                operator: default_operator(),
+                                substitute_policy: SubstitutePolicy::default(),
                             })
                         }
                         Err(error) => {
                             info_log!("{error}");
-                            Err("文件创建失败".to_string())
+                            Err(error)
                         }
                     }
                 }
                 Err(error) => {
-                    // 文件不存在或无效, 创建文件
                     info_log!("{error}");
-                    match open_or_create_data_file(data_filepath) {
+                    // 文件存在但无效时不能当作新文件, 否则会变成空库存, 需要从备份恢复
+                    if fs::metadata(data_filepath).is_ok() {
+                        return Err(error);
+                    }
+                    // 文件不存在, 创建文件
+                    match open_or_create_data_file(data_filepath, table_name) {
                         Ok(content) => {
                             let baseinof = DataBaseInfo {
                                 conn: content,
@@ -374,16 +859,36 @@ pub mod bom_manage {
                             Ok(BomManageCtrl {
                                 database: baseinof,
                                 element_map: map,
+                                operator: default_operator(),
+                                substitute_policy: SubstitutePolicy::default(),
                             })
                         }
                         Err(error) => {
                             info_log!("{error}");
-                            Err("文件创建失败".to_string())
+                            Err(error)
                         }
                     }
                 }
             }
         }
+    }
+
+    // BomManageCtrl 中和存储后端无关的方法, 任何 BomStore 都可以使用
+    impl<S: BomStore> BomManageCtrl<S> {
+        /**
+         * @description: 使用指定的存储后端, 例如 MemoryStore 或 JsonStore
+         * @param {S} store 存储后端
+         * @return {*}
+         */
+        pub fn with_store(store: S) -> BomResult<BomManageCtrl<S>> {
+            let element_map = store.read_all()?;
+            Ok(BomManageCtrl {
+                database: store,
+                element_map,
+                operator: default_operator(),
+                substitute_policy: SubstitutePolicy::default(),
+            })
+        }
 
         /**
          * @name: add_element
@@ -394,158 +899,1183 @@ pub mod bom_manage {
          * @author: TOTHTOT
          * @Date: 2024-08-01 14:40:26
          */
-        pub fn add_element(self: &mut Self, mut element: Element) -> Result<(), String> {
-            // 根据键获取数据
-            match self.element_map.get_mut(element.model.as_str()) {
-                Some(e) => {
-                    // 已经存在了的元件就修改数量
-                    element.modify_number(e.number + element.number);
-                }
-                None => {}
-            }
-            self.element_map.insert(element.model.clone(), element);
-            self.database
-                .write_hm_to_database(&self.element_map)
-                .map_err(|_| "写入数据库失败".to_string())?;
+        pub fn add_element(&mut self, element: Element) -> BomResult<()> {
+            self.add_element_for(element, "")
+        }
+
+        /**
+         * @description: 添加元件并在流水中记录原因或项目
+         * @param {Element} element 要写入到表中元件
+         * @param {&str} reason 原因或项目
+         * @return {*}
+         */
+        pub fn add_element_for(&mut self, element: Element, reason: &str) -> BomResult<()> {
+            // 数量在数据库中原子累加, 不会覆盖其他进程的修改, 成功后用返回的结果更新哈希表
+            check_model(&element.model)?;
+            let note = self.note(reason);
+            let element = self.database.add_element_number(&element, &note)?;
+            self.element_map.insert(element.key(), element);
             Ok(())
         }
 
         /**
-         * @description: 减少元件数量
+         * @description: 减少元件数量, 库存不足时返回 BomError::InsufficientStock, 数量不变
          * @param {String} model 减少的元件名称
          * @param {u32} number 减少的数量
          * @return {*}
          */
-        pub fn reduce_element(&mut self, model: String, number: u32) -> Result<(), String> {
-            // 根据键获取数据
-            match self.element_map.get_mut(model.as_str()) {
-                Some(e) => {
-                    // 已经存在了的元件就修改数量
-                    e.modify_number(e.number - number);
-                    let element = e.clone();
-                    self.element_map.insert(element.model.clone(), element);
-                    self.database
-                        .write_hm_to_database(&self.element_map)
-                        .map_err(|_| "写入数据库失败".to_string())?;
-                }
-                None => {
-                    return Err("元件不存在".to_string());
-                }
-            }
+        pub fn reduce_element(&mut self, model: String, number: u32) -> BomResult<()> {
+            self.reduce_element_for(model, number, "", false)?;
             Ok(())
         }
 
+        /**
+         * @description: 减少元件数量, 库存不足时取出全部库存
+         * @param {String} model 减少的元件名称
+         * @param {u32} number 需要的数量
+         * @return {实际取出的数量和缺少的数量}
+         */
+        pub fn reduce_element_available(
+            &mut self,
+            model: String,
+            number: u32,
+        ) -> BomResult<ReduceReport> {
+            self.reduce_element_for(model, number, "", true)
+        }
+
+        /**
+         * @description: 减少元件数量并在流水中记录原因或项目
+         * @param {String} model 减少的元件名称
+         * @param {u32} number 减少的数量
+         * @param {&str} reason 原因或项目
+         * @param {bool} take_available 库存不足时 true 取出全部库存, false 返回库存不足错误
+         * @return {实际取出的数量和缺少的数量}
+         */
+        pub fn reduce_element_for(
+            &mut self,
+            model: String,
+            number: u32,
+            reason: &str,
+            take_available: bool,
+        ) -> BomResult<ReduceReport> {
+            // 数量在数据库中读取和修改, 哈希表可能是旧的, 只在完成后更新
+            let note = self.note(reason);
+            let (model_name, manufacturer) = split_key(&model);
+            let report = self.database.reduce_element_number(
+                model_name,
+                manufacturer,
+                number,
+                take_available,
+                &note,
+            )?;
+            self.refresh(&model)?;
+            Ok(report)
+        }
+
         /**
          * @description: 移除一个元件, 或者所有元件
          * @param {String} model 元件名称, == all 删除所有元件
          * @return {*}
          */
-        pub fn remove_element(&mut self, model: &String) -> Result<(), String> {
-            if model == &"all".to_string() {
+        pub fn remove_element(&mut self, model: &str) -> BomResult<()> {
+            let note = self.note(ledger::REASON_REMOVE);
+            if model == "all" {
+                self.database.delete_all(&note)?;
                 self.element_map.clear();
-            }
-            else {
+            } else {
+                let (model_name, manufacturer) = split_key(model);
+                self.database
+                    .delete_element(model_name, manufacturer, &note)?;
                 // 从哈希表中删除元素
                 self.element_map.remove(model);
             }
-
-            // 尝试将更新后的哈希表写入数据库
-            self.database.write_hm_to_database(&self.element_map).map_err(|err| format!("Fail to write to database: {err}"))?;
             Ok(())
         }
         /**
-         * @name:
-         * @msg:
-         * @param {*} map
-         * @param {*} Element
-         * @param {String} model
+         * @name: del_element
+         * @msg: 从数据库和哈希表中删除一个元件
+         * @param {String} model 元件型号
          * @return {*}
          * @author: TOTHTOT
          * @Date: 2024-08-01 14:41:42
          */
-        pub fn del_element(&mut self, model: String) -> Result<(), String> {
+        pub fn del_element(&mut self, model: String) -> BomResult<()> {
+            // 只删除数据库中对应的那一行
+            let note = self.note(ledger::REASON_REMOVE);
+            let (model_name, manufacturer) = split_key(&model);
+            self.database
+                .delete_element(model_name, manufacturer, &note)?;
             // 从哈希表中删除元素
             self.element_map.remove(&model);
 
-            // 尝试将更新后的哈希表写入数据库
-            self.database
-                .write_hm_to_database(&self.element_map)
-                .map_err(|_| "写入数据库失败".to_string())?;
-
             // 如果没有错误，返回 Ok(())
             Ok(())
         }
-    }
 
-    /**
-     * @name: open_or_create_data_file
-     * @msg: 打开或者创建sqlite文件
-     * @param {*} filepath
-     * @return {成功返回 Connection 对象, 需要解包; 失败返回错误信息}
-     * @author: TOTHTOT
-     * @Date: 2024-07-30 14:33:14
-     */
-    fn open_or_create_data_file(filepath: &str) -> Result<Connection, String> {
-        match Connection::open(filepath) {
-            Ok(file) => {
-                // 写入表头
-                match file.execute(create_table_template!(), []) {
-                    Ok(_) => Ok(file),
-                    Err(_) => Err("链接数据库失败".to_string()),
-                }
-            }
-            Err(_) => Err("无法打开/创建文件".to_string()),
+        /**
+         * @description: 查看元件的出入库流水, 元件删除后流水仍然保留
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn element_history(&self, model: &str) -> BomResult<Vec<StockMovement>> {
+            let (model, manufacturer) = split_key(model);
+            self.database.element_history(model, manufacturer)
         }
-    }
 
-    /**
-     * @name: check_datafile
-     * @msg: 检测文件是否为合法sql文件, 合法就返回Ok(_), 否则返回错误信息
-     * @param {*} filepath 文件地址
-     * @return {*}
-     * @author: TOTHTOT
-     * @Date: 2024-07-29 13:57:45
-     */
-    fn check_datafile(filepath: &str) -> Result<(), String> {
-        if fs::metadata(filepath).is_ok() {
-            info_log!("{filepath} 文件存在");
-            let mut data_file = match fs::File::open(filepath) {
-                Ok(file) => file,
-                Err(err) => {
-                    info_log!("{err}");
-                    return Err("文件打开失败".to_string());
-                }
-            };
+        /**
+         * @description: 从存储中读取单个元件, 不经过哈希表
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn element(&self, model: &str) -> BomResult<Element> {
+            let (model, manufacturer) = split_key(model);
+            self.database.element(model, manufacturer)
+        }
 
-            // 读取前16个字节判断数据库文件是否有效
-            let mut buffer = [0; 16];
-            if let Err(_) = data_file.read_exact(&mut buffer) {
-                info_log!("{filepath} 文件无效");
-                return Err("文件无效".to_string());
-            }
+        /**
+         * @description: 修改元件的描述/类型/分类, element 要先用 element 或 query 读取,
+         * 期间被其他进程修改过时返回 BomError::Conflict, 重新读取后再修改
+         * @param {&Element} element 修改后的元件
+         * @return {*}
+         */
+        pub fn update_element(&mut self, element: &Element) -> BomResult<()> {
+            let stored = self.database.update_element(element)?;
+            self.element_map.insert(stored.key(), stored);
+            Ok(())
+        }
 
-            // SQLite 文件头的 magic number
-            let sqlite_magic_number: [u8; 16] = [
-                0x53, 0x51, 0x4c, 0x69, 0x74, 0x65, 0x20, 0x66, 0x6f, 0x72, 0x6d, 0x61, 0x74, 0x20,
-                0x33, 0x00,
-            ];
+        /**
+         * @description: 从存储中重新读取所有元件到哈希表, 用于看到其他进程的修改
+         * @return {*}
+         */
+        pub fn reload(&mut self) -> BomResult<()> {
+            self.element_map = self.database.read_all()?;
+            Ok(())
+        }
 
-            if buffer == sqlite_magic_number {
-                info_log!("{filepath} 是数据库文件");
-                return Ok(());
-            } else {
-                info_log!("{filepath} 不是数据库文件");
-                return Err("不是数据库文件".to_string());
+        // 从存储中重新读取一个元件到哈希表, 已经被删除时从哈希表中移除
+        fn refresh(&mut self, key: &str) -> BomResult<()> {
+            match self.element(key) {
+                Ok(element) => {
+                    self.element_map.insert(key.to_string(), element);
+                }
+                Err(BomError::NotFound(_)) => {
+                    self.element_map.remove(key);
+                }
+                Err(err) => return Err(err),
             }
-        } else {
-            info_log!("{filepath} 文件不存在");
-            return Err("文件不存在".to_string());
+            Ok(())
         }
-    }
 
-    /**
-     * @name: database_get_line
-     * @msg: 获取数据库中指定表中的行数
+        // 从存储中重新读取多个元件到哈希表, 重复的键只读一次
+        fn refresh_keys(&mut self, keys: Vec<String>) -> BomResult<()> {
+            let keys: HashSet<String> = keys.into_iter().collect();
+            for key in keys {
+                self.refresh(&key)?;
+            }
+            Ok(())
+        }
+
+        // 当前操作人生成流水说明
+        fn note(&self, reason: &str) -> MovementNote {
+            MovementNote {
+                reason: reason.to_string(),
+                operator: self.operator.clone(),
+            }
+        }
+    }
+
+    // BomManageCtrl 中依赖 sqlite 数据库的方法, 分类/位置/供应商等只有数据库支持
+    impl BomManageCtrl {
+        /**
+         * @description: 回放流水得到元件在某一时刻的数量
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {i64} timestamp unix 时间戳
+         * @return {*}
+         */
+        pub fn quantity_at(&self, model: &str, timestamp: i64) -> BomResult<i64> {
+            let (model, manufacturer) = split_key(model);
+            self.database.quantity_at(model, manufacturer, timestamp)
+        }
+
+        /**
+         * @description: 按解析后的参数查询元件, 例如所有 >= 10uF 且耐压 >= 25V 的电容
+         * @param {&ParamFilter} filter 查询条件
+         * @return {*}
+         */
+        pub fn query_params(&self, filter: &ParamFilter) -> BomResult<Vec<Element>> {
+            self.database.query_params(filter)
+        }
+
+        /**
+         * @description: 按条件查询元件, 过滤/排序/分页都在数据库中完成
+         * @param {&ElementQuery} query 查询条件, 例如 ElementQuery::new().status(ElementStatus::SHORTAGE).limit(20)
+         * @return {*}
+         */
+        pub fn query(&self, query: &ElementQuery) -> BomResult<Vec<Element>> {
+            self.database.query_elements(query)
+        }
+
+        /**
+         * @description: 符合条件的元件数量, 不受 limit 和 offset 影响
+         * @param {&ElementQuery} query 查询条件
+         * @return {*}
+         */
+        pub fn count(&self, query: &ElementQuery) -> BomResult<u32> {
+            self.database.count_elements(query)
+        }
+
+        /**
+         * @description: 按路径新增分类, 例如 "半导体/MOSFET/N沟道", 路径中不存在的上级分类一并创建
+         * @param {&str} path 分类路径
+         * @return {最后一级分类}
+         */
+        pub fn add_category(&self, path: &str) -> BomResult<Category> {
+            self.database.add_category_path(path)
+        }
+
+        /**
+         * @description: 按路径查找分类
+         * @param {&str} path 分类路径
+         * @return {不存在时返回 BomError::NotFound}
+         */
+        pub fn find_category(&self, path: &str) -> BomResult<Category> {
+            self.database.find_category(path)
+        }
+
+        /**
+         * @description: 修改元件的分类
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {&str} path 分类路径, 分类必须已经存在
+         * @return {*}
+         */
+        pub fn set_category(&mut self, model: &str, path: &str) -> BomResult<()> {
+            let category = self.database.find_category(path)?;
+            let element = self.element(model)?;
+            self.database.set_element_category(
+                &element.model,
+                &element.manufacturer,
+                Some(category.id),
+            )?;
+            self.refresh(model)
+        }
+
+        /**
+         * @description: 元件所在分类的路径, 没有分类时返回 None
+         * @param {&Element} element 元件
+         * @return {*}
+         */
+        pub fn category_path(&self, element: &Element) -> BomResult<Option<String>> {
+            element
+                .category_id()
+                .map(|id| self.database.category_path(id))
+                .transpose()
+        }
+
+        /**
+         * @description: 读取分类及其所有子分类下的元件
+         * @param {&str} path 分类路径
+         * @return {*}
+         */
+        pub fn category_elements(&self, path: &str) -> BomResult<Vec<Element>> {
+            let category = self.database.find_category(path)?;
+            self.database.category_elements(category.id)
+        }
+
+        /**
+         * @description: 设置元件自己的最低库存和补货点, 并更新状态
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {Option<Thresholds>} thresholds 阈值, None 清除后使用分类或默认阈值
+         * @return {*}
+         */
+        pub fn set_thresholds(
+            &mut self,
+            model: &str,
+            thresholds: Option<Thresholds>,
+        ) -> BomResult<()> {
+            let key = model;
+            let (model, manufacturer) = split_key(key);
+            self.database
+                .set_element_thresholds(model, manufacturer, thresholds)?;
+            self.refresh(key)
+        }
+
+        /**
+         * @description: 设置分类的最低库存和补货点, 子分类和分类下的元件没有自己的阈值时继承它
+         * @param {&str} path 分类路径
+         * @param {Option<Thresholds>} thresholds 阈值, None 清除后继承父分类
+         * @return {*}
+         */
+        pub fn set_category_thresholds(
+            &mut self,
+            path: &str,
+            thresholds: Option<Thresholds>,
+        ) -> BomResult<()> {
+            let category = self.database.find_category(path)?;
+            self.database
+                .set_category_thresholds(category.id, thresholds)?;
+            self.refresh_category(category.id)
+        }
+
+        // 从数据库重新读取分类及其所有子分类下的元件到哈希表
+        fn refresh_category(&mut self, id: i64) -> BomResult<()> {
+            for element in self.database.category_elements(id)? {
+                self.element_map.insert(element.key(), element);
+            }
+            Ok(())
+        }
+
+        /**
+         * @description: 元件生效的阈值
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn thresholds(&self, model: &str) -> BomResult<Thresholds> {
+            let (model, manufacturer) = split_key(model);
+            self.database.element_thresholds(model, manufacturer)
+        }
+
+        /**
+         * @description: 数量不超过补货点的元件
+         * @return {元件和生效的阈值}
+         */
+        pub fn below_reorder_point(&self) -> BomResult<Vec<(Element, Thresholds)>> {
+            self.database.below_reorder_point()
+        }
+
+        /**
+         * @description: 比对项目 BOM 和库存
+         * @param {&[BomLine]} lines 项目 BOM
+         * @param {u32} boards 板数
+         * @return {每一行需要的数量/库存/缺少的数量, 以及没有匹配的行}
+         */
+        pub fn check_bom(&self, lines: &[BomLine], boards: u32) -> BomResult<BomReport> {
+            let reserved = self.database.reserved_stock()?;
+            let substitutes = Substitutes {
+                groups: self.database.part_groups()?,
+                policy: self.substitute_policy.clone(),
+            };
+            Ok(bom_import::check_bom(
+                &self.database.read_hm_from_database()?,
+                &reserved,
+                lines,
+                boards,
+                &substitutes,
+            ))
+        }
+
+        /**
+         * @description: 读取项目 BOM 文件并和库存比对
+         * @param {&str} path BOM 文件路径
+         * @param {&BomFormat} format 文件格式
+         * @param {u32} boards 板数
+         * @return {*}
+         */
+        pub fn import_bom(
+            &self,
+            path: &str,
+            format: &BomFormat,
+            boards: u32,
+        ) -> BomResult<BomReport> {
+            let lines = bom_import::read_bom(path, format)?;
+            self.check_bom(&lines, boards)
+        }
+
+        /**
+         * @description: 可用数量, 库存减去预留中的生产单预留的数量
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn available(&self, model: &str) -> BomResult<u32> {
+            let element = self.element(model)?;
+            let reserved = self.database.reserved_stock()?;
+            Ok(element
+                .number
+                .saturating_sub(*reserved.get(model).unwrap_or(&0)))
+        }
+
+        /**
+         * @description: 把元件加入替代料组, 组不存在时创建, 排在组内最后
+         * @param {&str} group 组名
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家", 没写厂家时匹配所有厂家
+         * @return {*}
+         */
+        pub fn add_alternate(&self, group: &str, model: &str) -> BomResult<()> {
+            let (model, manufacturer) = split_key(model);
+            self.database
+                .add_group_member(group, model, manufacturer, None)
+        }
+
+        /**
+         * @description: 从替代料组中删除元件, 组内没有元件时删除组
+         * @param {&str} group 组名
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn remove_alternate(&self, group: &str, model: &str) -> BomResult<()> {
+            let (model, manufacturer) = split_key(model);
+            self.database
+                .remove_group_member(group, model, manufacturer)
+        }
+
+        /**
+         * @description: 所有替代料组
+         * @return {*}
+         */
+        pub fn part_groups(&self) -> BomResult<Vec<PartGroup>> {
+            self.database.part_groups()
+        }
+
+        /**
+         * @description: 按项目 BOM 新建生产单, 从可用库存中预留元件, 可用库存不足的部分不预留,
+         * 使用了替代料时预留替代料, 库中没有的元件按 BOM 中的型号记下需要的数量
+         * @param {&str} name 生产单名称
+         * @param {&[BomLine]} lines 项目 BOM
+         * @param {u32} boards 板数
+         * @return {生产单和预留时的缺料报告}
+         */
+        pub fn create_build(
+            &self,
+            name: &str,
+            lines: &[BomLine],
+            boards: u32,
+        ) -> BomResult<(Build, BomReport)> {
+            // 比对库存和写入生产单在同一个事务中, 避免两个生产单同时预留同一批库存
+            let tx = self.database.immediate_transaction()?;
+            let report = self.check_bom(lines, boards)?;
+            // 多行匹配到同一个元件时合并
+            let mut items: Vec<BuildItem> = Vec::new();
+            // 库中没有的元件也记下来, 预留为 0, 生成采购单时按缺料采购
+            let missing = report
+                .lines
+                .iter()
+                .filter_map(|l| l.missing())
+                .map(|(key, required)| (key, required, 0));
+            for (key, required, reserved) in
+                report.lines.iter().flat_map(|l| l.usage()).chain(missing)
+            {
+                match items.iter_mut().find(|item| item.key() == key) {
+                    Some(item) => {
+                        item.required += required;
+                        item.reserved += reserved;
+                    }
+                    None => {
+                        let (model, manufacturer) = split_key(&key);
+                        items.push(BuildItem {
+                            model: model.to_string(),
+                            manufacturer: manufacturer.to_string(),
+                            required,
+                            reserved,
+                        });
+                    }
+                }
+            }
+            let id = build::insert_build(&tx, name, boards, &items)?;
+            tx.commit()?;
+            Ok((self.database.build(id)?, report))
+        }
+
+        /**
+         * @description: 完成生产单, 扣减预留的元件, 流水原因为 "build:名称"
+         * @param {i64} id 生产单号
+         * @return {*}
+         */
+        pub fn commit_build(&mut self, id: i64) -> BomResult<()> {
+            let build = self.database.build(id)?;
+            let note = self.note(&format!("build:{}", build.name));
+            let build = self.database.commit_build(id, &note)?;
+            for item in build.items.iter() {
+                self.refresh(&item.key())?;
+            }
+            Ok(())
+        }
+
+        /**
+         * @description: 取消生产单, 释放预留的元件
+         * @param {i64} id 生产单号
+         * @return {*}
+         */
+        pub fn cancel_build(&self, id: i64) -> BomResult<()> {
+            self.database.finish_build(id, BuildStatus::Cancelled)
+        }
+
+        /**
+         * @description: 生成采购单草稿, 包括到达补货点的元件和预留中的生产单缺的料, 不写入数据库
+         * @return {每个供应商一张采购单}
+         */
+        pub fn draft_purchase_orders(&self) -> BomResult<Vec<PurchaseOrder>> {
+            self.database.draft_purchase_orders()
+        }
+
+        /**
+         * @description: 生成并保存采购单, 保存后的数量算作在途, 再次生成时不会重复采购
+         * @return {保存的采购单}
+         */
+        pub fn create_purchase_orders(&self) -> BomResult<Vec<PurchaseOrder>> {
+            let drafts = self.database.draft_purchase_orders()?;
+            self.database
+                .create_purchase_orders(&drafts)?
+                .into_iter()
+                .map(|id| self.database.purchase_order(id))
+                .collect()
+        }
+
+        /**
+         * @description: 所有采购单, 按创建顺序排序
+         * @return {*}
+         */
+        pub fn purchase_orders(&self) -> BomResult<Vec<PurchaseOrder>> {
+            self.database.purchase_orders()
+        }
+
+        /**
+         * @description: 采购单收货, 所有元件在一个事务中入库, 流水原因为 "po:采购单号"
+         * @param {i64} id 采购单号
+         * @return {收货的采购单}
+         */
+        pub fn receive_purchase_order(&mut self, id: i64) -> BomResult<PurchaseOrder> {
+            let note = self.note(&format!("po:{id}"));
+            let order = self.database.receive_purchase_order(id, &note)?;
+            for line in order.lines.iter() {
+                self.refresh(&line.key())?;
+            }
+            Ok(order)
+        }
+
+        /**
+         * @description: 取消未收货的采购单
+         * @param {i64} id 采购单号
+         * @return {*}
+         */
+        pub fn cancel_purchase_order(&self, id: i64) -> BomResult<()> {
+            self.database.cancel_purchase_order(id)
+        }
+
+        /**
+         * @description: 开始盘点所有元件
+         * @return {新的盘点单, 包括每个元件的账面数量}
+         */
+        pub fn start_audit(&self) -> BomResult<Audit> {
+            let id = self.database.start_audit(AuditScope::All)?;
+            self.database.audit(id)
+        }
+
+        /**
+         * @description: 开始盘点一个位置, 只比较放在这个位置的数量, 不含下级位置
+         * @param {&str} path 位置路径, 例如 "柜子A/抽屉3"
+         * @return {新的盘点单}
+         */
+        pub fn start_location_audit(&self, path: &str) -> BomResult<Audit> {
+            let location = self.database.find_location(path)?;
+            let id = self
+                .database
+                .start_audit(AuditScope::Location(location.id))?;
+            self.database.audit(id)
+        }
+
+        /**
+         * @description: 开始盘点一个分类及其子分类下的元件
+         * @param {&str} path 分类路径, 例如 "电阻/贴片"
+         * @return {新的盘点单}
+         */
+        pub fn start_category_audit(&self, path: &str) -> BomResult<Audit> {
+            let category = self.database.find_category(path)?;
+            let id = self
+                .database
+                .start_audit(AuditScope::Category(category.id))?;
+            self.database.audit(id)
+        }
+
+        /**
+         * @description: 录入元件的实际数量
+         * @param {i64} id 盘点单号
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {u32} counted 实际数量
+         * @return {*}
+         */
+        pub fn record_count(&self, id: i64, model: &str, counted: u32) -> BomResult<()> {
+            let (model, manufacturer) = split_key(model);
+            self.database.record_count(id, model, manufacturer, counted)
+        }
+
+        /**
+         * @description: 按盘点单号读取盘点单, 用 Audit::variances 查看差异
+         * @param {i64} id 盘点单号
+         * @return {*}
+         */
+        pub fn audit(&self, id: i64) -> BomResult<Audit> {
+            self.database.audit(id)
+        }
+
+        /**
+         * @description: 所有盘点单, 按开始顺序排序
+         * @return {*}
+         */
+        pub fn audits(&self) -> BomResult<Vec<Audit>> {
+            self.database.audits()
+        }
+
+        /**
+         * @description: 按盘点差异调整库存, 流水原因为 "audit"
+         * @param {i64} id 盘点单号
+         * @return {调整后的盘点单}
+         */
+        pub fn apply_audit(&mut self, id: i64) -> BomResult<Audit> {
+            let note = self.note(ledger::REASON_AUDIT);
+            let audit = self.database.apply_audit(id, &note)?;
+            for line in audit.variances() {
+                self.refresh(&line.key())?;
+            }
+            Ok(audit)
+        }
+
+        /**
+         * @description: 取消盘点中的盘点单, 不调整库存
+         * @param {i64} id 盘点单号
+         * @return {*}
+         */
+        pub fn cancel_audit(&self, id: i64) -> BomResult<()> {
+            self.database.cancel_audit(id)
+        }
+
+        /**
+         * @description: 给元件添加附件, 例如数据手册/照片/封装图
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {&str} path 要添加的文件
+         * @param {Option<AttachmentKind>} kind 附件类型, None 时按扩展名猜测
+         * @return {*}
+         */
+        pub fn add_attachment(
+            &self,
+            model: &str,
+            path: &str,
+            kind: Option<AttachmentKind>,
+        ) -> BomResult<Attachment> {
+            let (model, manufacturer) = split_key(model);
+            self.database
+                .add_attachment(model, manufacturer, Path::new(path), kind)
+        }
+
+        /**
+         * @description: 元件的所有附件
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn attachments(&self, model: &str) -> BomResult<Vec<Attachment>> {
+            let (model, manufacturer) = split_key(model);
+            self.database.attachments(model, manufacturer)
+        }
+
+        /**
+         * @description: 附件文件的路径, 用于打开附件
+         * @param {i64} id 附件 id
+         * @return {*}
+         */
+        pub fn attachment_path(&self, id: i64) -> BomResult<PathBuf> {
+            self.database.attachment_path(id)
+        }
+
+        /**
+         * @description: 把附件按添加时的文件名复制到目录中
+         * @param {i64} id 附件 id
+         * @param {&str} dir 目标目录
+         * @return {复制后的文件路径}
+         */
+        pub fn save_attachment(&self, id: i64, dir: &str) -> BomResult<PathBuf> {
+            self.database.save_attachment(id, Path::new(dir))
+        }
+
+        /**
+         * @description: 删除附件, 文件在 gc_attachments 时删除
+         * @param {i64} id 附件 id
+         * @return {*}
+         */
+        pub fn remove_attachment(&self, id: i64) -> BomResult<()> {
+            self.database.remove_attachment(id)
+        }
+
+        /**
+         * @description: 删除没有被引用的附件文件
+         * @return {*}
+         */
+        pub fn gc_attachments(&self) -> BomResult<AttachmentGc> {
+            self.database.gc_attachments()
+        }
+
+        /**
+         * @description: 给元件添加一条备注, 添加人为 operator
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {&str} text 内容
+         * @return {*}
+         */
+        pub fn add_note(&self, model: &str, text: &str) -> BomResult<Note> {
+            let (model, manufacturer) = split_key(model);
+            self.database
+                .add_note(model, manufacturer, text, &self.operator)
+        }
+
+        /**
+         * @description: 元件的所有备注, 按时间排序
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn notes(&self, model: &str) -> BomResult<Vec<Note>> {
+            let (model, manufacturer) = split_key(model);
+            self.database.notes(model, manufacturer)
+        }
+
+        /**
+         * @description: 删除备注
+         * @param {i64} id 备注 id
+         * @return {*}
+         */
+        pub fn remove_note(&self, id: i64) -> BomResult<()> {
+            self.database.remove_note(id)
+        }
+
+        /**
+         * @description: 按路径新增位置, 例如 "柜子A/抽屉3/格子12", 路径中不存在的上级位置一并创建
+         * @param {&str} path 位置路径
+         * @return {最后一级位置}
+         */
+        pub fn add_location(&self, path: &str) -> BomResult<Location> {
+            self.database.add_location_path(path)
+        }
+
+        /**
+         * @description: 添加元件并放到指定位置, 位置不存在时创建, 添加和放置在同一个事务中完成
+         * @param {Element} element 要添加的元件
+         * @param {&str} path 位置路径
+         * @return {*}
+         */
+        pub fn add_element_at(&mut self, element: Element, path: &str) -> BomResult<()> {
+            check_model(&element.model)?;
+            let note = self.note("");
+            let element = self.database.add_element_number_at(&element, &note, path)?;
+            self.element_map.insert(element.key(), element);
+            Ok(())
+        }
+
+        /**
+         * @description: 元件在各个位置的数量
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn locations(&self, model: &str) -> BomResult<Vec<StockLocation>> {
+            let (model, manufacturer) = split_key(model);
+            self.database.element_locations(model, manufacturer)
+        }
+
+        /**
+         * @description: 没有放到任何位置的数量
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn unplaced(&self, model: &str) -> BomResult<u32> {
+            let element = self.element(model)?;
+            let placed: u32 = self.locations(model)?.iter().map(|l| l.quantity).sum();
+            Ok(element.number.saturating_sub(placed))
+        }
+
+        /**
+         * @description: 在位置之间移动元件, from 为 None 时从未放置的库存中取, to 为 None 时取下不放
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {Option<&str>} from 原位置路径
+         * @param {Option<&str>} to 新位置路径, 不存在时创建
+         * @param {u32} quantity 数量
+         * @return {*}
+         */
+        pub fn move_stock(
+            &self,
+            model: &str,
+            from: Option<&str>,
+            to: Option<&str>,
+            quantity: u32,
+        ) -> BomResult<()> {
+            let from = match from {
+                Some(path) => Some(self.database.find_location(path)?.id),
+                None => None,
+            };
+            let (model, manufacturer) = split_key(model);
+            self.database
+                .move_stock(model, manufacturer, from, to, quantity)
+        }
+
+        /**
+         * @description: 新增或更新供应商料号
+         * @param {&SupplierPart} part 料号, 供应商不存在时创建
+         * @return {料号 id}
+         */
+        pub fn add_supplier_part(&self, part: &SupplierPart) -> BomResult<i64> {
+            self.database.element(&part.model, &part.manufacturer)?;
+            self.database.add_supplier_part(part)
+        }
+
+        /**
+         * @description: 写入料号的一次报价
+         * @param {i64} part_id 料号 id
+         * @param {i64} effective 报价日期, unix 时间戳
+         * @param {&[(u32, f64)]} breaks (起订数量, 单价) 列表
+         * @return {*}
+         */
+        pub fn set_price_breaks(
+            &self,
+            part_id: i64,
+            effective: i64,
+            breaks: &[(u32, f64)],
+        ) -> BomResult<()> {
+            self.database.set_price_breaks(part_id, effective, breaks)
+        }
+
+        /**
+         * @description: 导入供应商 CSV 文件, 每行一档价格
+         * @param {&str} path 文件路径
+         * @return {导入的行数}
+         */
+        pub fn import_supplier_csv(&self, path: &str) -> BomResult<usize> {
+            self.database.import_supplier_csv(path)
+        }
+
+        /**
+         * @description: 元件的所有供应商料号
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn supplier_parts(&self, model: &str) -> BomResult<Vec<SupplierPart>> {
+            let (model, manufacturer) = split_key(model);
+            self.database.supplier_parts(model, manufacturer)
+        }
+
+        /**
+         * @description: 买 quantity 个元件的最低报价
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @param {u32} quantity 购买数量
+         * @param {Option<i64>} at 按这个时间的价格, None 为当前价格
+         * @return {没有报价时为 None}
+         */
+        pub fn best_quote(
+            &self,
+            model: &str,
+            quantity: u32,
+            at: Option<i64>,
+        ) -> BomResult<Option<Quote>> {
+            let (model, manufacturer) = split_key(model);
+            self.database.best_quote(
+                model,
+                manufacturer,
+                quantity,
+                at.unwrap_or_else(ledger::now_timestamp),
+            )
+        }
+
+        /**
+         * @description: 库存金额, 每个元件按库存数量对应的价格档计算
+         * @param {Option<i64>} at 按这个时间的价格, None 为当前价格
+         * @return {*}
+         */
+        pub fn inventory_value(&self, at: Option<i64>) -> BomResult<Costing> {
+            self.database
+                .inventory_value(at.unwrap_or_else(ledger::now_timestamp))
+        }
+
+        /**
+         * @description: 库存统计报表, 包括按类型和状态的数量, 库存金额, 消耗排行,
+         * 呆滞库存和缺货预测
+         * @param {&ReportOptions} options 统计消耗的时间范围和每个列表的行数
+         * @return {*}
+         */
+        pub fn inventory_report(&self, options: &ReportOptions) -> BomResult<InventoryReport> {
+            self.database.inventory_report(options)
+        }
+
+        /**
+         * @description: 项目 BOM 的元件成本, 匹配到同一元件的行合并后按总数量取价格档,
+         * 使用了替代料时按替代料计价
+         * @param {&BomReport} report check_bom 或 import_bom 的结果
+         * @param {Option<i64>} at 按这个时间的价格, None 为当前价格
+         * @return {没有匹配到元件的行记在 unpriced 中}
+         */
+        pub fn cost_bom(&self, report: &BomReport, at: Option<i64>) -> BomResult<Costing> {
+            let mut items: Vec<(String, u32)> = Vec::new();
+            let mut unmatched = Vec::new();
+            for line in report.lines.iter() {
+                if line.element.is_none() {
+                    unmatched.push(line.line.value.clone());
+                }
+                for (key, required, _) in line.usage() {
+                    match items.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, quantity)) => *quantity += required,
+                        None => items.push((key, required)),
+                    }
+                }
+            }
+            let mut costing = self.database.cost_items(
+                items.iter().map(|(k, q)| (k.as_str(), *q)),
+                at.unwrap_or_else(ledger::now_timestamp),
+            )?;
+            costing.unpriced.extend(unmatched);
+            Ok(costing)
+        }
+
+        /**
+         * @description: 搜索元件, 匹配型号/描述/分类/厂家料号, 支持前缀和拼写错误
+         * @param {&str} text 搜索内容, 例如 "r10k", "10k resistor"
+         * @param {usize} limit 最多返回的条数
+         * @return {按相关度排序}
+         */
+        pub fn search(&self, text: &str, limit: usize) -> BomResult<Vec<SearchHit>> {
+            self.database.search(text, limit)
+        }
+
+        /**
+         * @description: 备份整个数据库, 只保留最近的几份
+         * @param {&str} reason 备份原因, 例如 backup::BACKUP_MANUAL
+         * @return {新的备份}
+         */
+        pub fn backup(&self, reason: &str) -> BomResult<Backup> {
+            self.database.backup(reason)
+        }
+
+        /**
+         * @description: 数据库的所有备份, 按备份号从旧到新排序
+         * @return {*}
+         */
+        pub fn backups(&self) -> BomResult<Vec<Backup>> {
+            self.database.backups()
+        }
+
+        /**
+         * @description: 恢复到某一份备份, 恢复前先备份当前的数据库
+         * @param {u32} id 备份号
+         * @return {*}
+         */
+        pub fn restore_backup(&mut self, id: u32) -> BomResult<()> {
+            self.database.restore_backup(id)?;
+            // 整张表被替换, 缓存中的和恢复后的元件都可能变化
+            let mut keys: Vec<String> = self.element_map.keys().cloned().collect();
+            keys.extend(self.database.element_keys()?);
+            self.refresh_keys(keys)
+        }
+
+        /**
+         * @description: 导出整个库存, 包括分类/位置/流水/供应商/单据/附件和备注,
+         * 附件文件复制到 export::attachment_files_dir 目录
+         * @param {&str} path 文件路径, CSV 格式时为目录
+         * @param {ExportFormat} format 格式, 可以用 ExportFormat::from_path 按扩展名判断
+         * @return {*}
+         */
+        pub fn export(&self, path: &str, format: ExportFormat) -> BomResult<()> {
+            let inventory = self.database.export_inventory()?;
+            export::write_inventory(&inventory, path, format)?;
+            self.database.export_attachment_files(
+                &inventory.attachments,
+                &export::attachment_files_dir(path, format),
+            )?;
+            Ok(())
+        }
+
+        /**
+         * @description: 导入库存文件, dry_run 为 true 时只返回差异报告, 不修改数据库
+         * @param {&str} path 文件路径, CSV 格式时为目录
+         * @param {ExportFormat} format 格式
+         * @param {ImportMode} mode 合并或替换
+         * @param {bool} dry_run 是否只生成差异报告
+         * @return {差异报告}
+         */
+        pub fn import(
+            &mut self,
+            path: &str,
+            format: ExportFormat,
+            mode: ImportMode,
+            dry_run: bool,
+        ) -> BomResult<ImportDiff> {
+            let inventory = export::read_inventory(path, format)?;
+            if dry_run {
+                return self.database.diff_inventory(&inventory, mode);
+            }
+            let diff = self.database.import_inventory(
+                &inventory,
+                mode,
+                &export::attachment_files_dir(path, format),
+                &self.note(ledger::REASON_IMPORT),
+            )?;
+            // 文件中的元件和分类阈值可能影响的元件需要更新, 替换时原有的元件可能被删除
+            let mut keys: Vec<String> = inventory.elements.iter().map(|e| e.key()).collect();
+            if mode == ImportMode::Replace {
+                keys.extend(self.element_map.keys().cloned());
+            }
+            self.refresh_keys(keys)?;
+            for category in &inventory.categories {
+                let category = self.database.find_category(&category.path)?;
+                self.refresh_category(category.id)?;
+            }
+            Ok(diff)
+        }
+
+        /**
+         * @description: 元件的标签, 写入文件用 label::write_labels
+         * @param {&str} model 元件型号, 有厂家时为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn element_label(&self, model: &str) -> BomResult<Label> {
+            let (model, manufacturer) = split_key(model);
+            self.database.element_label(model, manufacturer)
+        }
+
+        /**
+         * @description: 所有元件的标签, 按型号排序, 用于整页打印
+         * @return {*}
+         */
+        pub fn element_labels(&self) -> BomResult<Vec<Label>> {
+            self.database
+                .query_elements(&ElementQuery::new())?
+                .iter()
+                .map(|e| self.database.element_label(&e.model, &e.manufacturer))
+                .collect()
+        }
+
+        /**
+         * @description: 存放位置的标签
+         * @param {&str} path 位置路径, 例如 柜子A/抽屉3
+         * @return {*}
+         */
+        pub fn location_label(&self, path: &str) -> BomResult<Label> {
+            self.database.location_label(path)
+        }
+
+        /**
+         * @description: 解析扫码枪输入的标签内容或元件键, 找到对应的元件或位置
+         * @param {&str} text 扫描到的字符串
+         * @return {*}
+         */
+        pub fn scan(&self, text: &str) -> BomResult<Scanned> {
+            self.database.scan_label(text)
+        }
+    }
+
+    /**
+     * @description: 型号和厂家组成哈希表的键, 没有厂家时就是型号, 否则为 "型号@厂家"
+     * @param {&str} model 元件型号
+     * @param {&str} manufacturer 厂家
+     * @return {*}
+     */
+    pub fn element_key(model: &str, manufacturer: &str) -> String {
+        if manufacturer.is_empty() {
+            model.to_string()
+        } else {
+            format!("{model}@{manufacturer}")
+        }
+    }
+
+    /**
+     * @description: 把哈希表的键拆成型号和厂家, 与 Element::key 对应
+     * @param {&str} key 哈希表的键
+     * @return {(型号, 厂家)}
+     */
+    pub fn split_key(key: &str) -> (&str, &str) {
+        key.split_once('@').unwrap_or((key, ""))
+    }
+
+    /**
+     * @description: 检查型号, 型号中有 '@' 时键无法拆回型号和厂家, 返回 BomError::InvalidModel
+     * @param {&str} model 元件型号
+     * @return {*}
+     */
+    pub fn check_model(model: &str) -> BomResult<()> {
+        if model.contains('@') {
+            return Err(BomError::InvalidModel(model.to_string()));
+        }
+        Ok(())
+    }
+
+    /**
+     * @description: 默认操作人, 取系统用户名
+     * @return {*}
+     */
+    fn default_operator() -> String {
+        std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_default()
+    }
+
+    /**
+     * @name: open_or_create_data_file
+     * @msg: 打开或者创建sqlite文件
+     * @param {*} filepath
+     * @return {成功返回 Connection 对象, 需要解包; 失败返回错误信息}
+     * @author: TOTHTOT
+     * @Date: 2024-07-30 14:33:14
+     */
+    fn open_or_create_data_file(filepath: &str, tables: &str) -> BomResult<Connection> {
+        let mut file = Connection::open(filepath)?;
+        // 文件头正确但内容损坏时也不能继续使用
+        if let Err(err) = backup::integrity_check(&file, filepath) {
+            info_log!("数据库损坏: {err}");
+            return Err(err);
+        }
+        // 多个进程共用一个文件: 写锁被占用时等待一段时间, WAL 模式下读不阻塞写;
+        // 事务开始时就拿写锁, 避免读完再写时因为别人先写入而直接失败
+        file.busy_timeout(BUSY_TIMEOUT)?;
+        file.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+            row.get::<usize, String>(0)
+        })?;
+        file.set_transaction_behavior(TransactionBehavior::Immediate);
+        // 创建表头或把旧版本的文件升级到最新版本
+        if let Err(err) = migration::migrate(&file, tables) {
+            info_log!("升级数据库失败: {err}");
+            return Err(err);
+        }
+        Ok(file)
+    }
+
+    /**
+     * @name: check_datafile
+     * @msg: 检测文件是否为合法sql文件, 合法就返回Ok(_), 否则返回错误信息
+     * @param {*} filepath 文件地址
+     * @return {*}
+     * @author: TOTHTOT
+     * @Date: 2024-07-29 13:57:45
+     */
+    fn check_datafile(filepath: &str) -> BomResult<()> {
+        if fs::metadata(filepath).is_ok() {
+            info_log!("{filepath} 文件存在");
+            let mut data_file = match fs::File::open(filepath) {
+                Ok(file) => file,
+                Err(err) => {
+                    info_log!("{err}");
+                    return Err(err.into());
+                }
+            };
+
+            // 读取前16个字节判断数据库文件是否有效
+            let mut buffer = [0; 16];
+            if data_file.read_exact(&mut buffer).is_err() {
+                info_log!("{filepath} 文件无效");
+                return Err(BomError::CorruptFile(format!("{filepath} 文件无效")));
+            }
+
+            // SQLite 文件头的 magic number
+            let sqlite_magic_number: [u8; 16] = [
+                0x53, 0x51, 0x4c, 0x69, 0x74, 0x65, 0x20, 0x66, 0x6f, 0x72, 0x6d, 0x61, 0x74, 0x20,
+                0x33, 0x00,
+            ];
+
+            if buffer == sqlite_magic_number {
+                info_log!("{filepath} 是数据库文件");
+                Ok(())
+            } else {
+                info_log!("{filepath} 不是数据库文件");
+                Err(BomError::CorruptFile(format!("{filepath} 不是数据库文件")))
+            }
+        } else {
+            info_log!("{filepath} 文件不存在");
+            Err(BomError::Io(Error::from(ErrorKind::NotFound)))
+        }
+    }
+
+    /**
+     * @name: database_get_line
+     * @msg: 获取数据库中指定表中的行数
      * @param {*} conn sqlite数据库连接
      * @param {*} tables 表名
      * @return { 返回行数 }
@@ -565,118 +2095,2033 @@ pub mod bom_manage {
             0
         });
 
-        count
+        count
+    }
+
+    /**
+     * @name: test_write_to_database
+     * @msg: 测试函数, 写入测试数据到数据库中
+     * @param {*} conn 连接的sqlite 数据库
+     * @param {*} tables 表名
+     * @return {*}
+     * @author: TOTHTOT
+     * @Date: 2024-07-31 09:42:17
+     */
+    #[allow(dead_code)]
+    fn test_write_to_database(database: &DataBaseInfo) -> BomResult<()> {
+        let mut map: HashMap<String, Element> = HashMap::new();
+        let element = Element {
+            describe: "Component A".to_string(),
+            model: "R10K".to_string(),
+            manufacturer: String::new(),
+            number: 10,
+            element_type: ElementType::Resistor,
+            state: ElementStatus::ALOT,
+            category: None,
+            version: 0,
+        };
+        let element_2 = Element {
+            describe: "Component B".to_string(),
+            model: "C20uF".to_string(),
+            manufacturer: String::new(),
+            number: 20,
+            element_type: ElementType::Capacitor,
+            state: ElementStatus::ALOT,
+            category: None,
+            version: 0,
+        };
+        // 将实例写入哈希表
+        map.insert("component_a".to_string(), element);
+        map.insert("component_b".to_string(), element_2);
+
+        // 将哈希表写入数据库
+        database.write_hm_to_database(&map)?;
+
+        Ok(())
+    }
+
+    /**
+     * @name: test_read_from_database
+     * @msg: 测试从数据库中读取数据
+     * @param {*} database
+     * @return {*}
+     * @author: TOTHTOT
+     * @Date: 2024-07-31 14:31:01
+     */
+    #[allow(dead_code)]
+    fn test_read_from_database(database: &DataBaseInfo) -> BomResult<HashMap<String, Element>> {
+        // 从数据库中读取数据
+        let map: HashMap<String, Element> = match database.read_hm_from_database() {
+            Ok(map) => map,
+            Err(err) => {
+                info_log!("{err}");
+                return Err(err);
+            }
+        };
+
+        // 打印读取的数据
+        for (key, value) in &map {
+            info_log!("Key: {}, Value: {:#?}", key, value);
+        }
+        Ok(map)
+    }
+
+    // Element 结构体的方法
+    impl Element {
+        /**
+         * @description: 元件在哈希表中的键, 没有厂家时就是型号, 否则为 "型号@厂家"
+         * @return {*}
+         */
+        pub fn key(&self) -> String {
+            element_key(&self.model, &self.manufacturer)
+        }
+
+        /**
+         * @description: 写入数据库的分类 id, 没有指定分类时使用内置类型的分类
+         * @return {*}
+         */
+        pub fn category_id(&self) -> Option<i64> {
+            self.category.or_else(|| self.element_type.category_id())
+        }
+
+        /**
+         * @description: 从型号和描述中解析元件参数
+         * @return {*}
+         */
+        pub fn params(&self) -> ElementParams {
+            ElementParams::from_element(self)
+        }
+
+        /**
+         * @name: modify_describe
+         * @msg: 修改描述
+         * @param {*} mut self
+         * @param {String} describe 描述信息
+         * @return {void}
+         * @author: TOTHTOT
+         * @Date: 2024-07-29 10:22:18
+         */
+        pub fn modify_describe(&mut self, describe: String) -> &mut Self {
+            self.describe = describe;
+            self
+        }
+        /**
+         * @name: modify_number
+         * @msg: 修改元件数量, 同时根据数量更新状态
+         * @param {*} mut self
+         * @param {u32} number 数量
+         * @return {self}
+         * @author: TOTHTOT
+         * @Date: 2024-07-29 10:23:12
+         */
+        pub fn modify_number(&mut self, number: u32) -> &mut Self {
+            self.number = number;
+            if let Ok(state) = ElementStatus::from_number(number) {
+                self.state = state;
+            }
+            self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bom_manage::*;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::path::Path;
+
+    // 测试用的临时数据库文件, 每个测试使用不同的文件名避免互相干扰
+    fn temp_db_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("bom_manage_lib_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    fn test_element(model: &str, number: u32) -> Element {
+        Element {
+            describe: "电阻".to_string(),
+            model: model.to_string(),
+            manufacturer: String::new(),
+            number,
+            element_type: ElementType::Resistor,
+            state: ElementStatus::from_number(number).unwrap(),
+            category: None,
+            version: 0,
+        }
+    }
+
+    // 读取数据库中所有 (id, model, number), 按 id 排序
+    fn db_rows(ctrl: &BomManageCtrl) -> Vec<(i64, String, u32)> {
+        let mut stmt = ctrl
+            .database
+            .conn
+            .prepare("SELECT id, model, number FROM bom_data ORDER BY id")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn write_only_touches_changed_row() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("incremental");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 10))?;
+        ctrl.add_element(test_element("R20K", 20))?;
+        ctrl.add_element(test_element("R10K", 5))?;
+        ctrl.reduce_element("R20K".to_string(), 4)?;
+
+        // 行 id 不变说明没有删除重写整张表
+        assert_eq!(
+            db_rows(&ctrl),
+            vec![(1, "R10K".to_string(), 15), (2, "R20K".to_string(), 16)]
+        );
+
+        ctrl.del_element("R10K".to_string())?;
+        assert_eq!(db_rows(&ctrl), vec![(2, "R20K".to_string(), 16)]);
+
+        // 重新打开, 数据与哈希表一致
+        drop(ctrl);
+        let ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        assert_eq!(ctrl.element_map.len(), 1);
+        assert_eq!(ctrl.element_map["R20K"].number, 16);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_write_keeps_previous_data() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("rollback");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 10))?;
+        ctrl.add_element(test_element("R20K", 20))?;
+        let before = db_rows(&ctrl);
+
+        // 写入 BAD 时触发器报错, 模拟写到一半失败
+        ctrl.database.conn.execute_batch(
+            "CREATE TRIGGER fail_bad BEFORE INSERT ON bom_data WHEN NEW.model = 'BAD'
+             BEGIN SELECT RAISE(ABORT, 'injected failure'); END;
+             CREATE TRIGGER fail_update BEFORE UPDATE ON bom_data WHEN NEW.model = 'R10K'
+             BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+        )?;
+
+        // 整表写入: 删除已经执行, 插入到一半失败, 必须全部回滚
+        let mut map: HashMap<String, Element> = ctrl.element_map.clone();
+        map.insert("BAD".to_string(), test_element("BAD", 1));
+        assert!(ctrl.database.write_hm_to_database(&map).is_err());
+        assert_eq!(db_rows(&ctrl), before);
+
+        // 单个元件写入失败时哈希表和数据库都保持原值
+        assert!(ctrl.add_element(test_element("R10K", 5)).is_err());
+        assert_eq!(ctrl.element_map["R10K"].number, 10);
+        assert_eq!(db_rows(&ctrl), before);
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_models_are_merged_on_open() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("merge");
+        {
+            // 旧版本的表: 没有厂家列, 也没有唯一约束
+            let conn = rusqlite::Connection::open(&path)?;
+            conn.execute_batch(
+                "CREATE TABLE bom_data (id INTEGER PRIMARY KEY, describe TEXT NOT NULL, model TEXT NOT NULL, number INTEGER NOT NULL, element_type INTEGER NOT NULL, state INTEGER NOT NULL);
+                 INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 30, '电阻', '缺货');
+                 INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电容', 'C10uF', 5, '电容', '缺货');
+                 INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('电阻', 'R10K', 80, '电阻', '一般');",
+            )?;
+        }
+
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        assert_eq!(
+            db_rows(&ctrl),
+            vec![(1, "R10K".to_string(), 110), (2, "C10uF".to_string(), 5)]
+        );
+        assert!(matches!(
+            ctrl.element_map["R10K"].state,
+            ElementStatus::ALOT
+        ));
+
+        // 重复插入同型号会被唯一约束拒绝
+        assert!(ctrl
+            .database
+            .conn
+            .execute(
+                "INSERT INTO bom_data (describe, model, number, element_type, state) VALUES ('', 'R10K', 1, '', '')",
+                [],
+            )
+            .is_err());
+
+        // 同型号不同厂家是两个元件
+        let mut yageo = test_element("R10K", 7);
+        yageo.manufacturer = "Yageo".to_string();
+        ctrl.add_element(yageo.clone())?;
+        ctrl.add_element(yageo)?;
+        assert_eq!(ctrl.element_map["R10K"].number, 110);
+        assert_eq!(ctrl.element_map["R10K@Yageo"].number, 14);
+        Ok(())
+    }
+
+    #[test]
+    fn errors_are_typed() -> Result<(), Box<dyn Error>> {
+        assert!(matches!(
+            ElementType::from_string("连接器"),
+            Err(BomError::InvalidType(s)) if s == "连接器"
+        ));
+        assert!(matches!(
+            ElementStatus::from_string("很多"),
+            Err(BomError::InvalidStatus(_))
+        ));
+
+        let path = temp_db_path("errors");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        assert!(matches!(
+            ctrl.reduce_element("R1M".to_string(), 1),
+            Err(BomError::NotFound(model)) if model == "R1M"
+        ));
+        // 型号中有 '@' 时键和 "型号@厂家" 分不开, 不写入
+        assert!(matches!(
+            ctrl.add_element(test_element("R10K@0603", 1)),
+            Err(BomError::InvalidModel(model)) if model == "R10K@0603"
+        ));
+        assert!(matches!(
+            ctrl.add_element_at(test_element("R10K@0603", 1), "柜子A"),
+            Err(BomError::InvalidModel(_))
+        ));
+        assert!(ctrl.database.read_hm_from_database()?.is_empty());
+        assert!(matches!(
+            ctrl.database.find_location("柜子A"),
+            Err(BomError::NotFound(_))
+        ));
+
+        // 数据库错误保留 rusqlite::Error 作为 source
+        ctrl.database.conn.execute_batch("DROP TABLE bom_data")?;
+        let err = ctrl.add_element(test_element("R10K", 1)).unwrap_err();
+        assert!(matches!(err, BomError::Database(_)));
+        assert!(err
+            .source()
+            .is_some_and(|e| e.downcast_ref::<rusqlite::Error>().is_some()));
+
+        // 不是数据库的文件
+        let path = temp_db_path("corrupt");
+        std::fs::write(&path, b"this is not a sqlite database file")?;
+        assert!(matches!(
+            BomManageCtrl::new(&path, "bom_data"),
+            Err(BomError::CorruptFile(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reduce_checks_stock() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("reduce");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 120))?;
+        assert!(matches!(
+            ctrl.element_map["R10K"].state,
+            ElementStatus::ALOT
+        ));
+
+        // 状态随数量更新
+        ctrl.reduce_element("R10K".to_string(), 40)?;
+        assert_eq!(ctrl.element_map["R10K"].number, 80);
+        assert!(matches!(
+            ctrl.element_map["R10K"].state,
+            ElementStatus::NORMAL
+        ));
+
+        // 库存不足返回错误, 数量不变
+        assert!(matches!(
+            ctrl.reduce_element("R10K".to_string(), 81),
+            Err(BomError::InsufficientStock {
+                requested: 81,
+                available: 80,
+                ..
+            })
+        ));
+        assert_eq!(ctrl.element_map["R10K"].number, 80);
+        assert_eq!(db_rows(&ctrl)[0].2, 80);
+
+        // 刚好取完
+        ctrl.reduce_element("R10K".to_string(), 80)?;
+        assert_eq!(db_rows(&ctrl)[0].2, 0);
+        assert!(matches!(
+            ctrl.element_map["R10K"].state,
+            ElementStatus::Unknown
+        ));
+        // 取 0 个不报错
+        ctrl.reduce_element("R10K".to_string(), 0)?;
+
+        // 按库存取出, 报告缺少的数量
+        ctrl.add_element(test_element("R10K", 30))?;
+        let report = ctrl.reduce_element_available("R10K".to_string(), 50)?;
+        assert_eq!(
+            report,
+            ReduceReport {
+                taken: 30,
+                shortfall: 20,
+                remain: 0
+            }
+        );
+        let report = ctrl.reduce_element_available("R10K".to_string(), 5)?;
+        assert_eq!(report.taken, 0);
+        assert_eq!(report.shortfall, 5);
+
+        ctrl.add_element(test_element("R10K", 10))?;
+        let report = ctrl.reduce_element_available("R10K".to_string(), 4)?;
+        assert_eq!((report.taken, report.shortfall, report.remain), (4, 0, 6));
+        assert!(matches!(
+            ctrl.element_map["R10K"].state,
+            ElementStatus::SHORTAGE
+        ));
+
+        // 大数量不会下溢
+        assert!(ctrl.reduce_element("R10K".to_string(), u32::MAX).is_err());
+        assert_eq!(db_rows(&ctrl)[0].2, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn stock_changes_are_recorded_in_ledger() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("ledger");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.operator = "alice".to_string();
+        ctrl.add_element_for(test_element("R10K", 100), "采购")?;
+        ctrl.reduce_element_for("R10K".to_string(), 50, "项目A", false)?;
+        // 失败的出库不记录
+        assert!(ctrl.reduce_element("R10K".to_string(), 51).is_err());
+        ctrl.reduce_element_available("R10K".to_string(), 60)?;
+        ctrl.add_element(test_element("R10K", 8))?;
+        ctrl.del_element("R10K".to_string())?;
+
+        let history = ctrl.element_history("R10K")?;
+        let deltas: Vec<(i64, &str, &str)> = history
+            .iter()
+            .map(|m| (m.delta, m.reason.as_str(), m.operator.as_str()))
+            .collect();
+        assert_eq!(
+            deltas,
+            vec![
+                (100, "采购", "alice"),
+                (-50, "项目A", "alice"),
+                (-50, "", "alice"),
+                (8, "", "alice"),
+                (-8, ledger::REASON_REMOVE, "alice"),
+            ]
+        );
+        // 回放到现在等于当前数量 (已删除为 0)
+        assert_eq!(ctrl.quantity_at("R10K", ledger::now_timestamp())?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn quantity_is_replayed_at_past_time() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("replay");
+        let ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        let note = MovementNote {
+            reason: "导入".to_string(),
+            operator: "bob".to_string(),
+        };
+        for (timestamp, delta) in [(1000, 100), (2000, -30), (2000, -20), (3000, 5)] {
+            let mut movement = note.movement("C10uF", "Murata", delta);
+            movement.timestamp = timestamp;
+            ctrl.database.record_movement(&movement)?;
+        }
+        assert_eq!(ctrl.quantity_at("C10uF@Murata", 999)?, 0);
+        assert_eq!(ctrl.quantity_at("C10uF@Murata", 1000)?, 100);
+        assert_eq!(ctrl.quantity_at("C10uF@Murata", 2500)?, 50);
+        assert_eq!(ctrl.quantity_at("C10uF@Murata", 3000)?, 55);
+        // 不同厂家的流水互不影响
+        assert_eq!(ctrl.quantity_at("C10uF", 3000)?, 0);
+        assert_eq!(ctrl.element_history("C10uF@Murata")?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn query_capacitors_by_value_and_voltage() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("params");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        for (describe, model) in [
+            ("电容 25V 0805", "C10uF"),
+            ("电容 50V 1206", "C22uF"),
+            ("电容 16V 0805", "C47uF"),
+            ("电容 50V 0603", "C100nF"),
+            ("电阻 1% 0603", "4K7"),
+        ] {
+            let element_type = if model.starts_with('C') {
+                ElementType::Capacitor
+            } else {
+                ElementType::Resistor
+            };
+            ctrl.add_element(Element {
+                describe: describe.to_string(),
+                element_type,
+                ..test_element(model, 10)
+            })?;
+        }
+
+        // 容值 >= 10uF 且耐压 >= 25V 的电容
+        let filter = ParamFilter {
+            element_type: Some(ElementType::Capacitor),
+            min_value: Some(10e-6),
+            min_voltage: Some(25.0),
+            ..Default::default()
+        };
+        let models: Vec<String> = ctrl
+            .query_params(&filter)?
+            .into_iter()
+            .map(|e| e.model)
+            .collect();
+        assert_eq!(models, vec!["C10uF", "C22uF"]);
+
+        let filter = ParamFilter {
+            unit: Some(Unit::Ohm),
+            package: Some("0603".to_string()),
+            max_tolerance: Some(1.0),
+            ..Default::default()
+        };
+        let found = ctrl.query_params(&filter)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].params().to_string(), "4.7kΩ 1% 0603");
+        Ok(())
     }
 
-    /**
-     * @name: test_write_to_database
-     * @msg: 测试函数, 写入测试数据到数据库中
-     * @param {*} conn 连接的sqlite 数据库
-     * @param {*} tables 表名
-     * @return {*}
-     * @author: TOTHTOT
-     * @Date: 2024-07-31 09:42:17
-     */
-    #[allow(dead_code)]
-    fn test_write_to_database(database: &DataBaseInfo) -> Result<(), Box<dyn Error>> {
-        let mut map: HashMap<String, Element> = HashMap::new();
-        let element = Element {
-            describe: "Component A".to_string(),
-            model: "R10K".to_string(),
-            number: 10,
-            element_type: ElementType::Resistor,
-            state: ElementStatus::ALOT,
+    #[test]
+    fn elements_in_nested_categories() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("categories");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        let nmos = ctrl.add_category("半导体/MOSFET/N沟道")?;
+        let pmos = ctrl.add_category("半导体/MOSFET/P沟道")?;
+        ctrl.add_category("连接器/排针")?;
+        assert_eq!(
+            ctrl.database.find_category("半导体/MOSFET")?.id,
+            nmos.parent_id.unwrap()
+        );
+        // 路径中有空的名字时整条路径都不创建
+        assert!(matches!(
+            ctrl.add_category("电源/ /LDO"),
+            Err(BomError::InvalidCategory(_))
+        ));
+        assert!(matches!(
+            ctrl.find_category("电源"),
+            Err(BomError::NotFound(_))
+        ));
+
+        ctrl.add_element(Element {
+            category: Some(nmos.id),
+            element_type: ElementType::Unknown,
+            ..test_element("AO3400", 10)
+        })?;
+        ctrl.add_element(Element {
+            category: Some(pmos.id),
+            element_type: ElementType::Unknown,
+            ..test_element("AO3401", 10)
+        })?;
+        ctrl.add_element(test_element("R10K", 10))?;
+        ctrl.add_element(Element {
+            element_type: ElementType::Unknown,
+            ..test_element("X1", 10)
+        })?;
+
+        let models = |elements: Vec<Element>| -> Vec<String> {
+            elements.into_iter().map(|e| e.model).collect()
         };
-        let element_2 = Element {
-            describe: "Component B".to_string(),
-            model: "C20uF".to_string(),
-            number: 20,
+        assert_eq!(
+            models(ctrl.category_elements("半导体")?),
+            vec!["AO3400", "AO3401"]
+        );
+        assert_eq!(
+            models(ctrl.category_elements("半导体/MOSFET/P沟道")?),
+            vec!["AO3401"]
+        );
+        assert!(ctrl.category_elements("连接器/排针")?.is_empty());
+        // 内置类型的元件归入内置分类
+        assert_eq!(models(ctrl.category_elements("电阻")?), vec!["R10K"]);
+
+        // 重新打开后分类仍然保留
+        drop(ctrl);
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        let x1 = ctrl.element_map["X1"].clone();
+        assert_eq!(ctrl.category_path(&x1)?, None);
+        ctrl.set_category("X1", "连接器/排针")?;
+        let x1 = ctrl.element_map["X1"].clone();
+        assert_eq!(ctrl.category_path(&x1)?.as_deref(), Some("连接器/排针"));
+        let ao3400 = ctrl.element_map["AO3400"].clone();
+        assert_eq!(
+            ctrl.category_path(&ao3400)?.as_deref(),
+            Some("半导体/MOSFET/N沟道")
+        );
+        assert!(matches!(
+            ctrl.set_category("X1", "不存在"),
+            Err(BomError::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn status_uses_element_and_category_thresholds() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("thresholds");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        let mcu = ctrl.add_category("芯片/MCU")?;
+        ctrl.add_element(Element {
+            category: Some(mcu.id),
+            ..test_element("STM32F103", 50)
+        })?;
+        ctrl.add_element(test_element("R0402_10K", 50))?;
+        ctrl.add_element(test_element("R0402_1K", 5000))?;
+        // 默认阈值下 50 个都是缺货
+        assert_eq!(ctrl.element_map["STM32F103"].state, ElementStatus::SHORTAGE);
+
+        // 芯片 50 个就很多, 子分类 MCU 继承芯片的阈值
+        ctrl.set_category_thresholds("芯片", Some(Thresholds::new(2, 10)?))?;
+        assert_eq!(ctrl.element_map["STM32F103"].state, ElementStatus::ALOT);
+        assert_eq!(ctrl.thresholds("STM32F103")?, Thresholds::new(2, 10)?);
+        // 0402 电阻 1000 个才够
+        ctrl.set_category_thresholds("电阻", Some(Thresholds::new(1000, 2000)?))?;
+        assert_eq!(ctrl.element_map["R0402_10K"].state, ElementStatus::SHORTAGE);
+        // 元件自己的阈值优先
+        ctrl.set_thresholds("R0402_10K", Some(Thresholds::new(10, 20)?))?;
+        assert_eq!(ctrl.element_map["R0402_10K"].state, ElementStatus::ALOT);
+
+        // 取出后按阈值更新状态
+        ctrl.reduce_element("STM32F103".to_string(), 45)?;
+        assert_eq!(ctrl.element_map["STM32F103"].state, ElementStatus::NORMAL);
+        ctrl.reduce_element("R0402_1K".to_string(), 4000)?;
+        assert_eq!(ctrl.element_map["R0402_1K"].state, ElementStatus::SHORTAGE);
+        // 状态写入了数据库
+        let reopened = BomManageCtrl::new(&path, "bom_data")?;
+        assert_eq!(
+            reopened.element_map["R0402_1K"].state,
+            ElementStatus::SHORTAGE
+        );
+
+        let below: Vec<(String, u32)> = ctrl
+            .below_reorder_point()?
+            .into_iter()
+            .map(|(e, t)| (e.model, t.reorder_point - e.number))
+            .collect();
+        assert_eq!(
+            below,
+            vec![("R0402_1K".to_string(), 1000), ("STM32F103".to_string(), 5)]
+        );
+
+        // 换到阈值不同的分类, 状态跟着新分类变化并写入数据库
+        ctrl.add_category("传感器")?;
+        ctrl.set_category_thresholds("传感器", Some(Thresholds::new(10, 20)?))?;
+        ctrl.set_category("STM32F103", "传感器")?;
+        assert_eq!(ctrl.element_map["STM32F103"].state, ElementStatus::SHORTAGE);
+        let reopened = BomManageCtrl::new(&path, "bom_data")?;
+        assert_eq!(
+            reopened.element_map["STM32F103"].state,
+            ElementStatus::SHORTAGE
+        );
+        ctrl.set_category("STM32F103", "芯片/MCU")?;
+        assert_eq!(ctrl.element_map["STM32F103"].state, ElementStatus::NORMAL);
+
+        // 清除元件阈值后继承分类
+        ctrl.set_thresholds("R0402_10K", None)?;
+        assert_eq!(ctrl.element_map["R0402_10K"].state, ElementStatus::SHORTAGE);
+        Ok(())
+    }
+
+    #[test]
+    fn bom_report_counts_shortfall() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("bom_check");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(Element {
+            describe: "电阻 10k 0603 1%".to_string(),
+            ..test_element("RC0603FR-0710KL", 25)
+        })?;
+        ctrl.add_element(Element {
+            describe: "电阻 0805".to_string(),
+            ..test_element("R10K", 1000)
+        })?;
+        ctrl.add_element(Element {
+            describe: "稳压芯片 SOT-223".to_string(),
+            element_type: ElementType::Chip,
+            ..test_element("AMS1117-3.3", 1)
+        })?;
+
+        let bom = temp_db_path("bom_check_csv").replace(".db", ".csv");
+        std::fs::write(
+            &bom,
+            "Reference,Value,Footprint,Qty\n\
+             \"R1,R2,R3\",10k,Resistor_SMD:R_0603_1608Metric,3\n\
+             R4,10k,Resistor_SMD:R_0603_1608Metric,1\n\
+             U1,AMS1117-3.3,Package_TO_SOT_SMD:SOT-223-3_TabPin2,1\n\
+             J1,USB_C,Connector_USB:USB_C_Receptacle,1\n",
+        )?;
+        let report = ctrl.import_bom(&bom, &BomFormat::from_path(&bom), 5)?;
+        std::fs::remove_file(&bom)?;
+        let summary: Vec<(Option<String>, u32, u32, u32)> = report
+            .lines
+            .iter()
+            .map(|l| (l.element.clone(), l.required, l.on_hand, l.shortfall))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                // 封装不同的 R10K 不匹配, 同一元件的库存按行依次分配
+                (Some("RC0603FR-0710KL".to_string()), 15, 25, 0),
+                (Some("RC0603FR-0710KL".to_string()), 5, 25, 0),
+                (Some("AMS1117-3.3".to_string()), 5, 1, 4),
+                (None, 5, 0, 5),
+            ]
+        );
+        let unmatched: Vec<&str> = report.unmatched().map(|l| l.line.value.as_str()).collect();
+        assert_eq!(unmatched, vec!["USB_C"]);
+        assert!(!report.is_buildable());
+        assert!(ctrl
+            .check_bom(
+                &report.lines[0..2]
+                    .iter()
+                    .map(|l| l.line.clone())
+                    .collect::<Vec<_>>(),
+                1
+            )?
+            .is_buildable());
+        Ok(())
+    }
+
+    #[test]
+    fn bom_check_draws_from_substitutes() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("substitutes");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        let chip = |model: &str, manufacturer: &str, number: u32| Element {
+            describe: "LDO 3.3V".to_string(),
+            manufacturer: manufacturer.to_string(),
+            element_type: ElementType::Chip,
+            ..test_element(model, number)
+        };
+        let capacitor = |model: &str, describe: &str, number: u32| Element {
+            describe: describe.to_string(),
             element_type: ElementType::Capacitor,
-            state: ElementStatus::ALOT,
+            ..test_element(model, number)
         };
-        // 将实例写入哈希表
-        map.insert("component_a".to_string(), element);
-        map.insert("component_b".to_string(), element_2);
+        ctrl.add_element(chip("AMS1117-3.3", "", 2))?;
+        ctrl.add_element(chip("LM1117-3.3", "TI", 10))?;
+        ctrl.add_element(capacitor("CL10B104KB8NNNC", "电容 100nF 0603 10% 50V", 30))?;
+        ctrl.add_element(capacitor(
+            "CC0603KRX7R9BB104",
+            "电容 100nF 0603 10% 50V",
+            50,
+        ))?;
+        ctrl.add_element(capacitor(
+            "GRM188R71C104KA01D",
+            "电容 100nF 0603 10% 16V",
+            100,
+        ))?;
+        ctrl.add_alternate("LDO 3.3V", "AMS1117-3.3")?;
+        ctrl.add_alternate("LDO 3.3V", "LM1117-3.3@TI")?;
+        let groups = ctrl.part_groups()?;
+        assert_eq!(groups[0].members[1].priority, 1);
 
-        // 将哈希表写入数据库
-        database.write_hm_to_database(&map)?;
+        let lines = vec![
+            BomLine {
+                references: vec!["U1".to_string()],
+                value: "AMS1117-3.3".to_string(),
+                quantity: 1,
+                ..Default::default()
+            },
+            BomLine {
+                references: vec!["C1".to_string()],
+                value: "100nF 25V".to_string(),
+                footprint: "Capacitor_SMD:C_0603_1608Metric".to_string(),
+                quantity: 6,
+                ..Default::default()
+            },
+        ];
+        // 型号不够时用同组的替代料, 参数替代时耐压不够的不用, 库存多的先用
+        let report = ctrl.check_bom(&lines, 10)?;
+        assert!(report.is_buildable());
+        let allocations: Vec<Vec<(&str, MatchKind, u32)>> = report
+            .lines
+            .iter()
+            .map(|l| {
+                l.allocations
+                    .iter()
+                    .map(|a| (a.element.as_str(), a.matched_by, a.quantity))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            allocations,
+            vec![
+                vec![
+                    ("AMS1117-3.3", MatchKind::Model, 2),
+                    ("LM1117-3.3@TI", MatchKind::Alternate, 8),
+                ],
+                vec![
+                    ("CC0603KRX7R9BB104", MatchKind::Value, 50),
+                    ("CL10B104KB8NNNC", MatchKind::Value, 10),
+                ],
+            ]
+        );
+        let substitutions: Vec<&str> = report
+            .lines
+            .iter()
+            .flat_map(|l| l.substitutions())
+            .map(|a| a.element.as_str())
+            .collect();
+        assert_eq!(substitutions, vec!["LM1117-3.3@TI", "CL10B104KB8NNNC"]);
 
+        // 生产单预留实际使用的替代料
+        let (build, _) = ctrl.create_build("电源板", &lines, 10)?;
+        let items: Vec<(String, u32, u32)> = build
+            .items
+            .iter()
+            .map(|i| (i.key(), i.required, i.reserved))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                ("AMS1117-3.3".to_string(), 2, 2),
+                ("LM1117-3.3@TI".to_string(), 8, 8),
+                ("CC0603KRX7R9BB104".to_string(), 50, 50),
+                ("CL10B104KB8NNNC".to_string(), 10, 10),
+            ]
+        );
+        assert_eq!(ctrl.available("LM1117-3.3@TI")?, 2);
+        ctrl.cancel_build(build.id)?;
+
+        // 不使用替代料时缺料记在匹配到的元件上
+        ctrl.substitute_policy = SubstitutePolicy::exact();
+        let report = ctrl.check_bom(&lines, 10)?;
+        let short: Vec<u32> = report.lines.iter().map(|l| l.shortfall).collect();
+        assert_eq!(short, vec![8, 10]);
+        ctrl.remove_alternate("LDO 3.3V", "AMS1117-3.3")?;
+        ctrl.remove_alternate("LDO 3.3V", "LM1117-3.3@TI")?;
+        assert!(ctrl.part_groups()?.is_empty());
+        assert!(matches!(
+            ctrl.remove_alternate("LDO 3.3V", "AMS1117-3.3"),
+            Err(BomError::NotFound(_))
+        ));
         Ok(())
     }
 
-    /**
-     * @name: test_read_from_database
-     * @msg: 测试从数据库中读取数据
-     * @param {*} database
-     * @return {*}
-     * @author: TOTHTOT
-     * @Date: 2024-07-31 14:31:01
-     */
-    #[allow(dead_code)]
-    fn test_read_from_database(
-        database: &DataBaseInfo,
-    ) -> Result<HashMap<String, Element>, String> {
-        // 从数据库中读取数据
-        let map: HashMap<String, Element> = match database.read_hm_from_database() {
-            Ok(map) => map,
-            Err(err) => {
-                info_log!("{err}");
-                return Err(format!("Error reading from database: {}", err));
+    #[test]
+    fn purchase_orders_cover_reorder_and_builds() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("purchase");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 40))?;
+        ctrl.add_element(test_element("AMS1117", 20))?;
+        ctrl.add_element(test_element("C20uF", 500))?;
+        ctrl.add_element(test_element("X1", 10))?;
+        ctrl.set_thresholds("AMS1117", Some(Thresholds::new(2, 5)?))?;
+        let part =
+            |supplier: &str, sku: &str, model: &str, pack_size, moq, preferred| SupplierPart {
+                supplier: supplier.to_string(),
+                sku: sku.to_string(),
+                model: model.to_string(),
+                pack_size,
+                moq,
+                preferred,
+                ..Default::default()
+            };
+        let lcsc = ctrl.add_supplier_part(&part("LCSC", "C25804", "R10K", 100, 1, false))?;
+        ctrl.set_price_breaks(lcsc, 1_704_067_200, &[(100, 0.004)])?;
+        let mouser = ctrl.add_supplier_part(&part("Mouser", "603-R10K", "R10K", 1, 1, false))?;
+        ctrl.set_price_breaks(mouser, 1_704_067_200, &[(1, 0.1)])?;
+        let cheap = ctrl.add_supplier_part(&part("LCSC", "C6186", "AMS1117", 1, 1, false))?;
+        ctrl.set_price_breaks(cheap, 1_704_067_200, &[(1, 0.2)])?;
+        ctrl.add_supplier_part(&part("Mouser", "511-AMS1117", "AMS1117", 1, 10, true))?;
+
+        // 生产单缺 10 个 AMS1117, 预留的 20 个不算可用; 库中没有的 USB_C 也要采购
+        let lines = [
+            BomLine {
+                references: vec!["U1".to_string()],
+                value: "AMS1117".to_string(),
+                quantity: 30,
+                ..Default::default()
+            },
+            BomLine {
+                references: vec!["J1".to_string(), "J2".to_string()],
+                value: "USB_C".to_string(),
+                quantity: 2,
+                ..Default::default()
+            },
+        ];
+        let (build, _) = ctrl.create_build("电源板", &lines, 1)?;
+        assert_eq!(
+            build.items[1],
+            BuildItem {
+                model: "USB_C".to_string(),
+                manufacturer: String::new(),
+                required: 2,
+                reserved: 0,
+            }
+        );
+
+        // R10K 选报价低的 LCSC 并取整到包装数量, AMS1117 用首选料号, 没有供应商的放在最后
+        let drafts = ctrl.draft_purchase_orders()?;
+        let summary: Vec<(&str, String, u32, u32)> = drafts
+            .iter()
+            .flat_map(|o| {
+                o.lines
+                    .iter()
+                    .map(|l| (o.supplier.as_str(), l.key(), l.needed, l.quantity))
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("LCSC", "R10K".to_string(), 61, 100),
+                ("Mouser", "AMS1117".to_string(), 16, 16),
+                ("", "X1".to_string(), 91, 91),
+                ("", "USB_C".to_string(), 2, 2),
+            ]
+        );
+        assert_eq!(drafts[0].total(), 0.4);
+
+        // 保存后的数量算作在途, 不会重复采购
+        let orders = ctrl.create_purchase_orders()?;
+        assert_eq!(orders.len(), 3);
+        assert!(ctrl.draft_purchase_orders()?.is_empty());
+
+        let received = ctrl.receive_purchase_order(orders[0].id)?;
+        assert_eq!(received.supplier, "LCSC");
+        assert_eq!(ctrl.element_map["R10K"].number, 140);
+        let history = ctrl.element_history("R10K")?;
+        assert_eq!(
+            history.last().unwrap().reason,
+            format!("po:{}", orders[0].id)
+        );
+        assert!(matches!(
+            ctrl.receive_purchase_order(orders[0].id),
+            Err(BomError::OrderClosed { .. })
+        ));
+
+        // 取消后重新出现在草稿中
+        ctrl.cancel_purchase_order(orders[1].id)?;
+        let drafts = ctrl.draft_purchase_orders()?;
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].lines[0].model, "AMS1117");
+
+        // 库中没有的元件收货时新增, 包括下单后被删除的元件
+        ctrl.remove_element("X1")?;
+        ctrl.receive_purchase_order(orders[2].id)?;
+        assert_eq!(ctrl.element_map["X1"].number, 91);
+        assert_eq!(ctrl.element_map["USB_C"].number, 2);
+        assert_eq!(ctrl.element_history("USB_C")?.len(), 1);
+        let statuses: Vec<OrderStatus> = ctrl.purchase_orders()?.iter().map(|o| o.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                OrderStatus::Received,
+                OrderStatus::Cancelled,
+                OrderStatus::Received
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn inventory_report_from_stock_and_ledger() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("report");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 100))?;
+        ctrl.add_element(test_element("C20uF", 50))?;
+        ctrl.add_element(test_element("X1", 20))?;
+        ctrl.reduce_element_for("R10K".to_string(), 30, "电源板", false)?;
+        // 删除元件不算消耗
+        ctrl.remove_element("X1")?;
+        // C20uF 最后一次消耗在统计范围之前
+        let options = ReportOptions::last_days(30);
+        ctrl.database.record_movement(&StockMovement {
+            timestamp: options.from - 10,
+            model: "C20uF".to_string(),
+            delta: -5,
+            reason: "电源板".to_string(),
+            ..Default::default()
+        })?;
+
+        let report = ctrl.inventory_report(&options)?;
+        assert_eq!(
+            report.stock.total,
+            StockCount {
+                parts: 2,
+                quantity: 120
+            }
+        );
+        assert_eq!(report.stock.by_type[0].0, ElementType::Resistor);
+        assert_eq!(report.stock.by_type[0].1.parts, 2);
+        assert_eq!(report.value.unpriced.len(), 2);
+
+        let consumers: Vec<(&str, u64)> = report
+            .top_consumers
+            .iter()
+            .map(|c| (c.key.as_str(), c.quantity))
+            .collect();
+        assert_eq!(consumers, vec![("R10K", 30)]);
+        assert_eq!(report.slow_movers.len(), 1);
+        assert_eq!(report.slow_movers[0].key, "C20uF");
+        assert_eq!(report.slow_movers[0].last_used, Some(options.from - 10));
+        // 30 天用了 30 个, 剩下的 70 个还能用 70 天
+        assert_eq!(report.forecasts.len(), 1);
+        assert!((report.forecasts[0].days_left - 70.0).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn audit_adjusts_stock_by_variance() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("audit");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element_at(test_element("R10K", 60), "柜子A/抽屉1")?;
+        ctrl.add_element(test_element("R10K", 40))?;
+        ctrl.add_element_at(test_element("C20uF", 30), "柜子A/抽屉1")?;
+        ctrl.add_element(test_element("X1", 10))?;
+
+        // 只盘点放在抽屉1的数量
+        let audit = ctrl.start_location_audit("柜子A/抽屉1")?;
+        let expected: Vec<(String, u32)> =
+            audit.lines.iter().map(|l| (l.key(), l.expected)).collect();
+        assert_eq!(
+            expected,
+            vec![("C20uF".to_string(), 30), ("R10K".to_string(), 60)]
+        );
+
+        // 盘点期间的出库不会被覆盖
+        ctrl.reduce_element_for("R10K".to_string(), 10, "电源板", false)?;
+        ctrl.record_count(audit.id, "R10K", 55)?;
+        ctrl.record_count(audit.id, "C20uF", 30)?;
+        // 在抽屉1找到了没有记录位置的 X1
+        ctrl.record_count(audit.id, "X1", 4)?;
+        assert!(matches!(
+            ctrl.record_count(audit.id, "R99", 1),
+            Err(BomError::NotFound(_))
+        ));
+        let variances: Vec<(String, Option<i64>)> = ctrl
+            .audit(audit.id)?
+            .variances()
+            .iter()
+            .map(|l| (l.key(), l.variance()))
+            .collect();
+        assert_eq!(
+            variances,
+            vec![("R10K".to_string(), Some(-5)), ("X1".to_string(), Some(4))]
+        );
+
+        let applied = ctrl.apply_audit(audit.id)?;
+        assert_eq!(applied.status, AuditStatus::Applied);
+        assert_eq!(ctrl.element_map["R10K"].number, 85);
+        assert_eq!(ctrl.element_map["X1"].number, 14);
+        assert_eq!(ctrl.locations("R10K")?[0].quantity, 55);
+        assert_eq!(ctrl.locations("X1")?[0].quantity, 4);
+        let last = ctrl.element_history("R10K")?.pop().unwrap();
+        assert_eq!((last.delta, last.reason.as_str()), (-5, "audit"));
+        assert!(matches!(
+            ctrl.apply_audit(audit.id),
+            Err(BomError::AuditClosed { .. })
+        ));
+        assert!(matches!(
+            ctrl.cancel_audit(audit.id),
+            Err(BomError::AuditClosed { .. })
+        ));
+        assert_eq!(ctrl.database.element("R10K", "")?.number, 85);
+        assert!(matches!(
+            ctrl.record_count(audit.id, "R10K", 1),
+            Err(BomError::AuditClosed { .. })
+        ));
+
+        // 盘点调整不算消耗
+        let report = ctrl.inventory_report(&ReportOptions::last_days(1))?;
+        assert_eq!(report.top_consumers[0].quantity, 10);
+
+        // 按分类盘点比较总数量, 取消后库存不变
+        let audit = ctrl.start_category_audit("电阻")?;
+        assert_eq!(audit.lines.len(), 3);
+        ctrl.record_count(audit.id, "C20uF", 0)?;
+        ctrl.cancel_audit(audit.id)?;
+        assert!(matches!(
+            ctrl.apply_audit(audit.id),
+            Err(BomError::AuditClosed { .. })
+        ));
+        assert_eq!(ctrl.element("C20uF")?.number, 30);
+        let statuses: Vec<AuditStatus> = ctrl.audits()?.iter().map(|a| a.status).collect();
+        assert_eq!(statuses, vec![AuditStatus::Applied, AuditStatus::Cancelled]);
+        Ok(())
+    }
+
+    #[test]
+    fn attachments_are_content_addressed_and_collected() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("attachment");
+        let _ = std::fs::remove_dir_all(attachment::attachment_dir(&path));
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("AMS1117", 20))?;
+        ctrl.add_element(test_element("LM1117", 10))?;
+        let source =
+            std::env::temp_dir().join(format!("bom_manage_lib_ams1117_{}.pdf", std::process::id()));
+        std::fs::write(&source, b"%PDF-1.4 AMS1117")?;
+        let source = source.to_string_lossy().to_string();
+
+        // 两个元件使用同一份数据手册时只存一个文件, 重复添加返回已有的附件
+        let first = ctrl.add_attachment("AMS1117", &source, None)?;
+        assert_eq!(first.kind, AttachmentKind::Datasheet);
+        assert_eq!(ctrl.add_attachment("AMS1117", &source, None)?.id, first.id);
+        let second = ctrl.add_attachment("LM1117", &source, Some(AttachmentKind::Other))?;
+        assert_eq!(second.hash, first.hash);
+        assert_eq!(ctrl.attachments("AMS1117")?, vec![first.clone()]);
+        let stored = ctrl.attachment_path(first.id)?;
+        assert_eq!(std::fs::read(&stored)?, b"%PDF-1.4 AMS1117");
+        let dir = std::env::temp_dir().join(format!("bom_manage_lib_open_{}", std::process::id()));
+        let saved = ctrl.save_attachment(first.id, &dir.to_string_lossy())?;
+        assert_eq!(saved.file_name(), Path::new(&source).file_name());
+        let _ = std::fs::remove_dir_all(dir);
+        assert!(matches!(
+            ctrl.add_attachment("R99", &source, None),
+            Err(BomError::NotFound(_))
+        ));
+
+        let note = ctrl.add_note("AMS1117", "输入电压不要超过 15V")?;
+        assert_eq!(ctrl.notes("AMS1117")?, vec![note.clone()]);
+        ctrl.remove_note(note.id)?;
+        assert!(ctrl.notes("AMS1117")?.is_empty());
+        assert!(matches!(
+            ctrl.remove_note(note.id),
+            Err(BomError::NotFound(_))
+        ));
+
+        // 还有一个元件引用文件时不删除
+        ctrl.remove_attachment(first.id)?;
+        assert_eq!(ctrl.gc_attachments()?, AttachmentGc::default());
+        assert!(stored.is_file());
+
+        // 删除元件后附件记录一起删除, 文件在 gc 时删除
+        ctrl.add_note("LM1117", "停产")?;
+        ctrl.remove_element("LM1117")?;
+        let gc = ctrl.gc_attachments()?;
+        assert_eq!((gc.rows, gc.files, gc.bytes), (0, 1, 16));
+        assert!(!stored.exists());
+        assert!(matches!(
+            ctrl.attachment_path(second.id),
+            Err(BomError::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn builds_reserve_and_consume_stock() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("builds");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 100))?;
+        ctrl.add_element(test_element("AMS1117", 15))?;
+        let lines = vec![
+            BomLine {
+                references: vec!["R1".to_string(), "R2".to_string()],
+                value: "R10K".to_string(),
+                quantity: 2,
+                ..Default::default()
+            },
+            BomLine {
+                references: vec!["U1".to_string()],
+                value: "AMS1117".to_string(),
+                quantity: 1,
+                ..Default::default()
+            },
+        ];
+
+        // 两个并行的生产单不会重复占用库存
+        let (first, report) = ctrl.create_build("板子A 第1批", &lines, 10)?;
+        assert!(report.is_buildable());
+        assert_eq!(ctrl.available("R10K")?, 80);
+        assert_eq!(ctrl.available("AMS1117")?, 5);
+        let (second, report) = ctrl.create_build("板子A 第2批", &lines, 10)?;
+        assert!(!report.is_buildable());
+        assert_eq!(report.lines[1].reserved, 10);
+        assert_eq!(report.lines[1].shortfall, 5);
+        assert_eq!(
+            second.items[1],
+            BuildItem {
+                model: "AMS1117".to_string(),
+                manufacturer: String::new(),
+                required: 10,
+                reserved: 5,
             }
+        );
+        assert_eq!(ctrl.available("AMS1117")?, 0);
+        // 预留不改变库存数量
+        assert_eq!(ctrl.element_map["AMS1117"].number, 15);
+
+        // 取消释放预留
+        ctrl.cancel_build(second.id)?;
+        assert_eq!(ctrl.available("AMS1117")?, 5);
+        assert!(matches!(
+            ctrl.cancel_build(second.id),
+            Err(BomError::BuildClosed { .. })
+        ));
+
+        // 完成扣减库存并记流水
+        ctrl.commit_build(first.id)?;
+        assert_eq!(ctrl.element_map["R10K"].number, 80);
+        assert_eq!(ctrl.available("AMS1117")?, 5);
+        let history = ctrl.element_history("AMS1117")?;
+        assert_eq!(history.last().unwrap().delta, -10);
+        assert_eq!(history.last().unwrap().reason, "build:板子A 第1批");
+        assert!(matches!(
+            ctrl.commit_build(first.id),
+            Err(BomError::BuildClosed { .. })
+        ));
+        let statuses: Vec<BuildStatus> = ctrl.database.builds()?.iter().map(|b| b.status).collect();
+        assert_eq!(
+            statuses,
+            vec![BuildStatus::Committed, BuildStatus::Cancelled]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn commit_build_is_all_or_nothing() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("build_atomic");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 100))?;
+        ctrl.add_element(test_element("AMS1117", 15))?;
+        let lines = vec![
+            BomLine {
+                references: vec!["R1".to_string()],
+                value: "R10K".to_string(),
+                quantity: 1,
+                ..Default::default()
+            },
+            BomLine {
+                references: vec!["U1".to_string()],
+                value: "AMS1117".to_string(),
+                quantity: 1,
+                ..Default::default()
+            },
+        ];
+        let (build, _) = ctrl.create_build("板子B", &lines, 10)?;
+
+        // 另一个连接在预留之后取走了库存, 完成时第二个元件不足, R10K 的扣减也要回滚
+        let other = BomManageCtrl::new(&path, "bom_data")?;
+        other
+            .database
+            .reduce_element_number("AMS1117", "", 10, false, &MovementNote::default())?;
+        assert!(matches!(
+            ctrl.commit_build(build.id),
+            Err(BomError::InsufficientStock { .. })
+        ));
+        assert_eq!(ctrl.database.element("R10K", "")?.number, 100);
+        assert_eq!(ctrl.element_history("R10K")?.len(), 1);
+        assert_eq!(ctrl.database.build(build.id)?.status, BuildStatus::Reserved);
+
+        // 补货后完成, 再完成一次不会重复扣减
+        ctrl.add_element(test_element("AMS1117", 10))?;
+        ctrl.commit_build(build.id)?;
+        assert!(matches!(
+            ctrl.commit_build(build.id),
+            Err(BomError::BuildClosed { .. })
+        ));
+        assert_eq!(ctrl.database.element("R10K", "")?.number, 90);
+        assert_eq!(ctrl.database.element("AMS1117", "")?.number, 5);
+        assert_eq!(ctrl.element_map["AMS1117"].number, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn stock_split_across_locations() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("locations");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element_at(test_element("R10K", 100), "柜子A/抽屉3/格子12")?;
+        ctrl.add_element(test_element("R10K", 50))?;
+        assert_eq!(ctrl.unplaced("R10K")?, 50);
+
+        // 未放置的库存放到另一个格子, 再在格子之间移动
+        ctrl.move_stock("R10K", None, Some("柜子A/抽屉3/格子13"), 30)?;
+        ctrl.move_stock("R10K", Some("柜子A/抽屉3/格子12"), Some("柜子B/抽屉1"), 40)?;
+        let located = |ctrl: &BomManageCtrl| -> Vec<(String, u32)> {
+            ctrl.locations("R10K")
+                .unwrap()
+                .into_iter()
+                .map(|l| (l.path, l.quantity))
+                .collect()
         };
+        assert_eq!(
+            located(&ctrl),
+            vec![
+                ("柜子A/抽屉3/格子12".to_string(), 60),
+                ("柜子A/抽屉3/格子13".to_string(), 30),
+                ("柜子B/抽屉1".to_string(), 40),
+            ]
+        );
+        assert_eq!(ctrl.unplaced("R10K")?, 20);
+        // 其他进程修改的数量也算在内
+        let mut other = BomManageCtrl::new(&path, "bom_data")?;
+        other.add_element(test_element("R10K", 5))?;
+        assert_eq!(ctrl.unplaced("R10K")?, 25);
+        other.reduce_element("R10K".to_string(), 5)?;
+        assert_eq!(ctrl.unplaced("R10K")?, 20);
+        assert!(matches!(
+            ctrl.move_stock("R10K", Some("柜子B/抽屉1"), None, 41),
+            Err(BomError::InsufficientStock { available: 40, .. })
+        ));
+        assert!(matches!(
+            ctrl.move_stock("R10K", Some("柜子C"), None, 1),
+            Err(BomError::NotFound(_))
+        ));
+        // 库存不够或位置名无效时不创建位置, 也不增加库存
+        assert!(matches!(
+            ctrl.move_stock("R10K", None, Some("柜子D/抽屉1"), 21),
+            Err(BomError::InsufficientStock { available: 20, .. })
+        ));
+        assert!(matches!(
+            ctrl.database.find_location("柜子D"),
+            Err(BomError::NotFound(_))
+        ));
+        assert!(matches!(
+            ctrl.add_element_at(test_element("R10K", 10), "柜子D/ "),
+            Err(BomError::InvalidLocation(_))
+        ));
+        assert!(matches!(
+            ctrl.database.find_location("柜子D"),
+            Err(BomError::NotFound(_))
+        ));
+        assert_eq!(ctrl.database.element("R10K", "")?.number, 150);
+        assert_eq!(ctrl.element_map["R10K"].number, 150);
 
-        // 打印读取的数据
-        for (key, value) in &map {
-            info_log!("Key: {}, Value: {:#?}", key, value);
+        // 取出后位置上的数量不超过库存, 先扣未放置的, 再从数量少的位置扣
+        ctrl.reduce_element("R10K".to_string(), 60)?;
+        assert_eq!(
+            located(&ctrl),
+            vec![
+                ("柜子A/抽屉3/格子12".to_string(), 60),
+                ("柜子B/抽屉1".to_string(), 30),
+            ]
+        );
+        ctrl.remove_element("R10K")?;
+        assert!(ctrl.locations("R10K")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn inventory_and_bom_costs() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("suppliers");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 1000))?;
+        ctrl.add_element(test_element("R1K", 10))?;
+        let lcsc = ctrl.add_supplier_part(&SupplierPart {
+            supplier: "LCSC".to_string(),
+            sku: "C25804".to_string(),
+            model: "R10K".to_string(),
+            pack_size: 100,
+            currency: "CNY".to_string(),
+            ..Default::default()
+        })?;
+        let taobao = ctrl.add_supplier_part(&SupplierPart {
+            supplier: "淘宝".to_string(),
+            sku: "R10K-0603".to_string(),
+            model: "R10K".to_string(),
+            currency: "CNY".to_string(),
+            ..Default::default()
+        })?;
+        assert!(matches!(
+            ctrl.add_supplier_part(&SupplierPart {
+                model: "R2K".to_string(),
+                ..Default::default()
+            }),
+            Err(BomError::NotFound(_))
+        ));
+        let (jan, jun) = (1_704_067_200, 1_717_200_000);
+        ctrl.set_price_breaks(lcsc, jan, &[(100, 0.004), (1000, 0.002)])?;
+        ctrl.set_price_breaks(lcsc, jun, &[(100, 0.006), (1000, 0.003)])?;
+        ctrl.set_price_breaks(taobao, jan, &[(1, 0.005)])?;
+
+        // 历史价格取当时最近一次报价, 多个供应商取最低价
+        let quote = |qty, at| ctrl.best_quote("R10K", qty, Some(at)).unwrap().unwrap();
+        assert_eq!(quote(1000, jan + 1).unit_price, 0.002);
+        assert_eq!(quote(100, jan + 1).part.supplier, "LCSC");
+        assert_eq!(quote(100, jun).part.supplier, "淘宝");
+        assert!(ctrl.best_quote("R10K", 100, Some(jan - 1))?.is_none());
+
+        let value = ctrl.inventory_value(Some(jun))?;
+        assert_eq!(value.lines.len(), 1);
+        assert!((value.total - 3.0).abs() < 1e-9);
+        assert_eq!(value.unpriced, vec!["R1K".to_string()]);
+
+        // BOM 中同一元件的多行合并后取价格档
+        let line = |value: &str, quantity| BomLine {
+            references: Vec::new(),
+            value: value.to_string(),
+            footprint: String::new(),
+            model: String::new(),
+            quantity,
+        };
+        let report = ctrl.check_bom(&[line("R10K", 3), line("R10K", 2), line("U1", 1)], 100)?;
+        let cost = ctrl.cost_bom(&report, Some(jan))?;
+        assert_eq!(cost.lines[0].quantity, 500);
+        assert!((cost.total - 2.0).abs() < 1e-9);
+        assert_eq!(cost.currency, "CNY");
+        assert_eq!(cost.unpriced, vec!["U1".to_string()]);
+
+        // 不同货币的报价不比较也不相加
+        let mouser = ctrl.add_supplier_part(&SupplierPart {
+            supplier: "Mouser".to_string(),
+            sku: "603-RC0603FR-0710KL".to_string(),
+            model: "R10K".to_string(),
+            currency: "USD".to_string(),
+            ..Default::default()
+        })?;
+        ctrl.set_price_breaks(mouser, jan, &[(1, 0.001)])?;
+        assert!(matches!(
+            ctrl.best_quote("R10K", 100, Some(jun)),
+            Err(BomError::MixedCurrency { .. })
+        ));
+        assert!(matches!(
+            ctrl.inventory_value(Some(jun)),
+            Err(BomError::MixedCurrency { .. })
+        ));
+        let mut costing = Costing::default();
+        let line = |key: &str, currency: &str| CostLine {
+            key: key.to_string(),
+            quantity: 1,
+            unit_price: 1.0,
+            supplier: String::new(),
+            currency: currency.to_string(),
+        };
+        costing.push(line("R10K", "CNY"))?;
+        assert!(matches!(
+            costing.push(line("R1K", "USD")),
+            Err(BomError::MixedCurrency { .. })
+        ));
+        assert_eq!((costing.lines.len(), costing.total), (1, 1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn search_by_text_and_typos() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("search");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(Element {
+            describe: "电阻 10k 0603 1%".to_string(),
+            ..test_element("R10K", 100)
+        })?;
+        ctrl.add_element(Element {
+            describe: "电阻 1k 0603 1%".to_string(),
+            ..test_element("R1K", 100)
+        })?;
+        ctrl.add_element(Element {
+            describe: "LDO 3.3V SOT-223".to_string(),
+            element_type: ElementType::Chip,
+            ..test_element("AMS1117-3.3", 20)
+        })?;
+        let keys = |hits: Vec<SearchHit>| -> Vec<String> {
+            hits.into_iter().map(|h| h.element.key()).collect()
+        };
+
+        assert_eq!(keys(ctrl.search("r10k", 10)?), vec!["R10K"]);
+        assert_eq!(keys(ctrl.search("10k resistor", 10)?), vec!["R10K"]);
+        assert_eq!(keys(ctrl.search("电阻 0603", 10)?).len(), 2);
+        assert_eq!(keys(ctrl.search("ams", 10)?), vec!["AMS1117-3.3"]);
+
+        // 拼写错误时模糊匹配
+        let hits = ctrl.search("10k resistr", 10)?;
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].fuzzy);
+        assert_eq!(keys(ctrl.search("AMS1171", 10)?), vec!["AMS1117-3.3"]);
+
+        // 分类和厂家料号修改后索引同步更新
+        ctrl.add_category("电源/LDO")?;
+        ctrl.set_category("AMS1117-3.3", "电源/LDO")?;
+        assert_eq!(keys(ctrl.search("电源", 10)?), vec!["AMS1117-3.3"]);
+        ctrl.add_supplier_part(&SupplierPart {
+            supplier: "LCSC".to_string(),
+            sku: "C25804".to_string(),
+            model: "R10K".to_string(),
+            mpn: "0603WAF1002T5E".to_string(),
+            ..Default::default()
+        })?;
+        assert_eq!(keys(ctrl.search("0603waf", 10)?), vec!["R10K"]);
+        assert_eq!(keys(ctrl.search("c25804", 10)?), vec!["R10K"]);
+        ctrl.remove_element("R10K")?;
+        // 删除后只剩模糊命中的 R1K
+        let hits = ctrl.search("r10k", 10)?;
+        assert!(hits.iter().all(|h| h.fuzzy));
+        assert_eq!(keys(hits), vec!["R1K"]);
+        assert!(ctrl.search("  ", 10)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn query_filters_sorts_and_pages() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("query");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element_at(
+            Element {
+                describe: "电阻 10k 0603".to_string(),
+                ..test_element("R10K", 100)
+            },
+            "柜子A/抽屉1",
+        )?;
+        ctrl.add_element(Element {
+            describe: "电阻 1k 0603".to_string(),
+            ..test_element("R1K", 10)
+        })?;
+        ctrl.add_element_at(
+            Element {
+                describe: "电容 100nF 0603".to_string(),
+                element_type: ElementType::Capacitor,
+                ..test_element("C100N", 500)
+            },
+            "柜子A/抽屉2",
+        )?;
+        ctrl.add_element_at(
+            Element {
+                describe: "LDO 3.3V".to_string(),
+                element_type: ElementType::Chip,
+                ..test_element("AMS1117-3.3", 20)
+            },
+            "柜子B",
+        )?;
+        let models = |query: ElementQuery| -> Vec<String> {
+            ctrl.query(&query)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.model)
+                .collect()
+        };
+
+        assert_eq!(
+            models(ElementQuery::new()),
+            vec!["AMS1117-3.3", "C100N", "R10K", "R1K"]
+        );
+        assert_eq!(
+            models(ElementQuery::new().element_type(ElementType::Resistor)),
+            vec!["R10K", "R1K"]
+        );
+        assert_eq!(
+            models(ElementQuery::new().status(ElementStatus::SHORTAGE)),
+            vec!["AMS1117-3.3", "R1K"]
+        );
+        assert_eq!(
+            models(ElementQuery::new().number_range(Some(20), Some(100))),
+            vec!["AMS1117-3.3", "R10K"]
+        );
+        assert_eq!(
+            models(ElementQuery::new().location("柜子A")),
+            vec!["C100N", "R10K"]
+        );
+        assert_eq!(
+            models(
+                ElementQuery::new()
+                    .text("0603")
+                    .element_type(ElementType::Resistor)
+            ),
+            vec!["R10K", "R1K"]
+        );
+        assert!(matches!(
+            ctrl.query(&ElementQuery::new().location("柜子C")),
+            Err(BomError::NotFound(_))
+        ));
+
+        // 排序和分页
+        let by_number = ElementQuery::new().sort_by(SortField::Number, SortOrder::Desc);
+        assert_eq!(
+            models(by_number.clone()),
+            vec!["C100N", "R10K", "AMS1117-3.3", "R1K"]
+        );
+        assert_eq!(
+            models(by_number.clone().limit(2).offset(1)),
+            vec!["R10K", "AMS1117-3.3"]
+        );
+        assert_eq!(models(by_number.clone().offset(3)), vec!["R1K"]);
+        assert_eq!(ctrl.count(&by_number.limit(1))?, 4);
+        assert_eq!(
+            models(ElementQuery::new().sort_by(SortField::Status, SortOrder::Asc)),
+            vec!["AMS1117-3.3", "R1K", "R10K", "C100N"]
+        );
+
+        assert_eq!(ctrl.element("C100N")?.number, 500);
+        assert!(matches!(ctrl.element("C1U"), Err(BomError::NotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn export_import_round_trip() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("export_src");
+        let mut src = BomManageCtrl::new(&path, "bom_data")?;
+        src.operator = "alice".to_string();
+        src.add_category("半导体/LDO")?;
+        src.set_category_thresholds("半导体", Some(Thresholds::new(5, 10)?))?;
+        src.add_element_at(
+            Element {
+                describe: "电阻 10k 0603".to_string(),
+                ..test_element("R10K", 100)
+            },
+            "柜子A/抽屉3",
+        )?;
+        src.add_element(Element {
+            describe: "LDO 3.3V".to_string(),
+            element_type: ElementType::Chip,
+            ..test_element("AMS1117-3.3", 20)
+        })?;
+        src.set_category("AMS1117-3.3", "半导体/LDO")?;
+        src.set_thresholds("R10K", Some(Thresholds::new(20, 200)?))?;
+        src.reduce_element_for("R10K".to_string(), 30, "project-a", false)?;
+        let part = src.add_supplier_part(&SupplierPart {
+            supplier: "LCSC".to_string(),
+            sku: "C25804".to_string(),
+            model: "R10K".to_string(),
+            mpn: "0603WAF1002T5E".to_string(),
+            pack_size: 100,
+            ..Default::default()
+        })?;
+        src.set_price_breaks(part, 1_704_067_200, &[(100, 0.004), (1000, 0.002)])?;
+        src.add_alternate("LDO 3.3V", "AMS1117-3.3")?;
+        src.add_alternate("LDO 3.3V", "LM1117-3.3@TI")?;
+        // 单据/附件/备注和没有元件的位置也要导出
+        src.add_location("柜子B/空抽屉")?;
+        let bom = vec![BomLine {
+            references: vec!["R1".to_string(), "R2".to_string()],
+            value: "R10K".to_string(),
+            quantity: 2,
+            ..Default::default()
+        }];
+        src.create_build("板子A", &bom, 5)?;
+        src.create_purchase_orders()?;
+        let audit = src.start_location_audit("柜子A/抽屉3")?;
+        src.record_count(audit.id, "R10K", 69)?;
+        let datasheet =
+            std::env::temp_dir().join(format!("bom_manage_r10k_{}.pdf", std::process::id()));
+        std::fs::write(&datasheet, b"%PDF r10k")?;
+        let attachment = src.add_attachment("R10K", &datasheet.to_string_lossy(), None)?;
+        src.add_note("R10K", "换了新卷盘")?;
+        let expected = src.database.export_inventory()?;
+        assert_eq!(expected.elements.len(), 2);
+        assert_eq!(expected.movements.len(), 3);
+        assert!(expected.locations.contains(&"柜子B/空抽屉".to_string()));
+        assert_eq!(expected.builds.len(), 1);
+        assert_eq!(expected.purchase_orders.len(), 1);
+        assert_eq!(expected.audits.len(), 1);
+        assert_eq!(expected.attachments.len(), 1);
+        assert_eq!(expected.notes.len(), 1);
+        assert_eq!(expected.part_groups[0].members.len(), 2);
+
+        // 每种格式导出后替换导入到新数据库, 内容和原来一致
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        for name in ["json", "yaml", "csv"] {
+            let file = dir
+                .join(format!("bom_manage_export_{pid}.{name}"))
+                .to_string_lossy()
+                .to_string();
+            let format = match name {
+                "csv" => ExportFormat::Csv,
+                _ => ExportFormat::from_path(&file),
+            };
+            src.export(&file, format)?;
+            let mut dst = BomManageCtrl::new(&temp_db_path(name), "bom_data")?;
+            let diff = dst.import(&file, format, ImportMode::Replace, true)?;
+            assert_eq!(diff.added, vec!["AMS1117-3.3", "R10K"]);
+            assert_eq!(dst.count(&ElementQuery::new())?, 0);
+            dst.import(&file, format, ImportMode::Replace, false)?;
+            let mut imported = dst.database.export_inventory()?;
+            for (a, b) in imported.movements.iter_mut().zip(expected.movements.iter()) {
+                a.id = b.id;
+            }
+            assert_eq!(imported, expected, "{name}");
+            assert_eq!(
+                std::fs::read(dst.attachment_path(attachment.id)?)?,
+                b"%PDF r10k"
+            );
+            assert_eq!(dst.available("R10K")?, 60);
+            assert_eq!(dst.thresholds("AMS1117-3.3")?, Thresholds::new(5, 10)?);
+            assert_eq!(dst.element("R10K")?.state, ElementStatus::NORMAL);
+            assert_eq!(dst.search("0603waf", 5)?.len(), 1);
         }
-        return Ok(map);
+
+        // 表格中修改数量后合并导入, 差额补记一条流水
+        let file = dir
+            .join(format!("bom_manage_export_{pid}_merge"))
+            .to_string_lossy()
+            .to_string();
+        src.export(&file, ExportFormat::Csv)?;
+        let elements = std::path::Path::new(&file).join("elements.csv");
+        let text = std::fs::read_to_string(&elements)?.replace(",70,", ",90,");
+        std::fs::write(&elements, text)?;
+        src.add_element(test_element("R1K", 5))?;
+        let diff = src.import(&file, ExportFormat::Csv, ImportMode::Merge, true)?;
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].key, "R10K");
+        assert_eq!(diff.changed[0].fields, vec!["number"]);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.movements, 0);
+        assert!(diff.removed.is_empty());
+        assert_eq!((diff.builds, diff.audits, diff.notes), (0, 0, 0));
+        src.import(&file, ExportFormat::Csv, ImportMode::Merge, false)?;
+        assert_eq!(src.element("R10K")?.number, 90);
+        assert_eq!(src.element("R1K")?.number, 5);
+        let last = src.element_history("R10K")?.pop().unwrap();
+        assert_eq!((last.delta, last.reason.as_str()), (20, "import"));
+
+        // 替换导入时文件中没有的元件被删除
+        let diff = src.import(&file, ExportFormat::Csv, ImportMode::Replace, true)?;
+        assert_eq!(diff.removed, vec!["R1K"]);
+        Ok(())
     }
 
-    // Element 结构体的方法
-    impl Element {
-        /**
-         * @name: modify_describe
-         * @msg: 修改描述
-         * @param {*} mut self
-         * @param {String} describe 描述信息
-         * @return {void}
-         * @author: TOTHTOT
-         * @Date: 2024-07-29 10:22:18
-         */
-        pub fn modify_describe(&mut self, describe: String) -> &mut Self {
-            self.describe = describe;
-            self
+    #[test]
+    fn two_controllers_share_one_file() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("shared");
+        let mut a = BomManageCtrl::new(&path, "bom_data")?;
+        let mut b = BomManageCtrl::new(&path, "bom_data")?;
+
+        // b 打开时还没有 R10K, 也能看到并修改 a 写入的元件, 数量不会互相覆盖
+        a.add_element(test_element("R10K", 10))?;
+        b.add_element(test_element("R10K", 5))?;
+        b.reduce_element("R10K".to_string(), 3)?;
+        a.add_element(test_element("R10K", 1))?;
+        assert_eq!(a.element("R10K")?.number, 13);
+        assert_eq!(b.element("R10K")?.number, 13);
+        assert_eq!(a.quantity_at("R10K", i64::MAX)?, 13);
+
+        // 两边读取同一个版本, 先写入的成功, 后写入的返回冲突而不是覆盖
+        let mut from_a = a.element("R10K")?;
+        let mut from_b = b.element("R10K")?;
+        from_a.describe = "贴片电阻".to_string();
+        from_b.describe = "插件电阻".to_string();
+        a.update_element(&from_a)?;
+        match b.update_element(&from_b) {
+            Err(BomError::Conflict {
+                model,
+                expected,
+                found,
+            }) => {
+                assert_eq!(model, "R10K");
+                assert_eq!(expected, from_b.version);
+                assert!(found > expected);
+            }
+            other => panic!("expected conflict, got {other:?}"),
         }
-        /**
-         * @name: modify_number
-         * @msg: 修改元件数量
-         * @param {*} mut self
-         * @param {u32} number 数量
-         * @return {self}
-         * @author: TOTHTOT
-         * @Date: 2024-07-29 10:23:12
-         */
-        pub fn modify_number(&mut self, number: u32) -> &mut Self {
-            self.number = number;
-            self
+        assert_eq!(b.element("R10K")?.describe, "贴片电阻");
+
+        // 数量变化也会让旧的副本失效, 重新读取后可以写入
+        a.add_element(test_element("R10K", 1))?;
+        assert!(matches!(
+            b.database.write_element(&from_b),
+            Err(BomError::Conflict { .. })
+        ));
+        let mut again = b.element("R10K")?;
+        again.describe = "插件电阻".to_string();
+        b.update_element(&again)?;
+        assert_eq!(a.element("R10K")?.describe, "插件电阻");
+        assert_eq!(a.element("R10K")?.number, 14);
+
+        // 整表写回旧的哈希表同样被拒绝, 数据库不变
+        assert!(matches!(
+            a.database.write_hm_to_database(&a.element_map),
+            Err(BomError::Conflict { .. })
+        ));
+        assert_eq!(b.element("R10K")?.describe, "插件电阻");
+
+        // 被另一边删除后不能再修改
+        b.remove_element("R10K")?;
+        assert!(matches!(
+            a.reduce_element("R10K".to_string(), 1),
+            Err(BomError::NotFound(_))
+        ));
+        assert!(matches!(
+            a.update_element(&again),
+            Err(BomError::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_writers_do_not_lose_updates() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("concurrent");
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 0))?;
+
+        // 每个线程各自打开一个连接, 和多个进程一样只通过文件共享数据
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || -> BomResult<()> {
+                    let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+                    for _ in 0..25 {
+                        ctrl.add_element(test_element("R10K", 2))?;
+                        ctrl.reduce_element("R10K".to_string(), 1)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().expect("writer panicked")?;
         }
+        assert_eq!(ctrl.element("R10K")?.number, 100);
+        assert_eq!(ctrl.element_history("R10K")?.len(), 200);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bom_manage::*;
-    use std::error::Error;
+    #[test]
+    fn labels_resolve_back_to_elements() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("labels");
+        let mut bom = BomManageCtrl::new(&path, "bom_data")?;
+        bom.add_element_at(
+            Element {
+                describe: "电阻 10k 1% 0603".to_string(),
+                manufacturer: "Yageo".to_string(),
+                ..test_element("R10K", 100)
+            },
+            "柜子A/抽屉3",
+        )?;
+        bom.add_element(test_element("R1K", 5))?;
+
+        let label = bom.element_label("R10K@Yageo")?;
+        assert_eq!(label.title, "R10K@Yageo");
+        assert_eq!(label.lines, vec!["10kΩ 1% 0603", "柜子A/抽屉3"]);
+        let id = match &label.code {
+            LabelCode::Element { id, .. } => *id,
+            code => panic!("{code:?}"),
+        };
+        let element = |scanned: Scanned| match scanned {
+            Scanned::Element(element) => element.key(),
+            scanned => panic!("{scanned:?}"),
+        };
+        assert_eq!(element(bom.scan(&label.code.to_string())?), "R10K@Yageo");
+        // 扫码枪输入的型号乱码时按 id 查找, 元件不存在时返回 NotFound
+        assert_eq!(
+            element(bom.scan(&format!("BOM:E:{id}:R1??"))?),
+            "R10K@Yageo"
+        );
+        assert!(matches!(
+            bom.scan("BOM:E:999:R22K"),
+            Err(BomError::NotFound(_))
+        ));
+        // 不是标签时当作元件的键
+        assert_eq!(element(bom.scan("R1K\n")?), "R1K");
+        // 行号被新元件重用时, 旧标签不会解析成另一个元件
+        let reused = bom.element_label("R1K")?.code;
+        bom.remove_element("R1K")?;
+        bom.add_element(test_element("X5", 5))?;
+        assert_eq!(
+            bom.element_label("X5")?.code.to_string(),
+            reused.to_string().replace("R1K", "X5")
+        );
+        let garbled = reused.to_string().replace("R1K", "R1?");
+        assert!(matches!(bom.scan(&garbled), Err(BomError::NotFound(_))));
+
+        let label = bom.location_label("柜子A/抽屉3")?;
+        assert_eq!(label.lines, vec!["R10K@Yageo"]);
+        match bom.scan(&label.code.to_string())? {
+            Scanned::Location { path, .. } => assert_eq!(path, "柜子A/抽屉3"),
+            scanned => panic!("{scanned:?}"),
+        }
+        assert_eq!(bom.element_labels()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn backups_rotate_and_restore() -> Result<(), Box<dyn Error>> {
+        let path = temp_db_path("backup");
+        let _ = std::fs::remove_dir_all(backup::backup_dir(&path));
+        let mut ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        ctrl.add_element(test_element("R10K", 10))?;
+        let manual = ctrl.backup(backup::BACKUP_MANUAL)?;
+        ctrl.add_element(test_element("R20K", 20))?;
+
+        // 删除所有元件前自动备份
+        ctrl.remove_element("all")?;
+        let backups = ctrl.backups()?;
+        let reasons: Vec<&str> = backups.iter().map(|b| b.reason.as_str()).collect();
+        assert_eq!(reasons, vec!["manual", "remove-all"]);
+
+        ctrl.restore_backup(backups[1].id)?;
+        assert_eq!(ctrl.element("R20K")?.number, 20);
+        assert_eq!(ctrl.element_map.len(), 2);
+        // 恢复前的状态也有备份
+        assert_eq!(ctrl.backups()?.last().unwrap().reason, "restore");
+        ctrl.restore_backup(manual.id)?;
+        assert!(matches!(ctrl.element("R20K"), Err(BomError::NotFound(_))));
+        assert!(!ctrl.element_map.contains_key("R20K"));
+        assert!(matches!(
+            ctrl.restore_backup(999),
+            Err(BomError::NotFound(_))
+        ));
+
+        // 只保留最近的几份
+        for _ in 0..backup::BACKUP_KEEP {
+            ctrl.backup(backup::BACKUP_MANUAL)?;
+        }
+        let backups = ctrl.backups()?;
+        assert_eq!(backups.len(), backup::BACKUP_KEEP);
+        assert!(backups[0].id > manual.id);
+        let latest = backups.last().unwrap().id;
+        drop(ctrl);
+
+        // 截断的文件不会被当作新文件覆盖, 可以从备份恢复
+        let data = std::fs::read(&path)?;
+        std::fs::write(&path, &data[..data.len() / 2])?;
+        assert!(matches!(
+            BomManageCtrl::new(&path, "bom_data"),
+            Err(BomError::CorruptFile(_))
+        ));
+        std::fs::write(&path, b"")?;
+        assert!(matches!(
+            BomManageCtrl::new(&path, "bom_data"),
+            Err(BomError::CorruptFile(_))
+        ));
+        assert_eq!(std::fs::metadata(&path)?.len(), 0);
+
+        backup::restore_backup_file(&path, latest)?;
+        let ctrl = BomManageCtrl::new(&path, "bom_data")?;
+        assert_eq!(ctrl.element("R10K")?.number, 10);
+        assert!(std::path::Path::new(&format!("{path}.corrupt")).exists());
+        Ok(())
+    }
+
+    // 不同存储后端的 BomManageCtrl 行为相同
+    fn exercise_store<S: BomStore>(ctrl: &mut BomManageCtrl<S>) -> Result<(), Box<dyn Error>> {
+        ctrl.add_element(test_element("R10K", 10))?;
+        ctrl.add_element(test_element("R10K", 120))?;
+        ctrl.add_element(test_element("C10uF", 30))?;
+        assert_eq!(ctrl.element_map["R10K"].number, 130);
+        assert_eq!(ctrl.element_map["R10K"].state, ElementStatus::ALOT);
+
+        let report = ctrl.reduce_element_available("C10uF".to_string(), 40)?;
+        assert_eq!((report.taken, report.shortfall), (30, 10));
+        assert!(matches!(
+            ctrl.reduce_element("R10K".to_string(), 200),
+            Err(BomError::InsufficientStock { .. })
+        ));
+        ctrl.reduce_element("R10K".to_string(), 60)?;
+        assert_eq!(ctrl.element("R10K")?.state, ElementStatus::NORMAL);
+
+        let mut element = ctrl.element("R10K")?;
+        element.describe = "贴片电阻".to_string();
+        ctrl.update_element(&element)?;
+        assert!(matches!(
+            ctrl.update_element(&element),
+            Err(BomError::Conflict { .. })
+        ));
+        assert_eq!(ctrl.element_map["R10K"].describe, "贴片电阻");
+
+        ctrl.del_element("C10uF".to_string())?;
+        assert!(matches!(ctrl.element("C10uF"), Err(BomError::NotFound(_))));
+        let deltas: Vec<i64> = ctrl
+            .element_history("C10uF")?
+            .iter()
+            .map(|m| m.delta)
+            .collect();
+        assert_eq!(deltas, vec![30, -30]);
+
+        ctrl.remove_element("all")?;
+        ctrl.reload()?;
+        assert!(ctrl.element_map.is_empty());
+        assert_eq!(ctrl.element_history("R10K")?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn every_store_behaves_the_same() -> Result<(), Box<dyn Error>> {
+        exercise_store(&mut BomManageCtrl::new(
+            &temp_db_path("store_sqlite"),
+            "bom_data",
+        )?)?;
+        exercise_store(&mut BomManageCtrl::with_store(MemoryStore::default())?)?;
+
+        let path = temp_db_path("store_json").replace(".db", ".json");
+        exercise_store(&mut BomManageCtrl::with_store(JsonStore::open(&path)?)?)?;
+        // 重新打开 JSON 文件, 流水还在
+        let ctrl = BomManageCtrl::with_store(JsonStore::open(&path)?)?;
+        assert!(ctrl.element_map.is_empty());
+        assert_eq!(ctrl.element_history("R10K")?.len(), 4);
+
+        // 数据库导出的自定义分类在 JSON 文件中读写后保留
+        let mut db = BomManageCtrl::new(&temp_db_path("store_category"), "bom_data")?;
+        db.add_category("芯片/稳压")?;
+        db.add_element(test_element("AMS1117", 10))?;
+        db.set_category("AMS1117", "芯片/稳压")?;
+        // 元件和上级分类的阈值也一起读取, 状态和数据库中的相同
+        db.add_element(test_element("R10K", 30))?;
+        db.set_thresholds("R10K", Some(Thresholds::new(10, 20)?))?;
+        db.set_category_thresholds("芯片", Some(Thresholds::new(5, 8)?))?;
+        let path = temp_db_path("store_category").replace(".db", ".json");
+        db.export(&path, ExportFormat::Json)?;
+        let mut ctrl = BomManageCtrl::with_store(JsonStore::open(&path)?)?;
+        let category = ctrl.element("AMS1117")?.category;
+        for model in ["AMS1117", "R10K"] {
+            assert_eq!(ctrl.element(model)?.state, db.element(model)?.state);
+        }
+        assert_eq!(ctrl.element("AMS1117")?.state, ElementStatus::ALOT);
+        for (model, number) in [("R10K", 12), ("AMS1117", 4)] {
+            ctrl.reduce_element(model.to_string(), number)?;
+            db.reduce_element(model.to_string(), number)?;
+            assert_eq!(ctrl.element(model)?.state, db.element(model)?.state);
+        }
+        assert_eq!(ctrl.element("R10K")?.state, ElementStatus::NORMAL);
+        assert_eq!(ctrl.element("AMS1117")?.state, ElementStatus::NORMAL);
+        ctrl.add_element(test_element("R10K", 5))?;
+        db.add_element(test_element("R10K", 5))?;
+        assert_eq!(ctrl.element("R10K")?.state, db.element("R10K")?.state);
+        // 不知道路径的分类不能写入文件
+        let mut element = ctrl.element("R10K")?;
+        element.category = Some(99);
+        assert!(matches!(
+            ctrl.update_element(&element),
+            Err(BomError::NotFound(_))
+        ));
+        let ctrl = BomManageCtrl::with_store(JsonStore::open(&path)?)?;
+        assert_eq!(ctrl.element("AMS1117")?.category, category);
+        assert_eq!(ctrl.element("R10K")?.category, Some(1));
+        let inventory = export::read_inventory(&path, ExportFormat::Json)?;
+        let categories: Vec<Option<&str>> = inventory
+            .elements
+            .iter()
+            .map(|e| e.category.as_deref())
+            .collect();
+        assert_eq!(categories, vec![Some("芯片/稳压"), Some("电阻")]);
+        assert!(inventory.categories.iter().any(|c| c.path == "芯片/稳压"));
+        Ok(())
+    }
+
+    #[test]
+    fn json_store_keeps_the_rest_of_an_export() -> Result<(), Box<dyn Error>> {
+        let mut db = BomManageCtrl::new(&temp_db_path("store_keep"), "bom_data")?;
+        db.add_element_at(test_element("R10K", 100), "柜子A/抽屉1")?;
+        db.move_stock("R10K", Some("柜子A/抽屉1"), Some("柜子B"), 30)?;
+        db.add_element(test_element("C10uF", 30))?;
+        db.set_thresholds("R10K", Some(Thresholds::new(10, 20)?))?;
+        db.add_supplier_part(&SupplierPart {
+            supplier: "LCSC".to_string(),
+            sku: "C25804".to_string(),
+            model: "R10K".to_string(),
+            currency: "CNY".to_string(),
+            ..Default::default()
+        })?;
+        db.add_note("C10uF", "放在防潮箱")?;
+        let path = temp_db_path("store_keep").replace(".db", ".json");
+        db.export(&path, ExportFormat::Json)?;
+        let before = export::read_inventory(&path, ExportFormat::Json)?;
+
+        // 修改一个元件后, 其他元件和文件的其他内容不变, 位置上的数量不超过库存
+        let mut ctrl = BomManageCtrl::with_store(JsonStore::open(&path)?)?;
+        ctrl.reduce_element("R10K".to_string(), 50)?;
+        let after = export::read_inventory(&path, ExportFormat::Json)?;
+        let r10k = after.elements.iter().find(|e| e.model == "R10K").unwrap();
+        assert_eq!((r10k.min_stock, r10k.reorder_point), (Some(10), Some(20)));
+        let located: Vec<(&str, u32)> = r10k
+            .locations
+            .iter()
+            .map(|l| (l.path.as_str(), l.quantity))
+            .collect();
+        assert_eq!(located, vec![("柜子A/抽屉1", 50)]);
+        assert_eq!(after.elements[0], before.elements[0]);
+        assert_eq!(after.suppliers, before.suppliers);
+        assert_eq!(after.locations, before.locations);
+        assert_eq!(after.notes, before.notes);
+        assert_eq!(after.categories, before.categories);
+        assert_eq!(after.movements.len(), before.movements.len() + 1);
+        Ok(())
+    }
 
     #[test]
     fn it_works() -> Result<(), Box<dyn Error>> {
-        // 数据文件地址
-        const DATA_FILE: &str = "data_resource.db";
-        // 表名
-        const TABLE_NAME: &str = "bom_data";
-        // 创建数据库实例
-        let mut bom_manage_ctrl = match BomManageCtrl::new(DATA_FILE, TABLE_NAME) {
+        // 使用内存存储, 不在工作目录中生成数据文件
+        let mut bom_manage_ctrl = match BomManageCtrl::with_store(MemoryStore::default()) {
             Ok(bom_manage_ctrl) => bom_manage_ctrl,
             Err(error) => panic!("Error: {error}"),
         };
@@ -691,23 +4136,32 @@ mod tests {
         let res = Element {
             describe: "电阻".to_string(),
             model: "R20K".to_string(),
+            manufacturer: String::new(),
             number: 100,
             element_type: ElementType::Resistor,
             state: ElementStatus::ALOT,
+            category: None,
+            version: 0,
         };
         let cap = Element {
             describe: "电容".to_string(),
             model: "C10uF".to_string(),
+            manufacturer: String::new(),
             number: 100,
             element_type: ElementType::Capacitor,
             state: ElementStatus::ALOT,
+            category: None,
+            version: 0,
         };
         let cap2 = Element {
             describe: "电容".to_string(),
             model: "C10uF".to_string(),
+            manufacturer: String::new(),
             number: 120,
             element_type: ElementType::Capacitor,
             state: ElementStatus::ALOT,
+            category: None,
+            version: 0,
         };
 
         bom_manage_ctrl.add_element(res)?;